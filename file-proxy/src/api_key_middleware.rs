@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+    response::Response,
+};
+
+/// Header carrying the API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Routes reachable without an API key
+const PUBLIC_PATHS: &[&str] = &["/health"];
+
+pub async fn api_key_middleware(
+    State(api_key): State<Arc<String>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if PUBLIC_PATHS.contains(&req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    let provided = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok());
+
+    if provided.is_some_and(|key| constant_time_eq(key.as_bytes(), api_key.as_bytes())) {
+        next.run(req).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            axum::Json(serde_json::json!({
+                "error": "Invalid or missing API key"
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Compare without returning early on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
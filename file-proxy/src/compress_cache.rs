@@ -0,0 +1,207 @@
+use axum::body::Bytes;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+/// Identifies one compressed output; a new mtime means a new entry
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    /// Content coding of the output (`zstd`, `gzip`)
+    pub encoding: &'static str,
+    pub level: i32,
+}
+
+struct Entry {
+    cell: Arc<OnceCell<Bytes>>,
+    /// Compressed size once counted against the cap (0 while compressing)
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<CacheKey, Entry>,
+    total_bytes: u64,
+    tick: u64,
+}
+
+/// Recently compressed downloads, shared by concurrent and repeated requests
+///
+/// Requests for the same key while it is being compressed wait for that single
+/// compression instead of starting their own. Finished entries are evicted least
+/// recently used first once their total size exceeds `max_bytes`.
+pub struct CompressCache {
+    max_bytes: u64,
+    inner: Mutex<Inner>,
+}
+
+impl CompressCache {
+    /// Create a cache holding up to `max_bytes` of compressed data (0 disables caching,
+    /// concurrent requests still share one compression)
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Return the cached output for `key`, running `compress` if no request has produced it yet
+    /// A failed compression is not cached; the next request tries again
+    pub async fn get_or_compress<F, Fut, E>(&self, key: CacheKey, compress: F) -> Result<Bytes, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Bytes, E>>,
+    {
+        let cell = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.tick += 1;
+            let tick = inner.tick;
+
+            if !inner.entries.contains_key(&key) {
+                // Drop outputs of older versions of the same file
+                let stale: Vec<CacheKey> = inner
+                    .entries
+                    .keys()
+                    .filter(|k| k.path == key.path && k.encoding == key.encoding && k.level == key.level)
+                    .cloned()
+                    .collect();
+                for stale_key in stale {
+                    debug!("Invalidating cached compression of {:?}", stale_key.path);
+                    inner.remove(&stale_key);
+                }
+            }
+
+            let entry = inner.entries.entry(key.clone()).or_insert_with(|| Entry {
+                cell: Arc::new(OnceCell::new()),
+                size: 0,
+                last_used: tick,
+            });
+            entry.last_used = tick;
+            entry.cell.clone()
+        };
+
+        let data = cell.get_or_try_init(compress).await?.clone();
+        self.account(&key, &cell, data.len() as u64);
+        Ok(data)
+    }
+
+    /// Total size of the finished entries
+    pub fn total_bytes(&self) -> u64 {
+        self.inner.lock().unwrap().total_bytes
+    }
+
+    /// Count a finished entry against the cap and evict until it fits
+    fn account(&self, key: &CacheKey, cell: &Arc<OnceCell<Bytes>>, size: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(entry) = inner.entries.get_mut(key) else {
+            return;
+        };
+        // The entry may have been replaced by a newer one for the same key
+        if !Arc::ptr_eq(&entry.cell, cell) || entry.size != 0 {
+            return;
+        }
+        entry.size = size;
+        inner.total_bytes += size;
+
+        while inner.total_bytes > self.max_bytes {
+            let oldest = inner
+                .entries
+                .iter()
+                .filter(|(_, e)| e.cell.initialized())
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(oldest) => {
+                    debug!("Evicting cached compression of {:?}", oldest.path);
+                    inner.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total_bytes -= entry.size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn key(path: &str, modified_secs: u64) -> CacheKey {
+        CacheKey {
+            path: PathBuf::from(path),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(modified_secs),
+            encoding: "zstd",
+            level: 19,
+        }
+    }
+
+    async fn compress_counted(runs: &AtomicUsize, data: &'static [u8]) -> Result<Bytes, ()> {
+        runs.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        Ok(Bytes::from_static(data))
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_compress_once() {
+        let cache = CompressCache::new(1024);
+        let runs = AtomicUsize::new(0);
+
+        let (a, b) = tokio::join!(
+            cache.get_or_compress(key("a", 1), || compress_counted(&runs, b"aaaa")),
+            cache.get_or_compress(key("a", 1), || compress_counted(&runs, b"aaaa")),
+        );
+        assert_eq!(a.unwrap(), b.unwrap());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // Repeated requests are served from the cache
+        cache.get_or_compress(key("a", 1), || compress_counted(&runs, b"aaaa")).await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.total_bytes(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_modified_file_is_compressed_again() {
+        let cache = CompressCache::new(1024);
+        let runs = AtomicUsize::new(0);
+
+        cache.get_or_compress(key("a", 1), || compress_counted(&runs, b"old")).await.unwrap();
+        let data = cache.get_or_compress(key("a", 2), || compress_counted(&runs, b"newer")).await.unwrap();
+        assert_eq!(&data[..], b"newer");
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.total_bytes(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used_over_cap() {
+        let cache = CompressCache::new(8);
+        let runs = AtomicUsize::new(0);
+
+        cache.get_or_compress(key("a", 1), || compress_counted(&runs, b"aaaa")).await.unwrap();
+        cache.get_or_compress(key("b", 1), || compress_counted(&runs, b"bbbb")).await.unwrap();
+        cache.get_or_compress(key("a", 1), || compress_counted(&runs, b"aaaa")).await.unwrap();
+        cache.get_or_compress(key("c", 1), || compress_counted(&runs, b"cccc")).await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.total_bytes(), 8);
+
+        // "b" was the least recently used and had to go
+        cache.get_or_compress(key("a", 1), || compress_counted(&runs, b"aaaa")).await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        cache.get_or_compress(key("b", 1), || compress_counted(&runs, b"bbbb")).await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+    }
+}
@@ -1,6 +1,5 @@
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -8,22 +7,6 @@ pub struct Claims {
     pub exp: usize,
 }
 
-pub fn create_token(
-    secret: &str,
-    subject: Option<&str>,
-    expires_in_secs: u64,
-) -> Result<String, jsonwebtoken::errors::Error> {
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-    let claims = Claims {
-        sub: subject.map(|s| s.to_string()),
-        exp: (now + expires_in_secs) as usize,
-    };
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes()))
-}
-
 pub fn validate_token(
     secret: &str,
     token: &str,
@@ -38,3 +21,28 @@ pub fn validate_token(
     Ok(data.claims)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn create_token(secret: &str, subject: Option<&str>, expires_in_secs: u64) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let claims = Claims {
+            sub: subject.map(|s| s.to_string()),
+            exp: (now + expires_in_secs) as usize,
+        };
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_validate_token() {
+        let token = create_token("secret", Some("alice"), 60);
+        assert_eq!(validate_token("secret", &token).unwrap().sub.as_deref(), Some("alice"));
+        assert!(validate_token("other", &token).is_err());
+    }
+}
@@ -9,7 +9,7 @@ use axum::{
     response::Response,
 };
 
-use crate::jwt_helper::{validate_token, Claims};
+use crate::jwt_helper::validate_token;
 
 pub async fn jwt_middleware(
     State(secret): State<Arc<String>>,
@@ -1,24 +1,30 @@
 use axum::{
-    extract::Query,
-    http::StatusCode,
+    extract::{ConnectInfo, Query},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
     response::{IntoResponse, Response},
-    routing::get,
-    Router,
+    routing::{delete, get},
+    Extension, Router,
 };
+use anyhow::Context;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use sled::Db;
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path as StdPath, PathBuf};
 use std::sync::Arc;
 use tokio::fs::{File, read_dir};
 use tokio::io::AsyncReadExt;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::{error, info, warn};
 use aws_sdk_s3::Client as S3Client;
 
+mod compress_cache;
+use compress_cache::{CacheKey, CompressCache};
 mod s3;
-use s3::{S3Config, S3UrlResponse};
+use s3::{PresignedUrlCache, S3Config, S3UrlResponse};
 mod jwt_helper;
 mod jwt_middleware;
+mod api_key_middleware;
 use axum::middleware;
 
 // Command line arguments
@@ -38,9 +44,40 @@ struct Args {
     #[arg(short, long, env = "PORT", default_value = "3000")]
     port: u16,
 
+    /// Interface to bind to (e.g., 127.0.0.1 to only accept local connections)
+    #[arg(long, env = "BIND", default_value = "0.0.0.0")]
+    bind: IpAddr,
+
+    /// Number of runtime worker threads (default: number of CPU cores)
+    #[arg(long, env = "WORKERS", value_parser = clap::value_parser!(u16).range(1..))]
+    workers: Option<u16>,
+
     /// Database path
     #[arg(long, env = "DB_PATH", default_value = "./db/file-proxy.db")]
     db_path: String,
+
+    /// Maximum bytes of compressed downloads kept in memory (0 disables the cache)
+    #[arg(long, env = "COMPRESS_CACHE_BYTES", default_value = "268435456")]
+    compress_cache_bytes: u64,
+}
+
+/// ZSTD level used for `/download`
+const DOWNLOAD_COMPRESSION_LEVEL: i32 = 19;
+
+/// Gzip level used for `/download` when the client does not accept zstd
+const DOWNLOAD_GZIP_LEVEL: u32 = 6;
+
+impl Args {
+    /// Worker thread count, falling back to the number of CPU cores
+    fn worker_threads(&self) -> usize {
+        self.workers
+            .map(usize::from)
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    fn bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
 }
 
 // Configuration structures
@@ -48,13 +85,20 @@ struct Args {
 struct Config {
     s3: Option<S3Config>,
     jwt: Option<JwtConfig>,
+    /// Enable `DELETE /rm` (read-only deployments leave this off)
+    #[serde(default)]
+    allow_delete: bool,
+    /// Origins allowed to call the API from a browser ("*" allows any)
+    #[serde(default)]
+    cors_allowed_origins: Vec<String>,
+    /// If set, every route except `/health` requires this key in the `X-Api-Key` header
+    api_key: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 struct JwtConfig {
     use_jwt: bool,
     jwt_secret: String,
-    jwt_expires_in: u64,
 }
 
 #[derive(Clone)]
@@ -63,6 +107,9 @@ struct AppState {
     s3_client: Option<Arc<S3Client>>,
     s3_config: Option<Arc<S3Config>>,
     db: Option<Arc<Db>>,
+    presigned_urls: Arc<PresignedUrlCache>,
+    compress_cache: Arc<CompressCache>,
+    allow_delete: bool,
 }
 
 #[derive(Deserialize)]
@@ -70,6 +117,28 @@ struct DownloadQuery {
     file: String,
 }
 
+#[derive(Deserialize)]
+struct StatQuery {
+    file: String,
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    file: String,
+    /// Required to delete a directory and everything in it
+    #[serde(default)]
+    recursive: bool,
+    /// Must repeat `file` when deleting recursively
+    confirm: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct DeleteResponse {
+    deleted: String,
+    is_dir: bool,
+    freed_bytes: u64,
+}
+
 #[derive(Deserialize)]
 struct ListQuery {
     dir: String,
@@ -87,21 +156,49 @@ struct FileInfo {
     name: String,
     is_dir: bool,
     size: u64,
+    /// Last modification time (Unix timestamp, seconds)
+    modified: u64,
 }
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 2)]
-async fn main() -> anyhow::Result<()> {
+impl FileInfo {
+    fn new(name: String, metadata: &std::fs::Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            name,
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            modified,
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
     // Parse command line arguments
     let args = Args::parse();
 
+    let worker_threads = args.worker_threads();
+    info!("Starting runtime with {} worker threads", worker_threads);
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .enable_all()
+        .build()?
+        .block_on(run(args))
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
     info!("Starting file-proxy server with base directory: {}", args.dir);
     info!("Using configuration file: {}", args.config);
 
     // Load config if it exists
-    let config = load_config(&args.config).await;
+    let config = load_config(&args.config).await?;
     
     // Initialize database
     let db = match sled::open(&args.db_path) {
@@ -137,18 +234,92 @@ async fn main() -> anyhow::Result<()> {
         s3_client,
         s3_config,
         db,
+        presigned_urls: Arc::new(PresignedUrlCache::default()),
+        compress_cache: Arc::new(CompressCache::new(args.compress_cache_bytes)),
+        allow_delete: config.as_ref().is_some_and(|c| c.allow_delete),
     };
+    if state.allow_delete {
+        warn!("DELETE /rm is enabled");
+    }
+    let db = state.db.clone();
+
+    let app = build_app(state, config.as_ref());
+
+    let bind_addr = args.bind_addr();
+    
+    // Start server
+    let listener = tokio::net::TcpListener::bind(bind_addr).await?;
+    info!("Server running on http://{}", bind_addr);
+
+    serve_app(listener, app, shutdown_signal()).await?;
+
+    // Persist cache entries still held in sled's write buffer
+    if let Some(db) = db {
+        match db.flush_async().await {
+            Ok(bytes) => info!("Flushed database ({} bytes)", bytes),
+            Err(e) => error!("Failed to flush database on shutdown: {}", e),
+        }
+    }
+    info!("Server stopped");
 
+    Ok(())
+}
+
+/// Serve until `shutdown` resolves, then stop accepting connections and wait for
+/// in-flight requests to finish
+async fn serve_app<F>(listener: tokio::net::TcpListener, app: Router, shutdown: F) -> std::io::Result<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+/// Resolve on SIGINT (Ctrl-C) or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT, shutting down"),
+        _ = terminate => info!("Received SIGTERM, shutting down"),
+    }
+}
+
+/// Build the router with the middleware enabled by the configuration
+fn build_app(state: AppState, config: Option<&Config>) -> Router {
     // Build router
     let base_router = Router::new()
         .route("/download", get(download_file))
         .route("/ls", get(list_files))
+        .route("/stat", get(stat_file))
+        .route("/rm", delete(delete_file))
         .route("/get_s3_url", get(get_s3_url))
         .route("/health", get(health_check))
         .with_state(state);
 
     // Conditionally enable JWT middleware
-    let app = if let Some(cfg) = config.as_ref().and_then(|c| c.jwt.clone()) {
+    let app = if let Some(cfg) = config.and_then(|c| c.jwt.clone()) {
         if cfg.use_jwt {
             let secret = Arc::new(cfg.jwt_secret.clone());
             base_router.layer(middleware::from_fn_with_state(
@@ -162,35 +333,142 @@ async fn main() -> anyhow::Result<()> {
         base_router
     };
 
-    let bind_addr = format!("0.0.0.0:{}", args.port);
-    
-    // Start server
-    let listener = tokio::net::TcpListener::bind(&bind_addr).await?;
-    info!("Server running on http://0.0.0.0:{}", args.port);
+    // Conditionally require an API key
+    let app = match config.and_then(|c| c.api_key.clone()) {
+        Some(api_key) => {
+            info!("API key required for all routes except /health");
+            app.layer(middleware::from_fn_with_state(
+                Arc::new(api_key),
+                api_key_middleware::api_key_middleware,
+            ))
+        }
+        None => app,
+    };
 
-    axum::serve(listener, app).await?;
+    // CORS goes outermost so preflight requests are answered before authentication
+    match config.map(|c| &c.cors_allowed_origins) {
+        Some(origins) if !origins.is_empty() => app.layer(cors_layer(origins)),
+        _ => app,
+    }
+}
 
-    Ok(())
+/// CORS layer for browser clients calling from `origins`
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("Ignoring invalid CORS origin {}: {}", origin, e);
+                None
+            }
+        }))
+    };
+    info!("CORS enabled for origins: {:?}", origins);
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::DELETE, Method::OPTIONS])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::IF_NONE_MATCH,
+            HeaderName::from_static(api_key_middleware::API_KEY_HEADER),
+        ])
+        .expose_headers([header::ETAG, header::CONTENT_DISPOSITION])
 }
 
 async fn health_check() -> &'static str {
     "OK"
 }
 
-async fn load_config(path: &str) -> Option<Config> {
-    match tokio::fs::read_to_string(path).await {
-        Ok(content) => match serde_yaml::from_str::<Config>(&content) {
-            Ok(config) => Some(config),
-            Err(e) => {
-                warn!("Failed to parse config file {}: {}", path, e);
-                None
-            }
-        },
-        Err(e) => {
-            warn!("Failed to read config file {}: {}", path, e);
-            None
+/// Load the config file if there is one, with `APP__`-prefixed environment variables taking precedence
+///
+/// A missing file means running without config. A file that cannot be read or parsed,
+/// or an `APP__` override that does not apply, fails startup: running without the config
+/// would silently drop its auth and delete rules.
+async fn load_config(path: &str) -> anyhow::Result<Option<Config>> {
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!("Config file {} not found, running without config", path);
+            return Ok(None);
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read config file {}", path)),
+    };
+    let config = parse_config(&content, std::env::vars())
+        .with_context(|| format!("Failed to parse config file {}", path))?;
+    Ok(Some(config))
+}
+
+/// Parse config YAML with the `APP__` overrides among `vars` applied
+fn parse_config<I>(content: &str, vars: I) -> anyhow::Result<Config>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut value = serde_yaml::from_str::<serde_yaml::Value>(content)?;
+    apply_env_overrides(&mut value, vars)?;
+    Ok(serde_yaml::from_value::<Config>(value)?)
+}
+
+/// Prefix of environment variables that override config values
+const ENV_OVERRIDE_PREFIX: &str = "APP__";
+
+/// Overlay `APP__`-prefixed variables onto the parsed config YAML
+///
+/// The rest of the name is split on `__` into lowercased keys (`APP__S3__BUCKET` sets
+/// `s3.bucket`); numeric keys index into lists. Values are parsed as YAML, except that a
+/// value replacing an existing string stays a string. Missing mappings are created.
+fn apply_env_overrides<I>(config: &mut serde_yaml::Value, vars: I) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut overrides: Vec<(String, String)> = vars
+        .into_iter()
+        .filter_map(|(name, value)| name.strip_prefix(ENV_OVERRIDE_PREFIX).map(|path| (path.to_string(), value)))
+        .collect();
+    overrides.sort();
+
+    for (path, raw) in overrides {
+        let keys: Vec<String> = path.split("__").map(|key| key.to_lowercase()).collect();
+        let target = env_override_target(config, &keys)
+            .with_context(|| format!("Invalid config override {}{}", ENV_OVERRIDE_PREFIX, path))?;
+        *target = if target.is_string() {
+            serde_yaml::Value::String(raw)
+        } else {
+            serde_yaml::from_str(&raw).unwrap_or(serde_yaml::Value::String(raw))
+        };
+    }
+    Ok(())
+}
+
+/// Walk (and create) the nested keys of an override
+fn env_override_target<'a>(
+    mut node: &'a mut serde_yaml::Value,
+    keys: &[String],
+) -> anyhow::Result<&'a mut serde_yaml::Value> {
+    use serde_yaml::Value;
+
+    for key in keys {
+        if key.is_empty() {
+            anyhow::bail!("empty key");
+        }
+        if node.is_null() {
+            *node = Value::Mapping(serde_yaml::Mapping::new());
         }
+        node = match node {
+            Value::Mapping(map) => map.entry(Value::String(key.clone())).or_insert(Value::Null),
+            Value::Sequence(items) => {
+                let len = items.len();
+                let index: usize = key.parse().with_context(|| format!("'{}' is not a list index", key))?;
+                items
+                    .get_mut(index)
+                    .with_context(|| format!("index {} out of range (length {})", index, len))?
+            }
+            _ => anyhow::bail!("'{}' is not a mapping", key),
+        };
     }
+    Ok(node)
 }
 
 
@@ -239,6 +517,7 @@ async fn get_s3_url(
         s3_client.clone(),
         s3_config.clone(),
         db.clone(),
+        &state.presigned_urls,
         &file_path,
         &query.file,
         query.update,
@@ -296,11 +575,7 @@ async fn list_files(
         let file_name = entry.file_name();
         let name = file_name.to_string_lossy().to_string();
 
-        files.push(FileInfo {
-            name,
-            is_dir: metadata.is_dir(),
-            size: metadata.len(),
-        });
+        files.push(FileInfo::new(name, &metadata));
     }
 
     // Sort files by name
@@ -311,9 +586,127 @@ async fn list_files(
     Ok(axum::Json(files))
 }
 
+async fn stat_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Query(query): Query<StatQuery>,
+) -> Result<axum::Json<FileInfo>, AppError> {
+    // Validate file path to prevent directory traversal
+    let file_path = validate_path(&state.base_dir, &query.file)?;
+
+    let metadata = tokio::fs::metadata(&file_path)
+        .await
+        .map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                AppError::FileNotFound
+            } else {
+                error!("Failed to get file metadata: {}", e);
+                AppError::FileReadError
+            }
+        })?;
+
+    let name = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    Ok(axum::Json(FileInfo::new(name, &metadata)))
+}
+
+async fn delete_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    claims: Option<Extension<jwt_helper::Claims>>,
+    Query(query): Query<DeleteQuery>,
+) -> Result<axum::Json<DeleteResponse>, AppError> {
+    if !state.allow_delete {
+        return Err(AppError::DeleteDisabled);
+    }
+
+    // Never delete the base directory itself
+    if query.file.trim_matches('/').is_empty() {
+        return Err(AppError::InvalidPath);
+    }
+
+    // Validate file path to prevent directory traversal
+    let file_path = validate_path(&state.base_dir, &query.file)?;
+    if is_base_dir(&state.base_dir, &file_path) {
+        return Err(AppError::InvalidPath);
+    }
+
+    let metadata = tokio::fs::symlink_metadata(&file_path)
+        .await
+        .map_err(|e| {
+            error!("Failed to get file metadata: {}", e);
+            AppError::FileNotFound
+        })?;
+    let is_dir = metadata.is_dir();
+    if is_dir && !query.recursive {
+        return Err(AppError::IsADirectory);
+    }
+    if is_dir && query.confirm.as_deref() != Some(query.file.as_str()) {
+        return Err(AppError::ConfirmationRequired);
+    }
+
+    let requester = match claims {
+        Some(Extension(claims)) => format!("{} ({})", claims.sub.unwrap_or_default(), peer),
+        None => peer.to_string(),
+    };
+
+    let path = file_path.clone();
+    let freed_bytes = tokio::task::spawn_blocking(move || -> std::io::Result<u64> {
+        if is_dir {
+            let size = dir_size(&path)?;
+            std::fs::remove_dir_all(&path)?;
+            Ok(size)
+        } else {
+            std::fs::remove_file(&path)?;
+            Ok(metadata.len())
+        }
+    })
+    .await
+    .map_err(|e| {
+        error!("Delete task failed: {}", e);
+        AppError::DeleteError
+    })?
+    .map_err(|e| {
+        error!("Failed to delete {:?}: {}", file_path, e);
+        AppError::DeleteError
+    })?;
+
+    warn!(
+        "Deleted {}: {} ({} bytes freed) requested by {}",
+        if is_dir { "directory" } else { "file" },
+        query.file,
+        freed_bytes,
+        requester
+    );
+
+    Ok(axum::Json(DeleteResponse {
+        deleted: query.file,
+        is_dir,
+        freed_bytes,
+    }))
+}
+
+/// Total size of the files under a directory (symlinks are not followed)
+fn dir_size(path: &StdPath) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+        if metadata.is_dir() {
+            total += dir_size(&entry.path())?;
+        } else {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
+}
+
 async fn download_file(
     axum::extract::State(state): axum::extract::State<AppState>,
     Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
 ) -> Result<Response, AppError> {
     // Validate file path to prevent directory traversal
     let file_path = validate_path(&state.base_dir, &query.file)?;
@@ -328,61 +721,249 @@ async fn download_file(
         return Err(AppError::NotAFile);
     }
 
-    // Read file
-    let mut file = File::open(&file_path)
+    let metadata = tokio::fs::metadata(&file_path)
         .await
         .map_err(|e| {
-            error!("Failed to open file: {}", e);
+            error!("Failed to get file metadata: {}", e);
             AppError::FileReadError
         })?;
+    let modified = metadata.modified().map_err(|e| {
+        error!("Failed to get file modification time: {}", e);
+        AppError::FileReadError
+    })?;
 
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)
-        .await
-        .map_err(|e| {
-            error!("Failed to read file: {}", e);
-            AppError::FileReadError
-        })?;
+    // Unchanged since the client's copy: skip reading and compressing
+    let etag = weak_etag(metadata.len(), modified);
+    let client_has_current = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| etag_matches(v, &etag));
+    if client_has_current {
+        info!("Not modified: {}", query.file);
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
 
-    // Compress with ZSTD
-    let compressed = zstd::encode_all(buffer.as_slice(), 19)
-        .map_err(|e| {
-            error!("Failed to compress file: {}", e);
-            AppError::CompressionError
-        })?;
+    let encoding = DownloadEncoding::negotiate(
+        headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok()),
+    );
+
+    let body = match encoding.content_coding() {
+        Some(coding) => {
+            let key = CacheKey {
+                path: file_path.clone(),
+                modified,
+                encoding: coding,
+                level: encoding.level(),
+            };
+
+            // Concurrent and repeated downloads of the same file share one compression
+            state
+                .compress_cache
+                .get_or_compress(key, || read_and_compress(file_path.clone(), encoding))
+                .await?
+        }
+        None => read_file(&file_path).await?.into(),
+    };
 
     info!(
-        "Downloaded file: {}, original size: {}, compressed size: {}",
+        "Downloaded file: {}, original size: {}, sent size: {} ({}), compression cache: {} bytes",
         query.file,
-        buffer.len(),
-        compressed.len()
+        metadata.len(),
+        body.len(),
+        encoding.content_coding().unwrap_or("identity"),
+        state.compress_cache.total_bytes()
     );
 
-    Ok((
+    let file_name = file_path.file_name().unwrap().to_string_lossy();
+    let mut response = (
         StatusCode::OK,
         [
             ("Content-Type", "application/octet-stream"),
-            ("Content-Encoding", "zstd"),
             (
                 "Content-Disposition",
-                &format!(
-                    "attachment; filename=\"{}.zstd\"",
-                    file_path.file_name().unwrap().to_string_lossy()
-                ),
+                &format!("attachment; filename=\"{}{}\"", file_name, encoding.file_suffix()),
             ),
+            ("ETag", &etag),
+            ("Vary", "Accept-Encoding"),
         ],
-        compressed,
+        body,
     )
-        .into_response())
+        .into_response();
+    if let Some(coding) = encoding.content_coding() {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static(coding));
+    }
+    Ok(response)
+}
+
+/// Compression applied to a `/download` response, chosen from the request's `Accept-Encoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DownloadEncoding {
+    Zstd,
+    Gzip,
+    Identity,
+}
+
+impl DownloadEncoding {
+    /// Prefer zstd, then gzip, then the uncompressed file
+    ///
+    /// A request without `Accept-Encoding` accepts any coding (RFC 9110), so it keeps
+    /// getting zstd as before; a header listing neither zstd nor gzip gets the plain file.
+    /// Codings with `q=0` are refused, `*` covers codings not listed explicitly.
+    fn negotiate(accept_encoding: Option<&str>) -> Self {
+        let Some(accept_encoding) = accept_encoding else {
+            return DownloadEncoding::Zstd;
+        };
+
+        let mut listed: Vec<(String, f32)> = Vec::new();
+        let mut wildcard: Option<f32> = None;
+        for entry in accept_encoding.split(',') {
+            let mut params = entry.split(';');
+            let coding = params.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = params
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match coding.as_str() {
+                "" => {}
+                "*" => wildcard = Some(quality),
+                _ => listed.push((coding, quality)),
+            }
+        }
+        let accepts = |coding: &str| {
+            listed
+                .iter()
+                .find(|(name, _)| name == coding)
+                .map(|(_, quality)| *quality)
+                .or(wildcard)
+                .is_some_and(|quality| quality > 0.0)
+        };
+
+        if accepts("zstd") {
+            DownloadEncoding::Zstd
+        } else if accepts("gzip") {
+            DownloadEncoding::Gzip
+        } else {
+            DownloadEncoding::Identity
+        }
+    }
+
+    /// `Content-Encoding` value, or `None` for the uncompressed file
+    fn content_coding(self) -> Option<&'static str> {
+        match self {
+            DownloadEncoding::Zstd => Some("zstd"),
+            DownloadEncoding::Gzip => Some("gzip"),
+            DownloadEncoding::Identity => None,
+        }
+    }
+
+    fn level(self) -> i32 {
+        match self {
+            DownloadEncoding::Zstd => DOWNLOAD_COMPRESSION_LEVEL,
+            DownloadEncoding::Gzip => DOWNLOAD_GZIP_LEVEL as i32,
+            DownloadEncoding::Identity => 0,
+        }
+    }
+
+    /// Extension appended to the file name in `Content-Disposition`
+    fn file_suffix(self) -> &'static str {
+        match self {
+            DownloadEncoding::Zstd => ".zstd",
+            DownloadEncoding::Gzip => ".gz",
+            DownloadEncoding::Identity => "",
+        }
+    }
+
+    fn encode(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            DownloadEncoding::Zstd => zstd::encode_all(data, DOWNLOAD_COMPRESSION_LEVEL),
+            DownloadEncoding::Gzip => {
+                use std::io::Write;
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::new(DOWNLOAD_GZIP_LEVEL),
+                );
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            DownloadEncoding::Identity => Ok(data.to_vec()),
+        }
+    }
+}
+
+/// Weak ETag of the underlying file (size + mtime), independent of the compressed bytes
+fn weak_etag(size: u64, modified: std::time::SystemTime) -> String {
+    let mtime = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("W/\"{:x}-{:x}\"", size, mtime)
+}
+
+/// Check an `If-None-Match` value (a list of ETags or `*`) using weak comparison
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
+
+/// Read a whole file
+async fn read_file(file_path: &StdPath) -> Result<Vec<u8>, AppError> {
+    let mut file = File::open(file_path)
+        .await
+        .map_err(|e| {
+            error!("Failed to open file: {}", e);
+            AppError::FileReadError
+        })?;
+
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)
+        .await
+        .map_err(|e| {
+            error!("Failed to read file: {}", e);
+            AppError::FileReadError
+        })?;
+
+    Ok(buffer)
+}
+
+/// Read a file and compress it with `encoding`
+async fn read_and_compress(file_path: PathBuf, encoding: DownloadEncoding) -> Result<axum::body::Bytes, AppError> {
+    let buffer = read_file(&file_path).await?;
+
+    // Compress off the async workers
+    let compressed = tokio::task::spawn_blocking(move || encoding.encode(&buffer))
+        .await
+        .map_err(|e| {
+            error!("Compression task failed: {}", e);
+            AppError::CompressionError
+        })?
+        .map_err(|e| {
+            error!("Failed to compress file: {}", e);
+            AppError::CompressionError
+        })?;
+
+    Ok(compressed.into())
 }
 
 /// Validate and normalize the path to prevent directory traversal attacks
+///
+/// The canonicalized path staying inside `base_dir` is the only traversal check, so
+/// names such as `data..backup.json` are allowed. Targets that don't exist yet (e.g.
+/// upload destinations) are resolved through their canonicalized parent directory.
 fn validate_path(base_dir: &StdPath, requested_path: &str) -> Result<PathBuf, AppError> {
-    // Remove leading slashes
-    let cleaned_path = requested_path.trim_start_matches('/');
+    // Paths are relative to base_dir; leading and trailing slashes are ignored
+    let cleaned_path = requested_path.trim_matches('/');
 
-    // Reject paths with .. or .
-    if cleaned_path.contains("..") || cleaned_path.contains("./") || cleaned_path.contains("\\.") {
+    // Reject absolute paths and empty components (`a//b`)
+    if StdPath::new(cleaned_path).has_root()
+        || (!cleaned_path.is_empty() && cleaned_path.split('/').any(str::is_empty))
+    {
         return Err(AppError::InvalidPath);
     }
 
@@ -391,9 +972,19 @@ fn validate_path(base_dir: &StdPath, requested_path: &str) -> Result<PathBuf, Ap
     // Ensure the resolved path is within base_dir
     let canonical_base = std::fs::canonicalize(base_dir)
         .map_err(|_| AppError::InvalidPath)?;
-    
-    let canonical_full = std::fs::canonicalize(&full_path)
-        .map_err(|_| AppError::FileNotFound)?;
+
+    let canonical_full = match std::fs::canonicalize(&full_path) {
+        Ok(path) => path,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let (Some(parent), Some(name)) = (full_path.parent(), full_path.file_name()) else {
+                return Err(AppError::InvalidPath);
+            };
+            std::fs::canonicalize(parent)
+                .map_err(|_| AppError::FileNotFound)?
+                .join(name)
+        }
+        Err(_) => return Err(AppError::FileNotFound),
+    };
 
     if !canonical_full.starts_with(&canonical_base) {
         return Err(AppError::InvalidPath);
@@ -402,6 +993,14 @@ fn validate_path(base_dir: &StdPath, requested_path: &str) -> Result<PathBuf, Ap
     Ok(full_path)
 }
 
+/// Whether a validated path resolves to the base directory itself (e.g. `sub/..`)
+fn is_base_dir(base_dir: &StdPath, path: &StdPath) -> bool {
+    match (std::fs::canonicalize(base_dir), std::fs::canonicalize(path)) {
+        (Ok(base), Ok(path)) => base == path,
+        _ => false,
+    }
+}
+
 // Custom error type
 #[derive(Debug)]
 enum AppError {
@@ -409,11 +1008,16 @@ enum AppError {
     InvalidPath,
     NotAFile,
     NotADirectory,
+    IsADirectory,
+    ConfirmationRequired,
+    DeleteDisabled,
+    DeleteError,
     FileReadError,
     CompressionError,
     S3NotConfigured,
     S3UploadError,
     S3PresignError,
+    S3UrlUnreachable,
     DatabaseNotConfigured,
     DatabaseError,
 }
@@ -426,6 +1030,7 @@ impl AppError {
             s3::S3Error::UploadFailed(_) => AppError::S3UploadError,
             s3::S3Error::PresignFailed(_) => AppError::S3PresignError,
             s3::S3Error::DatabaseError(_) => AppError::DatabaseError,
+            s3::S3Error::UrlValidationFailed(_) => AppError::S3UrlUnreachable,
         }
     }
 }
@@ -437,6 +1042,19 @@ impl IntoResponse for AppError {
             AppError::InvalidPath => (StatusCode::BAD_REQUEST, "Invalid file path"),
             AppError::NotAFile => (StatusCode::BAD_REQUEST, "Path is not a file"),
             AppError::NotADirectory => (StatusCode::BAD_REQUEST, "Path is not a directory"),
+            AppError::IsADirectory => (
+                StatusCode::BAD_REQUEST,
+                "Path is a directory (set recursive=true to delete it)",
+            ),
+            AppError::ConfirmationRequired => (
+                StatusCode::BAD_REQUEST,
+                "Recursive delete requires confirm=<file>",
+            ),
+            AppError::DeleteDisabled => (StatusCode::FORBIDDEN, "Delete is disabled"),
+            AppError::DeleteError => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to delete",
+            ),
             AppError::FileReadError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Failed to read file",
@@ -457,6 +1075,10 @@ impl IntoResponse for AppError {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Failed to generate presigned URL",
             ),
+            AppError::S3UrlUnreachable => (
+                StatusCode::BAD_GATEWAY,
+                "Presigned URL is not reachable",
+            ),
             AppError::DatabaseNotConfigured => (
                 StatusCode::SERVICE_UNAVAILABLE,
                 "Database is not configured",
@@ -479,3 +1101,439 @@ impl IntoResponse for AppError {
         (status, body).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+
+    fn test_state(base_dir: &StdPath) -> AppState {
+        AppState {
+            base_dir: Arc::new(base_dir.to_path_buf()),
+            s3_client: None,
+            s3_config: None,
+            db: None,
+            presigned_urls: Arc::new(PresignedUrlCache::default()),
+            compress_cache: Arc::new(CompressCache::new(0)),
+            allow_delete: true,
+        }
+    }
+
+    async fn rm(state: &AppState, file: &str, recursive: bool, confirm: Option<&str>) -> Result<DeleteResponse, AppError> {
+        let query = Query(DeleteQuery {
+            file: file.to_string(),
+            recursive,
+            confirm: confirm.map(str::to_string),
+        });
+        let peer = ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 50000)));
+        delete_file(State(state.clone()), peer, None, query).await.map(|json| json.0)
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file-proxy-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/data.csv"), "a,b\n1,2\n").unwrap();
+        dir
+    }
+
+    async fn download(state: &AppState, file: &str, if_none_match: Option<&str>) -> Response {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = if_none_match {
+            headers.insert(header::IF_NONE_MATCH, value.parse().unwrap());
+        }
+        let query = Query(DownloadQuery { file: file.to_string() });
+        download_file(State(state.clone()), query, headers).await.unwrap()
+    }
+
+    async fn stat(state: &AppState, file: &str) -> Result<FileInfo, AppError> {
+        let query = Query(StatQuery { file: file.to_string() });
+        stat_file(State(state.clone()), query).await.map(|json| json.0)
+    }
+
+    #[test]
+    fn test_args_workers_and_bind() {
+        let args = Args::try_parse_from(["file-proxy", "--workers", "8", "--bind", "127.0.0.1", "--port", "8080"]).unwrap();
+        assert_eq!(args.worker_threads(), 8);
+        assert_eq!(args.bind_addr(), "127.0.0.1:8080".parse().unwrap());
+
+        let defaults = Args::try_parse_from(["file-proxy"]).unwrap();
+        assert!(defaults.worker_threads() >= 1);
+        assert_eq!(defaults.bind_addr(), "0.0.0.0:3000".parse().unwrap());
+
+        assert!(Args::try_parse_from(["file-proxy", "--workers", "0"]).is_err());
+        assert!(Args::try_parse_from(["file-proxy", "--bind", "not-an-ip"]).is_err());
+    }
+
+    #[test]
+    fn test_env_override_replaces_yaml_value() {
+        let yaml = r#"
+s3:
+  provider: "r2"
+  access_key_id: "key"
+  secret_access_key: "secret"
+  bucket: "from-file"
+  region: "auto"
+jwt: null
+"#;
+        let mut value: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+        let vars = [
+            ("APP__S3__BUCKET", "from-env"),
+            ("APP__ALLOW_DELETE", "true"),
+            ("APP__API_KEY", "\"12345\""),
+            ("S3__BUCKET", "ignored"),
+        ];
+        apply_env_overrides(&mut value, vars.iter().map(|(k, v)| (k.to_string(), v.to_string()))).unwrap();
+
+        let config: Config = serde_yaml::from_value(value).unwrap();
+        assert_eq!(config.s3.unwrap().bucket, "from-env");
+        assert!(config.allow_delete);
+        // New keys are parsed as YAML, so numeric-looking strings need quotes
+        assert_eq!(config.api_key.as_deref(), Some("12345"));
+    }
+
+    #[test]
+    fn test_bad_env_override_fails_config_parsing() {
+        let yaml = "allow_delete: false\napi_key: \"secret\"\n";
+        let vars = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+
+        assert!(parse_config(yaml, vars("APP__ALLOW_DELETE", "true")).unwrap().allow_delete);
+        // An override of the wrong type must not leave the service without its config
+        assert!(parse_config(yaml, vars("APP__ALLOW_DELETE", "sometimes")).is_err());
+        // Nor may one that walks into a scalar
+        assert!(parse_config(yaml, vars("APP__API_KEY__VALUE", "x")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stat_file() {
+        let dir = test_dir("stat-file");
+        let info = stat(&test_state(&dir), "sub/data.csv").await.unwrap();
+        assert_eq!(info.name, "data.csv");
+        assert!(!info.is_dir);
+        assert_eq!(info.size, 8);
+        assert!(info.modified > 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stat_directory() {
+        let dir = test_dir("stat-dir");
+        let info = stat(&test_state(&dir), "/sub").await.unwrap();
+        assert_eq!(info.name, "sub");
+        assert!(info.is_dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stat_missing_path_is_not_found() {
+        let dir = test_dir("stat-missing");
+        let result = stat(&test_state(&dir), "sub/missing.csv").await;
+        assert!(matches!(result, Err(AppError::FileNotFound)));
+        assert_eq!(
+            AppError::FileNotFound.into_response().status(),
+            StatusCode::NOT_FOUND
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_matching_etag_is_not_modified() {
+        let dir = test_dir("etag-match");
+        let state = test_state(&dir);
+
+        let first = download(&state, "sub/data.csv", None).await;
+        let etag = first.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/"));
+
+        let second = download(&state, "sub/data.csv", Some(&etag)).await;
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(second.headers()[header::ETAG], etag.as_str());
+        let body = axum::body::to_bytes(second.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_other_etag_returns_body() {
+        let dir = test_dir("etag-other");
+        let state = test_state(&dir);
+
+        let response = download(&state, "sub/data.csv", Some("W/\"0-0\"")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(header::ETAG));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(zstd::decode_all(&body[..]).unwrap(), b"a,b\n1,2\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    async fn download_accepting(state: &AppState, file: &str, accept_encoding: Option<&str>) -> Response {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = accept_encoding {
+            headers.insert(header::ACCEPT_ENCODING, value.parse().unwrap());
+        }
+        let query = Query(DownloadQuery { file: file.to_string() });
+        download_file(State(state.clone()), query, headers).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_download_gzip_only_client_gets_gzip() {
+        use std::io::Read;
+
+        let dir = test_dir("accept-gzip");
+        let state = test_state(&dir);
+
+        let response = download_accepting(&state, "sub/data.csv", Some("gzip, deflate")).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[header::VARY], "Accept-Encoding");
+        assert!(response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().ends_with(".csv.gz\""));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(&body[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, b"a,b\n1,2\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_zstd_capable_client_gets_zstd() {
+        let dir = test_dir("accept-zstd");
+        let state = test_state(&dir);
+
+        let response = download_accepting(&state, "sub/data.csv", Some("gzip, zstd")).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "zstd");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(zstd::decode_all(&body[..]).unwrap(), b"a,b\n1,2\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_without_accept_encoding_keeps_zstd() {
+        let dir = test_dir("accept-none");
+        let state = test_state(&dir);
+
+        // No header means any coding is acceptable, so existing clients keep getting zstd
+        let response = download_accepting(&state, "sub/data.csv", None).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "zstd");
+
+        // A client accepting neither compression gets the file as is
+        let response = download_accepting(&state, "sub/data.csv", Some("identity")).await;
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        assert!(response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().ends_with(".csv\""));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"a,b\n1,2\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_negotiate_download_encoding() {
+        assert_eq!(DownloadEncoding::negotiate(None), DownloadEncoding::Zstd);
+        assert_eq!(DownloadEncoding::negotiate(Some("br, gzip;q=0.5")), DownloadEncoding::Gzip);
+        assert_eq!(DownloadEncoding::negotiate(Some("zstd;q=0, gzip")), DownloadEncoding::Gzip);
+        assert_eq!(DownloadEncoding::negotiate(Some("GZIP")), DownloadEncoding::Gzip);
+        assert_eq!(DownloadEncoding::negotiate(Some("*")), DownloadEncoding::Zstd);
+        assert_eq!(DownloadEncoding::negotiate(Some("zstd;q=0, *")), DownloadEncoding::Gzip);
+        assert_eq!(DownloadEncoding::negotiate(Some("br")), DownloadEncoding::Identity);
+        assert_eq!(DownloadEncoding::negotiate(Some("")), DownloadEncoding::Identity);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("W/\"a-1\"", "W/\"a-1\""));
+        assert!(etag_matches("\"x\", W/\"a-1\"", "W/\"a-1\""));
+        assert!(etag_matches("*", "W/\"a-1\""));
+        assert!(!etag_matches("W/\"a-2\"", "W/\"a-1\""));
+    }
+
+    #[tokio::test]
+    async fn test_validate_path_allows_dotted_names() {
+        let dir = test_dir("dotted-name");
+        std::fs::write(dir.join("sub/data..backup.json"), "{}").unwrap();
+
+        let path = validate_path(&dir, "sub/data..backup.json").unwrap();
+        assert_eq!(path, dir.join("sub/data..backup.json"));
+        let info = stat(&test_state(&dir), "/sub/data..backup.json").await.unwrap();
+        assert_eq!(info.size, 2);
+
+        // Targets that don't exist yet resolve through their parent
+        assert_eq!(validate_path(&dir, "sub/new.csv").unwrap(), dir.join("sub/new.csv"));
+        assert!(matches!(validate_path(&dir, "missing/new.csv"), Err(AppError::FileNotFound)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_validate_path_rejects_traversal() {
+        let dir = test_dir("traversal");
+        let outside = dir.parent().unwrap().join(format!("file-proxy-outside-{}", std::process::id()));
+        std::fs::write(&outside, "secret").unwrap();
+        let escape = format!("sub/../../{}", outside.file_name().unwrap().to_string_lossy());
+
+        assert!(matches!(validate_path(&dir, &escape), Err(AppError::InvalidPath)));
+        assert!(matches!(validate_path(&dir, "../not-created-yet"), Err(AppError::InvalidPath)));
+        assert!(matches!(validate_path(&dir, "sub//data.csv"), Err(AppError::InvalidPath)));
+        assert!(validate_path(&dir, "sub/../sub/data.csv").is_ok());
+
+        // A path resolving to the base directory can't be deleted
+        assert!(matches!(rm(&test_state(&dir), "sub/..", true, Some("sub/..")).await, Err(AppError::InvalidPath)));
+        assert!(dir.join("sub/data.csv").exists());
+
+        std::fs::remove_file(&outside).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_file() {
+        let dir = test_dir("rm-file");
+        let deleted = rm(&test_state(&dir), "sub/data.csv", false, None).await.unwrap();
+        assert_eq!(deleted.freed_bytes, 8);
+        assert!(!deleted.is_dir);
+        assert!(!dir.join("sub/data.csv").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_directory_requires_recursive_and_confirm() {
+        let dir = test_dir("rm-dir");
+        let state = test_state(&dir);
+
+        assert!(matches!(rm(&state, "sub", false, None).await, Err(AppError::IsADirectory)));
+        assert!(matches!(rm(&state, "sub", true, Some("other")).await, Err(AppError::ConfirmationRequired)));
+        assert!(matches!(rm(&state, "/", true, Some("/")).await, Err(AppError::InvalidPath)));
+        assert!(dir.join("sub/data.csv").exists());
+
+        let deleted = rm(&state, "sub", true, Some("sub")).await.unwrap();
+        assert!(deleted.is_dir);
+        assert_eq!(deleted.freed_bytes, 8);
+        assert!(!dir.join("sub").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_disabled_by_default() {
+        let config: Config = serde_yaml::from_str("jwt: null").unwrap();
+        assert!(!config.allow_delete);
+
+        let dir = test_dir("rm-disabled");
+        let state = AppState {
+            allow_delete: config.allow_delete,
+            ..test_state(&dir)
+        };
+        assert!(matches!(rm(&state, "sub/data.csv", false, None).await, Err(AppError::DeleteDisabled)));
+        assert!(dir.join("sub/data.csv").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown_finishes_in_flight_request() {
+        use std::time::Duration;
+        use tokio::sync::{oneshot, Notify};
+
+        let started = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let app = Router::new().route("/slow", get({
+            let started = started.clone();
+            let release = release.clone();
+            move || {
+                let started = started.clone();
+                let release = release.clone();
+                async move {
+                    started.notify_one();
+                    release.notified().await;
+                    "done"
+                }
+            }
+        }));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_app(listener, app, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        let in_flight = tokio::spawn(async move {
+            reqwest::get(format!("http://{}/slow", addr)).await.unwrap().text().await.unwrap()
+        });
+        started.notified().await;
+        shutdown_tx.send(()).unwrap();
+
+        // New connections are refused once the signal is seen
+        let mut refused = false;
+        for _ in 0..50 {
+            if tokio::net::TcpStream::connect(addr).await.is_err() {
+                refused = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(refused, "server still accepts connections after shutdown");
+        assert!(!server.is_finished(), "server must wait for the in-flight request");
+
+        release.notify_one();
+        assert_eq!(in_flight.await.unwrap(), "done");
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    }
+
+    fn app_with(config_yaml: &str, dir: &StdPath) -> Router {
+        let config: Config = serde_yaml::from_str(config_yaml).unwrap();
+        build_app(test_state(dir), Some(&config))
+    }
+
+    async fn send(app: &Router, request: axum::http::Request<axum::body::Body>) -> Response {
+        use tower::ServiceExt;
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    fn get_request(uri: &str, api_key: Option<&str>) -> axum::http::Request<axum::body::Body> {
+        let mut builder = axum::http::Request::get(uri);
+        if let Some(key) = api_key {
+            builder = builder.header("X-Api-Key", key);
+        }
+        builder.body(axum::body::Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight() {
+        let dir = test_dir("cors");
+        let app = app_with(
+            "cors_allowed_origins: [\"https://dashboard.example.com\"]\napi_key: secret",
+            &dir,
+        );
+
+        let preflight = axum::http::Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/ls?dir=sub")
+            .header(header::ORIGIN, "https://dashboard.example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-api-key")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = send(&app, preflight).await;
+        assert!(response.status().is_success());
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://dashboard.example.com");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap().contains("x-api-key"));
+
+        // Other origins get no CORS headers
+        let mut request = get_request("/health", None);
+        request.headers_mut().insert(header::ORIGIN, HeaderValue::from_static("https://evil.example.com"));
+        let response = send(&app, request).await;
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_key_required() {
+        let dir = test_dir("api-key");
+        let app = app_with("api_key: secret", &dir);
+
+        let missing = send(&app, get_request("/stat?file=sub/data.csv", None)).await;
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        let wrong = send(&app, get_request("/stat?file=sub/data.csv", Some("guess"))).await;
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+        let valid = send(&app, get_request("/stat?file=sub/data.csv", Some("secret"))).await;
+        assert_eq!(valid.status(), StatusCode::OK);
+
+        // Health checks stay public
+        let health = send(&app, get_request("/health", None)).await;
+        assert_eq!(health.status(), StatusCode::OK);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
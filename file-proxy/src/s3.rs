@@ -4,12 +4,18 @@ use aws_sdk_s3::{Client as S3Client, primitives::ByteStream};
 use md5::{Md5, Digest};
 use serde::{Deserialize, Serialize};
 use sled::Db;
+use std::collections::HashMap;
 use std::path::{Path as StdPath, PathBuf};
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// Lifetime of the presigned URLs handed out by `/get_s3_url`
+const PRESIGNED_URL_TTL_SECS: u64 = 3600;
+/// A cached presigned URL is only reused while it stays valid for at least this long
+const PRESIGNED_URL_MIN_REMAINING: Duration = Duration::from_secs(600);
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct S3Config {
@@ -22,6 +28,8 @@ pub struct S3Config {
     pub force_path_style: Option<bool>,
     pub use_compression: Option<bool>,
     pub compression_level: Option<i32>,
+    /// Fetch each new presigned URL once before returning it (adds one request of latency)
+    pub validate_presigned_url: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -56,6 +64,8 @@ struct UploadRecord {
     status: UploadStatus,
     file_size: u64,
     timeout_seconds: u64,
+    /// Modification time (ms since the epoch) of the local file when it was uploaded
+    file_mtime: u64,
 }
 
 #[derive(Debug)]
@@ -65,6 +75,7 @@ pub enum S3Error {
     UploadFailed(String),
     PresignFailed(String),
     DatabaseError(String),
+    UrlValidationFailed(String),
 }
 
 impl std::fmt::Display for S3Error {
@@ -75,6 +86,7 @@ impl std::fmt::Display for S3Error {
             S3Error::UploadFailed(e) => write!(f, "Failed to upload to S3: {}", e),
             S3Error::PresignFailed(e) => write!(f, "Failed to generate presigned URL: {}", e),
             S3Error::DatabaseError(e) => write!(f, "Database error: {}", e),
+            S3Error::UrlValidationFailed(e) => write!(f, "Presigned URL is not reachable: {}", e),
         }
     }
 }
@@ -105,7 +117,17 @@ pub fn calculate_timeout(file_size_bytes: u64) -> u64 {
     // 1MB = 1_048_576 bytes, timeout = 2 seconds per MB
     // Add minimum timeout of 10 seconds and maximum of 3600 seconds (1 hour)
     let timeout = (file_size_bytes as f64 / 1_048_576.0 * 2.0).ceil() as u64;
-    timeout.max(10).min(3600)
+    timeout.clamp(10, 3600)
+}
+
+/// Modification time of a file in milliseconds since the epoch (0 if unavailable)
+fn file_mtime_millis(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 /// Check if file has been uploaded by MD5
@@ -183,10 +205,7 @@ pub async fn create_s3_client(config: &S3Config) -> Result<S3Client, S3Error> {
 
 /// Check if a file exists in S3
 pub async fn check_s3_file_exists(client: &S3Client, bucket: &str, key: &str) -> bool {
-    match client.head_object().bucket(bucket).key(key).send().await {
-        Ok(_) => true,
-        Err(_) => false,
-    }
+    client.head_object().bucket(bucket).key(key).send().await.is_ok()
 }
 
 /// Upload a file to S3, optionally with compression
@@ -266,35 +285,109 @@ pub async fn generate_presigned_url(
     Ok(presigned_request.uri().to_string())
 }
 
+/// Presigned URLs that passed validation, keyed by S3 key
+#[derive(Default)]
+pub struct PresignedUrlCache {
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl PresignedUrlCache {
+    /// Cached URL for `key`, if it is still valid for long enough to hand out
+    pub fn get(&self, key: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(_, expires_at)| {
+                expires_at.saturating_duration_since(Instant::now()) >= PRESIGNED_URL_MIN_REMAINING
+            })
+            .map(|(url, _)| url.clone())
+    }
+
+    /// Remember a validated URL that expires after `ttl`
+    pub fn insert(&self, key: &str, url: String, ttl: Duration) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (url, Instant::now() + ttl));
+    }
+
+    /// Forget the URL for `key` (e.g., the object is being re-uploaded)
+    pub fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// Check that a presigned download URL can actually be fetched
+///
+/// The signature covers the HTTP method, so a HEAD against a GET URL is always
+/// rejected; instead this issues the signed GET for a single byte.
+pub async fn check_presigned_url(url: &str) -> Result<(), S3Error> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| S3Error::UrlValidationFailed(e.to_string()))?;
+
+    let response = client
+        .get(url)
+        .header(reqwest::header::RANGE, "bytes=0-0")
+        .send()
+        .await
+        .map_err(|e| {
+            error!("Presigned URL check failed: {}", e);
+            S3Error::UrlValidationFailed(e.to_string())
+        })?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else {
+        error!("Presigned URL check returned {}", status);
+        Err(S3Error::UrlValidationFailed(format!("status {}", status)))
+    }
+}
+
+/// Presigned URL for `key`, fetched once to make sure it works
+/// Validated URLs are cached for their lifetime, failures are not cached
+async fn validated_presigned_url(
+    client: &S3Client,
+    bucket: &str,
+    key: &str,
+    url_cache: &PresignedUrlCache,
+) -> Result<String, S3Error> {
+    if let Some(url) = url_cache.get(key) {
+        info!("Using cached validated presigned URL for: {}", key);
+        return Ok(url);
+    }
+
+    let url = generate_presigned_url(client, bucket, key, PRESIGNED_URL_TTL_SECS).await?;
+    if let Err(e) = check_presigned_url(&url).await {
+        warn!("Generated presigned URL for {} is not reachable", key);
+        return Err(e);
+    }
+    url_cache.insert(key, url.clone(), Duration::from_secs(PRESIGNED_URL_TTL_SECS));
+    Ok(url)
+}
+
 /// Background task to upload file to S3
-pub async fn upload_file_background(
+/// `record` is the pending record saved for the upload; its status is updated as the upload progresses
+async fn upload_file_background(
     client: Arc<S3Client>,
     config: Arc<S3Config>,
     db: Arc<Db>,
     file_path: PathBuf,
-    original_path: String,
-    md5: String,
-    s3_key: String,
-    use_compression: bool,
+    record: UploadRecord,
     compression_level: i32,
-    file_size: u64,
-    timeout_seconds: u64,
 ) {
-    info!("Starting background upload for file: {} (MD5: {})", original_path, md5);
+    info!("Starting background upload for file: {} (MD5: {})", record.original_path, record.md5);
 
     // Update status to uploading
     let record = UploadRecord {
-        md5: md5.clone(),
-        s3_key: s3_key.clone(),
-        original_path: original_path.clone(),
-        compressed: use_compression,
         uploaded_at: std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs(),
         status: UploadStatus::Uploading,
-        file_size,
-        timeout_seconds,
+        ..record
     };
     if let Err(e) = save_upload_record(&db, &record) {
         error!("Failed to update upload status to uploading: {}", e);
@@ -306,8 +399,8 @@ pub async fn upload_file_background(
         &client,
         &config.bucket,
         &file_path,
-        &s3_key,
-        use_compression,
+        &record.s3_key,
+        record.compressed,
         compression_level,
     )
     .await;
@@ -315,33 +408,28 @@ pub async fn upload_file_background(
     // Update status based on result
     let final_status = match upload_result {
         Ok(_) => {
-            info!("Background upload completed successfully for: {}", original_path);
+            info!("Background upload completed successfully for: {}", record.original_path);
             UploadStatus::Completed
         }
         Err(e) => {
-            error!("Background upload failed for {}: {}", original_path, e);
+            error!("Background upload failed for {}: {}", record.original_path, e);
             UploadStatus::Failed
         }
     };
 
     let final_record = UploadRecord {
-        md5: md5.clone(),
-        s3_key: s3_key.clone(),
-        original_path: original_path.clone(),
-        compressed: use_compression,
         uploaded_at: std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs(),
         status: final_status.clone(),
-        file_size,
-        timeout_seconds,
+        ..record
     };
 
     if let Err(e) = save_upload_record(&db, &final_record) {
         error!("Failed to update final upload status: {}", e);
     } else {
-        info!("Updated upload status to {:?} for MD5: {}", final_status, md5);
+        info!("Updated upload status to {:?} for MD5: {}", final_status, final_record.md5);
     }
 }
 
@@ -350,6 +438,7 @@ pub async fn handle_get_s3_url(
     client: Arc<S3Client>,
     config: Arc<S3Config>,
     db: Arc<Db>,
+    url_cache: &PresignedUrlCache,
     file_path: &StdPath,
     original_path: &str,
     force_update: bool,
@@ -357,14 +446,15 @@ pub async fn handle_get_s3_url(
     let use_compression = config.use_compression.unwrap_or(true);
     let compression_level = config.compression_level.unwrap_or(19);
 
-    // Get file size for timeout calculation
-    let file_size = tokio::fs::metadata(file_path)
+    // Get file size for timeout calculation, and mtime to detect changes since the upload
+    let metadata = tokio::fs::metadata(file_path)
         .await
         .map_err(|e| {
             error!("Failed to get file metadata: {}", e);
             S3Error::FileReadError
-        })?
-        .len();
+        })?;
+    let file_size = metadata.len();
+    let file_mtime = file_mtime_millis(&metadata);
 
     // Calculate timeout based on file size
     let timeout_seconds = calculate_timeout(file_size);
@@ -391,6 +481,11 @@ pub async fn handle_get_s3_url(
             Some(record) => {
                 info!("Found upload record in database: status={:?}", record.status);
                 match record.status {
+                    UploadStatus::Completed if file_mtime > record.file_mtime => {
+                        // The local file was modified (or touched) after the upload
+                        info!("Local file modified since upload ({} > {}), will re-upload", file_mtime, record.file_mtime);
+                        true
+                    }
                     UploadStatus::Completed => {
                         // Verify the file still exists in S3
                         let exists = check_s3_file_exists(&client, &config.bucket, &final_s3_key).await;
@@ -422,6 +517,8 @@ pub async fn handle_get_s3_url(
     };
 
     if should_upload {
+        url_cache.remove(&final_s3_key);
+
         // Create pending record
         let record = UploadRecord {
             md5: md5.clone(),
@@ -435,25 +532,19 @@ pub async fn handle_get_s3_url(
             status: UploadStatus::Pending,
             file_size,
             timeout_seconds,
+            file_mtime,
         };
         save_upload_record(&db, &record)?;
         info!("Created pending upload record for MD5: {}", md5);
 
         // Start background upload task
-        let file_path_clone = file_path.to_path_buf();
-        let original_path_clone = original_path.to_string();
         tokio::spawn(upload_file_background(
             client.clone(),
             config.clone(),
             db.clone(),
-            file_path_clone,
-            original_path_clone,
-            md5.clone(),
-            final_s3_key.clone(),
-            use_compression,
+            file_path.to_path_buf(),
+            record,
             compression_level,
-            file_size,
-            timeout_seconds,
         ));
 
         // Return pending status immediately
@@ -474,8 +565,11 @@ pub async fn handle_get_s3_url(
     match record.status {
         UploadStatus::Completed => {
             // Generate presigned URL (valid for 1 hour)
-            let presigned_url =
-                generate_presigned_url(&client, &config.bucket, &final_s3_key, 3600).await?;
+            let presigned_url = if config.validate_presigned_url.unwrap_or(false) {
+                validated_presigned_url(&client, &config.bucket, &final_s3_key, url_cache).await?
+            } else {
+                generate_presigned_url(&client, &config.bucket, &final_s3_key, PRESIGNED_URL_TTL_SECS).await?
+            };
 
             Ok(S3UrlResponse {
                 url: Some(presigned_url),
@@ -511,23 +605,17 @@ pub async fn handle_get_s3_url(
                 status: UploadStatus::Pending,
                 file_size,
                 timeout_seconds,
+                file_mtime,
             };
             save_upload_record(&db, &record)?;
 
-            let file_path_clone = file_path.to_path_buf();
-            let original_path_clone = original_path.to_string();
             tokio::spawn(upload_file_background(
                 client.clone(),
                 config.clone(),
                 db.clone(),
-                file_path_clone,
-                original_path_clone,
-                md5.clone(),
-                final_s3_key.clone(),
-                use_compression,
+                file_path.to_path_buf(),
+                record,
                 compression_level,
-                file_size,
-                timeout_seconds,
             ));
 
             Ok(S3UrlResponse {
@@ -542,3 +630,136 @@ pub async fn handle_get_s3_url(
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{Method, StatusCode};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Local S3 endpoint: HEAD always succeeds, GET answers with `get_status`
+    async fn mock_s3_endpoint(get_status: StatusCode) -> (String, Arc<AtomicUsize>) {
+        let gets = Arc::new(AtomicUsize::new(0));
+        let counter = gets.clone();
+        let app = axum::Router::new().fallback(move |method: Method| {
+            let counter = counter.clone();
+            async move {
+                if method == Method::GET {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    (get_status, "x")
+                } else {
+                    (StatusCode::OK, "")
+                }
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), gets)
+    }
+
+    /// Client, config, database and a local file already recorded as uploaded
+    async fn uploaded_file(endpoint: String, name: &str) -> (Arc<S3Client>, Arc<S3Config>, Arc<Db>, PathBuf) {
+        let config = S3Config {
+            provider: "minio".to_string(),
+            access_key_id: "test".to_string(),
+            secret_access_key: "test".to_string(),
+            bucket: "bucket".to_string(),
+            region: "us-east-1".to_string(),
+            endpoint: Some(endpoint),
+            force_path_style: Some(true),
+            use_compression: Some(false),
+            compression_level: None,
+            validate_presigned_url: Some(true),
+        };
+        let client = create_s3_client(&config).await.unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let file_path = std::env::temp_dir().join(format!("file-proxy-{}-{}", name, std::process::id()));
+        tokio::fs::write(&file_path, name).await.unwrap();
+        let md5 = calculate_file_md5(&file_path).await.unwrap();
+        let file_mtime = file_mtime_millis(&std::fs::metadata(&file_path).unwrap());
+        save_upload_record(
+            &db,
+            &UploadRecord {
+                md5: md5.clone(),
+                s3_key: md5,
+                original_path: name.to_string(),
+                compressed: false,
+                uploaded_at: 0,
+                status: UploadStatus::Completed,
+                file_size: name.len() as u64,
+                timeout_seconds: 10,
+                file_mtime,
+            },
+        )
+        .unwrap();
+
+        (Arc::new(client), Arc::new(config), Arc::new(db), file_path)
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_presigned_url_is_an_error() {
+        let (endpoint, _) = mock_s3_endpoint(StatusCode::FORBIDDEN).await;
+        let (client, config, db, file_path) = uploaded_file(endpoint, "forbidden").await;
+        let cache = PresignedUrlCache::default();
+
+        let result = handle_get_s3_url(client, config, db, &cache, &file_path, "forbidden", false).await;
+        assert!(matches!(result, Err(S3Error::UrlValidationFailed(_))));
+
+        tokio::fs::remove_file(&file_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_validated_presigned_url_is_cached() {
+        let (endpoint, gets) = mock_s3_endpoint(StatusCode::PARTIAL_CONTENT).await;
+        let (client, config, db, file_path) = uploaded_file(endpoint, "cached").await;
+        let cache = PresignedUrlCache::default();
+
+        let first = handle_get_s3_url(client.clone(), config.clone(), db.clone(), &cache, &file_path, "cached", false)
+            .await
+            .unwrap();
+        let second = handle_get_s3_url(client, config, db, &cache, &file_path, "cached", false)
+            .await
+            .unwrap();
+        assert!(first.url.is_some());
+        assert_eq!(first.url, second.url);
+        assert_eq!(gets.load(Ordering::SeqCst), 1);
+
+        tokio::fs::remove_file(&file_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_touched_file_is_reuploaded_without_update() {
+        let (endpoint, gets) = mock_s3_endpoint(StatusCode::PARTIAL_CONTENT).await;
+        let (client, config, db, file_path) = uploaded_file(endpoint, "touched").await;
+        let cache = PresignedUrlCache::default();
+        let get_url = || handle_get_s3_url(client.clone(), config.clone(), db.clone(), &cache, &file_path, "touched", false);
+
+        assert_eq!(get_url().await.unwrap().status, UploadStatus::Completed);
+
+        // Same content, newer mtime: re-uploaded even though update=false
+        let touched = std::time::SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&file_path).unwrap().set_modified(touched).unwrap();
+        let response = get_url().await.unwrap();
+        assert_eq!(response.status, UploadStatus::Pending);
+        assert!(response.url.is_none());
+
+        let md5 = calculate_file_md5(&file_path).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while get_upload_record(&db, &md5).unwrap().unwrap().status != UploadStatus::Completed {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // The new record carries the new mtime, and the URL is signed and validated again
+        let response = get_url().await.unwrap();
+        assert_eq!(response.status, UploadStatus::Completed);
+        assert!(response.url.is_some());
+        assert_eq!(gets.load(Ordering::SeqCst), 2);
+
+        tokio::fs::remove_file(&file_path).await.unwrap();
+    }
+}
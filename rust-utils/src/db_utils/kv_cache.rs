@@ -91,11 +91,10 @@ where
 
     // 尝试从缓存中获取
     if let Some(cached_data) = db.get::<CachedValue<R>>(&cache_key)
-        .map_err(|e| format!("Failed to retrieve from cache for key '{}': {}", cache_key, e))? {
-        if SystemTime::now().duration_since(cached_data.timestamp)
+        .map_err(|e| format!("Failed to retrieve from cache for key '{}': {}", cache_key, e))?
+        && SystemTime::now().duration_since(cached_data.timestamp)
             .map_err(|e| format!("Time calculation error: {}", e))? < Duration::from_secs(ttl) {
-            return Ok(cached_data.value);
-        }
+        return Ok(cached_data.value);
     }
 
     // 缓存未命中或过期，执行原始函数
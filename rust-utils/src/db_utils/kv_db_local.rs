@@ -32,8 +32,7 @@ impl KvDbLocal {
     /// 获取数据库路径（在可执行文件目录下）
     fn get_db_path(db_name: &str) -> std::io::Result<PathBuf> {
         let exe_path = std::env::current_exe()?;
-        let exe_dir = exe_path.parent().ok_or(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        let exe_dir = exe_path.parent().ok_or(std::io::Error::other(
             "Cannot get parent directory",
         ))?;
         
@@ -82,7 +81,7 @@ impl KvDbLocal {
 
     /// 检查键是否存在
     pub fn has(&self, key: &str) -> SledResult<bool> {
-        Ok(self.db.contains_key(key.as_bytes())?)
+        self.db.contains_key(key.as_bytes())
     }
 
     /// 获取所有键
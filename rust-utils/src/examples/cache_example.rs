@@ -1,6 +1,5 @@
 use sean_rust_utils::kv_db_local::KvDbLocal;
 use sean_rust_utils::kv_cache;
-use tokio;
 
 async fn expensive_computation(input: u64) -> Result<String, Box<dyn std::error::Error>> {
     println!("Performing expensive computation for: {}", input);
@@ -1,5 +1,4 @@
 use sean_rust_utils::web2_utils::curl_helper::curl_to_reqwest;
-use tokio;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
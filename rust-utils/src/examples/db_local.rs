@@ -1,4 +1,4 @@
-use sean_rust_utils::{KvDbLocal};
+use sean_rust_utils::kv_db_local::KvDbLocal;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -1,6 +1,5 @@
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use serde_json::json;
 
 use sean_rust_utils::web2_utils::graphql_helper::GraphQLHelper;
@@ -38,7 +37,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let query_vars = HashMap::new();
 
-    match graphql_helper.query::<HashMap<String, Value>>(get_user_query, Some(query_vars)).await {
+    match graphql_helper.query(get_user_query, Some(query_vars)).await {
         Ok(data) => {
             if let Some(viewer) = data.get("viewer") {
                 println!("Raw Viewer Data: {:?}", viewer);
@@ -1,5 +1,4 @@
-use sean_rust_utils::{load_test_url};
-use tokio; // Import the tokio crate
+use sean_rust_utils::load_test_url::load_test_url;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
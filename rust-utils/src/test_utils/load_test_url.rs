@@ -1,16 +1,24 @@
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{Client, Method, Response, StatusCode, header::HeaderMap, header::HeaderName, header::HeaderValue};
+use reqwest::{Client, Method, Response, StatusCode, header::HeaderMap, header::HeaderName, header::HeaderValue, tls};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::time;
-use tokio::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, mpsc, watch};
+use tokio::task::JoinHandle;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::BufRead;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::collections::HashMap;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use rand::Rng;
 
 /// 测试结果结构体
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,6 +30,10 @@ pub struct TestResults {
     pub failed_requests: u64,
     pub success_rate_percent: f64,
     pub actual_requests_per_second: f64,
+    pub aborted_early: bool, // 是否因失败率超过阈值而提前终止
+    #[serde(default)]
+    pub cancelled: bool, // 是否被 `LoadTestHandle::cancel` 取消（结果只包含取消前完成的请求）
+    pub rps_timeseries: Vec<(u64, u64)>, // 每秒完成请求数 (距测试开始的秒数, 完成数)，没有请求完成的秒记为 0
 }
 
 /// HTTP请求配置结构体
@@ -32,6 +44,8 @@ pub struct RequestConfig {
     pub headers: HeaderMap,
     pub body: Option<String>,
     pub name: Option<String>, // 用于识别不同的请求
+    pub think_time: Option<Duration>, // 每个请求完成后、发送下一个请求前的停顿时间
+    pub think_time_max: Option<Duration>, // 设置时停顿时间在 [think_time, think_time_max] 内随机
 }
 
 impl RequestConfig {
@@ -43,6 +57,8 @@ impl RequestConfig {
             headers: HeaderMap::new(),
             body: None,
             name: None,
+            think_time: None,
+            think_time_max: None,
         }
     }
     
@@ -127,6 +143,8 @@ impl RequestConfig {
             headers,
             body,
             name: None,
+            think_time: None,
+            think_time_max: None,
         })
     }
     
@@ -135,6 +153,30 @@ impl RequestConfig {
         self.name = Some(name.to_string());
         self
     }
+
+    /// 设置固定的思考时间：请求完成后停顿该时长再发送下一个请求，
+    /// 同一配置的请求将按会话方式依次执行而不是并发发送
+    pub fn with_think_time(mut self, think_time: Duration) -> Self {
+        self.think_time = Some(think_time);
+        self.think_time_max = None;
+        self
+    }
+
+    /// 设置随机思考时间，每次停顿在 [min, max] 内均匀取值
+    pub fn with_think_time_range(mut self, min: Duration, max: Duration) -> Self {
+        self.think_time = Some(min);
+        self.think_time_max = Some(max);
+        self
+    }
+
+    /// 计算本次停顿时间
+    fn next_think_time(&self) -> Option<Duration> {
+        let min = self.think_time?;
+        match self.think_time_max {
+            Some(max) if max > min => Some(rand::rng().random_range(min..=max)),
+            _ => Some(min),
+        }
+    }
 }
 
 /// 解析curl命令参数（增强版本，支持转义符号）
@@ -238,6 +280,14 @@ fn parse_curl_args(command: &str) -> Result<Vec<String>, Box<dyn std::error::Err
     Ok(args)
 }
 
+/// `http2_only` 时拒绝未协商到 HTTP/2 的响应，返回的错误信息会记入请求日志
+fn require_http2(response: Response, http2_only: bool) -> Result<Response, String> {
+    if http2_only && response.version() != reqwest::Version::HTTP_2 {
+        return Err(format!("服务器协商的协议为 {:?}，http2_only 要求 HTTP/2", response.version()));
+    }
+    Ok(response)
+}
+
 /// 判断响应是否成功：
 /// 1) HTTP 状态码为 2xx
 /// 2) 如果是 JSON 且包含 GraphQL 风格的非空 errors 数组，则视为失败
@@ -291,6 +341,122 @@ pub struct ResponseSummary {
     pub error_distribution: HashMap<String, ResponseStat>,
     pub response_content_distribution: HashMap<String, ResponseStat>, // 按响应内容分类
     pub response_time_stats: ResponseTimeStats,
+    pub histogram: Vec<(u128, u64)>, // 响应时间直方图: (桶上界ms, 数量)，最后一个桶上界为 u128::MAX
+}
+
+impl ResponseSummary {
+    /// 以 Prometheus 文本格式（exposition format）输出统计，便于推送到 pushgateway
+    ///
+    /// 包含请求总数/成功数/失败数、成功率（0~1）以及响应时间分位数（summary 类型，单位 ms）。
+    /// `job_labels` 会附加到每条指标上，标签名需符合 Prometheus 规则 (`[a-zA-Z_][a-zA-Z0-9_]*`)，
+    /// 标签值中的 `\`、`"` 和换行会被转义
+    pub fn to_prometheus(&self, job_labels: &[(&str, &str)]) -> String {
+        let labels: Vec<String> = job_labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape_label_value(value)))
+            .collect();
+        let with_labels = |extra: Option<String>| -> String {
+            let all: Vec<String> = labels.iter().cloned().chain(extra).collect();
+            if all.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", all.join(","))
+            }
+        };
+        let plain = with_labels(None);
+
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            out.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} {}\n",
+                name, help, name, kind
+            ));
+            for (series, value) in samples {
+                out.push_str(&format!("{}{} {}\n", name, series, value));
+            }
+        };
+
+        metric(
+            "loadtest_requests_total",
+            "counter",
+            "Total requests completed",
+            &[(plain.clone(), self.total_requests.to_string())],
+        );
+        metric(
+            "loadtest_requests_success_total",
+            "counter",
+            "Successful requests",
+            &[(plain.clone(), self.success_count.to_string())],
+        );
+        metric(
+            "loadtest_requests_failed_total",
+            "counter",
+            "Failed requests",
+            &[(plain.clone(), self.failure_count.to_string())],
+        );
+        metric(
+            "loadtest_success_ratio",
+            "gauge",
+            "Share of successful requests (0-1)",
+            &[(plain.clone(), (self.success_rate / 100.0).to_string())],
+        );
+
+        // 分位数之外，summary 还需要 _sum 和 _count
+        let stats = &self.response_time_stats;
+        let mut latency: Vec<(String, String)> = [
+            ("0.5", stats.p50_ms),
+            ("0.9", stats.p90_ms),
+            ("0.95", stats.p95_ms),
+            ("0.99", stats.p99_ms),
+        ]
+        .iter()
+        .map(|(quantile, value)| {
+            (
+                with_labels(Some(format!("quantile=\"{}\"", quantile))),
+                value.to_string(),
+            )
+        })
+        .collect();
+        latency.push((
+            format!("_sum{}", plain),
+            (stats.avg_ms * self.total_requests as f64).to_string(),
+        ));
+        latency.push((format!("_count{}", plain), self.total_requests.to_string()));
+        metric(
+            "loadtest_latency_ms",
+            "summary",
+            "Response time in milliseconds",
+            &latency,
+        );
+
+        metric(
+            "loadtest_latency_min_ms",
+            "gauge",
+            "Fastest response time in milliseconds",
+            &[(plain.clone(), stats.min_ms.to_string())],
+        );
+        metric(
+            "loadtest_latency_max_ms",
+            "gauge",
+            "Slowest response time in milliseconds",
+            &[(plain, stats.max_ms.to_string())],
+        );
+
+        out
+    }
+}
+
+/// 转义 Prometheus 标签值中的反斜杠、双引号和换行
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// 默认直方图桶上界 (ms)，按 2 的幂指数增长: 1, 2, 4, ..., 65536
+pub fn default_histogram_buckets() -> Vec<u128> {
+    (0..=16).map(|i| 1u128 << i).collect()
 }
 
 /// 响应时间统计
@@ -333,8 +499,8 @@ fn response_text_is_success(status: StatusCode, body_text: &str) -> bool {
 /// * `Result<TestResults, Box<dyn std::error::Error>>` - 测试结果或错误
 /// 
 /// # 示例
-/// ```rust
-/// use load_test_url::load_test_url;
+/// ```rust,no_run
+/// use sean_rust_utils::load_test_url::load_test_url;
 /// 
 /// #[tokio::main]
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -348,6 +514,35 @@ pub async fn load_test_url(
     requests: u64,
     duration: u64,
 ) -> Result<TestResults, Box<dyn std::error::Error>> {
+    load_test_url_with_options(url, requests, duration, &LoadTestOptions::default()).await
+}
+
+/// 按指定选项对单个URL执行负载测试
+///
+/// 只使用选项中与客户端构建相关的部分（cookie、固定解析、HTTP/2、TLS 版本），
+/// 失败率阈值仅对 `load_test_requests_with_options` 生效
+pub async fn load_test_url_with_options(
+    url: &str,
+    requests: u64,
+    duration: u64,
+    options: &LoadTestOptions,
+) -> Result<TestResults, Box<dyn std::error::Error>> {
+    // 发送端保持存活，测试不会被取消
+    let (_cancel_tx, cancel_rx) = watch::channel(false);
+    run_load_test_url(url, requests, duration, options, cancel_rx)
+        .await
+        .map_err(|e| -> Box<dyn std::error::Error> { e })
+}
+
+/// 单URL负载测试的实际执行逻辑，`cancel_rx` 变为 true 时停止发送并取消进行中的请求，
+/// 返回截至取消时的结果
+async fn run_load_test_url(
+    url: &str,
+    requests: u64,
+    duration: u64,
+    options: &LoadTestOptions,
+    mut cancel_rx: watch::Receiver<bool>,
+) -> Result<TestResults, Box<dyn std::error::Error + Send + Sync>> {
     println!("🚀 开始负载测试");
     println!("📊 目标URL: {}", url);
     println!("⏱️  持续时间: {} 秒", duration);
@@ -359,10 +554,12 @@ pub async fn load_test_url(
     
     println!("⚡ 计算得出的请求频率: {:.2} 请求/秒", requests_per_second);
     println!("📏 请求间隔: {:.2} 毫秒", interval.as_millis());
+    options.print_client_options();
     println!();
 
     // 创建HTTP客户端
-    let client = Arc::new(Client::new());
+    let client = Arc::new(options.build_client()?);
+    let http2_only = options.http2_only;
     
     // 统计计数器
     let success_count = Arc::new(AtomicU64::new(0));
@@ -379,6 +576,7 @@ pub async fn load_test_url(
     );
 
     let start_time = Instant::now();
+    let completions = CompletionRecorder::new(start_time);
     
     // 创建任务列表
     let mut tasks = Vec::new();
@@ -389,18 +587,26 @@ pub async fn load_test_url(
     
     // 启动并发请求任务
     for _ in 0..std::cmp::min(requests, 50) {
+        if *cancel_rx.borrow() {
+            break;
+        }
+        
         let client = client.clone();
         let url = url.to_string();
         let success_count = success_count.clone();
         let failure_count = failure_count.clone();
         let total_sent = total_sent.clone();
+        let completions = completions.clone();
         let pb = pb.clone();
         
-        let task = tokio::spawn(async move {
+        let request_future = async move {
             // 发送请求
             total_sent.fetch_add(1, Ordering::Relaxed);
             
-            match client.get(&url).timeout(Duration::from_secs(10)).send().await {
+            let result = client.get(&url).timeout(Duration::from_secs(10)).send().await
+                .map_err(|err| err.to_string())
+                .and_then(|response| require_http2(response, http2_only));
+            match result {
                 Ok(response) => {
                     if response_is_success(response).await {
                         success_count.fetch_add(1, Ordering::Relaxed);
@@ -412,6 +618,7 @@ pub async fn load_test_url(
                     failure_count.fetch_add(1, Ordering::Relaxed);
                 }
             }
+            completions.record();
             
             let success = success_count.load(Ordering::Relaxed);
             let total = success + failure_count.load(Ordering::Relaxed);
@@ -419,15 +626,19 @@ pub async fn load_test_url(
             
             pb.set_message(format!("{} ({:.1}%)", success, success_rate));
             pb.inc(1);
-        });
+        };
         
-        tasks.push(task);
+        tasks.push(tokio::spawn(run_unless_aborted(request_future, cancel_rx.clone())));
     }
     
     // 控制发送频率，启动剩余请求
     let mut remaining_requests = requests.saturating_sub(50);
     while remaining_requests > 0 && Instant::now() < deadline {
-        interval_timer.tick().await;
+        // 等待下一个发送时机，测试被取消时立即退出
+        tokio::select! {
+            _ = interval_timer.tick() => {}
+            _ = cancel_rx.wait_for(|cancelled| *cancelled) => break,
+        }
         
         if Instant::now() >= deadline {
             break;
@@ -438,12 +649,16 @@ pub async fn load_test_url(
         let success_count = success_count.clone();
         let failure_count = failure_count.clone();
         let total_sent = total_sent.clone();
+        let completions = completions.clone();
         let pb = pb.clone();
         
-        let task = tokio::spawn(async move {
+        let request_future = async move {
             total_sent.fetch_add(1, Ordering::Relaxed);
             
-            match client.get(&url).timeout(Duration::from_secs(10)).send().await {
+            let result = client.get(&url).timeout(Duration::from_secs(10)).send().await
+                .map_err(|err| err.to_string())
+                .and_then(|response| require_http2(response, http2_only));
+            match result {
                 Ok(response) => {
                     if response_is_success(response).await {
                         success_count.fetch_add(1, Ordering::Relaxed);
@@ -455,6 +670,7 @@ pub async fn load_test_url(
                     failure_count.fetch_add(1, Ordering::Relaxed);
                 }
             }
+            completions.record();
             
             let success = success_count.load(Ordering::Relaxed);
             let total = success + failure_count.load(Ordering::Relaxed);
@@ -462,9 +678,9 @@ pub async fn load_test_url(
             
             pb.set_message(format!("{} ({:.1}%)", success, success_rate));
             pb.inc(1);
-        });
+        };
         
-        tasks.push(task);
+        tasks.push(tokio::spawn(run_unless_aborted(request_future, cancel_rx.clone())));
         remaining_requests -= 1;
     }
     
@@ -481,8 +697,12 @@ pub async fn load_test_url(
     let failure = failure_count.load(Ordering::Relaxed);
     let total_completed = success + failure;
     let sent = total_sent.load(Ordering::Relaxed);
+    let cancelled = *cancel_rx.borrow();
     
     println!("\n📈 测试结果:");
+    if cancelled {
+        println!("🛑 测试已被取消，以下为取消前的部分结果");
+    }
     println!("⏱️  实际执行时间: {:.2} 秒", elapsed.as_secs_f64());
     println!("📤 发送请求数: {}", sent);
     println!("📥 完成请求数: {}", total_completed);
@@ -506,9 +726,70 @@ pub async fn load_test_url(
         failed_requests: failure,
         success_rate_percent: success_rate,
         actual_requests_per_second: actual_rps,
+        aborted_early: false,
+        cancelled,
+        rps_timeseries: completions.timeseries(),
     })
 }
 
+/// 后台运行的单URL负载测试配置
+#[derive(Debug, Clone)]
+pub struct LoadTestConfig {
+    pub url: String,
+    pub requests: u64, // 总请求数
+    pub duration: u64, // 测试持续时间（秒）
+    pub options: LoadTestOptions,
+}
+
+impl LoadTestConfig {
+    pub fn new(url: &str, requests: u64, duration: u64) -> Self {
+        Self {
+            url: url.to_string(),
+            requests,
+            duration,
+            options: LoadTestOptions::default(),
+        }
+    }
+}
+
+/// 后台负载测试的句柄，可从外部取消并在结束后获取结果
+pub struct LoadTestHandle {
+    cancel_tx: watch::Sender<bool>,
+    task: tokio::task::JoinHandle<Result<TestResults, Box<dyn std::error::Error + Send + Sync>>>,
+}
+
+impl LoadTestHandle {
+    /// 停止发送新请求并立即取消进行中的请求，可重复调用
+    pub fn cancel(&self) {
+        self.cancel_tx.send_replace(true);
+    }
+
+    /// 测试是否已经结束（正常完成或取消后收尾完毕）
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// 等待测试结束并返回结果；取消后返回的是部分结果，`cancelled` 为 true
+    pub async fn await_results(self) -> Result<TestResults, Box<dyn std::error::Error>> {
+        match self.task.await {
+            Ok(result) => result.map_err(|e| -> Box<dyn std::error::Error> { e }),
+            Err(e) => Err(format!("负载测试任务异常退出: {}", e).into()),
+        }
+    }
+}
+
+/// 在后台启动单URL负载测试，立即返回句柄
+///
+/// 与 `load_test_url_with_options` 的行为相同，但可以通过 `LoadTestHandle::cancel`
+/// 在收到外部信号时提前停止，仍然拿到截至取消时的统计结果
+pub fn spawn_load_test(config: LoadTestConfig) -> LoadTestHandle {
+    let (cancel_tx, cancel_rx) = watch::channel(false);
+    let task = tokio::spawn(async move {
+        run_load_test_url(&config.url, config.requests, config.duration, &config.options, cancel_rx).await
+    });
+    LoadTestHandle { cancel_tx, task }
+}
+
 /// 对单个URL执行静默负载测试（不显示进度条和控制台输出）
 /// 
 /// # 参数
@@ -536,6 +817,7 @@ pub async fn load_test_url_silent(
     let total_sent = Arc::new(AtomicU64::new(0));
 
     let start_time = Instant::now();
+    let completions = CompletionRecorder::new(start_time);
     
     // 创建任务列表
     let mut tasks = Vec::new();
@@ -551,6 +833,7 @@ pub async fn load_test_url_silent(
         let success_count = success_count.clone();
         let failure_count = failure_count.clone();
         let total_sent = total_sent.clone();
+        let completions = completions.clone();
         
         let task = tokio::spawn(async move {
             // 发送请求
@@ -568,6 +851,7 @@ pub async fn load_test_url_silent(
                     failure_count.fetch_add(1, Ordering::Relaxed);
                 }
             }
+            completions.record();
         });
         
         tasks.push(task);
@@ -587,6 +871,7 @@ pub async fn load_test_url_silent(
         let success_count = success_count.clone();
         let failure_count = failure_count.clone();
         let total_sent = total_sent.clone();
+        let completions = completions.clone();
         
         let task = tokio::spawn(async move {
             total_sent.fetch_add(1, Ordering::Relaxed);
@@ -603,6 +888,7 @@ pub async fn load_test_url_silent(
                     failure_count.fetch_add(1, Ordering::Relaxed);
                 }
             }
+            completions.record();
         });
         
         tasks.push(task);
@@ -633,182 +919,257 @@ pub async fn load_test_url_silent(
         failed_requests: failure,
         success_rate_percent: success_rate,
         actual_requests_per_second: actual_rps,
+        aborted_early: false,
+        cancelled: false,
+        rps_timeseries: completions.timeseries(),
     })
 }
 
-/// 对多个请求配置执行负载测试
-/// 
-/// # 参数
-/// * `requests_configs` - 请求配置数组
-/// * `requests_per_config` - 每个配置的请求数
-/// * `duration` - 测试持续时间（秒）
-/// 
-/// # 返回值
-/// * `Result<LoadTestOutput, Box<dyn std::error::Error>>` - 测试结果或错误
-/// 分析请求日志生成响应归集统计
+/// 分析请求日志生成响应归集统计（使用默认直方图桶）
 pub fn analyze_response_logs(request_logs: &[RequestLog]) -> ResponseSummary {
+    analyze_response_logs_with_buckets(request_logs, &default_histogram_buckets())
+}
+
+/// 分析请求日志生成响应归集统计，`bucket_bounds_ms` 为直方图各桶的上界 (ms)
+///
+/// 耗时小于等于上界的请求计入该桶，超过所有上界的请求计入上界为 `u128::MAX` 的溢出桶
+pub fn analyze_response_logs_with_buckets(request_logs: &[RequestLog], bucket_bounds_ms: &[u128]) -> ResponseSummary {
+    let mut builder = ResponseSummaryBuilder::with_buckets(bucket_bounds_ms);
+    for log in request_logs {
+        builder.add(log);
+    }
+    let mut summary = builder.finish();
     if request_logs.is_empty() {
-        return ResponseSummary {
+        return summary;
+    }
+
+    // 日志都在内存中，用精确分位数替换直方图估算值
+    let mut durations: Vec<u128> = request_logs.iter().map(|log| log.duration_ms).collect();
+    durations.sort_unstable();
+    let percentile = |p: f64| -> u128 { durations[percentile_rank(durations.len() as u64, p) as usize] };
+
+    let stats = &mut summary.response_time_stats;
+    stats.p50_ms = percentile(50.0);
+    stats.p90_ms = percentile(90.0);
+    stats.p95_ms = percentile(95.0);
+    stats.p99_ms = percentile(99.0);
+    summary
+}
+
+/// 逐行读取 JSONL 请求日志文件（`LoadTestOptions::request_log_path` 的输出）生成响应归集统计，
+/// 不会把全部日志读入内存，分位数为直方图估算值
+pub fn analyze_request_log_file(path: &Path) -> std::io::Result<ResponseSummary> {
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut builder = ResponseSummaryBuilder::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let log: RequestLog = serde_json::from_str(&line)?;
+        builder.add(&log);
+    }
+    Ok(builder.finish())
+}
+
+/// 第 p 百分位在 `total` 个升序样本中的下标
+fn percentile_rank(total: u64, p: f64) -> u64 {
+    ((total as f64 * p / 100.0) as u64).min(total.saturating_sub(1))
+}
+
+/// 逐条累计请求日志的响应归集统计，不需要保留全部日志
+///
+/// 计数、各类分布、直方图和最小/最大/平均耗时与一次性分析的结果完全一致；
+/// 分位数由直方图估算，取目标排名所在桶的上界（不超过最大耗时），精度取决于桶的划分
+pub struct ResponseSummaryBuilder {
+    bounds: Vec<u128>,
+    bucket_counts: Vec<u64>,
+    total_requests: u64,
+    success_count: u64,
+    status_counts: HashMap<String, u64>,
+    error_counts: HashMap<String, u64>,
+    response_content_counts: HashMap<String, u64>,
+    min_ms: u128,
+    max_ms: u128,
+    sum_ms: u128,
+}
+
+impl Default for ResponseSummaryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResponseSummaryBuilder {
+    /// 使用默认直方图桶
+    pub fn new() -> Self {
+        Self::with_buckets(&default_histogram_buckets())
+    }
+
+    /// 使用指定的直方图桶上界 (ms)，规则与 `analyze_response_logs_with_buckets` 相同
+    pub fn with_buckets(bucket_bounds_ms: &[u128]) -> Self {
+        let mut bounds: Vec<u128> = bucket_bounds_ms.to_vec();
+        bounds.sort_unstable();
+        bounds.dedup();
+        if bounds.last() != Some(&u128::MAX) {
+            bounds.push(u128::MAX);
+        }
+
+        Self {
+            bucket_counts: vec![0; bounds.len()],
+            bounds,
             total_requests: 0,
             success_count: 0,
-            failure_count: 0,
-            success_rate: 0.0,
-            http_status_distribution: HashMap::new(),
-            error_distribution: HashMap::new(),
-            response_content_distribution: HashMap::new(),
-            response_time_stats: ResponseTimeStats {
-                min_ms: 0,
-                max_ms: 0,
-                avg_ms: 0.0,
-                p50_ms: 0,
-                p90_ms: 0,
-                p95_ms: 0,
-                p99_ms: 0,
-            },
-        };
+            status_counts: HashMap::new(),
+            error_counts: HashMap::new(),
+            response_content_counts: HashMap::new(),
+            min_ms: u128::MAX,
+            max_ms: 0,
+            sum_ms: 0,
+        }
     }
 
-    let total_requests = request_logs.len() as u64;
-    let success_count = request_logs.iter().filter(|log| log.success).count() as u64;
-    let failure_count = total_requests - success_count;
-    let success_rate = if total_requests > 0 { 
-        success_count as f64 / total_requests as f64 * 100.0 
-    } else { 
-        0.0 
-    };
+    /// 计入一条请求日志
+    pub fn add(&mut self, log: &RequestLog) {
+        self.total_requests += 1;
+        if log.success {
+            self.success_count += 1;
+        }
 
-    // 统计HTTP状态码分布
-    let mut status_counts: HashMap<String, u64> = HashMap::new();
-    for log in request_logs {
+        // 统计HTTP状态码分布
         let status_key = if let Some(status) = log.status {
             format!("{} {}", status, get_status_description(status))
         } else {
             "Network Error".to_string()
         };
-        *status_counts.entry(status_key).or_insert(0) += 1;
-    }
-
-    let http_status_distribution: HashMap<String, ResponseStat> = status_counts
-        .into_iter()
-        .map(|(status, count)| {
-            let percentage = count as f64 / total_requests as f64 * 100.0;
-            (status, ResponseStat { count, percentage })
-        })
-        .collect();
+        *self.status_counts.entry(status_key).or_insert(0) += 1;
 
-    // 统计错误分布
-    let mut error_counts: HashMap<String, u64> = HashMap::new();
-    for log in request_logs {
+        // 统计错误分布和响应内容分布 (只针对失败请求)
         if !log.success {
-            let error_key = if let Some(error) = &log.error {
-                // 网络错误等
-                let simplified_error: String = error.chars().take(100).collect();
-                simplified_error
-            } else if let Some(status) = log.status {
-                // HTTP状态码成功但业务逻辑失败（如GraphQL errors）
-                if status == 200 {
-                    // 尝试从响应内容中提取错误信息
-                    if let Some(excerpt) = &log.response_excerpt {
-                        extract_business_error_from_response(excerpt)
-                    } else {
-                        "HTTP 200 Business Logic Error".to_string()
-                    }
-                } else {
-                    format!("HTTP {}", status)
-                }
-            } else {
-                "Unknown Error".to_string()
-            };
-            *error_counts.entry(error_key).or_insert(0) += 1;
+            *self.error_counts.entry(error_key(log)).or_insert(0) += 1;
+            *self.response_content_counts.entry(log.response_content_key.clone()).or_insert(0) += 1;
         }
-    }
 
-    let error_distribution: HashMap<String, ResponseStat> = error_counts
-        .into_iter()
-        .map(|(error, count)| {
-            let percentage = count as f64 / total_requests as f64 * 100.0;
-            (error, ResponseStat { count, percentage })
-        })
-        .collect();
+        let index = self.bounds.partition_point(|&bound| bound < log.duration_ms);
+        self.bucket_counts[index] += 1;
+        self.min_ms = self.min_ms.min(log.duration_ms);
+        self.max_ms = self.max_ms.max(log.duration_ms);
+        self.sum_ms += log.duration_ms;
+    }
 
-    // 统计响应内容分布 (只针对失败请求)
-    let mut response_content_counts: HashMap<String, u64> = HashMap::new();
-    for log in request_logs {
-        if !log.success {
-            *response_content_counts.entry(log.response_content_key.clone()).or_insert(0) += 1;
-        }
+    /// 已计入的请求数
+    pub fn total_requests(&self) -> u64 {
+        self.total_requests
     }
 
-    let response_content_distribution: HashMap<String, ResponseStat> = response_content_counts
-        .into_iter()
-        .map(|(content_key, count)| {
-            let percentage = count as f64 / total_requests as f64 * 100.0;
-            (content_key, ResponseStat { count, percentage })
-        })
-        .collect();
+    /// 生成响应归集统计
+    pub fn finish(self) -> ResponseSummary {
+        let total_requests = self.total_requests;
+        let to_stats = |counts: HashMap<String, u64>| -> HashMap<String, ResponseStat> {
+            counts
+                .into_iter()
+                .map(|(key, count)| {
+                    let percentage = count as f64 / total_requests as f64 * 100.0;
+                    (key, ResponseStat { count, percentage })
+                })
+                .collect()
+        };
 
-    // 计算响应时间统计
-    let mut durations: Vec<u128> = request_logs.iter().map(|log| log.duration_ms).collect();
-    durations.sort_unstable();
+        let response_time_stats = if total_requests == 0 {
+            ResponseTimeStats {
+                min_ms: 0,
+                max_ms: 0,
+                avg_ms: 0.0,
+                p50_ms: 0,
+                p90_ms: 0,
+                p95_ms: 0,
+                p99_ms: 0,
+            }
+        } else {
+            ResponseTimeStats {
+                min_ms: self.min_ms,
+                max_ms: self.max_ms,
+                avg_ms: self.sum_ms as f64 / total_requests as f64,
+                p50_ms: self.estimate_percentile(50.0),
+                p90_ms: self.estimate_percentile(90.0),
+                p95_ms: self.estimate_percentile(95.0),
+                p99_ms: self.estimate_percentile(99.0),
+            }
+        };
 
-    let min_ms = durations.first().copied().unwrap_or(0);
-    let max_ms = durations.last().copied().unwrap_or(0);
-    let avg_ms = if !durations.is_empty() {
-        durations.iter().sum::<u128>() as f64 / durations.len() as f64
-    } else {
-        0.0
-    };
+        let success_rate = if total_requests > 0 {
+            self.success_count as f64 / total_requests as f64 * 100.0
+        } else {
+            0.0
+        };
 
-    let percentile = |p: f64| -> u128 {
-        if durations.is_empty() { return 0; }
-        let index = ((durations.len() as f64 * p / 100.0) as usize).min(durations.len() - 1);
-        durations[index]
-    };
+        ResponseSummary {
+            total_requests,
+            success_count: self.success_count,
+            failure_count: total_requests - self.success_count,
+            success_rate,
+            http_status_distribution: to_stats(self.status_counts),
+            error_distribution: to_stats(self.error_counts),
+            response_content_distribution: to_stats(self.response_content_counts),
+            response_time_stats,
+            histogram: self.bounds.into_iter().zip(self.bucket_counts).collect(),
+        }
+    }
 
-    let response_time_stats = ResponseTimeStats {
-        min_ms,
-        max_ms,
-        avg_ms,
-        p50_ms: percentile(50.0),
-        p90_ms: percentile(90.0),
-        p95_ms: percentile(95.0),
-        p99_ms: percentile(99.0),
-    };
+    /// 按直方图估算第 p 百分位：取排名所在桶的上界，并限制在 [min_ms, max_ms] 内
+    fn estimate_percentile(&self, p: f64) -> u128 {
+        let rank = percentile_rank(self.total_requests, p);
+        let mut seen = 0u64;
+        for (bound, count) in self.bounds.iter().zip(&self.bucket_counts) {
+            seen += count;
+            if seen > rank {
+                return (*bound).clamp(self.min_ms, self.max_ms);
+            }
+        }
+        self.max_ms
+    }
+}
 
-    ResponseSummary {
-        total_requests,
-        success_count,
-        failure_count,
-        success_rate,
-        http_status_distribution,
-        error_distribution,
-        response_content_distribution,
-        response_time_stats,
+/// 失败请求的错误归类key
+fn error_key(log: &RequestLog) -> String {
+    if let Some(error) = &log.error {
+        // 网络错误等
+        error.chars().take(100).collect()
+    } else if let Some(status) = log.status {
+        // HTTP状态码成功但业务逻辑失败（如GraphQL errors）
+        if status == 200 {
+            // 尝试从响应内容中提取错误信息
+            if let Some(excerpt) = &log.response_excerpt {
+                extract_business_error_from_response(excerpt)
+            } else {
+                "HTTP 200 Business Logic Error".to_string()
+            }
+        } else {
+            format!("HTTP {}", status)
+        }
+    } else {
+        "Unknown Error".to_string()
     }
 }
 
 /// 从响应内容中提取业务逻辑错误信息（如GraphQL errors）
 fn extract_business_error_from_response(response_text: &str) -> String {
     // 尝试解析JSON并查找错误信息
-    if let Ok(json_value) = serde_json::from_str::<Value>(response_text) {
-        if let Value::Object(obj) = &json_value {
-            // 检查GraphQL errors格式
-            if let Some(errors) = obj.get("errors").and_then(|v| v.as_array()) {
-                if let Some(first_error) = errors.first() {
-                    if let Some(message) = first_error.get("message").and_then(|v| v.as_str()) {
-                        return format!("GraphQL Error: {}", message);
-                    }
-                }
-                return "GraphQL Error: Unknown".to_string();
-            }
-            
-            // 检查其他常见错误格式
-            if let Some(error_msg) = obj.get("error").and_then(|v| v.as_str()) {
-                return format!("API Error: {}", error_msg);
-            }
-            if let Some(message) = obj.get("message").and_then(|v| v.as_str()) {
-                return format!("Message: {}", message);
+    if let Ok(Value::Object(obj)) = serde_json::from_str::<Value>(response_text) {
+        // 检查GraphQL errors格式
+        if let Some(errors) = obj.get("errors").and_then(|v| v.as_array()) {
+            if let Some(message) = errors.first().and_then(|e| e.get("message")).and_then(|v| v.as_str()) {
+                return format!("GraphQL Error: {}", message);
             }
+            return "GraphQL Error: Unknown".to_string();
+        }
+        
+        // 检查其他常见错误格式
+        if let Some(error_msg) = obj.get("error").and_then(|v| v.as_str()) {
+            return format!("API Error: {}", error_msg);
+        }
+        if let Some(message) = obj.get("message").and_then(|v| v.as_str()) {
+            return format!("Message: {}", message);
         }
     }
     
@@ -875,14 +1236,267 @@ fn get_status_description(status: u16) -> &'static str {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LoadTestOutput {
     pub results: TestResults,
-    pub request_logs: Vec<RequestLog>,
+    pub request_logs: Vec<RequestLog>, // 设置 `request_log_path` 时日志写入文件，这里为空
     pub response_summary: ResponseSummary,
 }
 
+/// 多请求负载测试选项
+#[derive(Debug, Clone)]
+pub struct LoadTestOptions {
+    /// 启用 cookie 存储，前一个响应的 `Set-Cookie` 会在后续请求中发送。
+    /// 注意：所有并发请求共享同一个 cookie 存储，请求之间不再相互独立
+    pub enable_cookies: bool,
+    /// 失败率阈值 (0.0-1.0)，最近 `fatal_error_window` 个请求的失败率超过该值时提前终止测试
+    pub fatal_error_threshold: Option<f64>,
+    /// 计算失败率的滚动窗口大小，窗口填满前不会触发终止
+    pub fatal_error_window: usize,
+    /// 将主机名固定解析到指定地址，绕过 DNS 轮询使所有请求打到同一后端。
+    /// 请求仍使用原主机名作为 `Host`/SNI，TLS 证书校验也基于原主机名；
+    /// 地址中的端口会被忽略，端口以 URL 为准
+    pub resolve_override: Option<(String, SocketAddr)>,
+    /// 不经协商直接使用 HTTP/2，用于明文 h2c 服务器（`http://` 下不会发送 Upgrade）。
+    /// 对 `https://` 同样生效：TLS 握手时 ALPN 只声明 h2，不支持 h2 的服务器会连接失败
+    pub http2_prior_knowledge: bool,
+    /// 只接受 HTTP/2 响应：`https://` 下照常通过 ALPN 协商（同时声明 h2 与 http/1.1），
+    /// 协商结果为 HTTP/1.1 的请求记为失败而不是静默降级，避免基准测试在不知情时测到 HTTP/1.1。
+    /// 明文 `http://` 没有 ALPN，总是使用 HTTP/1.1，h2c 服务器需同时开启 `http2_prior_knowledge`
+    pub http2_only: bool,
+    /// 允许的最低 TLS 版本，例如 `tls::Version::TLS_1_3` 用于测试只支持 TLS 1.3 的后端。
+    /// 默认的 native-tls 后端不支持要求 TLS 1.3，此时会改用 rustls；rustls 不支持 TLS 1.0/1.1
+    pub min_tls_version: Option<tls::Version>,
+    /// 将请求日志逐条写入 JSONL 文件（每行一个 `RequestLog`），不在内存中保留。
+    /// 用于百万级请求的长时间压测：日志由独立任务通过 channel 写入，响应摘要边写边统计，
+    /// 分位数为直方图估算值；此时 `LoadTestOutput::request_logs` 为空，
+    /// 事后可用 `analyze_request_log_file` 重新分析。默认 None，日志保存在内存中
+    pub request_log_path: Option<PathBuf>,
+}
+
+impl Default for LoadTestOptions {
+    fn default() -> Self {
+        Self {
+            enable_cookies: false,
+            fatal_error_threshold: None,
+            fatal_error_window: 20,
+            resolve_override: None,
+            http2_prior_knowledge: false,
+            http2_only: false,
+            min_tls_version: None,
+            request_log_path: None,
+        }
+    }
+}
+
+impl LoadTestOptions {
+    /// 按选项构建HTTP客户端
+    pub fn build_client(&self) -> Result<Client, reqwest::Error> {
+        let mut builder = Client::builder().cookie_store(self.enable_cookies);
+        if let Some((host, addr)) = &self.resolve_override {
+            builder = builder.resolve(host, *addr);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(version) = self.min_tls_version {
+            if version == tls::Version::TLS_1_3 {
+                builder = builder.use_rustls_tls();
+            }
+            builder = builder.min_tls_version(version);
+        }
+        builder.build()
+    }
+
+    /// 打印与客户端构建相关的选项
+    fn print_client_options(&self) {
+        if self.enable_cookies {
+            println!("🍪 已启用 cookie 存储（并发请求共享会话）");
+        }
+        if let Some((host, addr)) = &self.resolve_override {
+            println!("📌 固定解析: {} -> {}", host, addr.ip());
+        }
+        if self.http2_prior_knowledge {
+            println!("🔗 HTTP/2 prior knowledge");
+        }
+        if self.http2_only {
+            println!("🔗 仅接受 HTTP/2 响应");
+        }
+        if let Some(version) = self.min_tls_version {
+            println!("🔒 最低 TLS 版本: {:?}", version);
+        }
+    }
+}
+
+/// 日志 channel 容量，写入跟不上时请求任务在发送日志处等待，避免日志在内存中堆积
+const REQUEST_LOG_CHANNEL_CAPACITY: usize = 10_000;
+
+/// 请求日志的去向：小规模测试保存在内存中，大规模测试交给写入任务逐条写入文件
+#[derive(Clone)]
+enum RequestLogSink {
+    Memory(Arc<Mutex<Vec<RequestLog>>>),
+    File(mpsc::Sender<RequestLog>),
+}
+
+impl RequestLogSink {
+    /// 按选项创建日志去向，写入文件时同时启动写入任务
+    fn new(
+        options: &LoadTestOptions,
+        capacity: usize,
+    ) -> (Self, Option<JoinHandle<std::io::Result<ResponseSummary>>>) {
+        match &options.request_log_path {
+            Some(path) => {
+                let (tx, rx) = mpsc::channel(REQUEST_LOG_CHANNEL_CAPACITY);
+                (RequestLogSink::File(tx), Some(tokio::spawn(write_request_logs(path.clone(), rx))))
+            }
+            None => (RequestLogSink::Memory(Arc::new(Mutex::new(Vec::with_capacity(capacity)))), None),
+        }
+    }
+
+    async fn record(&self, log: RequestLog) {
+        match self {
+            RequestLogSink::Memory(logs) => logs.lock().await.push(log),
+            // 写入任务出错退出后发送会失败，错误在测试结束时由写入任务返回
+            RequestLogSink::File(tx) => {
+                let _ = tx.send(log).await;
+            }
+        }
+    }
+
+    /// 内存中的全部日志，写入文件时为空
+    async fn memory_logs(&self) -> Vec<RequestLog> {
+        match self {
+            RequestLogSink::Memory(logs) => logs.lock().await.to_vec(),
+            RequestLogSink::File(_) => Vec::new(),
+        }
+    }
+}
+
+/// 日志写入任务：逐条写入 JSONL 文件并累计响应摘要，所有发送端关闭后返回摘要
+async fn write_request_logs(
+    path: PathBuf,
+    mut rx: mpsc::Receiver<RequestLog>,
+) -> std::io::Result<ResponseSummary> {
+    let mut writer = tokio::io::BufWriter::new(tokio::fs::File::create(&path).await?);
+    let mut builder = ResponseSummaryBuilder::new();
+    while let Some(log) = rx.recv().await {
+        let mut line = serde_json::to_vec(&log)?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        builder.add(&log);
+    }
+    writer.flush().await?;
+    Ok(builder.finish())
+}
+
+/// 记录每个请求相对测试开始的完成时间，用于生成每秒完成数时间序列
+#[derive(Clone)]
+struct CompletionRecorder {
+    start: Instant,
+    offsets: Arc<std::sync::Mutex<Vec<Duration>>>,
+}
+
+impl CompletionRecorder {
+    fn new(start: Instant) -> Self {
+        Self {
+            start,
+            offsets: Arc::new(std::sync::Mutex::new(Vec::new())),
+        }
+    }
+
+    /// 记录一个请求在此刻完成
+    fn record(&self) {
+        let offset = self.start.elapsed();
+        self.offsets.lock().unwrap().push(offset);
+    }
+
+    fn timeseries(&self) -> Vec<(u64, u64)> {
+        build_rps_timeseries(&self.offsets.lock().unwrap())
+    }
+}
+
+/// 根据请求完成时间（相对测试开始）按秒归集完成数
+///
+/// 返回从第 0 秒到最后一个完成请求所在秒的连续序列，没有请求完成的秒记为 0，
+/// 便于看出爬升阶段和被限流时的吞吐下降
+pub fn build_rps_timeseries(completion_offsets: &[Duration]) -> Vec<(u64, u64)> {
+    let Some(last_second) = completion_offsets.iter().map(|offset| offset.as_secs()).max() else {
+        return Vec::new();
+    };
+
+    let mut counts = vec![0u64; last_second as usize + 1];
+    for offset in completion_offsets {
+        counts[offset.as_secs() as usize] += 1;
+    }
+    counts.into_iter().enumerate().map(|(second, count)| (second as u64, count)).collect()
+}
+
+/// 最近N个请求结果的滚动窗口，用于判断是否达到致命失败率
+struct FailureWindow {
+    outcomes: VecDeque<bool>,
+    failures: usize,
+    capacity: usize,
+    threshold: f64,
+}
+
+impl FailureWindow {
+    fn new(capacity: usize, threshold: f64) -> Self {
+        Self {
+            outcomes: VecDeque::with_capacity(capacity),
+            failures: 0,
+            capacity: capacity.max(1),
+            threshold,
+        }
+    }
+
+    /// 记录一次请求结果，窗口已满且失败率超过阈值时返回 true
+    fn record(&mut self, success: bool) -> bool {
+        if self.outcomes.len() == self.capacity && self.outcomes.pop_front() == Some(false) {
+            self.failures -= 1;
+        }
+        self.outcomes.push_back(success);
+        if !success {
+            self.failures += 1;
+        }
+        self.outcomes.len() == self.capacity
+            && self.failures as f64 / self.capacity as f64 > self.threshold
+    }
+}
+
+/// 将请求结果计入滚动窗口，达到失败率阈值时通知所有任务终止
+fn record_outcome(
+    failure_window: &Option<Arc<std::sync::Mutex<FailureWindow>>>,
+    abort_tx: &watch::Sender<bool>,
+    success: bool,
+) {
+    if let Some(window) = failure_window {
+        let tripped = window.lock().map(|mut window| window.record(success)).unwrap_or(false);
+        if tripped {
+            abort_tx.send_replace(true);
+        }
+    }
+}
+
+/// 执行请求，测试被终止时立即取消
+async fn run_unless_aborted<F: Future<Output = ()>>(request: F, mut abort_rx: watch::Receiver<bool>) {
+    tokio::select! {
+        _ = request => {}
+        _ = abort_rx.wait_for(|aborted| *aborted) => {}
+    }
+}
+
+/// 对多个请求配置执行负载测试（使用默认选项）
 pub async fn load_test_requests(
     request_configs: &[RequestConfig],
     requests_per_config: u64,
     duration: u64,
+) -> Result<LoadTestOutput, Box<dyn std::error::Error>> {
+    load_test_requests_with_options(request_configs, requests_per_config, duration, &LoadTestOptions::default()).await
+}
+
+/// 按指定选项对多个请求配置执行负载测试
+pub async fn load_test_requests_with_options(
+    request_configs: &[RequestConfig],
+    requests_per_config: u64,
+    duration: u64,
+    options: &LoadTestOptions,
 ) -> Result<LoadTestOutput, Box<dyn std::error::Error>> {
     if request_configs.is_empty() {
         return Err("请求配置数组不能为空".into());
@@ -909,18 +1523,30 @@ pub async fn load_test_requests(
     println!("⚡ 每个配置请求频率: {:.2} 请求/秒", requests_per_second_per_config);
     println!("⚡ 总体请求频率: {:.2} 请求/秒", total_requests_per_second);
     println!("📏 每个配置请求间隔: {:.2} 毫秒", interval_per_config.as_millis());
+    options.print_client_options();
+    if let Some(threshold) = options.fatal_error_threshold {
+        println!("🛑 失败率阈值: {:.1}% (最近 {} 个请求)", threshold * 100.0, options.fatal_error_window);
+    }
+    if let Some(path) = &options.request_log_path {
+        println!("📝 请求日志写入: {}", path.display());
+    }
     println!();
 
     // 创建HTTP客户端
-    let client = Arc::new(Client::new());
+    let client = Arc::new(options.build_client()?);
+    let http2_only = options.http2_only;
     
     // 统计计数器
     let success_count = Arc::new(AtomicU64::new(0));
     let failure_count = Arc::new(AtomicU64::new(0));
     let total_sent = Arc::new(AtomicU64::new(0));
-    let request_logs: Arc<Mutex<Vec<RequestLog>>> = Arc::new(Mutex::new(Vec::with_capacity(
-        (requests_per_config as usize) * request_configs.len(),
-    )));
+    let (request_logs, log_writer) =
+        RequestLogSink::new(options, (requests_per_config as usize) * request_configs.len());
+    let failure_window = options
+        .fatal_error_threshold
+        .map(|threshold| Arc::new(std::sync::Mutex::new(FailureWindow::new(options.fatal_error_window, threshold))));
+    let (abort_tx, _) = watch::channel(false);
+    let abort_tx = Arc::new(abort_tx);
     
     // 进度条
     let pb = ProgressBar::new(total_requests);
@@ -932,21 +1558,26 @@ pub async fn load_test_requests(
     );
 
     let start_time = Instant::now();
+    let completions = CompletionRecorder::new(start_time);
     
     // 为每个配置创建独立的任务组
     let mut config_tasks = Vec::new();
     
-    for (_config_index, config) in request_configs.iter().enumerate() {
+    for config in request_configs {
         let client = client.clone();
         let request_config = config.clone();
         let success_count = success_count.clone();
         let failure_count = failure_count.clone();
         let total_sent = total_sent.clone();
+        let completions = completions.clone();
         let pb = pb.clone();
         let request_logs = request_logs.clone();
+        let failure_window = failure_window.clone();
+        let abort_tx = abort_tx.clone();
         
         // 为每个配置创建独立的任务
         let config_task = tokio::spawn(async move {
+            let mut abort_rx = abort_tx.subscribe();
             let mut interval_timer = time::interval(interval_per_config);
             interval_timer.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
             
@@ -955,8 +1586,11 @@ pub async fn load_test_requests(
             
             // 为当前配置发送所有请求
             for _ in 0..requests_per_config {
-                // 等待下一个发送时机
-                interval_timer.tick().await;
+                // 等待下一个发送时机，测试被终止时立即退出
+                tokio::select! {
+                    _ = interval_timer.tick() => {}
+                    _ = abort_rx.wait_for(|aborted| *aborted) => break,
+                }
                 
                 // 检查是否已超时
                 if Instant::now() >= deadline {
@@ -968,10 +1602,14 @@ pub async fn load_test_requests(
                 let success_count = success_count.clone();
                 let failure_count = failure_count.clone();
                 let total_sent = total_sent.clone();
+                let completions = completions.clone();
                 let pb = pb.clone();
                 let request_logs = request_logs.clone();
+                let failure_window = failure_window.clone();
+                let request_abort_tx = abort_tx.clone();
                 
-                let request_task = tokio::spawn(async move {
+                let think_time = request_config.next_think_time();
+                let request_future = async move {
                     total_sent.fetch_add(1, Ordering::Relaxed);
                     let started = Instant::now();
                     
@@ -983,7 +1621,10 @@ pub async fn load_test_requests(
                         request = request.body(body);
                     }
                     
-                    match request.send().await {
+                    let result = request.send().await
+                        .map_err(|err| err.to_string())
+                        .and_then(|response| require_http2(response, http2_only));
+                    match result {
                         Ok(response) => {
                             let status = response.status();
                             let text = response.text().await.unwrap_or_default();
@@ -1015,17 +1656,17 @@ pub async fn load_test_requests(
                                 response_excerpt: excerpt,
                                 response_content_key,
                             };
-                            let mut guard = request_logs.lock().await;
-                            guard.push(log);
+                            record_outcome(&failure_window, &request_abort_tx, log.success);
+                            request_logs.record(log).await;
                         }
-                        Err(err) => {
+                        Err(error) => {
                             failure_count.fetch_add(1, Ordering::Relaxed);
                             let duration_ms = started.elapsed().as_millis();
                             let default_name = "Unknown".to_string();
                             let name = request_config.name.as_ref().unwrap_or(&default_name).clone();
                             
                             // 生成错误响应key
-                            let response_content_key = generate_response_content_key(None, "", Some(&err.to_string()));
+                            let response_content_key = generate_response_content_key(None, "", Some(&error));
                             
                             let log = RequestLog {
                                 config_name: name,
@@ -1034,24 +1675,37 @@ pub async fn load_test_requests(
                                 status: None,
                                 success: false,
                                 duration_ms,
-                                error: Some(err.to_string()),
+                                error: Some(error),
                                 response_excerpt: None,
                                 response_content_key,
                             };
-                            let mut guard = request_logs.lock().await;
-                            guard.push(log);
+                            record_outcome(&failure_window, &request_abort_tx, log.success);
+                            request_logs.record(log).await;
                         }
                     }
                     
+                    completions.record();
+                    
                     let success = success_count.load(Ordering::Relaxed);
                     let total = success + failure_count.load(Ordering::Relaxed);
                     let success_rate = if total > 0 { success as f64 / total as f64 * 100.0 } else { 0.0 };
                     
                     pb.set_message(format!("{} ({:.1}%)", success, success_rate));
                     pb.inc(1);
-                });
+                };
                 
-                request_tasks.push(request_task);
+                let request_future = run_unless_aborted(request_future, abort_tx.subscribe());
+                
+                // 设置了思考时间时等待请求完成并停顿，否则并发发送
+                if let Some(think_time) = think_time {
+                    request_future.await;
+                    tokio::select! {
+                        _ = time::sleep(think_time) => {}
+                        _ = abort_rx.wait_for(|aborted| *aborted) => break,
+                    }
+                } else {
+                    request_tasks.push(tokio::spawn(request_future));
+                }
             }
             
             // 等待当前配置的所有请求完成
@@ -1076,8 +1730,12 @@ pub async fn load_test_requests(
     let failure = failure_count.load(Ordering::Relaxed);
     let total_completed = success + failure;
     let sent = total_sent.load(Ordering::Relaxed);
+    let aborted_early = *abort_tx.borrow();
     
     println!("\n📈 测试结果:");
+    if aborted_early {
+        println!("🛑 失败率超过阈值，测试已提前终止");
+    }
     println!("⏱️  实际执行时间: {:.2} 秒", elapsed.as_secs_f64());
     println!("📤 发送请求数: {}", sent);
     println!("📥 完成请求数: {}", total_completed);
@@ -1093,8 +1751,19 @@ pub async fn load_test_requests(
     }
     
     // 获取请求日志并生成响应摘要
-    let logs = request_logs.lock().await.to_vec();
-    let response_summary = analyze_response_logs(&logs);
+    let (logs, response_summary) = match log_writer {
+        Some(writer) => {
+            // 关闭最后一个发送端，等待写入任务处理完剩余日志
+            drop(request_logs);
+            let summary = writer.await?.map_err(|e| format!("写入请求日志失败: {}", e))?;
+            (Vec::new(), summary)
+        }
+        None => {
+            let logs = request_logs.memory_logs().await;
+            let summary = analyze_response_logs(&logs);
+            (logs, summary)
+        }
+    };
     
     // 返回测试结果
     Ok(LoadTestOutput {
@@ -1106,8 +1775,450 @@ pub async fn load_test_requests(
             failed_requests: failure,
             success_rate_percent: success_rate,
             actual_requests_per_second: actual_rps,
+            aborted_early,
+            cancelled: false,
+            rps_timeseries: completions.timeseries(),
         },
         request_logs: logs,
         response_summary,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// 启动本地模拟HTTP服务，`handler` 接收请求序号与原始请求文本，返回完整的响应文本
+    async fn spawn_mock_server<F>(handler: F) -> String
+    where
+        F: Fn(usize, &str) -> String + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(handler);
+        tokio::spawn(async move {
+            let mut index = 0;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let request_index = index;
+                index += 1;
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 8192];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let response = handler(request_index, &request);
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn http_response(status: &str, extra_headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+            status,
+            body.len(),
+            extra_headers,
+            body
+        )
+    }
+
+    fn log_with_duration(duration_ms: u128) -> RequestLog {
+        RequestLog {
+            config_name: "test".to_string(),
+            method: "GET".to_string(),
+            url: "http://localhost".to_string(),
+            status: Some(200),
+            success: true,
+            duration_ms,
+            error: None,
+            response_excerpt: None,
+            response_content_key: "200".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_histogram_counts_sum_to_total() {
+        let logs: Vec<RequestLog> = [0, 1, 3, 7, 8, 15, 100, 1000, 100_000]
+            .into_iter()
+            .map(log_with_duration)
+            .collect();
+
+        let summary = analyze_response_logs_with_buckets(&logs, &[10, 1, 100]);
+        assert_eq!(summary.histogram, vec![(1, 2), (10, 3), (100, 2), (u128::MAX, 2)]);
+        let total: u64 = summary.histogram.iter().map(|(_, count)| count).sum();
+        assert_eq!(total, summary.total_requests);
+
+        let summary = analyze_response_logs(&logs);
+        let total: u64 = summary.histogram.iter().map(|(_, count)| count).sum();
+        assert_eq!(total, logs.len() as u64);
+    }
+
+    #[test]
+    fn test_streamed_summary_matches_in_memory_analysis() {
+        let mut logs: Vec<RequestLog> = (0..500u128).map(|i| log_with_duration((i * 37) % 3000)).collect();
+        for (i, log) in logs.iter_mut().enumerate() {
+            match i % 10 {
+                0 => {
+                    log.success = false;
+                    log.status = None;
+                    log.error = Some("connection refused".to_string());
+                    log.response_content_key = "network".to_string();
+                }
+                1 => {
+                    log.success = false;
+                    log.status = Some(503);
+                    log.response_content_key = "503".to_string();
+                }
+                _ => {}
+            }
+        }
+
+        let in_memory = analyze_response_logs(&logs);
+        let mut builder = ResponseSummaryBuilder::new();
+        for log in &logs {
+            builder.add(log);
+        }
+        let streamed = builder.finish();
+
+        assert_eq!(streamed.total_requests, in_memory.total_requests);
+        assert_eq!(streamed.success_count, in_memory.success_count);
+        assert_eq!(streamed.failure_count, in_memory.failure_count);
+        assert_eq!(streamed.success_rate, in_memory.success_rate);
+        assert_eq!(streamed.histogram, in_memory.histogram);
+        let counts = |distribution: &HashMap<String, ResponseStat>| -> HashMap<String, u64> {
+            distribution.iter().map(|(key, stat)| (key.clone(), stat.count)).collect()
+        };
+        assert_eq!(counts(&streamed.http_status_distribution), counts(&in_memory.http_status_distribution));
+        assert_eq!(counts(&streamed.error_distribution), counts(&in_memory.error_distribution));
+        assert_eq!(
+            counts(&streamed.response_content_distribution),
+            counts(&in_memory.response_content_distribution)
+        );
+
+        let (exact, estimated) = (&in_memory.response_time_stats, &streamed.response_time_stats);
+        assert_eq!((estimated.min_ms, estimated.max_ms), (exact.min_ms, exact.max_ms));
+        assert_eq!(estimated.avg_ms, exact.avg_ms);
+        // 估算的分位数是精确值所在桶的上界（不超过最大耗时）
+        let max_ms = exact.max_ms;
+        for (estimate, exact) in [
+            (estimated.p50_ms, exact.p50_ms),
+            (estimated.p90_ms, exact.p90_ms),
+            (estimated.p95_ms, exact.p95_ms),
+            (estimated.p99_ms, exact.p99_ms),
+        ] {
+            let bucket = streamed.histogram.iter().map(|(bound, _)| *bound).find(|&bound| bound >= exact).unwrap();
+            assert!(exact <= estimate && estimate <= bucket.min(max_ms), "{} vs {}", estimate, exact);
+        }
+
+        // 从 JSONL 文件重新分析得到相同的结果
+        let path = std::env::temp_dir().join(format!("load-test-logs-{}.jsonl", std::process::id()));
+        let lines: Vec<String> = logs.iter().map(|log| serde_json::to_string(log).unwrap()).collect();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+        let from_file = analyze_request_log_file(&path).unwrap();
+        assert_eq!(from_file.histogram, streamed.histogram);
+        assert_eq!(from_file.response_time_stats.p99_ms, estimated.p99_ms);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(ResponseSummaryBuilder::new().finish().total_requests, 0);
+    }
+
+    #[test]
+    fn test_prometheus_output_is_valid_exposition_format() {
+        let mut logs: Vec<RequestLog> = (1..=100).map(log_with_duration).collect();
+        logs[0].success = false;
+        let summary = analyze_response_logs(&logs);
+
+        let text = summary.to_prometheus(&[("job", "ci"), ("target", "api \"v2\"")]);
+        let labels = r#"job="ci",target="api \"v2\"""#;
+        for line in [
+            format!("loadtest_requests_total{{{}}} 100", labels),
+            format!("loadtest_requests_success_total{{{}}} 99", labels),
+            format!("loadtest_requests_failed_total{{{}}} 1", labels),
+            format!("loadtest_success_ratio{{{}}} 0.99", labels),
+            format!("loadtest_latency_ms{{{},quantile=\"0.99\"}} 100", labels),
+            format!("loadtest_latency_ms{{{},quantile=\"0.5\"}} 51", labels),
+            format!("loadtest_latency_ms_sum{{{}}} 5050", labels),
+            format!("loadtest_latency_ms_count{{{}}} 100", labels),
+            "# TYPE loadtest_latency_ms summary".to_string(),
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in:\n{}", line, text);
+        }
+
+        // 每行是注释或 `name{labels} value`，且样本紧跟在所属指标的 TYPE 声明之后
+        let mut declared: Option<String> = None;
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(matches!(kind, "counter" | "gauge" | "summary"), "{}", line);
+                declared = Some(name.to_string());
+                continue;
+            }
+            if line.starts_with("# HELP ") {
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "{}", line);
+            let name = series.split('{').next().unwrap();
+            assert!(!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'), "{}", line);
+            let label_part = &series[name.len()..];
+            assert!(label_part.is_empty() || (label_part.starts_with('{') && label_part.ends_with('}')), "{}", line);
+            let family = name.strip_suffix("_sum").or(name.strip_suffix("_count")).unwrap_or(name);
+            assert_eq!(declared.as_deref(), Some(family), "{}", line);
+        }
+
+        // 无标签时不输出空的大括号
+        assert!(summary.to_prometheus(&[]).lines().any(|l| l == "loadtest_requests_total 100"));
+    }
+
+    #[test]
+    fn test_rps_timeseries_buckets_by_second() {
+        // 第 0 秒 3 个，第 1 秒无完成（限流），第 2 秒 2 个，第 3 秒 1 个
+        let offsets: Vec<Duration> = [0, 200, 999, 2000, 2500, 3999]
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect();
+
+        let timeseries = build_rps_timeseries(&offsets);
+        assert_eq!(timeseries, vec![(0, 3), (1, 0), (2, 2), (3, 1)]);
+        let total: u64 = timeseries.iter().map(|(_, count)| count).sum();
+        assert_eq!(total, offsets.len() as u64);
+
+        assert!(build_rps_timeseries(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rps_timeseries_sums_to_completed_requests() {
+        let url = spawn_mock_server(|_, _| http_response("200 OK", "", "ok")).await;
+        let configs = vec![RequestConfig::from_url(&url)];
+
+        let output = load_test_requests(&configs, 10, 1).await.unwrap();
+        let total: u64 = output.results.rps_timeseries.iter().map(|(_, count)| count).sum();
+        assert_eq!(total, output.results.requests_completed);
+    }
+
+    #[tokio::test]
+    async fn test_request_logs_stream_to_file() {
+        let url = spawn_mock_server(|_, _| http_response("200 OK", "", "ok")).await;
+        let configs = vec![RequestConfig::from_url(&url)];
+        let path = std::env::temp_dir().join(format!("load-test-stream-{}.jsonl", std::process::id()));
+        let options = LoadTestOptions { request_log_path: Some(path.clone()), ..Default::default() };
+
+        let output = load_test_requests_with_options(&configs, 10, 1, &options).await.unwrap();
+        assert!(output.request_logs.is_empty());
+        assert_eq!(output.response_summary.total_requests, output.results.requests_completed);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count() as u64, output.results.requests_completed);
+        let logs: Vec<RequestLog> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert!(logs.iter().all(|log| log.success));
+        assert_eq!(analyze_response_logs(&logs).histogram, output.response_summary.histogram);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cookies_are_sent_on_later_requests() {
+        let url = spawn_mock_server(|index, request| {
+            if index == 0 {
+                http_response("200 OK", "Set-Cookie: session=abc123; Path=/\r\n", "login")
+            } else {
+                let cookie = request
+                    .lines()
+                    .find(|line| line.to_ascii_lowercase().starts_with("cookie:"))
+                    .map(|line| line[7..].trim().to_string())
+                    .unwrap_or_default();
+                http_response("200 OK", "", &cookie)
+            }
+        })
+        .await;
+
+        let options = LoadTestOptions { enable_cookies: true, ..Default::default() };
+        let client = options.build_client().unwrap();
+        client.get(format!("{}/login", url)).send().await.unwrap();
+        let echoed = client.get(format!("{}/me", url)).send().await.unwrap().text().await.unwrap();
+        assert_eq!(echoed, "session=abc123");
+
+        // 默认不保存 cookie
+        let client = LoadTestOptions::default().build_client().unwrap();
+        client.get(format!("{}/login", url)).send().await.unwrap();
+        let echoed = client.get(format!("{}/me", url)).send().await.unwrap().text().await.unwrap();
+        assert_eq!(echoed, "");
+    }
+
+    #[tokio::test]
+    async fn test_think_time_paces_requests_within_config() {
+        let url = spawn_mock_server(|_, _| http_response("200 OK", "", "ok")).await;
+        let think_time = Duration::from_millis(200);
+        let configs = vec![RequestConfig::from_url(&url).with_think_time(think_time)];
+
+        let started = Instant::now();
+        let output = load_test_requests(&configs, 3, 1).await.unwrap();
+        assert_eq!(output.results.requests_completed, 3);
+        assert!(started.elapsed() >= think_time * 3);
+    }
+
+    #[test]
+    fn test_think_time_range_stays_within_bounds() {
+        let config = RequestConfig::from_url("http://localhost")
+            .with_think_time_range(Duration::from_millis(100), Duration::from_millis(200));
+        for _ in 0..20 {
+            let think_time = config.next_think_time().unwrap();
+            assert!(think_time >= Duration::from_millis(100) && think_time <= Duration::from_millis(200));
+        }
+        assert_eq!(RequestConfig::from_url("http://localhost").next_think_time(), None);
+    }
+
+    #[test]
+    fn test_failure_window_uses_recent_outcomes() {
+        let mut window = FailureWindow::new(4, 0.5);
+        // 窗口未满时不触发
+        assert!(!window.record(false));
+        assert!(!window.record(false));
+        assert!(!window.record(false));
+        assert!(window.record(false));
+
+        // 早期的失败滑出窗口后恢复
+        let mut window = FailureWindow::new(4, 0.5);
+        for _ in 0..3 {
+            window.record(false);
+        }
+        assert!(window.record(true));
+        assert!(!window.record(true));
+        assert!(!window.record(true));
+    }
+
+    #[tokio::test]
+    async fn test_aborts_early_when_all_requests_fail() {
+        let url = spawn_mock_server(|_, _| http_response("500 Internal Server Error", "", "down")).await;
+        let configs = vec![RequestConfig::from_url(&url)];
+        let options = LoadTestOptions {
+            fatal_error_threshold: Some(0.5),
+            fatal_error_window: 5,
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        let output = load_test_requests_with_options(&configs, 100, 5, &options).await.unwrap();
+        assert!(output.results.aborted_early);
+        assert!(output.results.requests_completed >= 5);
+        assert!(output.results.requests_completed < 100);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_load_test_returns_partial_results() {
+        let url = spawn_mock_server(|_, _| http_response("200 OK", "", "ok")).await;
+
+        let started = Instant::now();
+        let handle = spawn_load_test(LoadTestConfig::new(&url, 1000, 20));
+        time::sleep(Duration::from_millis(300)).await;
+        assert!(!handle.is_finished());
+        handle.cancel();
+
+        let results = handle.await_results().await.unwrap();
+        assert!(results.cancelled);
+        assert!(!results.aborted_early);
+        assert!(results.requests_completed > 0);
+        assert!(results.requests_sent < 1000);
+        assert!(results.requests_completed <= results.requests_sent);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    /// 启动只支持 HTTP/2 明文 (h2c) 的本地服务器，返回其URL
+    async fn spawn_h2c_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let Ok(mut connection) = h2::server::handshake(socket).await else {
+                        return;
+                    };
+                    while let Some(Ok((_request, mut respond))) = connection.accept().await {
+                        let response = http::Response::builder().status(200).body(()).unwrap();
+                        if let Ok(mut stream) = respond.send_response(response, false) {
+                            let _ = stream.send_data(bytes::Bytes::from_static(b"ok"), true);
+                        }
+                    }
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge_negotiates_h2() {
+        let url = spawn_h2c_server().await;
+
+        let options = LoadTestOptions { http2_prior_knowledge: true, ..Default::default() };
+        let response = options.build_client().unwrap().get(&url).send().await.unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        let configs = vec![RequestConfig::from_url(&url)];
+        let options = LoadTestOptions { http2_prior_knowledge: true, http2_only: true, ..Default::default() };
+        let output = load_test_requests_with_options(&configs, 5, 1, &options).await.unwrap();
+        assert_eq!(output.results.successful_requests, 5);
+
+        // 默认客户端使用 HTTP/1.1，无法与只支持 h2c 的服务器通信
+        let output = load_test_requests(&configs, 2, 1).await.unwrap();
+        assert_eq!(output.results.successful_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_http2_only_rejects_http1_responses() {
+        let url = spawn_mock_server(|_, _| http_response("200 OK", "", "ok")).await;
+        let configs = vec![RequestConfig::from_url(&url)];
+
+        // 不使用 prior knowledge 时照常协商，服务器只支持 HTTP/1.1，响应被判为失败
+        let options = LoadTestOptions { http2_only: true, ..Default::default() };
+        let output = load_test_requests_with_options(&configs, 3, 1, &options).await.unwrap();
+        assert_eq!(output.results.successful_requests, 0);
+        assert_eq!(output.results.failed_requests, 3);
+        assert!(output.request_logs.iter().all(|log| log.error.as_deref().is_some_and(|e| e.contains("HTTP/2"))));
+
+        let output = load_test_requests_with_options(&configs, 3, 1, &LoadTestOptions::default()).await.unwrap();
+        assert_eq!(output.results.successful_requests, 3);
+    }
+
+    #[test]
+    fn test_min_tls_version_builds_client() {
+        for version in [tls::Version::TLS_1_2, tls::Version::TLS_1_3] {
+            let options = LoadTestOptions { min_tls_version: Some(version), ..Default::default() };
+            assert!(options.build_client().is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_override_pins_host_to_address() {
+        let url = spawn_mock_server(|_, request| {
+            let host = request
+                .lines()
+                .find(|line| line.to_ascii_lowercase().starts_with("host:"))
+                .map(|line| line[5..].trim().to_string())
+                .unwrap_or_default();
+            http_response("200 OK", "", &host)
+        })
+        .await;
+        let addr: SocketAddr = url.trim_start_matches("http://").parse().unwrap();
+
+        let options = LoadTestOptions {
+            resolve_override: Some(("backend.invalid".to_string(), addr)),
+            ..Default::default()
+        };
+        let client = options.build_client().unwrap();
+        let host = client
+            .get(format!("http://backend.invalid:{}/", addr.port()))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(host, format!("backend.invalid:{}", addr.port()));
+    }
+}
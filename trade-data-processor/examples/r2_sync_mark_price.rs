@@ -23,7 +23,6 @@ use clap::Parser;
 use std::path::PathBuf;
 use trade_data_processor::config::S3Config;
 use trade_data_processor::s3_helper::{S3Helper, SyncOptions, SyncDirection};
-use tracing_subscriber;
 use serde::Deserialize;
 
 /// R2 sync configuration
@@ -105,21 +104,23 @@ async fn main() -> Result<()> {
     };
 
     // Check if local folder exists (for local_to_s3 or bidirectional)
-    if matches!(sync_direction, SyncDirection::LocalToS3 | SyncDirection::Bidirectional) {
-        if !std::path::Path::new(local_folder).exists() {
-            eprintln!("❌ Error: Local folder does not exist: {}", local_folder);
-            eprintln!("Please ensure the folder exists before syncing.");
-            return Ok(());
-        }
+    if matches!(sync_direction, SyncDirection::LocalToS3 | SyncDirection::Bidirectional)
+        && !std::path::Path::new(local_folder).exists()
+    {
+        eprintln!("❌ Error: Local folder does not exist: {}", local_folder);
+        eprintln!("Please ensure the folder exists before syncing.");
+        return Ok(());
     }
 
     // Configure sync options
-    let mut options = SyncOptions::default();
-    options.direction = sync_direction;
-    options.dry_run = args.dry_run;
-    options.force = args.force;
-    options.delete = args.delete;
-    options.use_compression = args.compress;
+    let options = SyncOptions {
+        direction: sync_direction,
+        dry_run: args.dry_run,
+        force: args.force,
+        delete: args.delete,
+        use_compression: args.compress,
+        ..Default::default()
+    };
     
     if args.dry_run {
         println!("🔍 DRY RUN MODE - No files will be uploaded/downloaded");
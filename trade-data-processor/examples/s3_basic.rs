@@ -28,6 +28,11 @@ async fn main() -> Result<()> {
         endpoint: Some("http://localhost:9000".to_string()),
         force_path_style: Some(true),
         base_path: Some("examples".to_string()),
+        local_path: None,
+        remote_prefix: None,
+        cache_db_path: None,
+        sync_direction: None,
+        profile: None,
     };
 
     // Create S3 helper
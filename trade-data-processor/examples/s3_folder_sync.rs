@@ -10,8 +10,7 @@
 
 use anyhow::Result;
 use trade_data_processor::config::S3Config;
-use trade_data_processor::s3_helper::{S3Helper, SyncOptions};
-use tracing_subscriber;
+use trade_data_processor::s3_helper::{S3Helper, SyncDirection, SyncOptions};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -32,6 +31,12 @@ async fn main() -> Result<()> {
         region: Some("us-east-1".to_string()),
         endpoint: None,
         force_path_style: None,
+        base_path: None,
+        local_path: None,
+        remote_prefix: None,
+        cache_db_path: None,
+        sync_direction: None,
+        profile: None,
     };
 
     // Create S3 helper
@@ -49,8 +54,10 @@ async fn main() -> Result<()> {
     println!("\n=== Example 1: One-way sync (local -> S3) ===");
     
     // Configure sync options
-    let mut options = SyncOptions::default();
-    options.dry_run = true; // Set to false to actually sync
+    let mut options = SyncOptions {
+        dry_run: true, // Set to false to actually sync
+        ..Default::default()
+    };
     
     println!("Syncing {} -> s3://{}/{}", local_folder, s3_helper.bucket(), s3_prefix);
     
@@ -69,12 +76,13 @@ async fn main() -> Result<()> {
     println!("\n=== Example 2: Bidirectional sync (local <-> S3) ===");
     
     // Enable bidirectional sync
+    options.direction = SyncDirection::Bidirectional;
     options.dry_run = true; // Set to false to actually sync
     
     println!("Syncing {} <-> s3://{}/{}", local_folder, s3_helper.bucket(), s3_prefix);
     
     let stats = s3_helper
-        .sync_folder_bidirectional(local_folder, s3_prefix, db_path, options.clone())
+        .sync_folder(local_folder, s3_prefix, db_path, options.clone())
         .await?;
     
     println!("\nBidirectional Sync Statistics:");
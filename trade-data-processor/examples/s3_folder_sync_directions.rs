@@ -25,6 +25,12 @@ async fn main() -> Result<()> {
         region: Some("us-east-1".to_string()),
         endpoint: None, // Use default AWS endpoint
         force_path_style: None,
+        base_path: None,
+        local_path: None,
+        remote_prefix: None,
+        cache_db_path: None,
+        sync_direction: None,
+        profile: None,
     };
     
     // Create S3 helper
@@ -47,6 +53,8 @@ async fn main() -> Result<()> {
             "node_modules".to_string(),
         ],
         max_parallel: 4,
+        use_compression: true,
+        ..Default::default()
     };
     
     let stats = s3_helper.sync_folder(
@@ -112,6 +120,8 @@ async fn main() -> Result<()> {
         dry_run: true, // Just show what would be done
         exclude_patterns: vec![".git".to_string()],
         max_parallel: 4,
+        use_compression: true,
+        ..Default::default()
     };
     
     let stats = s3_helper.sync_folder(
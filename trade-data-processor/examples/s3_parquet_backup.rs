@@ -31,6 +31,11 @@ async fn main() -> Result<()> {
         endpoint: Some("https://s3.us-west-002.backblazeb2.com".to_string()),
         force_path_style: Some(true),
         base_path: Some("mark-price/merged".to_string()),
+        local_path: None,
+        remote_prefix: None,
+        cache_db_path: None,
+        sync_direction: None,
+        profile: None,
     };
 
     // Create S3 helper
@@ -0,0 +1,133 @@
+//! Content-sniffing decompression shared by all download paths
+//!
+//! Remote files arrive zstd-compressed (SFTP transfers, proxy `Content-Encoding: zstd`),
+//! gzip-compressed (`.gz` files, S3 objects) or plain. The format is detected from the
+//! magic bytes rather than from headers or file names, so HTTP, SSH and local sources
+//! all decode the same way.
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read};
+use tracing::debug;
+
+/// Magic bytes of a zstd frame
+pub const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Magic bytes of a gzip member
+pub const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Compression detected from the leading bytes of a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+    None,
+}
+
+impl Compression {
+    /// Detect the compression of `data` from its magic bytes
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if data.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Decompress zstd or gzip data, returning anything else unchanged
+pub fn decompress_auto(data: &[u8]) -> Result<Vec<u8>> {
+    match Compression::detect(data) {
+        Compression::None => Ok(data.to_vec()),
+        compression => decode(data, compression),
+    }
+}
+
+/// Same as `decompress_auto` for an owned buffer, which is returned without copying
+/// when it is not compressed
+pub fn decompress_auto_owned(data: Vec<u8>) -> Result<Vec<u8>> {
+    match Compression::detect(&data) {
+        Compression::None => {
+            debug!("Data is not compressed, using as-is (first 8 bytes: {:02X?})", &data[..data.len().min(8)]);
+            Ok(data)
+        }
+        compression => decode(&data, compression),
+    }
+}
+
+/// Stream-decompress zstd or gzip data, reading anything else as-is
+/// Nothing is decompressed up front, so a large file never sits in memory decoded
+pub fn decompress_reader<'a>(data: &'a [u8]) -> Result<Box<dyn BufRead + 'a>> {
+    Ok(match Compression::detect(data) {
+        Compression::Zstd => Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(data).context("Failed to start zstd decompression")?,
+        )),
+        Compression::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(data))),
+        Compression::None => Box::new(data),
+    })
+}
+
+fn decode(data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    let decompressed = match compression {
+        Compression::Zstd => zstd::decode_all(data).context("Failed to decompress zstd data")?,
+        Compression::Gzip => {
+            let mut decompressed = Vec::new();
+            flate2::read::MultiGzDecoder::new(data)
+                .read_to_end(&mut decompressed)
+                .context("Failed to decompress gzip data")?;
+            decompressed
+        }
+        Compression::None => return Ok(data.to_vec()),
+    };
+    debug!("Decompressed {:?} data from {} bytes to {} bytes", compression, data.len(), decompressed.len());
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const CONTENT: &[u8] = b"{\"s\":\"BTCUSDT\",\"p\":\"1.0\"}\n{\"s\":\"ETHUSDT\",\"p\":\"2.0\"}\n";
+
+    #[test]
+    fn test_decompress_zstd_gzip_and_plain() {
+        let zstd_data = zstd::encode_all(CONTENT, 3).unwrap();
+        assert_eq!(Compression::detect(&zstd_data), Compression::Zstd);
+        assert_eq!(decompress_auto(&zstd_data).unwrap(), CONTENT);
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(CONTENT).unwrap();
+        let gzip_data = encoder.finish().unwrap();
+        assert_eq!(Compression::detect(&gzip_data), Compression::Gzip);
+        assert_eq!(decompress_auto(&gzip_data).unwrap(), CONTENT);
+        assert_eq!(decompress_auto_owned(gzip_data).unwrap(), CONTENT);
+
+        assert_eq!(Compression::detect(CONTENT), Compression::None);
+        assert_eq!(decompress_auto(CONTENT).unwrap(), CONTENT);
+        assert_eq!(decompress_auto_owned(CONTENT.to_vec()).unwrap(), CONTENT);
+        assert!(decompress_auto(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decompress_reader_streams_every_format() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(CONTENT).unwrap();
+        let gzip_data = encoder.finish().unwrap();
+        let zstd_data = zstd::encode_all(CONTENT, 3).unwrap();
+
+        for data in [zstd_data.as_slice(), gzip_data.as_slice(), CONTENT] {
+            let mut decoded = Vec::new();
+            decompress_reader(data).unwrap().read_to_end(&mut decoded).unwrap();
+            assert_eq!(decoded, CONTENT);
+        }
+    }
+
+    #[test]
+    fn test_truncated_zstd_is_an_error() {
+        let zstd_data = zstd::encode_all(CONTENT, 3).unwrap();
+        let error = decompress_auto(&zstd_data[..6]).unwrap_err();
+        assert!(error.to_string().contains("zstd"), "{}", error);
+    }
+}
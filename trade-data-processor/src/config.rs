@@ -3,10 +3,14 @@
 //! This module defines the configuration structure for SSH connections,
 //! input/output paths, and merge strategies.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use parquet::basic::Encoding;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// SSH server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,12 +18,46 @@ pub struct SshConfig {
     pub host: String,
     pub port: Option<u16>,
     pub username: String,
+    /// Login password, used for password authentication only
     pub password: Option<String>,
     pub private_key_path: Option<String>,
+    /// Passphrase of the private key (falls back to the `SSH_KEY_PASSPHRASE` env var)
+    #[serde(default)]
+    pub private_key_passphrase: Option<String>,
+    /// Cap on the aggregate download throughput from this server (bytes per second)
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+    /// Cap on the commands executed on this server per second, shared by all tasks
+    #[serde(default)]
+    pub requests_per_sec: Option<f64>,
+    /// Command printing one file name per line; `{path}` is replaced by the shell-quoted directory
+    /// The default needs GNU find; use e.g. `ls -1A {path}` on BSD/busybox servers
+    #[serde(default = "default_list_command")]
+    pub list_command: String,
     /// Base input directory on this remote machine (e.g., "/hdd16/trade/wss-collector/data/mark-price/")
     pub input_base_path: String,
 }
 
+/// Environment variable holding the private key passphrase when it is not in the config
+pub const SSH_KEY_PASSPHRASE_ENV: &str = "SSH_KEY_PASSPHRASE";
+
+/// Default remote listing command: regular files (including dotfiles), names only
+pub const DEFAULT_SSH_LIST_COMMAND: &str = "find {path} -maxdepth 1 -type f -printf '%f\\n'";
+
+fn default_list_command() -> String {
+    DEFAULT_SSH_LIST_COMMAND.to_string()
+}
+
+impl SshConfig {
+    /// Passphrase for `private_key_path`
+    /// Precedence: `private_key_passphrase`, then `SSH_KEY_PASSPHRASE`, then none
+    pub fn key_passphrase(&self) -> Option<String> {
+        self.private_key_passphrase
+            .clone()
+            .or_else(|| std::env::var(SSH_KEY_PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()))
+    }
+}
+
 /// HTTP server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpConfig {
@@ -31,14 +69,201 @@ pub struct HttpConfig {
     /// Optional proxy URL for HTTP requests (e.g., "http://proxy.example.com:8080")
     #[serde(default)]
     pub proxy: Option<String>,
+    /// Additional proxies tried in order after `proxy` when a connection fails
+    #[serde(default)]
+    pub proxies: Vec<String>,
+    /// How the proxy is checked before downloading (only used when `proxy` is set)
+    #[serde(default)]
+    pub proxy_check: ProxyCheckConfig,
+    /// Cap on the aggregate download throughput from this server (bytes per second)
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+    /// Cap on the requests sent to this server per second, shared by all tasks
+    /// Unlike a concurrency limit this spaces requests out even when each one is quick
+    #[serde(default)]
+    pub requests_per_sec: Option<f64>,
+    /// How a `/ls` response is judged when checking whether a directory exists
+    #[serde(default)]
+    pub exists_check: PathExistsCheck,
+    /// Total timeout of one request in seconds, including reading the body
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Timeout for establishing the connection in seconds
+    #[serde(default = "default_http_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Order of the S3 and direct download attempts, and whether to fall back at all
+    #[serde(default)]
+    pub download_strategy: DownloadStrategy,
+    /// PEM file with a CA certificate to trust in addition to the system roots,
+    /// e.g. the private CA of an internal server
+    #[serde(default)]
+    pub ca_cert_path: Option<String>,
+    /// INSECURE: accept any server certificate (self-signed, expired, other host names),
+    /// also for S3 downloads through this client. Only for testing; prefer `ca_cert_path`
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+impl HttpConfig {
+    /// Proxies in failover order: `proxy` first, then `proxies`, without duplicates
+    pub fn proxy_urls(&self) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for url in self.proxy.iter().chain(&self.proxies) {
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+        urls
+    }
+}
+
+/// Default total request timeout (5 minutes, enough for a large daily file)
+pub const DEFAULT_HTTP_TIMEOUT_SECS: u64 = 300;
+
+/// Default connect timeout
+pub const DEFAULT_HTTP_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+fn default_http_timeout_secs() -> u64 {
+    DEFAULT_HTTP_TIMEOUT_SECS
+}
+
+fn default_http_connect_timeout_secs() -> u64 {
+    DEFAULT_HTTP_CONNECT_TIMEOUT_SECS
+}
+
+/// How `HttpClient::path_exists` interprets the `/ls` response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathExistsCheck {
+    /// 2xx status and a JSON array body (an empty array still counts as existing)
+    #[default]
+    JsonArray,
+    /// Any 2xx status, regardless of the body
+    Status,
+}
+
+/// Which download endpoints `HttpClient` uses for a file, and in what order
+/// `S3` is the presigned URL from `/get_s3_url`; direct is `/download` on the server itself
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStrategy {
+    /// Try S3, fall back to direct download
+    #[default]
+    S3First,
+    /// Try direct download, fall back to S3
+    DirectFirst,
+    /// Only download from S3
+    S3Only,
+    /// Only download directly, never asking for an S3 URL
+    DirectOnly,
+}
+
+/// Default URL probed by the proxy check
+pub const DEFAULT_PROXY_CHECK_URL: &str = "http://clients3.google.com/generate_204";
+
+/// Proxy availability check configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyCheckConfig {
+    /// URL requested through the proxy
+    #[serde(default = "default_proxy_check_url")]
+    pub url: String,
+    /// Probe the server's `base_url` instead of `url` (for networks where external sites are blocked)
+    #[serde(default)]
+    pub use_base_url: bool,
+    /// Required response status; any 2xx status passes when not set
+    #[serde(default)]
+    pub expected_status: Option<u16>,
+    /// Request timeout in seconds
+    #[serde(default = "default_proxy_check_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_proxy_check_url() -> String {
+    DEFAULT_PROXY_CHECK_URL.to_string()
+}
+
+fn default_proxy_check_timeout_secs() -> u64 {
+    5
+}
+
+impl Default for ProxyCheckConfig {
+    fn default() -> Self {
+        Self {
+            url: default_proxy_check_url(),
+            use_base_url: false,
+            expected_status: None,
+            timeout_secs: default_proxy_check_timeout_secs(),
+        }
+    }
 }
 
 /// Local file configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalFileConfig {
     /// Base path for local files (e.g., "/data/mark-price")
-    /// The program will append /{year}/{month}/{day} automatically
+    /// The day's directory is found by appending `path_template`
     pub base_path: String,
+    /// Directory of a day's files with `{year}`, `{month}` and `{day}` tokens
+    /// Relative templates are resolved against `base_path`; absolute ones are used as-is
+    #[serde(default = "default_local_path_template")]
+    pub path_template: String,
+    /// File name pattern with `*`/`?` wildcards and the same date tokens, e.g. "*_{year}{month}{day}.jsonl"
+    /// If None, files are picked by the data source's `file_extensions`
+    #[serde(default)]
+    pub glob: Option<String>,
+}
+
+/// Default local layout: one directory per day under `base_path`
+pub const DEFAULT_LOCAL_PATH_TEMPLATE: &str = "{year}/{month}/{day}";
+
+fn default_local_path_template() -> String {
+    DEFAULT_LOCAL_PATH_TEMPLATE.to_string()
+}
+
+impl LocalFileConfig {
+    /// Directory holding the files for a date
+    pub fn dir_for_date(&self, date: NaiveDate) -> PathBuf {
+        Path::new(&self.base_path).join(render_date_tokens(&self.path_template, date))
+    }
+
+    /// File name pattern for a date, if `glob` is set
+    pub fn glob_for_date(&self, date: NaiveDate) -> Option<String> {
+        self.glob.as_deref().map(|glob| render_date_tokens(glob, date))
+    }
+}
+
+/// Replace `{year}`, `{month}` and `{day}` (zero-padded) with the parts of a date
+pub fn render_date_tokens(template: &str, date: NaiveDate) -> String {
+    template
+        .replace("{year}", &date.format("%Y").to_string())
+        .replace("{month}", &date.format("%m").to_string())
+        .replace("{day}", &date.format("%d").to_string())
+}
+
+/// Match a file name against a pattern where `*` is any run of characters and `?` one character
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it is currently matched up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 /// S3-compatible storage configuration
@@ -48,10 +273,17 @@ pub struct S3Config {
     pub provider: String,
     /// S3 bucket name
     pub bucket: String,
-    /// Access key ID
+    /// Access key ID; leave both keys empty to use the AWS credential chain
+    /// (environment variables, `~/.aws/credentials` profile, instance metadata)
+    #[serde(default)]
     pub access_key_id: String,
     /// Secret access key
+    #[serde(default)]
     pub secret_access_key: String,
+    /// Named profile from `~/.aws/config` / `~/.aws/credentials` used by the credential
+    /// chain; defaults to `AWS_PROFILE` or "default"
+    #[serde(default)]
+    pub profile: Option<String>,
     /// Region (optional, will use provider defaults if not specified)
     #[serde(default)]
     pub region: Option<String>,
@@ -97,6 +329,88 @@ pub struct DataSourceConfig {
     /// List of local file paths to read data from (optional)
     #[serde(default)]
     pub local_files: Vec<LocalFileConfig>,
+    /// Symbols to keep (mark-price only); empty means all symbols
+    /// Matching is case-insensitive: symbols are trimmed and uppercased before comparing
+    #[serde(default)]
+    pub symbols_allowlist: Vec<String>,
+    /// Symbols to drop (mark-price only), applied after the allowlist
+    #[serde(default)]
+    pub symbols_denylist: Vec<String>,
+    /// File name suffixes picked up from each day's directory
+    /// Compressed files are detected by content; `.json` files may hold a top-level array
+    #[serde(default = "default_file_extensions")]
+    pub file_extensions: Vec<String>,
+    /// Reject records whose timestamp falls outside the processing date's UTC day (opt-in)
+    #[serde(default)]
+    pub reject_out_of_range: bool,
+    /// Seconds of tolerance on both sides of the UTC day when `reject_out_of_range` is set
+    #[serde(default)]
+    pub out_of_range_grace_secs: u64,
+    /// Add a `_source` field with the name of the source each record came from (opt-in)
+    /// Forward-filled rows carry the source of the row they were filled from
+    #[serde(default)]
+    pub add_source_column: bool,
+    /// Fields whose values form the dedup key (generic data types only), e.g. `symbol` and
+    /// `update_id`; empty keeps the first record of each timestamp second
+    #[serde(default)]
+    pub dedup_key_fields: Vec<String>,
+    /// Estimated in-memory size (bytes) above which symbols are spilled to a temporary
+    /// on-disk store (mark-price only); `None` keeps the whole day in memory
+    #[serde(default)]
+    pub spill_memory_budget_bytes: Option<usize>,
+    /// Fail before forward-fill when symbols × seconds exceeds this many rows (mark-price only)
+    #[serde(default)]
+    pub forward_fill_max_rows: Option<u64>,
+    /// Fail before forward-fill when the filled day's estimated in-memory size exceeds this
+    /// many bytes (mark-price only); with spilling only one symbol's day counts
+    #[serde(default)]
+    pub forward_fill_max_memory_bytes: Option<u64>,
+    /// Payload field name -> full field name (mark-price only); `None` uses the
+    /// Binance mapping (`p` -> `mark_price`, `r` -> `funding_rate`, ...)
+    #[serde(default)]
+    pub field_renames: Option<HashMap<String, String>>,
+    /// Fields that must be present and numeric after renaming (mark-price only);
+    /// `None` requires `mark_price` and `funding_rate`
+    #[serde(default)]
+    pub required_fields: Option<Vec<String>>,
+    /// Fields that may be missing or null but must be numeric when present (mark-price only)
+    #[serde(default)]
+    pub optional_numeric_fields: Vec<String>,
+    /// Sanity ranges for numeric fields (mark-price only)
+    /// Values outside the range are logged and counted, but the record is kept
+    #[serde(default)]
+    pub field_ranges: HashMap<String, FieldRange>,
+}
+
+/// Inclusive numeric range used for sanity checks; either bound may be omitted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FieldRange {
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+impl FieldRange {
+    /// Whether a value lies within the bounds
+    pub fn contains(&self, value: f64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+/// Default data file suffixes (`.jsonl`, `.jsonl.gz`, `.jsonl.zst`, `.json`)
+pub fn default_file_extensions() -> Vec<String> {
+    [".jsonl", ".jsonl.gz", ".jsonl.zst", ".json"]
+        .iter()
+        .map(|ext| ext.to_string())
+        .collect()
+}
+
+impl DataSourceConfig {
+    /// Whether a file name ends with one of the configured extensions
+    pub fn matches_file_extension(&self, file_name: &str) -> bool {
+        self.file_extensions.iter().any(|ext| file_name.ends_with(ext.as_str()))
+    }
 }
 
 /// Output configuration
@@ -110,10 +424,74 @@ pub struct OutputConfig {
     /// If None, all data will be written to a single file
     /// If Some(n), data will be split into multiple files with batch size n
     pub batch_size: Option<usize>,
+    /// Maximum rows per parquet row group within a file
+    /// If None, DEFAULT_ROW_GROUP_SIZE (128k rows) is used
+    #[serde(default)]
+    pub row_group_size: Option<usize>,
+    /// Columns written first, in this order; remaining columns follow sorted by name
+    /// If None, all columns are sorted by name
+    #[serde(default)]
+    pub column_order: Option<Vec<String>>,
+    /// Per-column parquet encodings by name, e.g. `order_id: DELTA_BINARY_PACKED`
+    /// Unlisted columns use the writer default
+    #[serde(default, with = "crate::parquet_writer::column_encodings_serde")]
+    pub column_encodings: HashMap<String, Encoding>,
+    /// Columns that use dictionary encoding; all others have it disabled
+    /// If None, every column uses dictionary encoding
+    #[serde(default)]
+    pub dictionary_columns: Option<Vec<String>>,
     /// Whether to write to /tmp first and then copy to output directory
     /// This can improve performance by writing to faster storage first
     #[serde(default)]
     pub use_temp_dir: bool,
+    /// Whether to write a `<file>.sha256` sidecar next to each parquet file
+    #[serde(default)]
+    pub write_checksum: bool,
+    /// Whether to keep `<path>/<name>_latest.parquet` pointing at the newest written file
+    /// (a `<name>_latest.json` pointer file where symlinks are unsupported)
+    #[serde(default)]
+    pub write_latest_pointer: bool,
+    /// Also insert the rows into a Postgres/Timescale table
+    /// Requires the `postgres` feature
+    #[serde(default)]
+    pub postgres: Option<PostgresOutputConfig>,
+    /// One file set per symbol (default) or one combined file set with a `symbol` column
+    /// Only affects processors that split by symbol (mark-price)
+    #[serde(default)]
+    pub output_mode: OutputMode,
+    /// Maximum number of per-symbol parquet writes running at once
+    /// If None, every symbol is written concurrently
+    #[serde(default)]
+    pub max_write_concurrency: Option<usize>,
+}
+
+/// How per-symbol data is laid out in the output files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    /// `<name>-<symbol>` files, one set per symbol
+    #[default]
+    PerSymbol,
+    /// All symbols' rows in `<name>` files, schema unioned across symbols
+    Combined,
+}
+
+/// Postgres sink configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostgresOutputConfig {
+    /// libpq-style connection string, e.g. "host=localhost user=postgres dbname=trades"
+    pub connection_string: String,
+    /// Target table, optionally schema-qualified ("public.mark_price")
+    /// Created from the first batch's columns if it does not exist
+    pub table: String,
+    /// Rows per INSERT statement
+    #[serde(default = "default_postgres_batch_size")]
+    pub batch_size: usize,
+}
+
+/// Default rows per Postgres INSERT statement
+pub fn default_postgres_batch_size() -> usize {
+    1000
 }
 
 /// Main configuration structure
@@ -127,11 +505,17 @@ pub struct Config {
 
 impl Config {
     /// Load configuration from YAML file
+    /// `APP__`-prefixed environment variables override values from the file
+    /// (see `apply_env_overrides`)
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = fs::read_to_string(path.as_ref())
             .context(format!("Failed to read config file: {:?}", path.as_ref()))?;
         
-        let config: Config = serde_yaml::from_str(&content)
+        let mut value: Value = serde_yaml::from_str(&content)
+            .context("Failed to parse config YAML")?;
+        apply_env_overrides(&mut value, std::env::vars())?;
+
+        let config: Config = serde_yaml::from_value(value)
             .context("Failed to parse config YAML")?;
         
         Ok(config)
@@ -144,6 +528,60 @@ impl Config {
     }
 }
 
+/// Prefix of environment variables that override config values
+pub const ENV_OVERRIDE_PREFIX: &str = "APP__";
+
+/// Overlay `APP__`-prefixed variables onto a parsed YAML config (env takes precedence)
+///
+/// The rest of the name is split on `__` into lowercased keys, e.g. `APP__OUTPUT__PATH`
+/// sets `output.path`; numeric keys index into lists (`APP__DATA_SOURCES__0__PROXY`).
+/// Values are parsed as YAML (`true`, `42`, `[a, b]`), except that a value replacing an
+/// existing string stays a string. Missing mappings are created along the way.
+pub fn apply_env_overrides<I>(config: &mut Value, vars: I) -> Result<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut overrides: Vec<(String, String)> = vars
+        .into_iter()
+        .filter_map(|(name, value)| name.strip_prefix(ENV_OVERRIDE_PREFIX).map(|path| (path.to_string(), value)))
+        .collect();
+    overrides.sort();
+
+    for (path, raw) in overrides {
+        let keys: Vec<String> = path.split("__").map(|key| key.to_lowercase()).collect();
+        let target = env_override_target(config, &keys)
+            .context(format!("Invalid config override {}{}", ENV_OVERRIDE_PREFIX, path))?;
+        *target = if target.is_string() {
+            Value::String(raw)
+        } else {
+            serde_yaml::from_str(&raw).unwrap_or(Value::String(raw))
+        };
+    }
+    Ok(())
+}
+
+/// Walk (and create) the nested keys of an override
+fn env_override_target<'a>(mut node: &'a mut Value, keys: &[String]) -> Result<&'a mut Value> {
+    for key in keys {
+        if key.is_empty() {
+            bail!("empty key");
+        }
+        if node.is_null() {
+            *node = Value::Mapping(Mapping::new());
+        }
+        node = match node {
+            Value::Mapping(map) => map.entry(Value::String(key.clone())).or_insert(Value::Null),
+            Value::Sequence(items) => {
+                let len = items.len();
+                let index: usize = key.parse().context(format!("'{}' is not a list index", key))?;
+                items.get_mut(index).context(format!("index {} out of range (length {})", index, len))?
+            }
+            _ => bail!("'{}' is not a mapping", key),
+        };
+    }
+    Ok(node)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -174,6 +612,9 @@ output:
         assert_eq!(config.data_sources.len(), 1);
         assert_eq!(config.data_sources[0].ssh_servers.len(), 2);
         assert_eq!(config.output.batch_size, Some(5000));
+        assert!(config.data_sources[0].matches_file_extension("data.jsonl.gz"));
+        assert!(!config.data_sources[0].matches_file_extension("notes.txt"));
+        assert_eq!(config.data_sources[0].ssh_servers[1].private_key_passphrase, None);
     }
     
     #[test]
@@ -196,5 +637,63 @@ output:
         assert_eq!(config.data_sources.len(), 1);
         assert_eq!(config.output.batch_size, None);
     }
-}
 
+    #[test]
+    fn test_key_passphrase_is_separate_from_password() {
+        let yaml = r#"
+host: "192.168.1.101"
+username: "user2"
+password: "login-password"
+private_key_path: "/home/user/.ssh/id_ed25519"
+private_key_passphrase: "key-passphrase"
+input_base_path: "/data/mark-price/"
+"#;
+
+        let ssh: SshConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(ssh.password.as_deref(), Some("login-password"));
+        assert_eq!(ssh.private_key_passphrase.as_deref(), Some("key-passphrase"));
+        // The configured passphrase wins over the env var and never falls back to the password
+        assert_eq!(ssh.key_passphrase().as_deref(), Some("key-passphrase"));
+    }
+
+    #[test]
+    fn test_env_override_replaces_yaml_value() {
+        let yaml = r#"
+data_sources:
+  - data_type: "mark-price"
+    ssh_servers: []
+output:
+  path: "./output"
+  name: "123"
+"#;
+        let mut value: Value = serde_yaml::from_str(yaml).unwrap();
+        let vars = [
+            ("APP__OUTPUT__PATH", "/data/override"),
+            ("APP__OUTPUT__NAME", "456"),
+            ("APP__OUTPUT__BATCH_SIZE", "5000"),
+            ("APP__DATA_SOURCES__0__SYMBOLS_ALLOWLIST", "[BTCUSDT, ETHUSDT]"),
+            ("OUTPUT__PATH", "/ignored"),
+        ];
+        apply_env_overrides(&mut value, vars.iter().map(|(k, v)| (k.to_string(), v.to_string()))).unwrap();
+
+        let config: Config = serde_yaml::from_value(value).unwrap();
+        assert_eq!(config.output.path, "/data/override");
+        // Replacing a string keeps it a string even when it looks like a number
+        assert_eq!(config.output.name, "456");
+        assert_eq!(config.output.batch_size, Some(5000));
+        assert_eq!(config.data_sources[0].symbols_allowlist, vec!["BTCUSDT", "ETHUSDT"]);
+
+        let mut value: Value = serde_yaml::from_str(yaml).unwrap();
+        let vars = [("APP__DATA_SOURCES__3__DATA_TYPE".to_string(), "x".to_string())];
+        assert!(apply_env_overrides(&mut value, vars).is_err());
+    }
+
+    #[test]
+    fn test_glob_match_wildcards() {
+        assert!(glob_match("*.jsonl", "a.jsonl"));
+        assert!(glob_match("trades_????????.jsonl*", "trades_20251106.jsonl.gz"));
+        assert!(glob_match("*_2025*06.jsonl", "x_2025_11_06.jsonl"));
+        assert!(!glob_match("*.jsonl", "a.jsonl.gz"));
+        assert!(!glob_match("trades_*", "orders_1"));
+    }
+}
@@ -2,21 +2,46 @@
 //!
 //! This module handles merging JSONL data from multiple sources with deduplication
 //! and forward-fill logic to ensure every second has data.
+//! By default a second holds one row (the first seen); with `dedup_key_fields` rows are
+//! deduplicated on those fields instead, so a second can hold several rows.
 
 use anyhow::{Context, Result};
 use chrono::{NaiveDate, TimeZone, Utc};
 use serde_json::Value;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
+use std::io::BufRead;
+use std::ops::RangeInclusive;
 use tracing::{info, debug, warn};
 
-use crate::writer::DataRow;
+use crate::writer::{DataRow, DataRowExt};
+use crate::error::{ProcessorError, ProcessorResult};
+
+/// Field holding the name of the source a row came from, when enabled
+pub const SOURCE_COLUMN: &str = "_source";
 
 /// Data merger with forward-fill capability
 pub struct DataMerger {
-    /// Map of timestamp (seconds) to data row
-    data_by_second: BTreeMap<i64, DataRow>,
+    /// Map of timestamp (seconds) to the data rows of that second, in arrival order
+    data_by_second: BTreeMap<i64, Vec<DataRow>>,
+    /// Fields whose values form the dedup key; empty dedups on the timestamp second
+    dedup_key_fields: Vec<String>,
+    /// Dedup keys of the stored rows when `dedup_key_fields` is set
+    seen_keys: HashSet<String>,
     /// The date being processed
     date: NaiveDate,
+    /// Grace window (seconds) around the UTC day; `None` accepts any timestamp
+    date_range_grace_secs: Option<u64>,
+    /// Store the source name in `SOURCE_COLUMN` of every added row
+    add_source_column: bool,
+}
+
+/// Seconds accepted for a date: its UTC day widened by `grace_secs` on both sides
+pub fn utc_day_range(date: NaiveDate, grace_secs: u64) -> RangeInclusive<i64> {
+    let day_start = date.and_hms_opt(0, 0, 0)
+        .map(|naive| Utc.from_utc_datetime(&naive).timestamp())
+        .unwrap_or_default();
+    let grace = grace_secs as i64;
+    (day_start - grace)..=(day_start + 86_399 + grace)
 }
 
 impl DataMerger {
@@ -24,30 +49,95 @@ impl DataMerger {
     pub fn new(date: NaiveDate) -> Self {
         Self {
             data_by_second: BTreeMap::new(),
+            dedup_key_fields: Vec::new(),
+            seen_keys: HashSet::new(),
             date,
+            date_range_grace_secs: None,
+            add_source_column: false,
+        }
+    }
+
+    /// Deduplicate on the values of these fields (e.g. `symbol` + `update_id`) instead of
+    /// the timestamp second, keeping every distinct row of a second
+    pub fn with_dedup_key_fields(mut self, fields: Vec<String>) -> Self {
+        self.dedup_key_fields = fields;
+        self
+    }
+
+    /// Dedup key of a row: its values of `fields` joined by a unit separator
+    /// Missing fields contribute an empty value
+    pub fn dedup_key(row: &DataRow, fields: &[String]) -> String {
+        fields
+            .iter()
+            .map(|field| match row.get(field) {
+                None => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\u{1f}")
+    }
+
+    /// Whether a row is a duplicate of one already stored for `timestamp_sec`
+    fn is_duplicate(&self, timestamp_sec: i64, row: &DataRow) -> bool {
+        if self.dedup_key_fields.is_empty() {
+            self.data_by_second.contains_key(&timestamp_sec)
+        } else {
+            self.seen_keys.contains(&Self::dedup_key(row, &self.dedup_key_fields))
         }
     }
 
+    /// Record the source of each row in a `_source` field
+    /// Forward-filled rows keep the source of the row they were filled from
+    pub fn with_source_column(mut self) -> Self {
+        self.add_source_column = true;
+        self
+    }
+
+    /// Reject records outside the date's UTC day (plus `grace_secs` on both sides)
+    pub fn with_date_range_check(mut self, grace_secs: u64) -> Self {
+        self.date_range_grace_secs = Some(grace_secs);
+        self
+    }
+
     /// Extract timestamp in seconds from a data row
     /// The "E" field contains timestamp in milliseconds
     pub fn extract_timestamp_seconds(row: &DataRow) -> Option<i64> {
-        row.get("E")
-            .and_then(|v| match v {
-                Value::Number(n) => n.as_i64(),
-                Value::String(s) => s.parse::<i64>().ok(),
-                _ => None,
-            })
+        row.get_i64("E")
             .map(|millis| millis / 1000) // Convert milliseconds to seconds
     }
 
     /// Add data from a JSONL source (one of the SSH servers)
     /// Deduplicates by timestamp - if a second already has data, it's skipped
-    pub fn add_jsonl_data(&mut self, jsonl_content: &str, source_name: &str) -> Result<usize> {
+    pub fn add_jsonl_data(&mut self, jsonl_content: &str, source_name: &str) -> ProcessorResult<usize> {
+        self.add_jsonl_reader(jsonl_content.as_bytes(), source_name)
+    }
+
+    /// Add JSONL data parsed line by line from a reader, without holding the whole file as a string
+    /// Same deduplication as `add_jsonl_data`; read errors (including invalid UTF-8) abort with the line number
+    /// Failures are returned as `ProcessorError::Merge`
+    pub fn add_jsonl_reader<R: BufRead>(&mut self, reader: R, source_name: &str) -> ProcessorResult<usize> {
+        self.add_jsonl_reader_impl(reader, source_name)
+            .map_err(|source| ProcessorError::Merge { source_name: source_name.to_string(), source })
+    }
+
+    fn add_jsonl_reader_impl<R: BufRead>(&mut self, mut reader: R, source_name: &str) -> Result<usize> {
         let mut added_count = 0;
         let mut skipped_count = 0;
-
-        for (line_num, line) in jsonl_content.lines().enumerate() {
-            let line = line.trim();
+        let mut out_of_range_count = 0;
+        let allowed_range = self.date_range_grace_secs.map(|grace| utc_day_range(self.date, grace));
+
+        // Reuse one buffer for every line instead of allocating a String per line
+        let mut buffer = String::new();
+        for line_num in 0usize.. {
+            buffer.clear();
+            let read = reader
+                .read_line(&mut buffer)
+                .with_context(|| format!("Failed to read line {} from {}", line_num + 1, source_name))?;
+            if read == 0 {
+                break;
+            }
+            let line = buffer.trim();
             if line.is_empty() {
                 continue;
             }
@@ -61,33 +151,54 @@ impl DataMerger {
                 }
             };
 
-            // Convert to DataRow
-            if let Value::Object(obj) = value {
-                let mut row: DataRow = obj.into_iter().collect();
+            // A line holds either one object or an array of objects (batched updates)
+            let records = match value {
+                Value::Array(items) => items,
+                other => vec![other],
+            };
 
-                // Extract timestamp
-                if let Some(timestamp_sec) = Self::extract_timestamp_seconds(&row) {
-                    // Check if this second already has data
-                    if self.data_by_second.contains_key(&timestamp_sec) {
-                        skipped_count += 1;
-                        debug!("Skipping duplicate data for timestamp {} from {}", timestamp_sec, source_name);
-                    } else {
-                        // Normalize the E field to seconds precision (keep it in milliseconds but aligned to second)
-                        let normalized_millis = timestamp_sec * 1000;
-                        row.insert("E".to_string(), Value::Number(normalized_millis.into()));
+            // Convert each record to DataRow
+            for value in records {
+                if let Value::Object(obj) = value {
+                    let mut row: DataRow = obj.into_iter().collect();
+
+                    // Extract timestamp
+                    if let Some(timestamp_sec) = Self::extract_timestamp_seconds(&row) {
+                        // Reject timestamps outside the processing date so forward-fill can't spread them
+                        if allowed_range.as_ref().is_some_and(|range| !range.contains(&timestamp_sec)) {
+                            out_of_range_count += 1;
+                            debug!("Skipping out-of-range timestamp {} from {}", timestamp_sec, source_name);
+                        } else if self.is_duplicate(timestamp_sec, &row) {
+                            // This second (or dedup key) already has data
+                            skipped_count += 1;
+                            debug!("Skipping duplicate data for timestamp {} from {}", timestamp_sec, source_name);
+                        } else {
+                            // Keyed on the original values, before E is normalized
+                            if !self.dedup_key_fields.is_empty() {
+                                self.seen_keys.insert(Self::dedup_key(&row, &self.dedup_key_fields));
+                            }
+
+                            // Normalize the E field to seconds precision (keep it in milliseconds but aligned to second)
+                            let normalized_millis = timestamp_sec * 1000;
+                            row.insert("E".to_string(), Value::Number(normalized_millis.into()));
+                            if self.add_source_column {
+                                row.insert(SOURCE_COLUMN.to_string(), Value::String(source_name.to_string()));
+                            }
                         
-                        self.data_by_second.insert(timestamp_sec, row);
-                        added_count += 1;
+                            self.data_by_second.entry(timestamp_sec).or_default().push(row);
+                            added_count += 1;
+                        }
+                    } else {
+                        warn!("Line {} from {} missing 'E' timestamp field, skipping", line_num + 1, source_name);
                     }
                 } else {
-                    warn!("Line {} from {} missing 'E' timestamp field, skipping", line_num + 1, source_name);
+                    warn!("Line {} from {} has a record that is not a JSON object, skipping", line_num + 1, source_name);
                 }
-            } else {
-                warn!("Line {} from {} is not a JSON object, skipping", line_num + 1, source_name);
             }
         }
 
-        info!("Added {} records from {} ({} skipped as duplicates)", added_count, source_name, skipped_count);
+        info!("Added {} records from {} ({} skipped as duplicates, {} out of range)",
+              added_count, source_name, skipped_count, out_of_range_count);
         Ok(added_count)
     }
 
@@ -96,7 +207,14 @@ impl DataMerger {
     /// - If data starts after 00:00:00, backfill with the first data point
     /// - If data ends before 23:59:59, forward-fill with the last data point
     /// - For missing seconds in between, use the previous second's data
-    pub fn apply_forward_fill(&mut self) -> Result<()> {
+    ///
+    /// A second with several rows (see `with_dedup_key_fields`) is carried forward as a whole
+    /// Failures are returned as `ProcessorError::ForwardFill`
+    pub fn apply_forward_fill(&mut self) -> ProcessorResult<()> {
+        self.apply_forward_fill_impl().map_err(ProcessorError::ForwardFill)
+    }
+
+    fn apply_forward_fill_impl(&mut self) -> Result<()> {
         if self.data_by_second.is_empty() {
             warn!("No data to forward-fill");
             return Ok(());
@@ -157,11 +275,13 @@ impl DataMerger {
                 // Update the E field to reflect the current timestamp
                 let mut filled_data = filled_data;
                 let normalized_millis = timestamp * 1000;
-                filled_data.insert("E".to_string(), Value::Number(normalized_millis.into()));
-                
-                // Also update T field if it exists
-                if filled_data.contains_key("T") {
-                    filled_data.insert("T".to_string(), Value::Number(normalized_millis.into()));
+                for row in &mut filled_data {
+                    row.insert("E".to_string(), Value::Number(normalized_millis.into()));
+
+                    // Also update T field if it exists
+                    if row.contains_key("T") {
+                        row.insert("T".to_string(), Value::Number(normalized_millis.into()));
+                    }
                 }
                 
                 self.data_by_second.insert(timestamp, filled_data);
@@ -173,20 +293,26 @@ impl DataMerger {
         Ok(())
     }
 
-    /// Get all data rows sorted by timestamp
+    /// Get all data rows sorted by timestamp (rows of the same second in arrival order)
     pub fn get_sorted_rows(&self) -> Vec<DataRow> {
-        self.data_by_second.values().cloned().collect()
+        self.data_by_second.values().flatten().cloned().collect()
     }
 
-    /// Get the number of unique seconds with data
+    /// Get the number of stored rows (one per second unless `dedup_key_fields` is set)
     pub fn len(&self) -> usize {
-        self.data_by_second.len()
+        self.data_by_second.values().map(Vec::len).sum()
     }
 
     /// Check if the merger is empty
     pub fn is_empty(&self) -> bool {
         self.data_by_second.is_empty()
     }
+
+    /// Remove all collected data
+    pub fn clear(&mut self) {
+        self.data_by_second.clear();
+        self.seen_keys.clear();
+    }
 }
 
 #[cfg(test)]
@@ -223,9 +349,79 @@ mod tests {
         assert_eq!(merger.len(), 3);
 
         // Verify the first timestamp kept the original value
-        let first_row = merger.data_by_second.get(&1762411870).unwrap();
+        let first_row = &merger.data_by_second.get(&1762411870).unwrap()[0];
         let price = first_row.get("p").unwrap().as_f64().unwrap();
         assert_eq!(price, 103308.50797101);
     }
+
+    #[test]
+    fn test_dedup_key_fields_keep_rows_of_same_second() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let fields = vec!["s".to_string(), "u".to_string()];
+        let mut merger = DataMerger::new(date).with_dedup_key_fields(fields);
+
+        let jsonl1 = r#"{"E":1762411870001,"s":"BTCUSDT","u":1}
+{"E":1762411870500,"s":"BTCUSDT","u":2}"#;
+        // Same update from another source is a duplicate; a new update id in the same second is not
+        let jsonl2 = r#"{"E":1762411870001,"s":"BTCUSDT","u":1}
+{"E":1762411870900,"s":"BTCUSDT","u":3}"#;
+        assert_eq!(merger.add_jsonl_data(jsonl1, "source1").unwrap(), 2);
+        assert_eq!(merger.add_jsonl_data(jsonl2, "source2").unwrap(), 1);
+
+        let ids: Vec<i64> = merger.get_sorted_rows().iter().map(|row| row.get_i64("u").unwrap()).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(merger.data_by_second.len(), 1);
+
+        // Forward-fill is still per second and carries the whole second forward
+        merger.apply_forward_fill().unwrap();
+        assert_eq!(merger.data_by_second.len(), 86400);
+        assert_eq!(merger.len(), 86400 * 3);
+    }
+
+    #[test]
+    fn test_reader_matches_string_path() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let jsonl = "{\"E\":1762411870001,\"p\":1.0}\r\n[{\"E\":1762411871001,\"p\":2.0}]\n\n{\"E\":1762411870001,\"p\":9.0}\n";
+
+        let mut from_string = DataMerger::new(date);
+        let string_count = from_string.add_jsonl_data(jsonl, "source1").unwrap();
+
+        let mut from_reader = DataMerger::new(date);
+        let reader_count = from_reader.add_jsonl_reader(std::io::Cursor::new(jsonl.as_bytes()), "source1").unwrap();
+
+        assert_eq!(reader_count, 2);
+        assert_eq!(reader_count, string_count);
+        assert_eq!(from_reader.get_sorted_rows(), from_string.get_sorted_rows());
+    }
+
+    #[test]
+    fn test_source_column_records_origin() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut merger = DataMerger::new(date).with_source_column();
+        merger.add_jsonl_data("{\"E\":1762411870001,\"p\":1.0}", "ssh-1").unwrap();
+        merger.add_jsonl_data("{\"E\":1762411870001,\"p\":9.0}\n{\"E\":1762411872001,\"p\":2.0}", "http-1").unwrap();
+        merger.apply_forward_fill().unwrap();
+
+        let source_at = |second: i64| merger.data_by_second[&second][0].get_str(SOURCE_COLUMN).map(str::to_string);
+        assert_eq!(source_at(1762411870).as_deref(), Some("ssh-1"));
+        assert_eq!(source_at(1762411872).as_deref(), Some("http-1"));
+        // The filled second carries the source of the row it was filled from
+        assert_eq!(source_at(1762411871).as_deref(), Some("ssh-1"));
+
+        // Off by default, so the schema is unchanged
+        let mut plain = DataMerger::new(date);
+        plain.add_jsonl_data("{\"E\":1762411870001,\"p\":1.0}", "ssh-1").unwrap();
+        assert!(!plain.get_sorted_rows()[0].contains_key(SOURCE_COLUMN));
+    }
+
+    #[test]
+    fn test_reader_reports_invalid_utf8_line() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut merger = DataMerger::new(date);
+
+        let content: &[u8] = b"{\"E\":1762411870001}\n\xff\xfe\n";
+        let err = merger.add_jsonl_reader(std::io::Cursor::new(content), "source1").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
 }
 
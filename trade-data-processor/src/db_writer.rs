@@ -0,0 +1,317 @@
+//! Postgres/Timescale writer module
+//!
+//! Inserts data rows into a database table with multi-row parameterized INSERTs.
+//! Column types are inferred from the first batch the same way as for Parquet output
+//! (see `ParquetWriter::infer_type`), and the table is created if it does not exist.
+//! Only available with the `postgres` feature.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, NoTls};
+use tracing::{error, info, warn};
+
+use crate::config::PostgresOutputConfig;
+use crate::parquet_writer::{ColumnType, ParquetWriter};
+use crate::writer::{DataRow, DataRowExt, Writer};
+
+/// Maximum number of bind parameters in a single Postgres statement
+const MAX_BIND_PARAMS: usize = u16::MAX as usize;
+
+type SqlParam = Box<dyn ToSql + Sync + Send>;
+
+/// Writer that batch-inserts rows into a Postgres table
+pub struct DbWriter {
+    config: PostgresOutputConfig,
+    client: Client,
+    columns: Option<Vec<(String, ColumnType)>>,
+    buffer: Vec<DataRow>,
+    rows_written: u64,
+}
+
+impl DbWriter {
+    /// Connect to the database from the config
+    pub async fn connect(config: PostgresOutputConfig) -> Result<Self> {
+        let (client, connection) = tokio_postgres::connect(&config.connection_string, NoTls)
+            .await
+            .context(format!("Failed to connect to Postgres for table {}", config.table))?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                error!("Postgres connection error: {}", e);
+            }
+        });
+
+        Ok(Self {
+            config,
+            client,
+            columns: None,
+            buffer: Vec::new(),
+            rows_written: 0,
+        })
+    }
+
+    /// Number of rows inserted so far
+    pub fn rows_written(&self) -> u64 {
+        self.rows_written
+    }
+
+    /// Infer columns (sorted by name) from the first row of a batch
+    pub fn infer_columns(rows: &[DataRow]) -> Vec<(String, ColumnType)> {
+        let Some(first) = rows.first() else {
+            return Vec::new();
+        };
+        let mut columns: Vec<(String, ColumnType)> = first
+            .iter()
+            .map(|(name, value)| (name.clone(), ParquetWriter::infer_type(value)))
+            .collect();
+        columns.sort_by(|a, b| a.0.cmp(&b.0));
+        columns
+    }
+
+    /// SQL type used for a column type
+    /// UInt64 is stored as BIGINT; values above i64::MAX are rejected on insert
+    pub fn sql_type(column_type: &ColumnType) -> &'static str {
+        match column_type {
+            ColumnType::String => "TEXT",
+            ColumnType::Int64 | ColumnType::UInt64 => "BIGINT",
+            ColumnType::Float64 => "DOUBLE PRECISION",
+            ColumnType::Boolean => "BOOLEAN",
+        }
+    }
+
+    /// Quote a column name as one identifier, dots included ("mark.price")
+    pub fn quote_ident(name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+
+    /// Quote a table name, keeping a schema qualifier ("public.trades") as separate parts
+    pub fn quote_table(name: &str) -> String {
+        name.split('.').map(Self::quote_ident).collect::<Vec<_>>().join(".")
+    }
+
+    /// `CREATE TABLE IF NOT EXISTS` statement for the inferred columns
+    pub fn create_table_sql(table: &str, columns: &[(String, ColumnType)]) -> String {
+        let defs: Vec<String> = columns
+            .iter()
+            .map(|(name, col_type)| format!("{} {}", Self::quote_ident(name), Self::sql_type(col_type)))
+            .collect();
+        format!("CREATE TABLE IF NOT EXISTS {} ({})", Self::quote_table(table), defs.join(", "))
+    }
+
+    /// Multi-row INSERT statement with `$n` placeholders for `row_count` rows
+    pub fn insert_sql(table: &str, columns: &[(String, ColumnType)], row_count: usize) -> String {
+        let names: Vec<String> = columns.iter().map(|(name, _)| Self::quote_ident(name)).collect();
+        let rows: Vec<String> = (0..row_count)
+            .map(|row| {
+                let placeholders: Vec<String> = (0..columns.len())
+                    .map(|col| format!("${}", row * columns.len() + col + 1))
+                    .collect();
+                format!("({})", placeholders.join(", "))
+            })
+            .collect();
+        format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            Self::quote_table(table),
+            names.join(", "),
+            rows.join(", ")
+        )
+    }
+
+    /// Rows per INSERT, capped so the statement stays under the bind parameter limit
+    pub fn rows_per_statement(batch_size: usize, column_count: usize) -> usize {
+        batch_size.min(MAX_BIND_PARAMS / column_count.max(1)).max(1)
+    }
+
+    /// Convert a row value to a bind parameter for its column type
+    /// Missing fields, `null` and values that cannot be coerced become NULL
+    fn to_param(row: &DataRow, name: &str, column_type: &ColumnType) -> Result<SqlParam> {
+        let param: SqlParam = match column_type {
+            ColumnType::String => {
+                let text = match row.get(name) {
+                    None | Some(Value::Null) => None,
+                    Some(Value::String(s)) => Some(s.clone()),
+                    Some(other) => Some(other.to_string()),
+                };
+                Box::new(text)
+            }
+            ColumnType::Int64 => Box::new(row.get_i64(name)),
+            ColumnType::UInt64 => {
+                let value = row.get_u64(name)
+                    .map(i64::try_from)
+                    .transpose()
+                    .context(format!("Value of column {} does not fit in BIGINT", name))?;
+                Box::new(value)
+            }
+            ColumnType::Float64 => Box::new(row.get_f64(name)),
+            ColumnType::Boolean => Box::new(row.get_bool(name)),
+        };
+        Ok(param)
+    }
+
+    async fn ensure_table(&mut self, rows: &[DataRow]) -> Result<()> {
+        if self.columns.is_some() {
+            return Ok(());
+        }
+
+        let columns = Self::infer_columns(rows);
+        if columns.is_empty() {
+            anyhow::bail!("Cannot infer columns from empty data");
+        }
+
+        let sql = Self::create_table_sql(&self.config.table, &columns);
+        self.client.execute(sql.as_str(), &[]).await
+            .context(format!("Failed to create table {}", self.config.table))?;
+        info!("Using table {} with {} columns", self.config.table, columns.len());
+
+        self.columns = Some(columns);
+        Ok(())
+    }
+
+    async fn insert_buffer(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.buffer);
+        self.ensure_table(&rows).await?;
+        let columns = self.columns.clone().expect("columns inferred above");
+
+        let extra: Vec<&String> = rows[0].keys()
+            .filter(|key| !columns.iter().any(|(name, _)| name == *key))
+            .collect();
+        if !extra.is_empty() {
+            warn!("Ignoring fields not in table {}: {:?}", self.config.table, extra);
+        }
+
+        let chunk_size = Self::rows_per_statement(self.config.batch_size, columns.len());
+        for chunk in rows.chunks(chunk_size) {
+            let mut params: Vec<SqlParam> = Vec::with_capacity(chunk.len() * columns.len());
+            for row in chunk {
+                for (name, col_type) in &columns {
+                    params.push(Self::to_param(row, name, col_type)?);
+                }
+            }
+            let param_refs: Vec<&(dyn ToSql + Sync)> = params.iter()
+                .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+                .collect();
+
+            let sql = Self::insert_sql(&self.config.table, &columns, chunk.len());
+            let inserted = self.client.execute(sql.as_str(), &param_refs).await
+                .context(format!("Failed to insert {} rows into {}", chunk.len(), self.config.table))?;
+            self.rows_written += inserted;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Writer for DbWriter {
+    async fn write_rows(&mut self, rows: Vec<DataRow>) -> Result<()> {
+        self.buffer.extend(rows);
+        if self.buffer.len() >= self.config.batch_size {
+            self.insert_buffer().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush_buffer(&mut self) -> Result<()> {
+        self.insert_buffer().await
+    }
+}
+
+impl Drop for DbWriter {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            warn!("Buffer not empty on drop, {} records will be lost", self.buffer.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn row(timestamp: u64, symbol: &str, price: f64) -> DataRow {
+        let mut row = DataRow::new();
+        row.insert("timestamp".to_string(), json!(timestamp));
+        row.insert("symbol".to_string(), json!(symbol));
+        row.insert("mark_price".to_string(), json!(price));
+        row.insert("is_settled".to_string(), json!(false));
+        row
+    }
+
+    #[test]
+    fn test_infer_columns_matches_parquet_types() {
+        let columns = DbWriter::infer_columns(&[row(1, "BTCUSDT", 1.5)]);
+        assert_eq!(
+            columns,
+            vec![
+                ("is_settled".to_string(), ColumnType::Boolean),
+                ("mark_price".to_string(), ColumnType::Float64),
+                ("symbol".to_string(), ColumnType::String),
+                ("timestamp".to_string(), ColumnType::UInt64),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sql_statements() {
+        let columns = vec![
+            ("symbol".to_string(), ColumnType::String),
+            ("timestamp".to_string(), ColumnType::UInt64),
+        ];
+        assert_eq!(
+            DbWriter::create_table_sql("public.mark_price", &columns),
+            r#"CREATE TABLE IF NOT EXISTS "public"."mark_price" ("symbol" TEXT, "timestamp" BIGINT)"#
+        );
+        assert_eq!(
+            DbWriter::insert_sql("mark_price", &columns, 2),
+            r#"INSERT INTO "mark_price" ("symbol", "timestamp") VALUES ($1, $2), ($3, $4)"#
+        );
+        assert_eq!(DbWriter::quote_ident(r#"we"ird"#), r#""we""ird""#);
+        // Dots in column names are part of the name, not a qualifier
+        assert_eq!(DbWriter::quote_ident("mark.price"), r#""mark.price""#);
+    }
+
+    #[test]
+    fn test_rows_per_statement_respects_param_limit() {
+        assert_eq!(DbWriter::rows_per_statement(1000, 4), 1000);
+        assert_eq!(DbWriter::rows_per_statement(100_000, 10), 6553);
+        assert_eq!(DbWriter::rows_per_statement(0, 4), 1);
+    }
+
+    /// Needs a database: `TEST_DATABASE_URL=postgres://... cargo test --features postgres -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_insert_rows_roundtrip() {
+        let connection_string = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let table = format!("db_writer_test_{}", std::process::id());
+        let config = PostgresOutputConfig {
+            connection_string,
+            table: table.clone(),
+            batch_size: 2,
+        };
+
+        let mut writer = DbWriter::connect(config.clone()).await.unwrap();
+        writer.write_rows(vec![row(1, "BTCUSDT", 1.5), row(2, "BTCUSDT", 2.5)]).await.unwrap();
+        writer.write_rows(vec![row(3, "ETHUSDT", 3.5)]).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+        assert_eq!(writer.rows_written(), 3);
+
+        let check = DbWriter::connect(config).await.unwrap();
+        let rows = check.client
+            .query(format!("SELECT symbol, timestamp, mark_price FROM {} ORDER BY timestamp", table).as_str(), &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2].get::<_, String>(0), "ETHUSDT");
+        assert_eq!(rows[2].get::<_, i64>(1), 3);
+        assert_eq!(rows[0].get::<_, f64>(2), 1.5);
+
+        check.client.execute(format!("DROP TABLE {}", table).as_str(), &[]).await.unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+//! Download helpers shared by the HTTP and SSH clients
+//!
+//! This module collects the results of parallel download tasks, either in the order
+//! the files were requested (deterministic merges, golden-file tests) or in the order
+//! the downloads finished, and provides the bandwidth and request-rate limiters shared
+//! by those tasks.
+//! Per-file transfer sizes are logged as structured fields (`source`, `file`,
+//! `bytes_compressed`, `bytes_decompressed`, `ratio`) and summed into `TransferStats`.
+
+use anyhow::{Context, Result};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Order of the results returned by `download_files_parallel`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadOrder {
+    /// Same order as the requested file paths
+    #[default]
+    Input,
+    /// Order in which the downloads finished (no reordering)
+    Completion,
+}
+
+/// Wait for all download tasks and return their results in the requested order
+/// Fails on the first task that returns an error or panics
+pub async fn collect_download_results<T>(
+    handles: Vec<JoinHandle<Result<T>>>,
+    order: DownloadOrder,
+) -> Result<Vec<T>> {
+    // Tag each task with its index so results can be put back in input order
+    let mut pending: FuturesUnordered<_> = handles
+        .into_iter()
+        .enumerate()
+        .map(|(index, handle)| async move { (index, handle.await) })
+        .collect();
+
+    let mut results = Vec::with_capacity(pending.len());
+    while let Some((index, joined)) = pending.next().await {
+        match joined {
+            Ok(Ok(result)) => results.push((index, result)),
+            Ok(Err(e)) => {
+                error!("Parallel download FAILED: {}", e);
+                return Err(e);
+            }
+            Err(e) => return Err(anyhow::anyhow!("Download task failed: {}", e)),
+        }
+    }
+
+    if order == DownloadOrder::Input {
+        results.sort_by_key(|(index, _)| *index);
+    }
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// Token bucket refilled at `rate` tokens per second, holding up to one second of budget
+/// (at least one token) so short bursts pass without waiting
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    /// Available budget; negative when callers are waiting for it
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        let capacity = rate.max(1.0);
+        Self {
+            rate,
+            capacity,
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `amount` tokens and return how long the caller must wait before using them
+    fn reserve(&self, amount: f64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(state.last_refill).as_secs_f64() * self.rate;
+        state.tokens = (state.tokens + refill).min(self.capacity);
+        state.last_refill = now;
+
+        state.tokens -= amount;
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.rate)
+        }
+    }
+}
+
+/// Token-bucket limiter capping the aggregate throughput of all tasks sharing it
+/// The bucket holds up to one second of budget; callers that exceed it sleep instead of dropping data
+pub struct BandwidthLimiter {
+    bucket: TokenBucket,
+}
+
+impl BandwidthLimiter {
+    /// Create a limiter allowing `bytes_per_sec` bytes per second
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bucket: TokenBucket::new(bytes_per_sec.max(1) as f64),
+        }
+    }
+
+    /// Take `bytes` from the budget and return how long the caller must wait before using them
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        self.bucket.reserve(bytes as f64)
+    }
+
+    /// Wait until `bytes` fit in the budget
+    pub async fn acquire(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Blocking variant of `acquire` for use inside `spawn_blocking`
+    pub fn acquire_blocking(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Token-bucket limiter capping how many requests per second all tasks sharing it send
+/// to one server, independent of how many run concurrently
+pub struct RequestLimiter {
+    bucket: TokenBucket,
+}
+
+/// Smallest accepted request rate, so a zero or negative setting cannot stall forever
+const MIN_REQUESTS_PER_SEC: f64 = 0.001;
+
+impl RequestLimiter {
+    /// Create a limiter allowing `requests_per_sec` requests per second (may be fractional)
+    pub fn new(requests_per_sec: f64) -> Self {
+        Self {
+            bucket: TokenBucket::new(requests_per_sec.max(MIN_REQUESTS_PER_SEC)),
+        }
+    }
+
+    /// Wait until the next request may be sent
+    pub async fn acquire(&self) {
+        let wait = self.bucket.reserve(1.0);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Read a response body chunk by chunk, throttled by `limiter` if set
+pub async fn read_body(mut response: reqwest::Response, limiter: Option<&BandwidthLimiter>) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.context("Failed to read response body")? {
+        if let Some(limiter) = limiter {
+            limiter.acquire(chunk.len()).await;
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// Blocking read of a whole stream in chunks, throttled by `limiter` if set
+pub fn read_to_end_throttled<R: std::io::Read>(reader: &mut R, limiter: Option<&BandwidthLimiter>) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(data);
+        }
+        if let Some(limiter) = limiter {
+            limiter.acquire_blocking(n);
+        }
+        data.extend_from_slice(&buf[..n]);
+    }
+}
+
+/// Aggregate sizes of a set of downloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferStats {
+    /// Number of files downloaded
+    pub files: usize,
+    /// Bytes received over the wire
+    pub bytes_compressed: u64,
+    /// Bytes after decompression (equal to `bytes_compressed` for uncompressed files)
+    pub bytes_decompressed: u64,
+}
+
+impl TransferStats {
+    /// Add one downloaded file
+    pub fn record(&mut self, bytes_compressed: usize, bytes_decompressed: usize) {
+        self.files += 1;
+        self.bytes_compressed += bytes_compressed as u64;
+        self.bytes_decompressed += bytes_decompressed as u64;
+    }
+
+    /// Bytes not transferred thanks to compression
+    pub fn bytes_saved(&self) -> u64 {
+        self.bytes_decompressed.saturating_sub(self.bytes_compressed)
+    }
+
+    /// Transferred bytes as a fraction of the decompressed size (1.0 when nothing was compressed)
+    pub fn ratio(&self) -> f64 {
+        compression_ratio(self.bytes_compressed as usize, self.bytes_decompressed as usize)
+    }
+}
+
+/// Compressed size as a fraction of the decompressed size
+pub fn compression_ratio(bytes_compressed: usize, bytes_decompressed: usize) -> f64 {
+    if bytes_decompressed == 0 {
+        1.0
+    } else {
+        bytes_compressed as f64 / bytes_decompressed as f64
+    }
+}
+
+/// Log one downloaded file with its sizes as structured fields
+pub fn log_transfer(source: &str, file: &str, bytes_compressed: usize, bytes_decompressed: usize) {
+    info!(
+        source,
+        file,
+        bytes_compressed = bytes_compressed as u64,
+        bytes_decompressed = bytes_decompressed as u64,
+        ratio = compression_ratio(bytes_compressed, bytes_decompressed),
+        "Downloaded {}",
+        file
+    );
+}
+
+/// Human-readable throughput of `bytes` transferred in `elapsed`
+pub fn format_throughput(bytes: usize, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    format!("{:.1} KiB/s", bytes as f64 / 1024.0 / secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn staggered_tasks() -> Vec<JoinHandle<Result<u64>>> {
+        // The first task finishes last
+        [60u64, 30, 0]
+            .into_iter()
+            .map(|delay_ms| {
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                    Ok(delay_ms)
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_collect_in_input_order() {
+        let results = collect_download_results(staggered_tasks(), DownloadOrder::Input).await.unwrap();
+        assert_eq!(results, vec![60, 30, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_in_completion_order() {
+        let results = collect_download_results(staggered_tasks(), DownloadOrder::Completion).await.unwrap();
+        assert_eq!(results, vec![0, 30, 60]);
+    }
+
+    #[tokio::test]
+    async fn test_limiter_sleeps_when_budget_is_exhausted() {
+        let limiter = BandwidthLimiter::new(10_000);
+        let started = Instant::now();
+
+        // The first second of budget is available immediately, the next 20 KB take ~2 s
+        for _ in 0..30 {
+            limiter.acquire(1_000).await;
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(1_900), "elapsed {:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(4), "elapsed {:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_request_limiter_spaces_requests() {
+        let limiter = RequestLimiter::new(20.0);
+        let started = Instant::now();
+
+        // 20 requests fit in the initial burst, the next 10 take ~0.5 s
+        for _ in 0..30 {
+            limiter.acquire().await;
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(450), "elapsed {:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "elapsed {:?}", elapsed);
+    }
+}
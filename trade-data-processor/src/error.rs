@@ -0,0 +1,122 @@
+//! Error module
+//!
+//! This module defines the error type returned at the public API boundary
+//! (downloads, S3 access, merges, parquet writes), so callers can match on specific
+//! failure kinds such as a proxy being down. Internals keep using anyhow.
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors returned by the public download, S3, merge and write functions
+#[derive(Debug, Error)]
+pub enum ProcessorError {
+    /// The configured HTTP proxy could not be used
+    #[error("proxy {proxy} is unavailable: {reason}")]
+    ProxyUnavailable { proxy: String, reason: String },
+
+    /// A remote file could not be downloaded
+    #[error("failed to download {path}: {source:#}")]
+    Download {
+        path: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// The requested S3 object does not exist
+    #[error("S3 object not found: s3://{bucket}/{key}")]
+    S3NotFound { bucket: String, key: String },
+
+    /// Source data could not be read into a merger (e.g. a decompression error mid-file)
+    #[error("failed to merge data from {source_name}: {source:#}")]
+    Merge {
+        source_name: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// Forward-filling the merged data failed, e.g. over the configured `ForwardFillLimits`
+    #[error("forward-fill failed: {0:#}")]
+    ForwardFill(#[source] anyhow::Error),
+
+    /// A parquet file could not be written
+    #[error("failed to write {path:?}: {source:#}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// Any other failure
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Result type for the public API
+pub type ProcessorResult<T> = std::result::Result<T, ProcessorError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{HttpConfig, DEFAULT_HTTP_CONNECT_TIMEOUT_SECS, DEFAULT_HTTP_TIMEOUT_SECS};
+    use crate::http_client::HttpClient;
+
+    #[tokio::test]
+    async fn test_proxy_failure_is_proxy_unavailable() {
+        // Nothing listens on port 1, so the proxy connection is refused
+        let client = HttpClient::new(HttpConfig {
+            base_url: "http://127.0.0.1:9".to_string(),
+            input_base_path: "mark-price".to_string(),
+            proxy: Some("http://127.0.0.1:1".to_string()),
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        }).unwrap();
+
+        let err = client.check_proxy_availability().await.unwrap_err();
+        match err {
+            ProcessorError::ProxyUnavailable { proxy, .. } => assert_eq!(proxy, "http://127.0.0.1:1"),
+            other => panic!("expected ProxyUnavailable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_merge_failures_are_typed() {
+        use crate::mark_price_merger::{ForwardFillLimits, MarkPriceMerger};
+        use chrono::NaiveDate;
+
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut merger = MarkPriceMerger::new(date);
+        let err = merger.add_jsonl_reader(&b"\xff\xfe\n"[..], "ssh-1").unwrap_err();
+        match err {
+            ProcessorError::Merge { source_name, .. } => assert_eq!(source_name, "ssh-1"),
+            other => panic!("expected Merge, got {:?}", other),
+        }
+
+        let limits = ForwardFillLimits { max_estimated_rows: Some(1), max_memory_bytes: None };
+        let mut merger = MarkPriceMerger::new(date).with_forward_fill_limits(limits);
+        merger
+            .add_jsonl_data(r#"{"E":1762411870001,"s":"BTCUSDT","p":"1.5","r":"0.0001"}"#, "ssh-1")
+            .unwrap();
+        assert!(matches!(merger.apply_forward_fill(), Err(ProcessorError::ForwardFill(_))));
+    }
+
+    #[test]
+    fn test_converts_to_and_from_anyhow() {
+        let err: ProcessorError = anyhow::anyhow!("boom").into();
+        assert!(matches!(err, ProcessorError::Other(_)));
+
+        let err: anyhow::Error = ProcessorError::S3NotFound {
+            bucket: "bucket".to_string(),
+            key: "missing.parquet".to_string(),
+        }
+        .into();
+        assert!(matches!(err.downcast_ref::<ProcessorError>(), Some(ProcessorError::S3NotFound { .. })));
+    }
+}
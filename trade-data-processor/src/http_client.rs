@@ -5,18 +5,19 @@
 
 use anyhow::{Context, Result};
 use serde::Deserialize;
-use tracing::{info, debug, error};
+use tracing::{info, debug, error, warn};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use indicatif::{ProgressBar, ProgressStyle};
 
 
-use crate::config::HttpConfig;
+use crate::compression::decompress_auto_owned;
+use crate::config::{DownloadStrategy, HttpConfig, PathExistsCheck};
+use crate::download::{collect_download_results, format_throughput, log_transfer, read_body, BandwidthLimiter, DownloadOrder, RequestLimiter, TransferStats};
+use crate::error::{ProcessorError, ProcessorResult};
 use urlencoding::encode;
 
-/// Magic bytes for zstd format (0x28, 0xB5, 0x2F, 0xFD)
-const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
-
 /// File list response from the HTTP server
 #[derive(Debug, Deserialize)]
 struct FileInfo {
@@ -37,190 +38,275 @@ struct S3UrlResponse {
     md5: String,
 }
 
+/// Endpoint a file is downloaded from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DownloadPath {
+    S3,
+    Direct,
+}
+
+impl DownloadPath {
+    /// Endpoints tried, in order, for a strategy
+    fn order(strategy: DownloadStrategy) -> &'static [DownloadPath] {
+        match strategy {
+            DownloadStrategy::S3First => &[DownloadPath::S3, DownloadPath::Direct],
+            DownloadStrategy::DirectFirst => &[DownloadPath::Direct, DownloadPath::S3],
+            DownloadStrategy::S3Only => &[DownloadPath::S3],
+            DownloadStrategy::DirectOnly => &[DownloadPath::Direct],
+        }
+    }
+
+    /// Source label used in logs and transfer records
+    fn source(self) -> &'static str {
+        match self {
+            DownloadPath::S3 => "S3",
+            DownloadPath::Direct => "HTTP",
+        }
+    }
+}
+
+/// Outcome of a proxy check
+#[derive(Debug, Clone)]
+pub struct ProxyCheckResult {
+    /// URL that was probed
+    pub url: String,
+    /// Whether the URL answered with the expected status
+    pub reachable: bool,
+    /// Response status, if a response was received
+    pub status: Option<u16>,
+    /// Time until the response (or failure)
+    pub latency: Duration,
+    /// Why the check failed
+    pub error: Option<String>,
+}
+
 /// HTTP client wrapper for file operations
 pub struct HttpClient {
     config: HttpConfig,
-    client: reqwest::Client,
+    /// One reqwest client per configured proxy, shared with download tasks
+    proxies: Arc<ProxyPool>,
+    /// Shared by all downloads of this client when `max_bytes_per_sec` is set
+    limiter: Option<Arc<BandwidthLimiter>>,
 }
 
-impl HttpClient {
-    /// Create a new HTTP client
-    pub fn new(config: HttpConfig) -> Self {
+/// One reqwest client per proxy (or a single direct client) with the active one pinned
+///
+/// Requests go through the active proxy. When the connection itself fails the next proxy
+/// becomes active and the request is retried; HTTP error statuses never fail over.
+/// Every request first waits for the pool's request-rate limit, if one is configured.
+struct ProxyPool {
+    clients: Vec<(Option<String>, reqwest::Client)>,
+    active: AtomicUsize,
+    rate_limiter: Option<RequestLimiter>,
+}
+
+impl ProxyPool {
+    fn new(config: &HttpConfig) -> Result<Self> {
+        let proxies = config.proxy_urls();
+        let clients = if proxies.is_empty() {
+            vec![(None, Self::build_client(config, None)?)]
+        } else {
+            proxies
+                .into_iter()
+                .map(|proxy_url| {
+                    let client = Self::build_client(config, Some(&proxy_url))?;
+                    Ok((Some(proxy_url), client))
+                })
+                .collect::<Result<_>>()?
+        };
+        let rate_limiter = config.requests_per_sec.map(|rate| {
+            info!("HTTP requests to {} limited to {} per second", config.base_url, rate);
+            RequestLimiter::new(rate)
+        });
+        Ok(Self {
+            clients,
+            active: AtomicUsize::new(0),
+            rate_limiter,
+        })
+    }
+
+    fn build_client(config: &HttpConfig, proxy_url: Option<&str>) -> Result<reqwest::Client> {
         let mut builder = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(6000)); // 10 minute timeout for large files
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs));
         
         // Configure proxy if provided
-        if let Some(proxy_url) = &config.proxy {
-            match reqwest::Proxy::all(proxy_url) {
-                Ok(proxy) => {
-                    builder = builder.proxy(proxy);
-                    info!("HTTP client configured with proxy: {}", proxy_url);
-                }
-                Err(e) => {
-                    error!("Failed to configure proxy {}: {}", proxy_url, e);
-                    panic!("Failed to configure proxy: {}", e);
-                }
-            }
+        if let Some(proxy_url) = proxy_url {
+            let proxy = reqwest::Proxy::all(proxy_url)
+                .context(format!("Failed to configure proxy {}", proxy_url))?;
+            builder = builder.proxy(proxy);
+            info!("HTTP client configured with proxy: {}", proxy_url);
         }
-        
-        let client = builder
-            .build()
-            .expect("Failed to create HTTP client");
-        
-        Self {
-            config,
-            client,
+
+        if let Some(ref ca_cert_path) = config.ca_cert_path {
+            let cert = Self::load_ca_certificate(ca_cert_path)
+                .context(format!("Failed to load CA certificate {}", ca_cert_path))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if config.accept_invalid_certs {
+            warn!("TLS certificate verification disabled for {}, connections are not secure", config.base_url);
+            builder = builder.danger_accept_invalid_certs(true);
         }
+        
+        builder.build().context("Failed to create HTTP client")
     }
 
-    /// Check if proxy is available and working
-    /// 
-    /// This method tries to access a test URL through the proxy to verify connectivity.
-    /// If the proxy is not available or not working, this will return an error.
-    /// The test URL used is: http://clients3.google.com/generate_204
-    pub async fn check_proxy_availability(&self) -> Result<()> {
-        // Only check if proxy is configured
-        if self.config.proxy.is_none() {
-            return Ok(());
+    fn load_ca_certificate(path: &str) -> Result<reqwest::Certificate> {
+        let pem = std::fs::read(path).context(format!("Failed to read {}", path))?;
+        reqwest::Certificate::from_pem(&pem).context(format!("Failed to parse PEM certificate {}", path))
+    }
+
+    fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    fn proxy_url(&self, index: usize) -> Option<&str> {
+        self.clients[index].0.as_deref()
+    }
+
+    /// Send a request built by `request`, failing over to the next proxy on connection errors
+    async fn send<F>(&self, request: F) -> reqwest::Result<reqwest::Response>
+    where
+        F: Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    {
+        if let Some(ref rate_limiter) = self.rate_limiter {
+            rate_limiter.acquire().await;
         }
 
-        let proxy_url = self.config.proxy.as_ref().unwrap();
-        let check_url = "http://clients3.google.com/generate_204";
-        
-        info!("Checking proxy availability: {} via {}", check_url, proxy_url);
-        
-        // Create a request with 5 second timeout for the check
-        let timeout = std::time::Duration::from_secs(5);
-        let response = self.client
-            .get(check_url)
-            .timeout(timeout)
-            .send()
-            .await;
-        
-        match response {
-            Ok(resp) => {
-                if resp.status().is_success() || resp.status().as_u16() == 204 {
-                    info!("✅ Proxy check passed: proxy is available and working");
-                    Ok(())
-                } else {
-                    let err_msg = format!(
-                        "❌ Proxy check failed: proxy returned status {} when accessing {}",
-                        resp.status(),
-                        check_url
+        let mut index = self.active();
+        let mut attempts = 1;
+        loop {
+            match request(&self.clients[index].1).send().await {
+                Err(e) if e.is_connect() && attempts < self.clients.len() => {
+                    let next = (index + 1) % self.clients.len();
+                    warn!(
+                        "Connection through proxy {} failed: {}, failing over to {}",
+                        self.proxy_url(index).unwrap_or("none"),
+                        e,
+                        self.proxy_url(next).unwrap_or("none")
                     );
-                    error!("{}", err_msg);
-                    anyhow::bail!(err_msg);
+                    // Concurrent requests may have failed over already; keep theirs
+                    let _ = self.active.compare_exchange(index, next, Ordering::SeqCst, Ordering::SeqCst);
+                    index = next;
+                    attempts += 1;
                 }
-            }
-            Err(e) => {
-                let err_msg = format!(
-                    "❌ Proxy check failed: cannot access {} through proxy {}: {}",
-                    check_url,
-                    proxy_url,
-                    e
-                );
-                error!("{}", err_msg);
-                anyhow::bail!(err_msg);
+                result => return result,
             }
         }
     }
+}
 
-    /// Decompress data based on encoding
-    /// 
-    /// Handles zstd, gzip, and other compressions
-    fn decompress_if_needed(data: Vec<u8>) -> Result<Vec<u8>> {
-        if data.is_empty() {
-            return Ok(data);
-        }
-        
-        // Check for zstd
-        if data.len() >= 4 && &data[0..4] == ZSTD_MAGIC {
-            debug!("Detected zstd compressed data, decompressing...");
-            let decompressed = zstd::decode_all(&data[..])
-                .context("Failed to decompress zstd data")?;
-            debug!("Decompressed {} bytes to {} bytes", data.len(), decompressed.len());
-            return Ok(decompressed);
-        }
-        
-        // Check for gzip (magic bytes: 0x1f 0x8b)
-        if data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b {
-            debug!("Detected gzip compressed data, decompressing...");
-            let mut decoder = flate2::read::GzDecoder::new(&data[..]);
-            let mut decompressed = Vec::new();
-            use std::io::Read;
-            decoder.read_to_end(&mut decompressed)
-                .context("Failed to decompress gzip data")?;
-            debug!("Decompressed {} bytes to {} bytes", data.len(), decompressed.len());
-            return Ok(decompressed);
-        }
+impl HttpClient {
+    /// Create a new HTTP client
+    /// Fails if a proxy URL is invalid or `ca_cert_path` cannot be read or parsed
+    pub fn new(config: HttpConfig) -> Result<Self> {
+        let proxies = Arc::new(ProxyPool::new(&config)?);
+
+        let limiter = config.max_bytes_per_sec.map(|rate| {
+            info!("HTTP downloads limited to {} bytes/s", rate);
+            Arc::new(BandwidthLimiter::new(rate))
+        });
         
-        debug!("Data is not compressed, using as-is (first 8 bytes: {:02X?})", 
-            &data.iter().take(8).collect::<Vec<_>>());
-        Ok(data)
+        Ok(Self {
+            config,
+            proxies,
+            limiter,
+        })
+    }
+
+    /// Proxy requests currently go through, if any
+    pub fn active_proxy(&self) -> Option<&str> {
+        self.proxies.proxy_url(self.proxies.active())
     }
 
-    /// Get S3 presigned URL for a file
+    /// Request the configured check URL through the active proxy (if any)
     /// 
-    /// Makes a request to: {base_url}/get_s3_url?file={path}
-    /// Returns S3 presigned URL and metadata
-    async fn get_s3_url(&self, remote_path: &str) -> Result<S3UrlResponse> {
-        let encoded_path = encode(remote_path);
-        let url = format!("{}/get_s3_url?file={}", self.config.base_url.trim_end_matches('/'), encoded_path);
-        
-        debug!("Getting S3 URL from HTTP endpoint: {}", url);
-        
-        let response = self.client.get(&url)
+    /// Uses `proxy_check.url`, or `base_url` when `proxy_check.use_base_url` is set.
+    /// Never fails: an unreachable target is reported in the result.
+    pub async fn probe_proxy(&self) -> ProxyCheckResult {
+        self.probe_proxy_at(self.proxies.active()).await
+    }
+
+    async fn probe_proxy_at(&self, index: usize) -> ProxyCheckResult {
+        let check = &self.config.proxy_check;
+        let url = if check.use_base_url {
+            self.config.base_url.clone()
+        } else {
+            check.url.clone()
+        };
+
+        let started = Instant::now();
+        let response = self.proxies.clients[index].1
+            .get(&url)
+            .timeout(Duration::from_secs(check.timeout_secs))
             .send()
-            .await
-            .context(format!("Failed to send HTTP request to {}", url))?;
-        
-        if !response.status().is_success() {
-            anyhow::bail!("HTTP request failed with status: {} for {}", response.status(), url);
+            .await;
+        let latency = started.elapsed();
+
+        match response {
+            Ok(resp) => {
+                let status = resp.status();
+                let reachable = match check.expected_status {
+                    Some(expected) => status.as_u16() == expected,
+                    None => status.is_success(),
+                };
+                ProxyCheckResult {
+                    url,
+                    reachable,
+                    status: Some(status.as_u16()),
+                    latency,
+                    error: (!reachable).then(|| format!("unexpected status {}", status)),
+                }
+            }
+            Err(e) => ProxyCheckResult {
+                url,
+                reachable: false,
+                status: None,
+                latency,
+                error: Some(e.to_string()),
+            },
         }
-        
-        let s3_response: S3UrlResponse = response.json()
-            .await
-            .context("Failed to parse S3 URL response")?;
-        
-        debug!("Got S3 URL for {}: compressed={}", remote_path, s3_response.compressed);
-        Ok(s3_response)
     }
 
-    /// Download file from S3 using presigned URL
+    /// Check if a proxy is available and working
     /// 
-    /// Downloads file from S3 and decompresses if needed
-    async fn download_from_s3(&self, s3_url: &str, is_compressed: bool) -> Result<Vec<u8>> {
-        debug!("Downloading from S3: {}", s3_url);
-        
-        let response = self.client.get(s3_url)
-            .send()
-            .await
-            .context(format!("Failed to download from S3: {}", s3_url))?;
-        
-        let status = response.status();
-        if !status.is_success() {
-            anyhow::bail!("S3 download failed with status: {} for {}", status, s3_url);
+    /// Probes the configured check URL through each proxy in order (see `probe_proxy`) and
+    /// pins the first one that works. If none works, this returns
+    /// `ProcessorError::ProxyUnavailable` naming all of them.
+    /// Does nothing when no proxy is configured.
+    pub async fn check_proxy_availability(&self) -> ProcessorResult<()> {
+        // Only check if proxy is configured
+        if self.active_proxy().is_none() {
+            return Ok(());
         }
-        
-        let data = response.bytes()
-            .await
-            .context("Failed to read S3 response bytes")?
-            .to_vec();
-        
-        debug!("Downloaded {} bytes from S3", data.len());
-        
-        // Decompress if needed
-        if is_compressed {
-            let original_size = data.len();
-            let decompressed = Self::decompress_if_needed(data)?;
-            if decompressed.len() != original_size {
-                info!("Downloaded and decompressed {} bytes (compressed: {} bytes) from S3", 
-                    decompressed.len(), original_size);
+
+        let mut failed = Vec::new();
+        let mut reasons = Vec::new();
+        for index in 0..self.proxies.clients.len() {
+            let proxy_url = self.proxies.proxy_url(index).unwrap_or_default();
+            info!("Checking proxy availability via {}", proxy_url);
+            let result = self.probe_proxy_at(index).await;
+
+            if result.reachable {
+                info!("✅ Proxy check passed: {} reachable via {} in {:?}", result.url, proxy_url, result.latency);
+                self.proxies.active.store(index, Ordering::SeqCst);
+                return Ok(());
             }
-            Ok(decompressed)
-        } else {
-            Ok(data)
+
+            let reason = format!(
+                "cannot access {}: {}",
+                result.url,
+                result.error.as_deref().unwrap_or("unknown error")
+            );
+            error!("❌ Proxy check failed through proxy {}: {}", proxy_url, reason);
+            failed.push(proxy_url.to_string());
+            reasons.push(reason);
         }
+
+        Err(ProcessorError::ProxyUnavailable {
+            proxy: failed.join(", "),
+            reason: reasons.join("; "),
+        })
     }
 
     /// List files in a remote directory
@@ -233,8 +319,7 @@ impl HttpClient {
         
         debug!("Listing files from HTTP endpoint: {}", url);
         
-        let response = self.client.get(&url)
-            .send()
+        let response = self.proxies.send(|client| client.get(&url))
             .await
             .context(format!("Failed to send HTTP request to {}", url))?;
         
@@ -259,84 +344,56 @@ impl HttpClient {
 
     /// Download a file from the remote server
     /// 
-    /// First tries to get S3 presigned URL via {base_url}/get_s3_url?file={path}
-    /// and download from S3. If that fails, falls back to direct download via
-    /// {base_url}/download?file={path}
+    /// Tries the endpoints in the order of `download_strategy`: an S3 presigned URL from
+    /// {base_url}/get_s3_url?file={path}, or a direct download via {base_url}/download?file={path}.
+    /// With `S3First` (default) or `DirectFirst` a failed attempt falls back to the other one.
     /// Returns file contents as bytes (automatically decompresses if zstd)
-    pub async fn download_file(&self, remote_path: &str) -> Result<Vec<u8>> {
-        // Try S3 download first (default behavior)
-        match self.get_s3_url(remote_path).await {
-            Ok(s3_response) => {
-                info!("Using S3 download for {}", remote_path);
-                match self.download_from_s3(&s3_response.url, s3_response.compressed).await {
-                    Ok(data) => {
-                        info!("Successfully downloaded {} bytes from S3 for {}", data.len(), remote_path);
-                        return Ok(data);
-                    }
-                    Err(e) => {
-                        // S3 download failed, log and fall back to direct download
-                        info!("S3 download failed for {}: {}, falling back to direct download", remote_path, e);
-                    }
-                }
-            }
-            Err(e) => {
-                // Could not get S3 URL, log and fall back to direct download
-                info!("Could not get S3 URL for {}: {}, falling back to direct download", remote_path, e);
-            }
-        }
-        
-        // Fallback: direct download from HTTP server
-        let encoded_path = encode(remote_path);
-        let url = format!("{}/download?file={}", self.config.base_url.trim_end_matches('/'), encoded_path);
-        
-        debug!("Downloading file from HTTP endpoint: {}", url);
-        debug!("Original path: {}, Encoded path: {}", remote_path, encoded_path);
-        
-        let response = self.client.get(&url)
-            .send()
-            .await
-            .context(format!("Failed to send HTTP request to {}", url))?;
-        
-        let status = response.status();
-        if !status.is_success() {
-            let headers = format!("{:?}", response.headers());
-            anyhow::bail!("HTTP request failed with status: {} for {}\nHeaders: {}", status, url, headers);
-        }
-        
-        // Log response headers for debugging
-        debug!("Response headers: {:?}", response.headers());
-        
-        let data = response.bytes()
+    pub async fn download_file(&self, remote_path: &str) -> ProcessorResult<Vec<u8>> {
+        self.download_file_impl(remote_path)
             .await
-            .context(format!("Failed to read response bytes from {}", url))?
-            .to_vec();
-        
-        debug!("Received {} bytes from {}", data.len(), remote_path);
-        
-        let original_size = data.len();
-        let data = Self::decompress_if_needed(data)?;
-        
-        if data.len() != original_size {
-            info!("Downloaded and decompressed {} bytes (compressed: {} bytes) from {}", data.len(), original_size, remote_path);
-        } else {
-            info!("Downloaded {} bytes from {}", data.len(), remote_path);
-        }
-        
+            .map_err(|source| ProcessorError::Download { path: remote_path.to_string(), source })
+    }
+
+    async fn download_file_impl(&self, remote_path: &str) -> Result<Vec<u8>> {
+        let (data, original_size, source) = Self::download_with_strategy(
+            &self.proxies,
+            &self.config.base_url,
+            remote_path,
+            self.limiter.as_deref(),
+            self.config.download_strategy,
+        )
+        .await?;
+
+        info!("Successfully downloaded {} bytes via {} for {}", data.len(), source, remote_path);
+        log_transfer(source, remote_path, original_size, data.len());
         Ok(data)
     }
 
     /// Download multiple files concurrently
     /// 
     /// Downloads multiple files in parallel.
-    /// First tries S3 download, falls back to direct HTTP download if S3 fails.
+    /// Each file is fetched like `download_file`, following `download_strategy`.
     /// Automatically decompresses zstd compressed data.
-    /// Returns a vector of (file_path, file_contents) tuples.
-    pub async fn download_files_parallel(&self, file_paths: Vec<String>) -> Result<Vec<(String, Vec<u8>)>> {
+    /// Returns (file_path, file_contents) tuples in the order of `file_paths`, with the
+    /// transfer sizes summed over all files.
+    pub async fn download_files_parallel(&self, file_paths: Vec<String>) -> Result<(Vec<(String, Vec<u8>)>, TransferStats)> {
+        self.download_files_parallel_ordered(file_paths, DownloadOrder::Input).await
+    }
+
+    /// Download multiple files concurrently, returning results in the given order
+    /// `DownloadOrder::Completion` skips the reordering step
+    pub async fn download_files_parallel_ordered(
+        &self,
+        file_paths: Vec<String>,
+        order: DownloadOrder,
+    ) -> Result<(Vec<(String, Vec<u8>)>, TransferStats)> {
         let total_files = file_paths.len();
         let completed = Arc::new(AtomicUsize::new(0));
+        let transferred = Arc::new(AtomicUsize::new(0));
+        let started = std::time::Instant::now();
         let mut handles = vec![];
         
-        info!("Starting parallel download of {} files (S3 with fallback)", total_files);
+        info!("Starting parallel download of {} files ({:?})", total_files, self.config.download_strategy);
         
         // Create progress bar
         let progress_bar = ProgressBar::new(total_files as u64);
@@ -349,188 +406,160 @@ impl HttpClient {
         let progress_bar = Arc::new(progress_bar);
         
         for file_path in file_paths {
-            let client = self.client.clone();
+            let proxies = Arc::clone(&self.proxies);
             let base_url = self.config.base_url.clone();
             let completed = Arc::clone(&completed);
+            let transferred = Arc::clone(&transferred);
+            let limiter = self.limiter.clone();
             let progress_bar = Arc::clone(&progress_bar);
+            let strategy = self.config.download_strategy;
             
             let handle = tokio::spawn(async move {
-                let file_name = file_path.split('/').last().unwrap_or(&file_path);
-                let download_source: &str;
-                let data: Vec<u8>;
-                let mut original_size: usize;
-                
-                // Try S3 download first
-                let encoded_path = encode(&file_path);
-                let s3_url_endpoint = format!("{}/get_s3_url?file={}", base_url.trim_end_matches('/'), encoded_path);
-                
-                match client.get(&s3_url_endpoint).send().await {
-                    Ok(resp) if resp.status().is_success() => {
-                        // Got S3 URL, try to download from S3
-                        match resp.json::<S3UrlResponse>().await {
-                            Ok(s3_response) => {
-                                info!("Using S3 download for {}", file_name);
-                                match client.get(&s3_response.url).send().await {
-                                    Ok(s3_resp) if s3_resp.status().is_success() => {
-                                        match s3_resp.bytes().await {
-                                            Ok(bytes) => {
-                                                let bytes_vec = bytes.to_vec();
-                                                original_size = bytes_vec.len();
-                                                
-                                                // Decompress if needed
-                                                match s3_response.compressed {
-                                                    true => {
-                                                        match HttpClient::decompress_if_needed(bytes_vec) {
-                                                            Ok(d) => {
-                                                                data = d;
-                                                                download_source = "S3";
-                                                            }
-                                                            Err(e) => {
-                                                                let err_msg = format!("S3 decompression failed for {}: {}, falling back to direct download", file_name, e);
-                                                                info!("{}", err_msg);
-                                                                // Fall back to direct download
-                                                                match Self::download_direct(&client, &base_url, &file_path).await {
-                                                                    Ok((d, os)) => {
-                                                                        data = d;
-                                                                        original_size = os;
-                                                                        download_source = "HTTP";
-                                                                    }
-                                                                    Err(e) => return Err(e),
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    false => {
-                                                        data = bytes_vec;
-                                                        download_source = "S3";
-                                                    }
-                                                }
-                                            }
-                                            Err(e) => {
-                                                info!("Failed to read S3 response for {}: {}, falling back to direct download", file_name, e);
-                                                match Self::download_direct(&client, &base_url, &file_path).await {
-                                                    Ok((d, os)) => {
-                                                        data = d;
-                                                        original_size = os;
-                                                        download_source = "HTTP";
-                                                    }
-                                                    Err(e) => return Err(e),
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Ok(s3_resp) => {
-                                        info!("S3 download failed with status {} for {}, falling back to direct download", s3_resp.status(), file_name);
-                                        match Self::download_direct(&client, &base_url, &file_path).await {
-                                            Ok((d, os)) => {
-                                                data = d;
-                                                original_size = os;
-                                                download_source = "HTTP";
-                                            }
-                                            Err(e) => return Err(e),
-                                        }
-                                    }
-                                    Err(e) => {
-                                        info!("S3 request failed for {}: {}, falling back to direct download", file_name, e);
-                                        match Self::download_direct(&client, &base_url, &file_path).await {
-                                            Ok((d, os)) => {
-                                                data = d;
-                                                original_size = os;
-                                                download_source = "HTTP";
-                                            }
-                                            Err(e) => return Err(e),
-                                        }
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                info!("Failed to parse S3 URL response for {}: {}, falling back to direct download", file_name, e);
-                                match Self::download_direct(&client, &base_url, &file_path).await {
-                                    Ok((d, os)) => {
-                                        data = d;
-                                        original_size = os;
-                                        download_source = "HTTP";
-                                    }
-                                    Err(e) => return Err(e),
-                                }
-                            }
-                        }
-                    }
-                    _ => {
-                        // Could not get S3 URL, fall back to direct download
-                        info!("Could not get S3 URL for {}, falling back to direct download", file_name);
-                        match Self::download_direct(&client, &base_url, &file_path).await {
-                            Ok((d, os)) => {
-                                data = d;
-                                original_size = os;
-                                download_source = "HTTP";
-                            }
-                            Err(e) => return Err(e),
-                        }
-                    }
-                }
+                let file_name = file_path.rsplit('/').next().unwrap_or(&file_path);
+                let (data, original_size, download_source) =
+                    Self::download_with_strategy(&proxies, &base_url, &file_path, limiter.as_deref(), strategy).await?;
                 
                 let count = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                transferred.fetch_add(original_size, Ordering::Relaxed);
                 
                 if data.len() != original_size {
                     progress_bar.set_message(format!("{} ({} -> {} bytes via {})", file_name, original_size, data.len(), download_source));
-                    info!("Downloaded and decompressed {} bytes (compressed: {} bytes) from {} via {} [{}/{} files completed]", 
-                        data.len(),
-                        original_size,
-                        file_name,
-                        download_source,
-                        count,
-                        total_files
-                    );
                 } else {
                     progress_bar.set_message(format!("{} ({} bytes via {})", file_name, data.len(), download_source));
-                    info!("Downloaded {} bytes from {} via {} [{}/{} files completed]", 
-                        data.len(), 
-                        file_name,
-                        download_source,
-                        count,
-                        total_files
-                    );
                 }
+                log_transfer(download_source, file_name, original_size, data.len());
+                debug!("[{}/{} files completed]", count, total_files);
                 
                 progress_bar.inc(1);
                 
-                Ok::<(String, Vec<u8>), anyhow::Error>((file_path, data))
+                Ok::<(String, Vec<u8>, usize), anyhow::Error>((file_path, data, original_size))
             });
             
             handles.push(handle);
         }
         
-        let mut results = vec![];
-        for handle in handles {
-            match handle.await {
-                Ok(Ok(result)) => results.push(result),
-                Ok(Err(e)) => {
-                    error!("Parallel download FAILED: {}", e);
-                    progress_bar.finish_with_message("Download failed!");
-                    return Err(e);
-                }
+        let results = match collect_download_results(handles, order).await {
+            Ok(results) => results,
+            Err(e) => {
+                progress_bar.finish_with_message("Download failed!");
+                return Err(e);
+            }
+        };
+        
+        progress_bar.finish_with_message(format!("Completed: {} files", results.len()));
+        let mut stats = TransferStats::default();
+        let results: Vec<(String, Vec<u8>)> = results
+            .into_iter()
+            .map(|(file_path, data, original_size)| {
+                stats.record(original_size, data.len());
+                (file_path, data)
+            })
+            .collect();
+        let transferred = transferred.load(Ordering::Relaxed);
+        info!(
+            source = self.config.base_url.as_str(),
+            files = stats.files as u64,
+            bytes_compressed = stats.bytes_compressed,
+            bytes_decompressed = stats.bytes_decompressed,
+            bytes_saved = stats.bytes_saved(),
+            ratio = stats.ratio(),
+            "Completed parallel download: {} files succeeded ({} bytes in {:.1?}, {})",
+            results.len(),
+            transferred,
+            started.elapsed(),
+            format_throughput(transferred, started.elapsed())
+        );
+        Ok((results, stats))
+    }
+
+    /// Download a file from the endpoints of `strategy` in order, falling back on failure
+    /// Returns the data, the size received over the wire and the source label for logging
+    async fn download_with_strategy(
+        proxies: &ProxyPool,
+        base_url: &str,
+        file_path: &str,
+        limiter: Option<&BandwidthLimiter>,
+        strategy: DownloadStrategy,
+    ) -> Result<(Vec<u8>, usize, &'static str)> {
+        let paths = DownloadPath::order(strategy);
+        let mut last_error = None;
+        for (attempt, path) in paths.iter().enumerate() {
+            let result = match path {
+                DownloadPath::S3 => Self::download_s3(proxies, base_url, file_path, limiter).await,
+                DownloadPath::Direct => Self::download_direct(proxies, base_url, file_path, limiter).await,
+            };
+            match result {
+                Ok((data, original_size)) => return Ok((data, original_size, path.source())),
                 Err(e) => {
-                    progress_bar.finish_with_message("Download failed!");
-                    return Err(anyhow::anyhow!("Download task failed: {}", e));
+                    if let Some(next) = paths.get(attempt + 1) {
+                        info!("{} download failed for {}: {:#}, falling back to {} download", path.source(), file_path, e, next.source());
+                    }
+                    last_error = Some(e);
                 }
             }
         }
-        
-        progress_bar.finish_with_message(format!("Completed: {} files", results.len()));
-        info!("Completed parallel download: {} files succeeded", results.len());
-        Ok(results)
+        Err(last_error.expect("every strategy has at least one download path"))
+    }
+
+    /// Download a file through an S3 presigned URL
+    ///
+    /// Asks {base_url}/get_s3_url?file={path} for the URL, then downloads from S3 and
+    /// decompresses if the object is marked compressed
+    async fn download_s3(
+        proxies: &ProxyPool,
+        base_url: &str,
+        file_path: &str,
+        limiter: Option<&BandwidthLimiter>,
+    ) -> Result<(Vec<u8>, usize)> {
+        let url = format!("{}/get_s3_url?file={}", base_url.trim_end_matches('/'), encode(file_path));
+        debug!("Getting S3 URL from HTTP endpoint: {}", url);
+
+        let response = proxies.send(|client| client.get(&url))
+            .await
+            .context(format!("Failed to send HTTP request to {}", url))?;
+        if !response.status().is_success() {
+            anyhow::bail!("HTTP request failed with status: {} for {}", response.status(), url);
+        }
+        let s3_response: S3UrlResponse = response.json()
+            .await
+            .context("Failed to parse S3 URL response")?;
+
+        info!("Using S3 download for {}", file_path);
+        let response = proxies.send(|client| client.get(&s3_response.url))
+            .await
+            .context(format!("Failed to download from S3: {}", s3_response.url))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("S3 download failed with status: {} for {}", status, s3_response.url);
+        }
+
+        let data = read_body(response, limiter)
+            .await
+            .context("Failed to read S3 response bytes")?;
+        debug!("Downloaded {} bytes from S3", data.len());
+
+        let original_size = data.len();
+        let data = if s3_response.compressed {
+            decompress_auto_owned(data).context("S3 decompression failed")?
+        } else {
+            data
+        };
+        Ok((data, original_size))
     }
 
-    /// Helper function for direct HTTP download (used as fallback)
-    async fn download_direct(client: &reqwest::Client, base_url: &str, file_path: &str) -> Result<(Vec<u8>, usize)> {
+    /// Helper function for direct HTTP download
+    async fn download_direct(
+        proxies: &ProxyPool,
+        base_url: &str,
+        file_path: &str,
+        limiter: Option<&BandwidthLimiter>,
+    ) -> Result<(Vec<u8>, usize)> {
         let encoded_path = encode(file_path);
         let url = format!("{}/download?file={}", base_url.trim_end_matches('/'), encoded_path);
         
         debug!("Direct download from HTTP endpoint: {}", url);
         
-        let response = client.get(&url)
-            .send()
+        let response = proxies.send(|client| client.get(&url))
             .await
             .context(format!("Failed to send HTTP request to {}", url))?;
         
@@ -542,38 +571,55 @@ impl HttpClient {
         
         debug!("Response headers: {:?}", response.headers());
         
-        let data = response.bytes()
+        let data = read_body(response, limiter)
             .await
-            .context(format!("Failed to read response bytes from {}", url))?
-            .to_vec();
+            .context(format!("Failed to read response bytes from {}", url))?;
         
         debug!("Received {} bytes from {}", data.len(), file_path);
         
         let original_size = data.len();
-        let data = HttpClient::decompress_if_needed(data)?;
+        let data = decompress_auto_owned(data)?;
         
         Ok((data, original_size))
     }
 
     /// Check if a remote path exists
     /// 
-    /// Tries to list the directory to check if it exists
+    /// Tries to list the directory to check if it exists. With the default `exists_check`
+    /// the body must also be a JSON array, so a proxy answering 200 with an error object
+    /// is not taken as an existing directory.
     pub async fn path_exists(&self, remote_path: &str) -> Result<bool> {
         let encoded_path = encode(remote_path);
         let url = format!("{}/ls?dir={}", self.config.base_url.trim_end_matches('/'), encoded_path);
         
         debug!("Checking if path exists: {}", url);
         
-        let response = self.client.get(&url)
-            .send()
+        let response = self.proxies.send(|client| client.get(&url))
             .await
             .context(format!("Failed to send HTTP request to {}", url))?;
         
-        let exists = response.status().is_success();
+        let exists = if !response.status().is_success() {
+            false
+        } else {
+            match self.config.exists_check {
+                PathExistsCheck::Status => true,
+                PathExistsCheck::JsonArray => {
+                    let body = response.bytes()
+                        .await
+                        .context(format!("Failed to read response from {}", url))?;
+                    Self::is_listing(&body)
+                }
+            }
+        };
         debug!("Path {} exists: {}", remote_path, exists);
         Ok(exists)
     }
 
+    /// Whether a `/ls` body is a directory listing (a JSON array, possibly empty)
+    fn is_listing(body: &[u8]) -> bool {
+        matches!(serde_json::from_slice::<serde_json::Value>(body), Ok(serde_json::Value::Array(_)))
+    }
+
     /// Get the host identifier for logging
     pub fn host_identifier(&self) -> String {
         self.config.base_url.clone()
@@ -583,6 +629,7 @@ impl HttpClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{DEFAULT_HTTP_CONNECT_TIMEOUT_SECS, DEFAULT_HTTP_TIMEOUT_SECS};
 
     #[test]
     fn test_http_client_creation() {
@@ -590,9 +637,19 @@ mod tests {
             base_url: "http://localhost:8080".to_string(),
             input_base_path: "/data/mark-price".to_string(),
             proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
         };
         
-        let client = HttpClient::new(config);
+        let client = HttpClient::new(config).unwrap();
         assert_eq!(client.host_identifier(), "http://localhost:8080");
     }
     
@@ -602,10 +659,562 @@ mod tests {
             base_url: "http://localhost:8080".to_string(),
             input_base_path: "/data/mark-price".to_string(),
             proxy: Some("http://proxy.example.com:8080".to_string()),
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
         };
         
-        let client = HttpClient::new(config);
+        let client = HttpClient::new(config).unwrap();
         assert_eq!(client.host_identifier(), "http://localhost:8080");
     }
-}
+    
+    /// Serve `/download?file=<name>` after a per-file delay; S3 URLs are never available
+    async fn mock_file_server(delays_ms: &'static [(&'static str, u64)]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let n = stream.read(&mut buf).await.unwrap();
+                    let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+
+                    let response = match path.split_once("/download?file=") {
+                        Some((_, file)) => {
+                            let delay = delays_ms.iter().find(|(name, _)| *name == file).map_or(0, |(_, d)| *d);
+                            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", file.len(), file)
+                        }
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                    };
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_download_files_parallel_keeps_input_order() {
+        // The first file is the slowest, so completion order is the reverse of input order
+        let base_url = mock_file_server(&[("a", 300), ("b", 150), ("c", 0)]).await;
+        let client = HttpClient::new(HttpConfig {
+            base_url,
+            input_base_path: "mark-price".to_string(),
+            proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        }).unwrap();
+        let file_paths = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let (results, _) = client.download_files_parallel(file_paths.clone()).await.unwrap();
+        let names: Vec<&str> = results.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+        assert_eq!(results[0].1, b"a".to_vec());
+
+        let (results, _) = client.download_files_parallel_ordered(file_paths, DownloadOrder::Completion).await.unwrap();
+        let names: Vec<&str> = results.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(names, vec!["c", "b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_stats_sum_per_file_sizes() {
+        use std::io::Write;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // "z" is served gzip-compressed, "a" as plain text
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&[b'x'; 1000]).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let served = gzipped.clone();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let body: &[u8] = if request.contains("/download?file=z ") {
+                    &served
+                } else if request.contains("/download?file=a ") {
+                    b"abc"
+                } else {
+                    b""
+                };
+                let status = if body.is_empty() { "404 Not Found" } else { "200 OK" };
+                let header = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, body.len());
+                stream.write_all(header.as_bytes()).await.unwrap();
+                stream.write_all(body).await.unwrap();
+            }
+        });
 
+        let client = HttpClient::new(HttpConfig {
+            base_url,
+            input_base_path: "mark-price".to_string(),
+            proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        }).unwrap();
+        let (results, stats) = client.download_files_parallel(vec!["a".to_string(), "z".to_string()]).await.unwrap();
+        assert_eq!(results[1].1, vec![b'x'; 1000]);
+
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.bytes_compressed, 3 + gzipped.len() as u64);
+        assert_eq!(stats.bytes_decompressed, 3 + 1000);
+        assert_eq!(stats.bytes_saved(), 1000 - gzipped.len() as u64);
+        assert!(stats.ratio() < 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_check_uses_configured_url() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Mock proxy recording the request line and answering 204
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                tx.send(request.lines().next().unwrap_or("").to_string()).unwrap();
+                stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").await.unwrap();
+            }
+        });
+
+        let mut config = HttpConfig {
+            base_url: "http://data-server.test:8080".to_string(),
+            input_base_path: "mark-price".to_string(),
+            proxy: Some(proxy),
+            proxies: Vec::new(),
+            proxy_check: crate::config::ProxyCheckConfig {
+                url: "http://probe.test/health".to_string(),
+                expected_status: Some(204),
+                ..Default::default()
+            },
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        };
+
+        let result = HttpClient::new(config.clone()).unwrap().probe_proxy().await;
+        assert!(result.reachable, "{:?}", result);
+        assert_eq!(result.status, Some(204));
+        assert_eq!(rx.recv().await.unwrap(), "GET http://probe.test/health HTTP/1.1");
+
+        // Probe the data server itself instead of the external URL
+        config.proxy_check.use_base_url = true;
+        HttpClient::new(config.clone()).unwrap().check_proxy_availability().await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), "GET http://data-server.test:8080/ HTTP/1.1");
+
+        // A different status than expected is reported as unreachable
+        config.proxy_check.expected_status = Some(200);
+        let result = HttpClient::new(config).unwrap().probe_proxy().await;
+        assert!(!result.reachable);
+        assert_eq!(result.status, Some(204));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_failover_selects_live_proxy() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Live proxy answering every request with an empty listing
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]").await.unwrap();
+            }
+        });
+        // Nothing listens on port 1, so connecting to this proxy is refused
+        let dead = "http://127.0.0.1:1".to_string();
+
+        let config = HttpConfig {
+            base_url: "http://data-server.test:8080".to_string(),
+            input_base_path: "mark-price".to_string(),
+            proxy: Some(dead.clone()),
+            proxies: vec![dead.clone(), live.clone()],
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        };
+        assert_eq!(config.proxy_urls(), vec![dead.clone(), live.clone()]);
+
+        // The startup check pins the first working proxy
+        let client = HttpClient::new(config.clone()).unwrap();
+        client.check_proxy_availability().await.unwrap();
+        assert_eq!(client.active_proxy(), Some(live.as_str()));
+
+        // Without the check, a refused connection fails over to the next proxy
+        let client = HttpClient::new(config).unwrap();
+        assert_eq!(client.active_proxy(), Some(dead.as_str()));
+        assert!(client.path_exists("mark-price").await.unwrap());
+        assert_eq!(client.active_proxy(), Some(live.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_path_exists_rejects_error_body_with_ok_status() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Like some proxies, answer 200 with an error object for a missing directory
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let body = if request.contains("/ls?dir=empty ") {
+                    "[]"
+                } else {
+                    r#"{"error": "no such directory"}"#
+                };
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let mut config = HttpConfig {
+            base_url,
+            input_base_path: "mark-price".to_string(),
+            proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        };
+        let client = HttpClient::new(config.clone()).unwrap();
+        assert!(!client.path_exists("missing").await.unwrap());
+        assert!(client.path_exists("empty").await.unwrap());
+
+        // Servers whose status code is authoritative can opt out of body parsing
+        config.exists_check = PathExistsCheck::Status;
+        assert!(HttpClient::new(config).unwrap().path_exists("missing").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes_per_sec_throttles_download() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Serve a 30 KB payload for every download
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let body = vec![b'x'; 30_000];
+                let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+                stream.write_all(header.as_bytes()).await.unwrap();
+                stream.write_all(&body).await.unwrap();
+            }
+        });
+
+        let client = HttpClient::new(HttpConfig {
+            base_url: base_url.clone(),
+            input_base_path: "mark-price".to_string(),
+            proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: Some(10_000),
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        }).unwrap();
+
+        // One second of budget is available up front, the remaining 20 KB take ~2 s
+        let started = std::time::Instant::now();
+        let (data, _) = HttpClient::download_direct(&client.proxies, &base_url, "a", client.limiter.as_deref())
+            .await
+            .unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(data.len(), 30_000);
+        assert!(elapsed >= std::time::Duration::from_millis(1_900), "elapsed {:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_from_config() {
+        let base_url = mock_file_server(&[("slow", 5_000)]).await;
+        let client = HttpClient::new(HttpConfig {
+            base_url: base_url.clone(),
+            input_base_path: "mark-price".to_string(),
+            proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: 1,
+            connect_timeout_secs: 1,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        }).unwrap();
+
+        let started = std::time::Instant::now();
+        let result = HttpClient::download_direct(&client.proxies, &base_url, "slow", None).await;
+        let elapsed = started.elapsed();
+        assert!(result.is_err());
+        assert!(elapsed < std::time::Duration::from_secs(3), "elapsed {:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_private_ca_is_trusted_only_when_configured() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Server certificate for 127.0.0.1 signed by a private test CA (see testdata/tls)
+        let testdata = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tls");
+        let identity = native_tls::Identity::from_pkcs8(
+            &std::fs::read(testdata.join("server.pem")).unwrap(),
+            &std::fs::read(testdata.join("server-key.pem")).unwrap(),
+        )
+        .unwrap();
+        let acceptor = tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    // Handshakes rejected by the client end here
+                    let Ok(mut stream) = acceptor.accept(stream).await else { return };
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                        .await;
+                });
+            }
+        });
+        let base_url = format!("https://127.0.0.1:{}", addr.port());
+        let client_with = |ca_cert_path: Option<String>, accept_invalid_certs: bool| {
+            HttpClient::new(HttpConfig {
+                base_url: base_url.clone(),
+                input_base_path: "mark-price".to_string(),
+                proxy: None,
+                proxies: Vec::new(),
+                proxy_check: Default::default(),
+                max_bytes_per_sec: None,
+                requests_per_sec: None,
+                exists_check: Default::default(),
+                timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+                connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+                download_strategy: DownloadStrategy::DirectOnly,
+                ca_cert_path,
+                accept_invalid_certs,
+            })
+        };
+
+        let client = client_with(None, false).unwrap();
+        assert!(HttpClient::download_direct(&client.proxies, &base_url, "a", None).await.is_err());
+
+        let client = client_with(Some(testdata.join("ca.pem").to_string_lossy().to_string()), false).unwrap();
+        let (data, _) = HttpClient::download_direct(&client.proxies, &base_url, "a", None).await.unwrap();
+        assert_eq!(data, b"ok".to_vec());
+
+        let client = client_with(None, true).unwrap();
+        let (data, _) = HttpClient::download_direct(&client.proxies, &base_url, "a", None).await.unwrap();
+        assert_eq!(data, b"ok".to_vec());
+
+        // A missing or unparsable CA is a construction error, not a panic
+        let error = client_with(Some("/nonexistent/ca.pem".to_string()), false).err().unwrap();
+        assert!(format!("{:#}", error).contains("/nonexistent/ca.pem"), "{:#}", error);
+        let error = client_with(Some(testdata.join("server-key.pem").to_string_lossy().to_string()), false).err().unwrap();
+        assert!(format!("{:#}", error).contains("Failed to load CA certificate"), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_requests_per_sec_throttles_parallel_downloads() {
+        let base_url = mock_file_server(&[]).await;
+        let client = HttpClient::new(HttpConfig {
+            base_url,
+            input_base_path: "mark-price".to_string(),
+            proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: Some(10.0),
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: Default::default(),
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        }).unwrap();
+        let file_paths: Vec<String> = (0..10).map(|i| format!("f{}", i)).collect();
+
+        // Each download asks for an S3 URL, then falls back to a direct download: 20 requests,
+        // of which the first 10 fit in the initial burst and the rest take ~1 s
+        let started = std::time::Instant::now();
+        let (results, _) = client.download_files_parallel(file_paths).await.unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(results.len(), 10);
+        assert!(elapsed >= std::time::Duration::from_millis(900), "elapsed {:?}", elapsed);
+    }
+
+    /// Serve `/get_s3_url` (pointing at `/s3/<file>` on the same server, or 404 when S3 is
+    /// unavailable), `/s3/<file>` and `/download`, recording the path of every request
+    async fn mock_s3_server(s3_available: bool) -> (String, Arc<std::sync::Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        let s3_base = base_url.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let target = request.split_whitespace().nth(1).unwrap_or("").to_string();
+                let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+                let file = query.strip_prefix("file=").unwrap_or("");
+                recorded.lock().unwrap().push(path.to_string());
+
+                let (status, body) = match path {
+                    "/get_s3_url" if s3_available => (
+                        "200 OK",
+                        format!(r#"{{"url":"{}/s3/{}","uploaded":true,"compressed":false,"md5":""}}"#, s3_base, file),
+                    ),
+                    "/download" => ("200 OK", format!("direct:{}", file)),
+                    _ if path.starts_with("/s3/") => ("200 OK", format!("s3:{}", &path[4..])),
+                    _ => ("404 Not Found", String::new()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base_url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_download_strategy_controls_endpoints() {
+        let cases = [
+            (DownloadStrategy::S3First, true, Some("s3:a"), vec!["/get_s3_url", "/s3/a"]),
+            (DownloadStrategy::S3First, false, Some("direct:a"), vec!["/get_s3_url", "/download"]),
+            (DownloadStrategy::DirectFirst, true, Some("direct:a"), vec!["/download"]),
+            (DownloadStrategy::S3Only, true, Some("s3:a"), vec!["/get_s3_url", "/s3/a"]),
+            (DownloadStrategy::S3Only, false, None, vec!["/get_s3_url"]),
+            (DownloadStrategy::DirectOnly, true, Some("direct:a"), vec!["/download"]),
+        ];
+
+        for (strategy, s3_available, expected_body, expected_requests) in cases {
+            let (base_url, requests) = mock_s3_server(s3_available).await;
+            let client = HttpClient::new(HttpConfig {
+                base_url,
+                input_base_path: "mark-price".to_string(),
+                proxy: None,
+                proxies: Vec::new(),
+                proxy_check: Default::default(),
+                max_bytes_per_sec: None,
+                requests_per_sec: None,
+                exists_check: Default::default(),
+                timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+                connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+                download_strategy: strategy,
+                ca_cert_path: None,
+                accept_invalid_certs: false,
+            }).unwrap();
+
+            let result = client.download_file("a").await;
+            assert_eq!(
+                result.ok().map(|data| String::from_utf8(data).unwrap()),
+                expected_body.map(str::to_string),
+                "{:?} with S3 available: {}",
+                strategy,
+                s3_available
+            );
+            assert_eq!(*requests.lock().unwrap(), expected_requests, "{:?}", strategy);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parallel_download_follows_strategy() {
+        let (base_url, requests) = mock_s3_server(true).await;
+        let client = HttpClient::new(HttpConfig {
+            base_url,
+            input_base_path: "mark-price".to_string(),
+            proxy: None,
+            proxies: Vec::new(),
+            proxy_check: Default::default(),
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            exists_check: Default::default(),
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: DEFAULT_HTTP_CONNECT_TIMEOUT_SECS,
+            download_strategy: DownloadStrategy::DirectOnly,
+            ca_cert_path: None,
+            accept_invalid_certs: false,
+        }).unwrap();
+
+        let (results, _) = client.download_files_parallel(vec!["a".to_string(), "b".to_string()]).await.unwrap();
+        assert_eq!(results[1].1, b"direct:b".to_vec());
+        assert_eq!(*requests.lock().unwrap(), vec!["/download", "/download"]);
+    }
+}
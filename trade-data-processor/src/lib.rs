@@ -7,23 +7,41 @@
 //! Supports multiple data types:
 //! - Generic data: DataMerger
 //! - Mark-price: MarkPriceMerger
+//!
+//! Data types are routed to mergers through the ProcessorRegistry.
+//!
+//! With the `postgres` feature, rows can also be written to a Postgres table (DbWriter).
 
+pub mod compression;
 pub mod config;
+pub mod error;
 pub mod ssh_client;
 pub mod http_client;
+pub mod download;
 pub mod data_merger;
 pub mod mark_price_merger;
+pub mod processor;
 pub mod writer;
 pub mod parquet_writer;
 pub mod s3_helper;
+#[cfg(feature = "postgres")]
+pub mod db_writer;
+pub mod logging;
 
 // Re-export commonly used types
-pub use config::{Config, DataSourceConfig, OutputConfig, SshConfig, HttpConfig, LocalFileConfig, S3Config};
+pub use error::{ProcessorError, ProcessorResult};
+pub use config::{Config, DataSourceConfig, OutputConfig, OutputMode, PostgresOutputConfig, FieldRange, SshConfig, HttpConfig, PathExistsCheck, ProxyCheckConfig, LocalFileConfig, S3Config};
 pub use ssh_client::SshClient;
-pub use http_client::HttpClient;
+pub use http_client::{HttpClient, ProxyCheckResult};
+pub use compression::{decompress_auto, decompress_auto_owned, Compression};
+pub use download::{DownloadOrder, TransferStats};
 pub use data_merger::DataMerger;
-pub use mark_price_merger::MarkPriceMerger;
-pub use writer::{Writer, DataRow};
-pub use parquet_writer::{ParquetWriter, ParquetWriterConfig};
-pub use s3_helper::{S3Helper, S3Provider, SyncDirection, SyncOptions, SyncStats, SyncDatabase, FileMetadata};
+pub use mark_price_merger::{ForwardFillLimits, MarkPriceMerger, RowValidation, SpillStats};
+pub use processor::{DataProcessor, ProcessorConstructor, ProcessorRegistry};
+pub use writer::{Writer, DataRow, DataRowExt};
+pub use parquet_writer::{parse_column_encoding, verify_parquet_dir, ParquetWriter, ParquetWriterConfig, VerifyReport, WrittenFileInfo, DEFAULT_ROW_GROUP_SIZE};
+#[cfg(feature = "postgres")]
+pub use db_writer::DbWriter;
+pub use logging::{init_tracing, LogFormat};
+pub use s3_helper::{BatchUploadOptions, BatchUploadResult, DuplicateKeyPolicy, S3Helper, S3Provider, SyncDirection, SyncOptions, SyncPlan, SyncStats, SyncDatabase, FileMetadata, TransferTiming};
 
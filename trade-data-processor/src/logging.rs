@@ -0,0 +1,50 @@
+//! Logging module
+//!
+//! This module sets up the tracing subscriber. Log levels come from `RUST_LOG`
+//! (default "info") and the output is either human-readable text or JSON lines
+//! for log aggregators such as Loki or ELK.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use std::io::IsTerminal;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+/// Log output format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable text, colored only when stdout is a terminal
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Install the global tracing subscriber
+/// Fails if a global subscriber is already set
+pub fn init_tracing(format: LogFormat) -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry().with(filter);
+
+    match format {
+        LogFormat::Text => registry
+            .with(fmt::layer().with_ansi(std::io::stdout().is_terminal()))
+            .try_init()?,
+        LogFormat::Json => registry.with(fmt::layer().json()).try_init()?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_json_tracing() {
+        init_tracing(LogFormat::Json).unwrap();
+        tracing::info!(check = "json", "logging initialized");
+
+        // Only one global subscriber can be installed
+        assert!(init_tracing(LogFormat::Text).is_err());
+    }
+}
@@ -6,14 +6,27 @@
 use anyhow::{Context, Result, bail};
 use chrono::NaiveDate;
 use clap::Parser;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
 use tracing::{info, error, warn};
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{self, StreamExt};
 use trade_data_processor::{
-    Config, DataMerger, MarkPriceMerger, ParquetWriter, ParquetWriterConfig, SshClient, HttpClient, Writer, DataRow,
+    Config, DataProcessor, LogFormat, DEFAULT_ROW_GROUP_SIZE, ParquetWriter, ParquetWriterConfig, ProcessorRegistry, SshClient, HttpClient, Writer, DataRow,
+    SshConfig, HttpConfig, LocalFileConfig, OutputMode, SpillStats,
 };
+use trade_data_processor::compression::{decompress_auto_owned, decompress_reader, Compression};
+use trade_data_processor::config::glob_match;
 use std::fs;
+use std::io::{BufRead, Read};
 use std::path::Path;
 
 /// CLI arguments
@@ -26,34 +39,80 @@ struct Args {
     config: PathBuf,
 
     /// Date to process (format: YYYY-MM-DD)
-    #[arg(short, long, value_name = "DATE")]
-    date: String,
+    #[arg(
+        short,
+        long,
+        value_name = "DATE",
+        required_unless_present = "start_date",
+        conflicts_with_all = ["start_date", "end_date"]
+    )]
+    date: Option<String>,
+
+    /// First date of an inclusive range to process (format: YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", requires = "end_date")]
+    start_date: Option<String>,
+
+    /// Last date of an inclusive range to process (format: YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", requires = "start_date")]
+    end_date: Option<String>,
 
     /// Data type to process (e.g., "mark-price")
     #[arg(short = 't', long, value_name = "TYPE")]
     data_type: String,
+
+    /// Skip dates (and mark-price symbols) whose output parquet files already exist
+    #[arg(long)]
+    skip_existing: bool,
+
+    /// Write a manifest-<date>.json summary into the output directory for each day
+    #[arg(long)]
+    manifest: bool,
+
+    /// Replace invalid UTF-8 in downloaded files with U+FFFD instead of skipping the file
+    #[arg(long)]
+    lossy_utf8: bool,
+
+    /// Only re-read the existing output parquet files for the date(s) and report
+    /// unreadable files or schema mismatches; nothing is downloaded or written
+    #[arg(long)]
+    verify_only: bool,
+
+    /// Log output format: text or json
+    #[arg(long, value_enum, env = "LOG_FORMAT", default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(fmt::layer())
-        .with(EnvFilter::from_default_env().add_directive(tracing::Level::INFO.into()))
-        .init();
-
     // Parse CLI arguments
     let args = Args::parse();
 
+    // Initialize tracing (level from RUST_LOG, default info)
+    trade_data_processor::init_tracing(args.log_format)?;
+
     // Load configuration
     info!("Loading configuration from {:?}", args.config);
     let config = Config::from_file(&args.config)
         .context("Failed to load configuration")?;
 
-    // Parse date
-    let date = NaiveDate::parse_from_str(&args.date, "%Y-%m-%d")
-        .context(format!("Failed to parse date: {}", args.date))?;
-    info!("Processing date: {}", date);
+    // Parse date or date range
+    let (start_date, end_date) = match (&args.date, &args.start_date, &args.end_date) {
+        (Some(date), _, _) => {
+            let date = parse_date(date)?;
+            (date, date)
+        }
+        (None, Some(start), Some(end)) => (parse_date(start)?, parse_date(end)?),
+        _ => bail!("Either --date or both --start-date and --end-date must be provided"),
+    };
+    if end_date < start_date {
+        bail!("End date {} is before start date {}", end_date, start_date);
+    }
+
+    if args.verify_only {
+        return verify_date_range(start_date, end_date, &config.output);
+    }
+
+    info!("Processing dates: {} to {}", start_date, end_date);
 
     // Find data source configuration
     let data_source = config.find_data_source(&args.data_type)
@@ -62,656 +121,1033 @@ async fn main() -> Result<()> {
     info!("Processing data type: {}", args.data_type);
     info!("Number of SSH servers: {}", data_source.ssh_servers.len());
 
-    // Process data based on data type
-    process_data(date, data_source, &config.output, &args.data_type).await?;
+    // Create clients once so connections are reused across every day in the range
+    let clients = SourceClients::new(data_source)?;
+    let registry = ProcessorRegistry::default();
+    let options = ProcessOptions {
+        skip_existing: args.skip_existing,
+        manifest: args.manifest,
+        lossy_utf8: args.lossy_utf8,
+    };
+
+    // Process each day based on data type
+    let summaries = process_date_range(start_date, end_date, |date| {
+        process_data(date, data_source, &config.output, &args.data_type, &registry, &clients, options)
+    })
+    .await;
+
+    // Report per-day summary
+    let mut written_days = 0;
+    let mut existing_days = 0;
+    let mut failed_days = 0;
+    for (date, result) in &summaries {
+        match result {
+            Ok(DayOutcome::Written) => {
+                written_days += 1;
+                info!("{}: written", date);
+            }
+            Ok(DayOutcome::AlreadyExists) => {
+                existing_days += 1;
+                info!("{}: output already exists, skipped", date);
+            }
+            Ok(DayOutcome::NoData) => warn!("{}: no data found, skipped", date),
+            Err(e) => {
+                failed_days += 1;
+                error!("{}: failed: {:#}", date, e);
+            }
+        }
+    }
+    info!(
+        "Processed {} day(s): {} written, {} already existed, {} without data, {} failed",
+        summaries.len(),
+        written_days,
+        existing_days,
+        summaries.len() - written_days - existing_days - failed_days,
+        failed_days
+    );
+
+    if failed_days > 0 {
+        bail!("{} of {} day(s) failed to process", failed_days, summaries.len());
+    }
+    if written_days == 0 && existing_days == 0 {
+        bail!("No data collected from any source for {} to {}", start_date, end_date);
+    }
 
     info!("Processing completed successfully!");
     Ok(())
 }
 
-/// Main processing logic - routes to appropriate merger based on data type
-async fn process_data(
+/// Parse a CLI date argument (format: YYYY-MM-DD)
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .context(format!("Failed to parse date: {}", value))
+}
+
+/// Outcome of processing a single day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum DayOutcome {
+    /// Data was collected and written to Parquet
+    Written,
+    /// No source had data for the day
+    NoData,
+    /// Output already existed and `--skip-existing` was set
+    AlreadyExists,
+}
+
+/// Per-run processing options from the CLI
+#[derive(Debug, Clone, Copy, Default)]
+struct ProcessOptions {
+    /// Skip dates (and mark-price symbols) whose output already exists
+    skip_existing: bool,
+    /// Write a manifest JSON for each processed day
+    manifest: bool,
+    /// Replace invalid UTF-8 in downloaded files instead of rejecting the file
+    lossy_utf8: bool,
+}
+
+/// Machine-readable record of what a single day's run did
+#[derive(Debug, Default, Serialize)]
+struct RunManifest {
     date: NaiveDate,
-    data_source: &trade_data_processor::DataSourceConfig,
+    data_type: String,
+    outcome: Option<DayOutcome>,
+    /// Records added per source (after deduplication)
+    source_record_counts: BTreeMap<String, usize>,
+    /// Symbols processed (mark-price only)
+    symbols: Vec<String>,
+    /// Row counts per symbol (mark-price only)
+    symbol_row_counts: BTreeMap<String, RowCounts>,
+    rows_before_forward_fill: usize,
+    rows_after_forward_fill: usize,
+    /// Spill activity (mark-price with `spill_memory_budget_bytes` only)
+    spill_stats: Option<SpillStats>,
+    output_files: Vec<PathBuf>,
+    elapsed_secs: f64,
+}
+
+/// Row counts before and after forward-fill
+#[derive(Debug, Default, Serialize)]
+struct RowCounts {
+    before_forward_fill: usize,
+    after_forward_fill: usize,
+}
+
+/// Remote clients for a data source, created once and reused across days
+struct SourceClients {
+    ssh: Vec<SshClient>,
+    http: Vec<HttpClient>,
+}
+
+impl SourceClients {
+    fn new(data_source: &trade_data_processor::DataSourceConfig) -> Result<Self> {
+        Ok(Self {
+            ssh: data_source.ssh_servers.iter().cloned().map(SshClient::new).collect(),
+            http: data_source.http_servers.iter().cloned().map(HttpClient::new).collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Run the per-day processor for every date in the inclusive range
+///
+/// A failing day is recorded and the remaining days are still processed.
+async fn process_date_range<F, Fut>(
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    mut process_day: F,
+) -> Vec<(NaiveDate, Result<DayOutcome>)>
+where
+    F: FnMut(NaiveDate) -> Fut,
+    Fut: std::future::Future<Output = Result<DayOutcome>>,
+{
+    let mut summaries = Vec::new();
+    for date in start_date.iter_days().take_while(|d| *d <= end_date) {
+        info!("Processing date: {}", date);
+        let result = process_day(date).await;
+        summaries.push((date, result));
+    }
+    summaries
+}
+
+/// Re-read the output parquet files of each day in an inclusive range
+/// Fails if any day has no output directory, an unreadable file or a schema mismatch
+fn verify_date_range(
+    start_date: NaiveDate,
+    end_date: NaiveDate,
     output_config: &trade_data_processor::OutputConfig,
-    data_type: &str,
 ) -> Result<()> {
-    // Build the date path components
-    let year = date.format("%Y").to_string();
-    let month = date.format("%m").to_string();
-    let day = date.format("%d").to_string();
+    info!("Verifying output for dates: {} to {}", start_date, end_date);
+
+    let mut failed_files = 0;
+    let mut failed_days = 0;
+    for date in start_date.iter_days().take_while(|d| *d <= end_date) {
+        let dir = parquet_writer_config(output_config, date, 0).parquet_dir(date);
+        let report = match trade_data_processor::verify_parquet_dir(&dir) {
+            Ok(report) => report,
+            Err(e) => {
+                failed_days += 1;
+                error!("{}: {:#}", date, e);
+                continue;
+            }
+        };
 
-    // Route to appropriate processor based on data type
-    match data_type.to_lowercase().as_str() {
-        "mark-price" => {
-            info!("Using MarkPriceMerger for mark-price data");
-            process_mark_price_data(date, data_source, output_config, &year, &month, &day).await
-        }
-        _ => {
-            info!("Using generic DataMerger for data type: {}", data_type);
-            process_generic_data(date, data_source, output_config, &year, &month, &day).await
+        for (path, error) in &report.failures {
+            error!("{}: {:?} failed verification: {}", date, path, error);
         }
+        info!(
+            "{}: {} file(s) readable with {} rows, {} failed",
+            date,
+            report.files.len(),
+            report.total_rows(),
+            report.failures.len()
+        );
+        failed_files += report.failures.len();
+    }
+
+    if failed_files > 0 || failed_days > 0 {
+        bail!("Verification failed: {} file(s) failed, {} day(s) could not be checked", failed_files, failed_days);
     }
+
+    info!("Verification completed successfully!");
+    Ok(())
 }
 
-/// Process mark-price specific data
-async fn process_mark_price_data(
+/// Main processing logic - routes to the processor registered for the data type
+async fn process_data(
     date: NaiveDate,
     data_source: &trade_data_processor::DataSourceConfig,
     output_config: &trade_data_processor::OutputConfig,
-    year: &str,
-    month: &str,
-    day: &str,
-) -> Result<()> {
-    let mut merger = MarkPriceMerger::new(date);
+    data_type: &str,
+    registry: &ProcessorRegistry,
+    clients: &SourceClients,
+    options: ProcessOptions,
+) -> Result<DayOutcome> {
+    let started = Instant::now();
+    let mut manifest = RunManifest {
+        date,
+        data_type: data_type.to_string(),
+        ..Default::default()
+    };
+
+    // Create the processor registered for this data type
+    if registry.is_registered(data_type) {
+        info!("Using registered processor for data type: {}", data_type);
+    } else {
+        info!("Using generic DataMerger for data type: {}", data_type);
+    }
+    let mut processor = registry.create(data_source, date);
+
+    let outcome = run_processor(
+        date,
+        data_source,
+        output_config,
+        clients,
+        options,
+        processor.as_mut(),
+        &mut manifest,
+    )
+    .await?;
+
+    if options.manifest {
+        manifest.outcome = Some(outcome);
+        manifest.elapsed_secs = started.elapsed().as_secs_f64();
+        let manifest_path = write_manifest(&manifest, output_config)?;
+        info!("Wrote run manifest to {:?}", manifest_path);
+    }
+
+    Ok(outcome)
+}
+
+/// Write a run manifest to `<output>/manifest-<date>.json`
+fn write_manifest(manifest: &RunManifest, output_config: &trade_data_processor::OutputConfig) -> Result<PathBuf> {
+    let output_dir = Path::new(&output_config.path);
+    fs::create_dir_all(output_dir)
+        .context(format!("Failed to create output directory: {:?}", output_dir))?;
+
+    let manifest_path = output_dir.join(format!("manifest-{}.json", manifest.date.format("%Y-%m-%d")));
+    let content = serde_json::to_string_pretty(manifest)
+        .context("Failed to serialize run manifest")?;
+    fs::write(&manifest_path, content)
+        .context(format!("Failed to write run manifest: {:?}", manifest_path))?;
+
+    Ok(manifest_path)
+}
+
+/// Maximum number of sources (local directories and SSH/HTTP servers) contacted at once
+const MAX_CONCURRENT_SOURCES: usize = 8;
+
+/// Files collected from a single data source for one day
+struct SourceData {
+    /// Source name used for logging (e.g., "ssh-1-192.168.1.100")
+    source_name: String,
+    /// Downloaded (file path, file contents) pairs
+    files: Vec<(String, Vec<u8>)>,
+}
+
+/// A pending fetch of one source's files
+/// Resolves to `Ok(None)` when the source is skipped and `Err` when the run must abort
+type SourceFetch<'a> = BoxFuture<'a, Result<Option<SourceData>>>;
 
-    // Process each local file source
+/// Collect the files for a date from every configured source
+///
+/// Local directories, SSH servers and HTTP servers are contacted concurrently.
+async fn collect_source_data(
+    date: NaiveDate,
+    data_source: &trade_data_processor::DataSourceConfig,
+    clients: &SourceClients,
+) -> Result<Vec<SourceData>> {
+    // Build the date path component (e.g., "2025/11/06")
+    let date_path = date.format("%Y/%m/%d").to_string();
+
+    let mut fetches: Vec<SourceFetch<'_>> = Vec::new();
     for (idx, local_config) in data_source.local_files.iter().enumerate() {
-        let source_name = format!("local-{}", idx + 1);
-        info!("Processing mark-price source: {}", source_name);
-
-        // Build the local path (e.g., "/data/mark-price/2025/11/06")
-        let local_dir = format!(
-            "{}/{}/{}/{}",
-            local_config.base_path.trim_end_matches('/'),
-            year, month, day
-        );
-        info!("Local directory: {}", local_dir);
+        fetches.push(read_local_source(idx, local_config, data_source, date).boxed());
+    }
+    for (idx, (ssh_config, client)) in data_source.ssh_servers.iter().zip(&clients.ssh).enumerate() {
+        fetches.push(fetch_ssh_source(idx, ssh_config, client, data_source, date_path.clone()).boxed());
+    }
+    for (idx, (http_config, client)) in data_source.http_servers.iter().zip(&clients.http).enumerate() {
+        fetches.push(fetch_http_source(idx, http_config, client, data_source, date_path.clone()).boxed());
+    }
 
-        // Check if local directory exists
-        let local_path = Path::new(&local_dir);
-        if !local_path.exists() {
-            warn!("Local directory does not exist: {}, skipping", local_dir);
-            continue;
-        }
+    fetch_sources_concurrently(fetches).await
+}
 
-        if !local_path.is_dir() {
-            warn!("Path is not a directory: {}, skipping", local_dir);
-            continue;
+/// Run source fetches concurrently (at most `MAX_CONCURRENT_SOURCES` at a time)
+///
+/// Results are returned in the order the fetches were given, so merging keeps the
+/// same "first source wins" deduplication priority as a sequential run.
+async fn fetch_sources_concurrently(fetches: Vec<SourceFetch<'_>>) -> Result<Vec<SourceData>> {
+    let results: Vec<Result<Option<SourceData>>> = stream::iter(fetches)
+        .buffered(MAX_CONCURRENT_SOURCES)
+        .collect()
+        .await;
+
+    let mut sources = Vec::new();
+    for result in results {
+        if let Some(source) = result? {
+            sources.push(source);
         }
+    }
+    Ok(sources)
+}
 
-        // List JSONL files in the local directory
-        let entries = match fs::read_dir(&local_dir) {
-            Ok(entries) => entries,
-            Err(e) => {
-                error!("Failed to read local directory {}: {}", local_dir, e);
-                continue;
-            }
-        };
+/// Decode downloaded file contents as UTF-8
+///
+/// In strict mode invalid UTF-8 is an error naming the file, source and byte offset,
+/// which usually points at a corrupt download or failed decompression.
+/// In lossy mode invalid sequences are replaced with U+FFFD.
+fn decode_utf8<'a>(content: &'a [u8], file_path: &str, source_name: &str, lossy: bool) -> Result<Cow<'a, str>> {
+    if lossy {
+        return Ok(String::from_utf8_lossy(content));
+    }
 
-        let mut jsonl_files = Vec::new();
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                        if file_name.ends_with(".jsonl") {
-                            jsonl_files.push(path);
-                        }
-                    }
-                }
+    match std::str::from_utf8(content) {
+        Ok(text) => Ok(Cow::Borrowed(text)),
+        Err(e) => bail!(
+            "Invalid UTF-8 in {} from {} at byte offset {} of {} (corrupt download or failed decompression?)",
+            file_path,
+            source_name,
+            e.valid_up_to(),
+            content.len()
+        ),
+    }
+}
+
+/// Check that a downloaded file decompresses cleanly and, in strict mode, is valid UTF-8
+///
+/// Runs one line at a time over the streaming decoder before anything is merged,
+/// so a bad file is skipped whole instead of leaving its first records in the merger.
+/// Compressed files are therefore decompressed twice, trading CPU for never holding
+/// the decoded file in memory.
+fn check_source_file(content: &[u8], file_path: &str, source_name: &str, lossy: bool) -> Result<()> {
+    if lossy && Compression::detect(content) == Compression::None {
+        return Ok(());
+    }
+
+    let mut reader = decompress_reader(content)?;
+    let mut line = Vec::new();
+    let mut offset = 0;
+    loop {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .with_context(|| format!("Failed to decompress {} from {} after {} bytes", file_path, source_name, offset))?;
+        if read == 0 {
+            return Ok(());
+        }
+        if !lossy {
+            if let Err(e) = std::str::from_utf8(&line) {
+                bail!(
+                    "Invalid UTF-8 in {} from {} at byte offset {} (corrupt download or failed decompression?)",
+                    file_path,
+                    source_name,
+                    offset + e.valid_up_to()
+                );
             }
         }
+        offset += read;
+    }
+}
 
-        info!("Found {} JSONL files in {}", jsonl_files.len(), local_dir);
+/// Reader that replaces invalid UTF-8 with U+FFFD one line at a time
+/// Used in lossy mode so the mergers' line reads never fail on bad bytes
+struct LossyUtf8Lines<R> {
+    inner: R,
+    line: Vec<u8>,
+    pos: usize,
+}
 
-        // Process each JSONL file
-        for file_path in jsonl_files {
-            let file_name = file_path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
-            
-            match fs::read_to_string(&file_path) {
-                Ok(content) => {
-                    match merger.add_jsonl_data(&content, &source_name) {
-                        Ok(count) => {
-                            info!("Added {} mark-price records from local file {}", count, file_name);
-                        }
-                        Err(e) => {
-                            error!("Failed to parse mark-price data from local file {}: {}", file_name, e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to read local file {:?}: {}", file_path, e);
-                    continue;
-                }
+impl<R: BufRead> LossyUtf8Lines<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, line: Vec::new(), pos: 0 }
+    }
+}
+
+impl<R: BufRead> Read for LossyUtf8Lines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+impl<R: BufRead> BufRead for LossyUtf8Lines<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.pos == self.line.len() {
+            self.line.clear();
+            self.pos = 0;
+            self.inner.read_until(b'\n', &mut self.line)?;
+            let replaced = match String::from_utf8_lossy(&self.line) {
+                Cow::Owned(text) => Some(text),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(text) = replaced {
+                self.line = text.into_bytes();
             }
         }
+        Ok(&self.line[self.pos..])
     }
 
-    // Process each SSH server
-    for (idx, ssh_config) in data_source.ssh_servers.iter().enumerate() {
-        let source_name = format!("ssh-{}-{}", idx + 1, ssh_config.host);
-        info!("Processing mark-price source: {}", source_name);
+    fn consume(&mut self, amount: usize) {
+        self.pos += amount;
+    }
+}
 
-        // Build the remote path
-        let remote_dir = format!(
-            "{}/{}/{}/{}",
-            ssh_config.input_base_path.trim_end_matches('/'),
-            year, month, day
-        );
-        info!("Remote directory for {}: {}", ssh_config.host, remote_dir);
+/// Convert a `.json` file holding a top-level array into line-delimited records
+/// Any other content is returned unchanged and parsed as JSONL
+fn normalize_json_records<'a>(content: Cow<'a, str>, file_path: &str) -> Result<Cow<'a, str>> {
+    if !file_path.ends_with(".json") || !content.trim_start().starts_with('[') {
+        return Ok(content);
+    }
 
-        let client = SshClient::new(ssh_config.clone());
+    let records: Vec<serde_json::Value> = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid JSON array in {}", file_path))?;
+    let lines: Vec<String> = records.iter().map(|record| record.to_string()).collect();
+    Ok(Cow::Owned(lines.join("\n")))
+}
 
-        // Check if remote directory exists
-        match client.path_exists(&remote_dir).await {
-            Ok(true) => {
-                info!("Remote directory exists: {}", remote_dir);
-            }
-            Ok(false) => {
-                warn!("Remote directory does not exist: {} on {}, skipping", remote_dir, ssh_config.host);
+/// Feed collected source files into a merger
+/// JSONL files are parsed line by line straight from the (gzip/zstd) decoder;
+/// only `.json` files are decoded in memory, since a top-level array has to be parsed whole
+/// Files that fail to decompress, decode or parse are logged and skipped
+/// Returns the number of records added per source
+fn merge_source_data<F>(
+    sources: Vec<SourceData>,
+    record_kind: &str,
+    lossy_utf8: bool,
+    mut add_jsonl_reader: F,
+) -> BTreeMap<String, usize>
+where
+    F: FnMut(&mut dyn BufRead, &str) -> Result<usize>,
+{
+    let mut record_counts = BTreeMap::new();
+    for source in sources {
+        let source_count = record_counts.entry(source.source_name.clone()).or_insert(0);
+        for (file_path, content) in source.files {
+            if let Err(e) = check_source_file(&content, &file_path, &source.source_name, lossy_utf8) {
+                error!("Skipping {} data file: {:#}", record_kind, e);
                 continue;
             }
-            Err(e) => {
-                error!("Failed to check remote directory on {}: {}", ssh_config.host, e);
-                continue;
+            let file_name = file_path.rsplit('/').next().unwrap_or(&file_path);
+
+            let result = if file_path.ends_with(".json") {
+                decompress_auto_owned(content)
+                    .and_then(|content| {
+                        let content_str = decode_utf8(&content, &file_path, &source.source_name, lossy_utf8)?;
+                        let content_str = normalize_json_records(content_str, &file_path)?;
+                        add_jsonl_reader(&mut content_str.as_bytes(), &source.source_name)
+                    })
+            } else {
+                match decompress_reader(&content) {
+                    Ok(reader) if lossy_utf8 => add_jsonl_reader(&mut LossyUtf8Lines::new(reader), &source.source_name),
+                    Ok(mut reader) => add_jsonl_reader(&mut reader, &source.source_name),
+                    Err(e) => Err(e),
+                }
+            };
+            match result {
+                Ok(count) => {
+                    *source_count += count;
+                    info!("Added {} {} records from {} ({})", count, record_kind, file_name, source.source_name);
+                }
+                Err(e) => {
+                    error!("Failed to parse {} data from {}: {:#}", record_kind, file_path, e);
+                }
             }
         }
+    }
+    record_counts
+}
 
-        // List JSONL files in the remote directory
-        let files = match client.list_files(&remote_dir).await {
-            Ok(files) => files,
-            Err(e) => {
-                error!("Failed to list files on {}: {}", ssh_config.host, e);
-                continue;
-            }
-        };
+/// Read the data files for a date from a local directory source
+async fn read_local_source(
+    idx: usize,
+    local_config: &LocalFileConfig,
+    data_source: &trade_data_processor::DataSourceConfig,
+    date: NaiveDate,
+) -> Result<Option<SourceData>> {
+    let source_name = format!("local-{}", idx + 1);
+    info!("Processing source: {}", source_name);
+
+    // Build the local path from the template (e.g., "/data/mark-price/2025/11/06")
+    let local_path = local_config.dir_for_date(date);
+    info!("Local directory: {:?}", local_path);
+
+    // Check if local directory exists
+    if !local_path.exists() {
+        warn!("Local directory does not exist: {:?}, skipping", local_path);
+        return Ok(None);
+    }
 
-        let jsonl_files: Vec<String> = files
-            .into_iter()
-            .filter(|f| f.ends_with(".jsonl"))
-            .collect();
+    if !local_path.is_dir() {
+        warn!("Path is not a directory: {:?}, skipping", local_path);
+        return Ok(None);
+    }
 
-        info!("Found {} JSONL files on {}", jsonl_files.len(), ssh_config.host);
+    // List data files in the local directory
+    let entries = match fs::read_dir(&local_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read local directory {:?}: {}", local_path, e);
+            return Ok(None);
+        }
+    };
+
+    // Pick files by the glob when set, otherwise by file extension
+    let glob = local_config.glob_for_date(date);
+    let mut data_files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let matches = match &glob {
+            Some(glob) => glob_match(glob, &entry.file_name().to_string_lossy()),
+            None => data_source.matches_file_extension(&path.to_string_lossy()),
+        };
+        if path.is_file() && matches {
+            data_files.push(path);
+        }
+    }
+    data_files.sort();
 
-        // Build full paths for parallel download
-        let remote_file_paths: Vec<String> = jsonl_files
-            .iter()
-            .map(|file| format!("{}/{}", remote_dir, file))
-            .collect();
+    info!("Found {} data files in {:?}", data_files.len(), local_path);
 
-        // Download all files in parallel
-        info!("Starting parallel download of {} files from {}", remote_file_paths.len(), ssh_config.host);
-        match client.download_files_parallel(remote_file_paths.clone()).await {
-            Ok(downloaded_files) => {
-                info!("Successfully downloaded {} files from {}", downloaded_files.len(), ssh_config.host);
-                
-                // Process each downloaded file
-                for (remote_file_path, content) in downloaded_files {
-                    let content_str = String::from_utf8_lossy(&content);
-                    
-                    match merger.add_jsonl_data(&content_str, &source_name) {
-                        Ok(count) => {
-                            let file_name = remote_file_path.split('/').last().unwrap_or(&remote_file_path);
-                            info!("Added {} mark-price records from {}", count, file_name);
-                        }
-                        Err(e) => {
-                            error!("Failed to parse mark-price data from {}: {}", remote_file_path, e);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to download files from {}: {}", ssh_config.host, e);
-                continue;
-            }
+    // Read each data file
+    let mut files = Vec::new();
+    for file_path in data_files {
+        match fs::read(&file_path) {
+            Ok(content) => files.push((file_path.to_string_lossy().to_string(), content)),
+            Err(e) => error!("Failed to read local file {:?}: {}", file_path, e),
         }
     }
 
-    // Process each HTTP server
-    for (idx, http_config) in data_source.http_servers.iter().enumerate() {
-        let source_name = format!("http-{}-{}", idx + 1, http_config.base_url);
-        info!("Processing mark-price source: {}", source_name);
+    Ok(Some(SourceData { source_name, files }))
+}
 
-        // Build the remote path (e.g., "mark-price/2025/11/06")
-        let remote_dir = format!(
-            "{}/{}/{}/{}",
-            http_config.input_base_path.trim_end_matches('/'),
-            year, month, day
-        );
-        info!("Remote directory for {}: {}", http_config.base_url, remote_dir);
+/// Download the data files for a date from an SSH server source
+async fn fetch_ssh_source(
+    idx: usize,
+    ssh_config: &SshConfig,
+    client: &SshClient,
+    data_source: &trade_data_processor::DataSourceConfig,
+    date_path: String,
+) -> Result<Option<SourceData>> {
+    let source_name = format!("ssh-{}-{}", idx + 1, ssh_config.host);
+    info!("Processing source: {}", source_name);
+
+    // Build the remote path
+    let remote_dir = format!("{}/{}", ssh_config.input_base_path.trim_end_matches('/'), date_path);
+    info!("Remote directory for {}: {}", ssh_config.host, remote_dir);
+
+    // Check if remote directory exists
+    match client.path_exists(&remote_dir).await {
+        Ok(true) => {
+            info!("Remote directory exists: {}", remote_dir);
+        }
+        Ok(false) => {
+            warn!("Remote directory does not exist: {} on {}, skipping", remote_dir, ssh_config.host);
+            return Ok(None);
+        }
+        Err(e) => {
+            error!("Failed to check remote directory on {}: {}", ssh_config.host, e);
+            return Ok(None);
+        }
+    }
 
-        let client = HttpClient::new(http_config.clone());
+    // List data files in the remote directory
+    let files = match client.list_files(&remote_dir).await {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to list files on {}: {}", ssh_config.host, e);
+            return Ok(None);
+        }
+    };
+
+    // Build full paths for parallel download
+    let remote_file_paths: Vec<String> = files
+        .into_iter()
+        .filter(|f| data_source.matches_file_extension(f))
+        .map(|file| format!("{}/{}", remote_dir, file))
+        .collect();
+
+    info!("Found {} data files on {}", remote_file_paths.len(), ssh_config.host);
+
+    // Download all files in parallel
+    info!("Starting parallel download of {} files from {}", remote_file_paths.len(), ssh_config.host);
+    match client.download_files_parallel(remote_file_paths).await {
+        Ok((files, stats)) => {
+            info!(
+                "Successfully downloaded {} files from {} ({} bytes saved by compression)",
+                files.len(),
+                ssh_config.host,
+                stats.bytes_saved()
+            );
+            Ok(Some(SourceData { source_name, files }))
+        }
+        Err(e) => {
+            error!("Failed to download files from {}: {}", ssh_config.host, e);
+            Ok(None)
+        }
+    }
+}
 
-        // Check proxy availability if configured (fail fast if proxy is not working)
-        client.check_proxy_availability().await
-            .context("Proxy availability check failed - aborting")?;
+/// Download the data files for a date from an HTTP server source
+async fn fetch_http_source(
+    idx: usize,
+    http_config: &HttpConfig,
+    client: &HttpClient,
+    data_source: &trade_data_processor::DataSourceConfig,
+    date_path: String,
+) -> Result<Option<SourceData>> {
+    let source_name = format!("http-{}-{}", idx + 1, http_config.base_url);
+    info!("Processing source: {}", source_name);
+
+    // Build the remote path (e.g., "mark-price/2025/11/06")
+    let remote_dir = format!("{}/{}", http_config.input_base_path.trim_end_matches('/'), date_path);
+    info!("Remote directory for {}: {}", http_config.base_url, remote_dir);
+
+    // Check proxy availability if configured (fail fast if proxy is not working)
+    client.check_proxy_availability().await
+        .context("Proxy availability check failed - aborting")?;
+
+    // Check if remote directory exists
+    match client.path_exists(&remote_dir).await {
+        Ok(true) => {
+            info!("Remote directory exists: {}", remote_dir);
+        }
+        Ok(false) => {
+            warn!("Remote directory does not exist: {} on {}, skipping", remote_dir, http_config.base_url);
+            return Ok(None);
+        }
+        Err(e) => {
+            error!("Failed to check remote directory on {}: {}", http_config.base_url, e);
+            return Ok(None);
+        }
+    }
 
-        // Check if remote directory exists
-        match client.path_exists(&remote_dir).await {
-            Ok(true) => {
-                info!("Remote directory exists: {}", remote_dir);
-            }
-            Ok(false) => {
-                warn!("Remote directory does not exist: {} on {}, skipping", remote_dir, http_config.base_url);
-                continue;
-            }
-            Err(e) => {
-                error!("Failed to check remote directory on {}: {}", http_config.base_url, e);
-                continue;
-            }
+    // List data files in the remote directory
+    let files = match client.list_files(&remote_dir).await {
+        Ok(files) => files,
+        Err(e) => {
+            error!("Failed to list files on {}: {}", http_config.base_url, e);
+            return Ok(None);
+        }
+    };
+
+    // Build full paths for parallel download
+    let remote_file_paths: Vec<String> = files
+        .into_iter()
+        .filter(|f| data_source.matches_file_extension(f))
+        .map(|file| format!("{}/{}", remote_dir, file))
+        .collect();
+
+    info!("Found {} data files on {}", remote_file_paths.len(), http_config.base_url);
+
+    // Download all files in parallel
+    info!("Starting parallel download of {} files from {}", remote_file_paths.len(), http_config.base_url);
+    match client.download_files_parallel(remote_file_paths).await {
+        Ok((files, stats)) => {
+            info!(
+                "Successfully downloaded {} files from {} ({} bytes saved by compression)",
+                files.len(),
+                http_config.base_url,
+                stats.bytes_saved()
+            );
+            Ok(Some(SourceData { source_name, files }))
         }
+        Err(e) => {
+            error!("Failed to download files from {}: {}", http_config.base_url, e);
+            Ok(None)
+        }
+    }
+}
 
-        // List JSONL files in the remote directory
-        let files = match client.list_files(&remote_dir).await {
-            Ok(files) => files,
-            Err(e) => {
-                error!("Failed to list files on {}: {}", http_config.base_url, e);
-                continue;
-            }
-        };
+/// Collect, merge, forward-fill and write one day's data with the given processor
+async fn run_processor(
+    date: NaiveDate,
+    data_source: &trade_data_processor::DataSourceConfig,
+    output_config: &trade_data_processor::OutputConfig,
+    clients: &SourceClients,
+    options: ProcessOptions,
+    processor: &mut dyn DataProcessor,
+    manifest: &mut RunManifest,
+) -> Result<DayOutcome> {
+    let data_type = manifest.data_type.clone();
+    // Combined output writes every symbol into the output's own file set
+    let per_symbol_files = processor.splits_by_symbol() && output_config.output_mode == OutputMode::PerSymbol;
+
+    // Skip the whole day before downloading anything if its single output file already exists
+    if options.skip_existing && !per_symbol_files && output_exists(output_config, date) {
+        info!(
+            "Output already exists for {} ({:?}), skipping",
+            date,
+            parquet_writer_config(output_config, date, 0).expected_parquet_path(date)
+        );
+        return Ok(DayOutcome::AlreadyExists);
+    }
 
-        let jsonl_files: Vec<String> = files
-            .into_iter()
-            .filter(|f| f.ends_with(".jsonl"))
-            .collect();
+    // Collect files from all sources concurrently, then merge in source order
+    let sources = collect_source_data(date, data_source, clients).await?;
+    manifest.source_record_counts = merge_source_data(sources, &data_type, options.lossy_utf8, |reader, source_name| {
+        processor.add_jsonl_reader(reader, source_name)
+    });
 
-        info!("Found {} JSONL files on {}", jsonl_files.len(), http_config.base_url);
+    // Check if we have any data
+    if processor.is_empty() {
+        warn!("No {} data collected from any source for {}", data_type, date);
+        return Ok(DayOutcome::NoData);
+    }
 
-        // Build full paths for parallel download
-        let remote_file_paths: Vec<String> = jsonl_files
-            .iter()
-            .map(|file| format!("{}/{}", remote_dir, file))
+    // Drop symbols whose output already exists so they are neither forward-filled nor rewritten
+    if options.skip_existing && per_symbol_files {
+        let existing: Vec<String> = processor
+            .symbols()
+            .into_iter()
+            .filter(|symbol| output_exists(&symbol_output_config(output_config, symbol), date))
             .collect();
+        for symbol in &existing {
+            processor.remove_symbol(symbol);
+        }
+        info!("Skipping {} symbol(s) with existing output: {:?}", existing.len(), existing);
 
-        // Download all files in parallel
-        info!("Starting parallel download of {} files from {}", remote_file_paths.len(), http_config.base_url);
-        match client.download_files_parallel(remote_file_paths.clone()).await {
-            Ok(downloaded_files) => {
-                info!("Successfully downloaded {} files from {}", downloaded_files.len(), http_config.base_url);
-                
-                // Process each downloaded file
-                for (remote_file_path, content) in downloaded_files {
-                    let content_str = String::from_utf8_lossy(&content);
-                    
-                    match merger.add_jsonl_data(&content_str, &source_name) {
-                        Ok(count) => {
-                            let file_name = remote_file_path.split('/').last().unwrap_or(&remote_file_path);
-                            info!("Added {} mark-price records from {}", count, file_name);
-                        }
-                        Err(e) => {
-                            error!("Failed to parse mark-price data from {}: {}", remote_file_path, e);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to download files from {}: {}", http_config.base_url, e);
-                continue;
-            }
+        if processor.is_empty() {
+            info!("Output already exists for all symbols on {}, skipping", date);
+            return Ok(DayOutcome::AlreadyExists);
         }
     }
 
-    // Check if we have any data
-    if merger.is_empty() {
-        bail!("No mark-price data collected from any source");
+    let symbols = processor.symbols();
+    if processor.splits_by_symbol() {
+        info!("Collected data for {} symbols: {:?}", symbols.len(), symbols);
+        manifest.symbols = symbols.clone();
+        manifest.symbols.sort();
     }
 
-    let symbols = merger.get_symbols();
-    info!("Collected data for {} symbols: {:?}", symbols.len(), symbols);
-
     // Log statistics before forward-fill
     for symbol in &symbols {
-        info!("Symbol {} - unique seconds before forward-fill: {}", symbol, merger.len_for_symbol(symbol));
+        let len = processor.len_for_symbol(symbol);
+        if processor.splits_by_symbol() {
+            info!("Symbol {} - unique seconds before forward-fill: {}", symbol, len);
+            manifest.symbol_row_counts.entry(symbol.clone()).or_default().before_forward_fill = len;
+        } else {
+            info!("Total unique seconds before forward-fill: {}", len);
+        }
+        manifest.rows_before_forward_fill += len;
     }
 
     // Apply forward-fill
-    merger.apply_forward_fill()
+    processor.forward_fill()
         .context("Failed to apply forward-fill")?;
 
+    if let Some(stats) = processor.spill_stats() {
+        info!(
+            "Spill summary: {} spill event(s), {} symbol(s), {} rows (~{} bytes) spilled, peak in-memory ~{} bytes",
+            stats.spill_events, stats.spilled_symbols, stats.spilled_rows, stats.spilled_bytes, stats.peak_resident_bytes
+        );
+        manifest.spill_stats = Some(stats);
+    }
+
     // Log statistics after forward-fill
     for symbol in &symbols {
-        info!("Symbol {} - total seconds after forward-fill: {}", symbol, merger.len_for_symbol(symbol));
+        let len = processor.len_for_symbol(symbol);
+        if processor.splits_by_symbol() {
+            info!("Symbol {} - total seconds after forward-fill: {}", symbol, len);
+            manifest.symbol_row_counts.entry(symbol.clone()).or_default().after_forward_fill = len;
+        } else {
+            info!("Total seconds after forward-fill: {}", len);
+        }
+        manifest.rows_after_forward_fill += len;
+    }
+
+    if processor.splits_by_symbol() && !per_symbol_files {
+        info!("Writing {} symbols to one combined {} file set", symbols.len(), data_type);
+        let files = write_combined_output(processor, &symbols, date, output_config).await
+            .context("Failed to write combined output")?;
+        manifest.output_files.extend(files);
+        return Ok(DayOutcome::Written);
     }
 
     // Write to Parquet - one file per symbol in the same directory (in parallel)
-    info!("Writing mark-price data to Parquet files (one per symbol) in parallel...");
+    info!("Writing {} data to Parquet ({} file set(s)) in parallel...", data_type, symbols.len());
     
-    // Prepare write tasks for parallel execution
-    let mut write_tasks = Vec::new();
+    // Write tasks wait for a free slot when max_write_concurrency is set
+    let mut write_tasks = WriteTasks::new(output_config.max_write_concurrency);
     
     for symbol in symbols {
-        let rows = merger.get_sorted_rows_for_symbol(&symbol)
+        let rows = processor.rows_for_symbol(&symbol)
             .context(format!("Failed to get rows for symbol {}", symbol))?;
         
         info!("Preparing to write {} rows for symbol {}", rows.len(), symbol);
+
+        // Database inserts run one symbol at a time so table creation cannot race
+        if let Some(ref postgres_config) = output_config.postgres {
+            write_rows_to_postgres(&rows, postgres_config).await
+                .context(format!("Failed to write rows for symbol {} to Postgres", symbol))?;
+        }
         
-        // Create a modified output config with symbol-specific name only (same path)
-        let symbol_output_config = trade_data_processor::OutputConfig {
-            path: output_config.path.clone(),
-            name: format!("{}-{}", output_config.name, symbol),
-            batch_size: output_config.batch_size,
-            use_temp_dir: output_config.use_temp_dir,
+        // Per-symbol processors get a symbol-specific name only (same path)
+        let symbol_output_config = if processor.splits_by_symbol() {
+            symbol_output_config(output_config, &symbol)
+        } else {
+            output_config.clone()
         };
         
         // Clone symbol for use in the spawned task
         let symbol_for_task = symbol.clone();
         
         // Spawn a task for each symbol's parquet write
-        let task = tokio::spawn(async move {
+        write_tasks.spawn(symbol, async move {
             write_rows_to_parquet(rows, date, &symbol_output_config).await
                 .context(format!("Failed to write parquet for symbol {}", symbol_for_task))
-        });
-        
-        write_tasks.push((symbol, task));
+        }).await;
     }
     
     // Wait for all write tasks to complete and collect results
     let total_tasks = write_tasks.len();
     info!("Waiting for {} parallel write tasks to complete...", total_tasks);
-    for (symbol, task) in write_tasks {
-        match task.await {
-            Ok(Ok(())) => {
-                info!("Successfully wrote parquet file for symbol {}", symbol);
-            }
-            Ok(Err(e)) => {
-                error!("Failed to write parquet for symbol {}: {}", symbol, e);
-                return Err(e);
-            }
-            Err(e) => {
-                error!("Task panicked for symbol {}: {}", symbol, e);
-                return Err(anyhow::anyhow!("Task panicked for symbol {}: {}", symbol, e));
-            }
-        }
-    }
+    manifest.output_files.extend(write_tasks.join().await?);
     
-    info!("All {} parquet files written successfully", total_tasks);
+    info!("All {} parquet write tasks completed successfully", total_tasks);
 
-    Ok(())
+    Ok(DayOutcome::Written)
 }
 
-/// Process generic data
-async fn process_generic_data(
-    date: NaiveDate,
-    data_source: &trade_data_processor::DataSourceConfig,
-    output_config: &trade_data_processor::OutputConfig,
-    year: &str,
-    month: &str,
-    day: &str,
-) -> Result<()> {
-    let mut merger = DataMerger::new(date);
-
-    // Process each local file source
-    for (idx, local_config) in data_source.local_files.iter().enumerate() {
-        let source_name = format!("local-{}", idx + 1);
-        info!("Processing generic data source: {}", source_name);
-
-        // Build the local path (e.g., "/data/mark-price/2025/11/06")
-        let local_dir = format!(
-            "{}/{}/{}/{}",
-            local_config.base_path.trim_end_matches('/'),
-            year, month, day
-        );
-        info!("Local directory: {}", local_dir);
+/// Per-symbol parquet write tasks, at most `limit` of them running at once
+struct WriteTasks {
+    limit: Option<Arc<Semaphore>>,
+    tasks: Vec<(String, JoinHandle<Result<Vec<PathBuf>>>)>,
+}
 
-        // Check if local directory exists
-        let local_path = Path::new(&local_dir);
-        if !local_path.exists() {
-            warn!("Local directory does not exist: {}, skipping", local_dir);
-            continue;
+impl WriteTasks {
+    /// `max_concurrency` of `None` runs every task immediately
+    fn new(max_concurrency: Option<usize>) -> Self {
+        Self {
+            limit: max_concurrency.map(|n| Arc::new(Semaphore::new(n.max(1)))),
+            tasks: Vec::new(),
         }
+    }
 
-        if !local_path.is_dir() {
-            warn!("Path is not a directory: {}, skipping", local_dir);
-            continue;
-        }
+    fn len(&self) -> usize {
+        self.tasks.len()
+    }
 
-        // List JSONL files in the local directory
-        let entries = match fs::read_dir(&local_dir) {
-            Ok(entries) => entries,
-            Err(e) => {
-                error!("Failed to read local directory {}: {}", local_dir, e);
-                continue;
-            }
+    /// Spawn a write for `symbol`, first waiting for a free slot if the limit is reached
+    /// Waiting here also keeps the caller from materializing further symbols' rows
+    async fn spawn<F>(&mut self, symbol: String, write: F)
+    where
+        F: Future<Output = Result<Vec<PathBuf>>> + Send + 'static,
+    {
+        let permit = match &self.limit {
+            Some(limit) => Some(limit.clone().acquire_owned().await.expect("write semaphore is never closed")),
+            None => None,
         };
+        let task = tokio::spawn(async move {
+            let _permit = permit;
+            write.await
+        });
+        self.tasks.push((symbol, task));
+    }
 
-        let mut jsonl_files = Vec::new();
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                        if file_name.ends_with(".jsonl") {
-                            jsonl_files.push(path);
-                        }
-                    }
+    /// Wait for the tasks in spawn order, returning every written file or the first failure
+    async fn join(self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for (symbol, task) in self.tasks {
+            match task.await {
+                Ok(Ok(written)) => {
+                    info!("Successfully wrote parquet file for symbol {}", symbol);
+                    files.extend(written);
                 }
-            }
-        }
-
-        info!("Found {} JSONL files in {}", jsonl_files.len(), local_dir);
-
-        // Process each JSONL file
-        for file_path in jsonl_files {
-            let file_name = file_path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
-            
-            match fs::read_to_string(&file_path) {
-                Ok(content) => {
-                    match merger.add_jsonl_data(&content, &source_name) {
-                        Ok(count) => {
-                            info!("Added {} records from local file {}", count, file_name);
-                        }
-                        Err(e) => {
-                            error!("Failed to parse data from local file {}: {}", file_name, e);
-                        }
-                    }
+                Ok(Err(e)) => {
+                    error!("Failed to write parquet for symbol {}: {}", symbol, e);
+                    return Err(e);
                 }
                 Err(e) => {
-                    error!("Failed to read local file {:?}: {}", file_path, e);
-                    continue;
+                    error!("Task panicked for symbol {}: {}", symbol, e);
+                    return Err(anyhow::anyhow!("Task panicked for symbol {}: {}", symbol, e));
                 }
             }
         }
+        Ok(files)
     }
+}
 
-    // Process each SSH server
-    for (idx, ssh_config) in data_source.ssh_servers.iter().enumerate() {
-        let source_name = format!("ssh-{}-{}", idx + 1, ssh_config.host);
-        info!("Processing generic data source: {}", source_name);
-
-        // Build the remote path
-        let remote_dir = format!(
-            "{}/{}/{}/{}",
-            ssh_config.input_base_path.trim_end_matches('/'),
-            year, month, day
-        );
-        info!("Remote directory for {}: {}", ssh_config.host, remote_dir);
-
-        let client = SshClient::new(ssh_config.clone());
-
-        // Check if remote directory exists
-        match client.path_exists(&remote_dir).await {
-            Ok(true) => {
-                info!("Remote directory exists: {}", remote_dir);
-            }
-            Ok(false) => {
-                warn!("Remote directory does not exist: {} on {}, skipping", remote_dir, ssh_config.host);
-                continue;
-            }
-            Err(e) => {
-                error!("Failed to check remote directory on {}: {}", ssh_config.host, e);
-                continue;
-            }
-        }
-
-        // List JSONL files in the remote directory
-        let files = match client.list_files(&remote_dir).await {
-            Ok(files) => files,
-            Err(e) => {
-                error!("Failed to list files on {}: {}", ssh_config.host, e);
-                continue;
-            }
-        };
-
-        let jsonl_files: Vec<String> = files
-            .into_iter()
-            .filter(|f| f.ends_with(".jsonl"))
-            .collect();
-
-        info!("Found {} JSONL files on {}", jsonl_files.len(), ssh_config.host);
+/// Sorted rows of one symbol for combined output
+/// Rows without a `symbol` field get one so symbols stay distinguishable in the file
+fn combined_symbol_rows(processor: &dyn DataProcessor, symbol: &str) -> Result<Vec<DataRow>> {
+    let mut rows = processor.rows_for_symbol(symbol)
+        .context(format!("Failed to get rows for symbol {}", symbol))?;
+    for row in &mut rows {
+        row.entry("symbol".to_string())
+            .or_insert_with(|| serde_json::Value::String(symbol.to_string()));
+    }
+    Ok(rows)
+}
 
-        // Build full paths for parallel download
-        let remote_file_paths: Vec<String> = jsonl_files
-            .iter()
-            .map(|file| format!("{}/{}", remote_dir, file))
-            .collect();
+/// Write every symbol's sorted rows (symbols in name order) to one combined file set
+///
+/// Symbols are loaded one at a time, as for per-symbol output, so spilled symbols stay on
+/// disk until their turn. A first pass keeps only the rows that introduce a new column;
+/// since a column's type comes from its first value, that sample gives the same union
+/// schema as inferring it from every row. The second pass streams the rows to the writer.
+async fn write_combined_output(
+    processor: &dyn DataProcessor,
+    symbols: &[String],
+    date: NaiveDate,
+    output_config: &trade_data_processor::OutputConfig,
+) -> Result<Vec<PathBuf>> {
+    let mut symbols = symbols.to_vec();
+    symbols.sort();
 
-        // Download all files in parallel
-        info!("Starting parallel download of {} files from {}", remote_file_paths.len(), ssh_config.host);
-        match client.download_files_parallel(remote_file_paths.clone()).await {
-            Ok(downloaded_files) => {
-                info!("Successfully downloaded {} files from {}", downloaded_files.len(), ssh_config.host);
-                
-                // Process each downloaded file
-                for (remote_file_path, content) in downloaded_files {
-                    let content_str = String::from_utf8_lossy(&content);
-                    
-                    match merger.add_jsonl_data(&content_str, &source_name) {
-                        Ok(count) => {
-                            let file_name = remote_file_path.split('/').last().unwrap_or(&remote_file_path);
-                            info!("Added {} records from {}", count, file_name);
-                        }
-                        Err(e) => {
-                            error!("Failed to parse data from {}: {}", remote_file_path, e);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to download files from {}: {}", ssh_config.host, e);
-                continue;
+    let mut schema_sample = Vec::new();
+    let mut columns = HashSet::new();
+    let mut total_rows = 0;
+    for symbol in &symbols {
+        let rows = combined_symbol_rows(processor, symbol)?;
+        total_rows += rows.len();
+        for row in rows {
+            if row.keys().any(|key| !columns.contains(key)) {
+                columns.extend(row.keys().cloned());
+                schema_sample.push(row);
             }
         }
     }
+    if total_rows == 0 {
+        return Ok(Vec::new());
+    }
 
-    // Process each HTTP server
-    for (idx, http_config) in data_source.http_servers.iter().enumerate() {
-        let source_name = format!("http-{}-{}", idx + 1, http_config.base_url);
-        info!("Processing generic data source: {}", source_name);
-
-        // Build the remote path (e.g., "mark-price/2025/11/06")
-        let remote_dir = format!(
-            "{}/{}/{}/{}",
-            http_config.input_base_path.trim_end_matches('/'),
-            year, month, day
-        );
-        info!("Remote directory for {}: {}", http_config.base_url, remote_dir);
-
-        let client = HttpClient::new(http_config.clone());
+    // Without batch_size everything goes to a single file, which the writer buffers whole
+    let batch_size = output_config.batch_size.unwrap_or(total_rows).max(1);
+    info!("Writing {} combined rows in files of up to {} rows", total_rows, batch_size);
+    let mut writer = ParquetWriter::new(parquet_writer_config(output_config, date, batch_size));
+    writer.init_schema(&schema_sample)?;
+    drop(schema_sample);
 
-        // Check proxy availability if configured (fail fast if proxy is not working)
-        client.check_proxy_availability().await
-            .context("Proxy availability check failed - aborting")?;
+    // Hand the writer chunks that top its buffer up to exactly batch_size, so files
+    // split at the same rows as a single concatenated write would
+    let mut buffered = 0;
+    for symbol in &symbols {
+        let rows = combined_symbol_rows(processor, symbol)?;
 
-        // Check if remote directory exists
-        match client.path_exists(&remote_dir).await {
-            Ok(true) => {
-                info!("Remote directory exists: {}", remote_dir);
-            }
-            Ok(false) => {
-                warn!("Remote directory does not exist: {} on {}, skipping", remote_dir, http_config.base_url);
-                continue;
-            }
-            Err(e) => {
-                error!("Failed to check remote directory on {}: {}", http_config.base_url, e);
-                continue;
-            }
+        if let Some(ref postgres_config) = output_config.postgres {
+            write_rows_to_postgres(&rows, postgres_config).await
+                .context(format!("Failed to write rows for symbol {} to Postgres", symbol))?;
         }
 
-        // List JSONL files in the remote directory
-        let files = match client.list_files(&remote_dir).await {
-            Ok(files) => files,
-            Err(e) => {
-                error!("Failed to list files on {}: {}", http_config.base_url, e);
-                continue;
-            }
-        };
-
-        let jsonl_files: Vec<String> = files
-            .into_iter()
-            .filter(|f| f.ends_with(".jsonl"))
-            .collect();
-
-        info!("Found {} JSONL files on {}", jsonl_files.len(), http_config.base_url);
-
-        // Build full paths for parallel download
-        let remote_file_paths: Vec<String> = jsonl_files
-            .iter()
-            .map(|file| format!("{}/{}", remote_dir, file))
-            .collect();
-
-        // Download all files in parallel
-        info!("Starting parallel download of {} files from {}", remote_file_paths.len(), http_config.base_url);
-        match client.download_files_parallel(remote_file_paths.clone()).await {
-            Ok(downloaded_files) => {
-                info!("Successfully downloaded {} files from {}", downloaded_files.len(), http_config.base_url);
-                
-                // Process each downloaded file
-                for (remote_file_path, content) in downloaded_files {
-                    let content_str = String::from_utf8_lossy(&content);
-                    
-                    match merger.add_jsonl_data(&content_str, &source_name) {
-                        Ok(count) => {
-                            let file_name = remote_file_path.split('/').last().unwrap_or(&remote_file_path);
-                            info!("Added {} records from {}", count, file_name);
-                        }
-                        Err(e) => {
-                            error!("Failed to parse data from {}: {}", remote_file_path, e);
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to download files from {}: {}", http_config.base_url, e);
-                continue;
+        let mut rows = rows.into_iter();
+        loop {
+            let chunk: Vec<DataRow> = rows.by_ref().take(batch_size - buffered).collect();
+            if chunk.is_empty() {
+                break;
             }
+            buffered = (buffered + chunk.len()) % batch_size;
+            writer.write_rows(chunk).await
+                .context(format!("Failed to write combined rows for symbol {}", symbol))?;
         }
     }
 
-    // Check if we have any data
-    if merger.is_empty() {
-        bail!("No data collected from any source");
-    }
-
-    info!("Total unique seconds before forward-fill: {}", merger.len());
-
-    // Apply forward-fill
-    merger.apply_forward_fill()
-        .context("Failed to apply forward-fill")?;
+    writer.flush_buffer().await
+        .context("Failed to flush final buffer")?;
+    Ok(writer.written_files().to_vec())
+}
 
-    info!("Total seconds after forward-fill: {}", merger.len());
+/// Build the Parquet writer configuration for an output and date
+/// Shared by the writer and the `--skip-existing` check so their file paths can't diverge
+fn parquet_writer_config(
+    output_config: &trade_data_processor::OutputConfig,
+    date: NaiveDate,
+    batch_size: usize,
+) -> ParquetWriterConfig {
+    ParquetWriterConfig {
+        path: output_config.path.clone(),
+        name: output_config.name.clone(),
+        batch_size,
+        has_batch_config: output_config.batch_size.is_some(),
+        filter: Vec::new(), // No filtering
+        date: Some(date),
+        use_temp_dir: output_config.use_temp_dir,
+        write_checksum: output_config.write_checksum,
+        write_latest_pointer: output_config.write_latest_pointer,
+        row_group_size: output_config.row_group_size.unwrap_or(DEFAULT_ROW_GROUP_SIZE),
+        column_order: output_config.column_order.clone(),
+        column_encodings: output_config.column_encodings.clone(),
+        dictionary_columns: output_config.dictionary_columns.clone(),
+    }
+}
 
-    // Write to Parquet
-    info!("Writing data to Parquet file...");
-    let rows = merger.get_sorted_rows();
-    write_rows_to_parquet(rows, date, output_config).await?;
+/// Check whether the output for a date has already been written
+fn output_exists(output_config: &trade_data_processor::OutputConfig, date: NaiveDate) -> bool {
+    parquet_writer_config(output_config, date, 0)
+        .expected_parquet_path(date)
+        .exists()
+}
 
-    Ok(())
+/// Build the output configuration for a single mark-price symbol (same path, symbol-specific name)
+fn symbol_output_config(
+    output_config: &trade_data_processor::OutputConfig,
+    symbol: &str,
+) -> trade_data_processor::OutputConfig {
+    trade_data_processor::OutputConfig {
+        name: format!("{}-{}", output_config.name, symbol),
+        ..output_config.clone()
+    }
 }
 
 /// Write data rows to Parquet file (generic helper function)
+/// Returns the paths of the files written
 async fn write_rows_to_parquet(
     rows: Vec<DataRow>,
     date: NaiveDate,
     output_config: &trade_data_processor::OutputConfig,
-) -> Result<()> {
+) -> Result<Vec<PathBuf>> {
     info!("Writing {} rows to Parquet", rows.len());
 
-    match output_config.batch_size {
+    let written_files = match output_config.batch_size {
         // If batch_size is None, write all data to a single file
         None => {
             info!("Writing all data to a single file");
             
-            // Create Parquet writer configuration
-            let parquet_config = ParquetWriterConfig {
-                path: output_config.path.clone(),
-                name: output_config.name.clone(),
-                batch_size: rows.len(), // Use total rows as batch size
-                has_batch_config: false, // No batch_size configured
-                filter: Vec::new(), // No filtering
-                date: Some(date),
-                use_temp_dir: output_config.use_temp_dir,
-            };
+            // Create Parquet writer configuration (use total rows as batch size)
+            let parquet_config = parquet_writer_config(output_config, date, rows.len());
 
             // Create writer
             let mut writer = ParquetWriter::new(parquet_config);
@@ -723,6 +1159,8 @@ async fn write_rows_to_parquet(
             // Flush
             writer.flush_buffer().await
                 .context("Failed to flush final buffer")?;
+
+            writer.written_files().to_vec()
         }
         
         // If batch_size is Some(n), split data into multiple files
@@ -730,18 +1168,11 @@ async fn write_rows_to_parquet(
             info!("Writing data in batches of {} rows", batch_size);
             
             // Create Parquet writer configuration
-            let parquet_config = ParquetWriterConfig {
-                path: output_config.path.clone(),
-                name: output_config.name.clone(),
-                batch_size,
-                has_batch_config: true, // Batch_size explicitly configured
-                filter: Vec::new(), // No filtering
-                date: Some(date),
-                use_temp_dir: output_config.use_temp_dir,
-            };
+            let parquet_config = parquet_writer_config(output_config, date, batch_size);
 
-            // Create writer
+            // Create writer with the schema of all rows, not just the first batch
             let mut writer = ParquetWriter::new(parquet_config);
+            writer.init_schema(&rows)?;
 
             // Write in batches
             for (batch_idx, chunk) in rows.chunks(batch_size).enumerate() {
@@ -753,9 +1184,620 @@ async fn write_rows_to_parquet(
             // Flush any remaining data
             writer.flush_buffer().await
                 .context("Failed to flush final buffer")?;
+
+            writer.written_files().to_vec()
         }
-    }
+    };
 
     info!("Successfully wrote all data to Parquet");
+    Ok(written_files)
+}
+
+/// Insert data rows into the configured Postgres table
+#[cfg(feature = "postgres")]
+async fn write_rows_to_postgres(
+    rows: &[DataRow],
+    postgres_config: &trade_data_processor::PostgresOutputConfig,
+) -> Result<()> {
+    info!("Writing {} rows to Postgres table {}", rows.len(), postgres_config.table);
+
+    let mut writer = trade_data_processor::DbWriter::connect(postgres_config.clone()).await?;
+    for chunk in rows.chunks(postgres_config.batch_size.max(1)) {
+        writer.write_rows(chunk.to_vec()).await
+            .context("Failed to write rows to Postgres")?;
+    }
+    writer.flush_buffer().await
+        .context("Failed to flush Postgres buffer")?;
+
+    info!("Inserted {} rows into {}", writer.rows_written(), postgres_config.table);
     Ok(())
 }
+
+#[cfg(not(feature = "postgres"))]
+async fn write_rows_to_postgres(
+    _rows: &[DataRow],
+    _postgres_config: &trade_data_processor::PostgresOutputConfig,
+) -> Result<()> {
+    bail!("output.postgres is configured but this build does not include the `postgres` feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use trade_data_processor::{DataMerger, DataSourceConfig, LocalFileConfig, OutputConfig};
+
+    /// Create an empty scratch directory unique to this test run
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("trade-data-processor-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn data_source(data_type: &str, local_files: Vec<LocalFileConfig>) -> DataSourceConfig {
+        DataSourceConfig {
+            data_type: data_type.to_string(),
+            ssh_servers: Vec::new(),
+            http_servers: Vec::new(),
+            local_files,
+            symbols_allowlist: Vec::new(),
+            symbols_denylist: Vec::new(),
+            file_extensions: trade_data_processor::config::default_file_extensions(),
+            reject_out_of_range: false,
+            out_of_range_grace_secs: 0,
+            add_source_column: false,
+            dedup_key_fields: Vec::new(),
+            spill_memory_budget_bytes: None,
+            forward_fill_max_rows: None,
+            forward_fill_max_memory_bytes: None,
+            field_renames: None,
+            required_fields: None,
+            optional_numeric_fields: Vec::new(),
+            field_ranges: Default::default(),
+        }
+    }
+
+    /// Local source reading `<dir>/input/YYYY/MM/DD`
+    fn local_files(dir: &Path) -> LocalFileConfig {
+        LocalFileConfig {
+            base_path: dir.join("input").to_string_lossy().to_string(),
+            path_template: trade_data_processor::config::DEFAULT_LOCAL_PATH_TEMPLATE.to_string(),
+            glob: None,
+        }
+    }
+
+    fn output_config(path: &Path, name: &str) -> OutputConfig {
+        OutputConfig {
+            path: path.to_string_lossy().to_string(),
+            name: name.to_string(),
+            batch_size: None,
+            row_group_size: None,
+            column_order: None,
+            use_temp_dir: false,
+            write_checksum: false,
+            write_latest_pointer: false,
+            postgres: None,
+            output_mode: OutputMode::PerSymbol,
+            max_write_concurrency: None,
+            column_encodings: Default::default(),
+            dictionary_columns: None,
+        }
+    }
+
+    /// Expected parquet path for a mark-price symbol
+    fn symbol_output_path(output: &OutputConfig, symbol: &str, date: NaiveDate) -> PathBuf {
+        parquet_writer_config(&symbol_output_config(output, symbol), date, 0).expected_parquet_path(date)
+    }
+
+    #[tokio::test]
+    async fn test_date_range_processes_each_day() {
+        let start = NaiveDate::from_ymd_opt(2025, 11, 5).unwrap();
+        let end = NaiveDate::from_ymd_opt(2025, 11, 7).unwrap();
+        let calls = RefCell::new(Vec::new());
+
+        let summaries = process_date_range(start, end, |date| {
+            calls.borrow_mut().push(date);
+            async { Ok(DayOutcome::Written) }
+        })
+        .await;
+
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(
+            calls.into_inner(),
+            vec![
+                start,
+                NaiveDate::from_ymd_opt(2025, 11, 6).unwrap(),
+                end,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_date_range_continues_past_failed_day() {
+        let start = NaiveDate::from_ymd_opt(2025, 11, 5).unwrap();
+        let end = NaiveDate::from_ymd_opt(2025, 11, 7).unwrap();
+        let failing = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+
+        let summaries = process_date_range(start, end, |date| async move {
+            if date == failing {
+                bail!("download failed");
+            }
+            Ok(DayOutcome::NoData)
+        })
+        .await;
+
+        assert_eq!(summaries.len(), 3);
+        assert!(summaries[1].1.is_err());
+        assert_eq!(summaries[2].1.as_ref().unwrap(), &DayOutcome::NoData);
+    }
+
+    #[tokio::test]
+    async fn test_skip_existing_generic_day() {
+        let dir = test_dir("skip-generic");
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let output = output_config(&dir, "trades");
+        let source = data_source("trades", Vec::new());
+        let clients = SourceClients::new(&source).unwrap();
+        let registry = ProcessorRegistry::default();
+        let options = ProcessOptions { skip_existing: true, ..Default::default() };
+
+        // Nothing written yet: the day is processed (and has no data)
+        let outcome = process_data(date, &source, &output, "trades", &registry, &clients, options).await.unwrap();
+        assert_eq!(outcome, DayOutcome::NoData);
+
+        // Once the expected file exists the day is skipped
+        let expected = parquet_writer_config(&output, date, 0).expected_parquet_path(date);
+        assert_eq!(expected, dir.join("2025/11/06/trades_2025-11-06.parquet"));
+        fs::create_dir_all(expected.parent().unwrap()).unwrap();
+        fs::write(&expected, b"").unwrap();
+
+        let outcome = process_data(date, &source, &output, "trades", &registry, &clients, options).await.unwrap();
+        assert_eq!(outcome, DayOutcome::AlreadyExists);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_skip_existing_mark_price_symbol() {
+        let dir = test_dir("skip-mark-price");
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+
+        // Local source with two symbols
+        let input_dir = dir.join("input/2025/11/06");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(
+            input_dir.join("data.jsonl"),
+            r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.5","r":"0.00005"}"#,
+        )
+        .unwrap();
+        let source = data_source(
+            "mark-price",
+            vec![local_files(&dir)],
+        );
+
+        // BTCUSDT was already written by a previous run
+        let output = output_config(&dir.join("output"), "mark-price");
+        let btc_path = symbol_output_path(&output, "BTCUSDT", date);
+        fs::create_dir_all(btc_path.parent().unwrap()).unwrap();
+        fs::write(&btc_path, b"").unwrap();
+
+        let clients = SourceClients::new(&source).unwrap();
+        let registry = ProcessorRegistry::default();
+        let options = ProcessOptions { skip_existing: true, ..Default::default() };
+        let outcome = process_data(date, &source, &output, "mark-price", &registry, &clients, options).await.unwrap();
+
+        assert_eq!(outcome, DayOutcome::Written);
+        assert_eq!(fs::metadata(&btc_path).unwrap().len(), 0, "existing output must not be rewritten");
+        assert!(symbol_output_path(&output, "ETHUSDT", date).exists());
+
+        // A second run finds every symbol already written
+        let outcome = process_data(date, &source, &output, "mark-price", &registry, &clients, options).await.unwrap();
+        assert_eq!(outcome, DayOutcome::AlreadyExists);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sources_fetched_concurrently_and_merged() {
+        // Both mock sources wait on the same barrier, so they only complete if contacted concurrently
+        let barrier = tokio::sync::Barrier::new(2);
+        let mock_source = |source_name: &'static str, jsonl: &'static str| {
+            let barrier = &barrier;
+            async move {
+                barrier.wait().await;
+                Ok(Some(SourceData {
+                    source_name: source_name.to_string(),
+                    files: vec![(format!("/data/{}.jsonl", source_name), jsonl.as_bytes().to_vec())],
+                }))
+            }
+            .boxed()
+        };
+        let fetches = vec![
+            mock_source("ssh-1", r#"{"E":1762411870001,"p":"1.0"}"#),
+            mock_source("http-1", r#"{"E":1762411871001,"p":"2.0"}"#),
+        ];
+
+        let sources = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            fetch_sources_concurrently(fetches),
+        )
+        .await
+        .expect("sources were not fetched concurrently")
+        .unwrap();
+
+        let source_names: Vec<&str> = sources.iter().map(|s| s.source_name.as_str()).collect();
+        assert_eq!(source_names, vec!["ssh-1", "http-1"]);
+
+        let mut merger = DataMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap());
+        merge_source_data(sources, "generic", false, |reader, source_name| {
+            Ok(merger.add_jsonl_reader(reader, source_name)?)
+        });
+        assert_eq!(merger.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_lists_symbols_and_files() {
+        let dir = test_dir("manifest");
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+
+        let input_dir = dir.join("input/2025/11/06");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(
+            input_dir.join("data.jsonl"),
+            r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.5","r":"0.00005"}
+{"E":1762411871001,"s":"ETHUSDT","p":"3377.6","r":"0.00005"}"#,
+        )
+        .unwrap();
+        let source = data_source(
+            "mark-price",
+            vec![local_files(&dir)],
+        );
+        let output = output_config(&dir.join("output"), "mark-price");
+        let clients = SourceClients::new(&source).unwrap();
+        let registry = ProcessorRegistry::default();
+        let options = ProcessOptions { manifest: true, ..Default::default() };
+
+        process_data(date, &source, &output, "mark-price", &registry, &clients, options).await.unwrap();
+
+        let content = fs::read_to_string(dir.join("output/manifest-2025-11-06.json")).unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(manifest["date"], "2025-11-06");
+        assert_eq!(manifest["outcome"], "written");
+        assert_eq!(manifest["symbols"], serde_json::json!(["BTCUSDT", "ETHUSDT"]));
+        assert_eq!(manifest["source_record_counts"]["local-1"], 3);
+        assert_eq!(manifest["rows_before_forward_fill"], 3);
+        assert_eq!(manifest["symbol_row_counts"]["ETHUSDT"]["after_forward_fill"], 86400);
+
+        let mut output_files: Vec<PathBuf> = manifest["output_files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| PathBuf::from(f.as_str().unwrap()))
+            .collect();
+        output_files.sort();
+        assert_eq!(
+            output_files,
+            vec![
+                symbol_output_path(&output, "BTCUSDT", date),
+                symbol_output_path(&output, "ETHUSDT", date),
+            ]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_combined_output_writes_one_file_for_all_symbols() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let dir = test_dir("combined");
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+
+        let input_dir = dir.join("input/2025/11/06");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(
+            input_dir.join("data.jsonl"),
+            r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.5","r":"0.00005"}"#,
+        )
+        .unwrap();
+        let source = data_source(
+            "mark-price",
+            vec![local_files(&dir)],
+        );
+        let output = OutputConfig {
+            output_mode: OutputMode::Combined,
+            ..output_config(&dir.join("output"), "mark-price")
+        };
+        let clients = SourceClients::new(&source).unwrap();
+        let registry = ProcessorRegistry::default();
+
+        let outcome = process_data(date, &source, &output, "mark-price", &registry, &clients, ProcessOptions::default()).await.unwrap();
+        assert_eq!(outcome, DayOutcome::Written);
+
+        let day_dir = parquet_writer_config(&output, date, 0).parquet_dir(date);
+        let files: Vec<PathBuf> = fs::read_dir(&day_dir).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(files, vec![parquet_writer_config(&output, date, 0).expected_parquet_path(date)]);
+        assert!(!symbol_output_path(&output, "BTCUSDT", date).exists());
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&files[0]).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut symbols = std::collections::BTreeSet::new();
+        let mut total_rows = 0;
+        for batch in reader {
+            let batch = batch.unwrap();
+            total_rows += batch.num_rows();
+            let column = batch
+                .column_by_name("symbol")
+                .unwrap()
+                .as_any()
+                .downcast_ref::<arrow::array::StringArray>()
+                .unwrap();
+            symbols.extend(column.iter().flatten().map(str::to_string));
+        }
+        assert_eq!(total_rows, 2 * 86400);
+        assert_eq!(symbols.into_iter().collect::<Vec<_>>(), ["BTCUSDT", "ETHUSDT"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_combined_output_batches_keep_columns_of_later_symbols() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let dir = test_dir("combined-batches");
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+
+        let input_dir = dir.join("input/2025/11/06");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(
+            input_dir.join("data.jsonl"),
+            r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.5","r":"0.00005","i":"3377.1"}"#,
+        )
+        .unwrap();
+        let source = data_source("mark-price", vec![local_files(&dir)]);
+        let output = OutputConfig {
+            output_mode: OutputMode::Combined,
+            batch_size: Some(50_000),
+            ..output_config(&dir.join("output"), "mark-price")
+        };
+        let clients = SourceClients::new(&source).unwrap();
+        let registry = ProcessorRegistry::default();
+
+        let outcome = process_data(date, &source, &output, "mark-price", &registry, &clients, ProcessOptions::default()).await.unwrap();
+        assert_eq!(outcome, DayOutcome::Written);
+
+        let day_dir = parquet_writer_config(&output, date, 0).parquet_dir(date);
+        let mut row_counts = Vec::new();
+        for entry in fs::read_dir(&day_dir).unwrap() {
+            let reader = SerializedFileReader::new(fs::File::open(entry.unwrap().path()).unwrap()).unwrap();
+            let metadata = reader.metadata().file_metadata();
+            assert!(metadata.schema_descr().columns().iter().any(|column| column.name() == "index_price"));
+            row_counts.push(metadata.num_rows());
+        }
+        row_counts.sort();
+        assert_eq!(row_counts, [22_800, 50_000, 50_000, 50_000]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strict_utf8_names_source() {
+        let content = b"{\"E\":1762411870001}\n\xff\xfe";
+
+        let err = check_source_file(content, "/data/2025/11/06/a.jsonl", "ssh-1-host", false).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("/data/2025/11/06/a.jsonl"), "{}", message);
+        assert!(message.contains("ssh-1-host"), "{}", message);
+        assert!(message.contains("byte offset 20"), "{}", message);
+
+        check_source_file(content, "/data/2025/11/06/a.jsonl", "ssh-1-host", true).unwrap();
+        let mut lossy = String::new();
+        LossyUtf8Lines::new(&content[..]).read_to_string(&mut lossy).unwrap();
+        assert!(lossy.ends_with("\u{FFFD}\u{FFFD}"));
+    }
+
+    #[test]
+    fn test_compressed_files_are_streamed_and_bad_ones_skipped_whole() {
+        let good = zstd::encode_all(&b"{\"E\":1762411870001}\n{\"E\":1762411871001}\n"[..], 3).unwrap();
+        let bad = zstd::encode_all(&b"{\"E\":1762411872001}\n\xff\n"[..], 3).unwrap();
+        let mut truncated = zstd::encode_all(&b"{\"E\":1762411873001}\n{\"E\":1762411874001}\n"[..], 3).unwrap();
+        truncated.truncate(truncated.len() - 4);
+        let sources = vec![SourceData {
+            source_name: "http-1".to_string(),
+            files: vec![
+                ("/data/good.jsonl.zst".to_string(), good),
+                ("/data/bad.jsonl.zst".to_string(), bad.clone()),
+                ("/data/truncated.jsonl.zst".to_string(), truncated),
+            ],
+        }];
+
+        let mut merger = DataMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap());
+        let counts = merge_source_data(sources, "generic", false, |reader, source_name| {
+            Ok(merger.add_jsonl_reader(reader, source_name)?)
+        });
+        assert_eq!(counts["http-1"], 2);
+        assert_eq!(merger.len(), 2);
+
+        // Lossy mode keeps the valid record of the file with a bad line
+        let sources = vec![SourceData {
+            source_name: "http-1".to_string(),
+            files: vec![("/data/bad.jsonl.zst".to_string(), bad)],
+        }];
+        let mut merger = DataMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap());
+        let counts = merge_source_data(sources, "generic", true, |reader, source_name| {
+            Ok(merger.add_jsonl_reader(reader, source_name)?)
+        });
+        assert_eq!(counts["http-1"], 1);
+    }
+
+    #[test]
+    fn test_strict_utf8_skips_invalid_file() {
+        let sources = vec![SourceData {
+            source_name: "ssh-1-host".to_string(),
+            files: vec![
+                ("/data/bad.jsonl".to_string(), b"{\"E\":1762411870001}\n\xff".to_vec()),
+                ("/data/good.jsonl".to_string(), br#"{"E":1762411871001}"#.to_vec()),
+            ],
+        }];
+
+        let mut merger = DataMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap());
+        let counts = merge_source_data(sources, "generic", false, |reader, source_name| {
+            Ok(merger.add_jsonl_reader(reader, source_name)?)
+        });
+        assert_eq!(counts["ssh-1-host"], 1);
+        assert_eq!(merger.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_local_source_discovers_gz_files() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let dir = test_dir("gz-discovery");
+        let input_dir = dir.join("input/2025/11/06");
+        fs::create_dir_all(&input_dir).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(br#"{"E":1762411870001,"p":"1.0"}"#).unwrap();
+        fs::write(input_dir.join("data.jsonl.gz"), encoder.finish().unwrap()).unwrap();
+        fs::write(input_dir.join("notes.txt"), b"not data").unwrap();
+
+        let local = local_files(&dir);
+        let source = data_source("trades", vec![local.clone()]);
+        let data = read_local_source(0, &local, &source, NaiveDate::from_ymd_opt(2025, 11, 6).unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.files.len(), 1);
+        assert!(data.files[0].0.ends_with("data.jsonl.gz"));
+
+        let mut merger = DataMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap());
+        let counts = merge_source_data(vec![data], "generic", false, |reader, source_name| {
+            Ok(merger.add_jsonl_reader(reader, source_name)?)
+        });
+        assert_eq!(counts["local-1"], 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_source_with_flat_layout_template() {
+        let dir = test_dir("flat-layout");
+        let flat_dir = dir.join("input/flat");
+        fs::create_dir_all(&flat_dir).unwrap();
+        for name in ["trades_20251106.jsonl", "trades_20251107.jsonl", "orders_20251106.jsonl"] {
+            fs::write(flat_dir.join(name), br#"{"E":1762411870001,"p":"1.0"}"#).unwrap();
+        }
+
+        let local = LocalFileConfig {
+            path_template: "flat".to_string(),
+            glob: Some("trades_{year}{month}{day}.json*".to_string()),
+            ..local_files(&dir)
+        };
+        let source = data_source("trades", vec![local.clone()]);
+        let data = read_local_source(0, &local, &source, NaiveDate::from_ymd_opt(2025, 11, 6).unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        let names: Vec<String> = data.files.into_iter().map(|(path, _)| path).collect();
+        assert_eq!(names, vec![flat_dir.join("trades_20251106.jsonl").to_string_lossy().to_string()]);
+
+        // An absolute template ignores base_path
+        let absolute = LocalFileConfig {
+            base_path: "/nonexistent".to_string(),
+            path_template: flat_dir.to_string_lossy().to_string(),
+            glob: Some("*_{year}{month}{day}.jsonl".to_string()),
+        };
+        let data = read_local_source(0, &absolute, &source, NaiveDate::from_ymd_opt(2025, 11, 6).unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.files.len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_fails_on_corrupt_file() {
+        let dir = test_dir("verify");
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let output = output_config(&dir, "trades");
+
+        let mut row = DataRow::new();
+        row.insert("E".to_string(), serde_json::json!(1762411870000u64));
+        row.insert("p".to_string(), serde_json::json!("1.0"));
+        write_rows_to_parquet(vec![row.clone(), row], date, &output).await.unwrap();
+        verify_date_range(date, date, &output).unwrap();
+
+        let day_dir = parquet_writer_config(&output, date, 0).parquet_dir(date);
+        fs::write(day_dir.join("corrupt_2025-11-06.parquet"), b"PAR1 not really parquet").unwrap();
+        let report = trade_data_processor::verify_parquet_dir(&day_dir).unwrap();
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.total_rows(), 2);
+        assert_eq!(report.failures.len(), 1);
+        assert!(verify_date_range(date, date, &output).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_json_array_file_parsed() {
+        let sources = vec![SourceData {
+            source_name: "local-1".to_string(),
+            files: vec![(
+                "/data/records.json".to_string(),
+                br#"[{"E":1762411870001,"p":"1.0"}, {"E":1762411871001,"p":"2.0"}]"#.to_vec(),
+            )],
+        }];
+
+        let mut merger = DataMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap());
+        let counts = merge_source_data(sources, "generic", false, |reader, source_name| {
+            Ok(merger.add_jsonl_reader(reader, source_name)?)
+        });
+        assert_eq!(counts["local-1"], 2);
+        assert_eq!(merger.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_write_tasks_respect_concurrency_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut tasks = WriteTasks::new(Some(2));
+        for i in 0..20 {
+            let (running, peak) = (running.clone(), peak.clone());
+            tasks.spawn(format!("SYM{}", i), async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(vec![PathBuf::from(format!("SYM{}.parquet", i))])
+            }).await;
+        }
+        assert_eq!(tasks.len(), 20);
+        let files = tasks.join().await.unwrap();
+        assert_eq!(files.len(), 20);
+        assert_eq!(files[19], PathBuf::from("SYM19.parquet"));
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        // A failing symbol still fails the whole write
+        let mut tasks = WriteTasks::new(Some(2));
+        for i in 0..4 {
+            tasks.spawn(format!("SYM{}", i), async move {
+                if i == 2 {
+                    bail!("disk full");
+                }
+                Ok(Vec::new())
+            }).await;
+        }
+        assert_eq!(tasks.join().await.unwrap_err().to_string(), "disk full");
+    }
+}
@@ -9,14 +9,346 @@
 //! - estimated_settle_price (P): Estimated settle price
 //! - funding_rate (r): Funding rate
 //! - next_funding_time (T): Next funding time (optional)
+//!
+//! The short-to-full field mapping above is the default; other exchanges can supply
+//! their own rename map with `with_field_renames`. Which fields are required is
+//! configurable as well (`with_validation`), e.g. for feeds that omit the funding rate
+//! between funding windows.
+//!
+//! With a spill budget set, symbols that were least recently updated are moved to a
+//! temporary on-disk sled store once the in-memory rows exceed the budget, and
+//! forward-fill then processes one symbol at a time from that store.
 
 use anyhow::{Context, Result};
 use chrono::{NaiveDate, TimeZone, Utc};
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::{BTreeMap, HashMap};
-use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{info, debug, error, warn};
+
+use crate::config::FieldRange;
+use crate::data_merger::{utc_day_range, SOURCE_COLUMN};
+use crate::error::{ProcessorError, ProcessorResult};
+use crate::writer::{DataRow, DataRowExt};
+
+/// Symbol allow/deny lists applied while adding mark-price data
+///
+/// Symbols are normalized by trimming whitespace and uppercasing before comparison,
+/// so " btcusdt" in the config matches "BTCUSDT" in the data.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolFilter {
+    allowlist: HashSet<String>,
+    denylist: HashSet<String>,
+}
+
+impl SymbolFilter {
+    /// Create a filter; an empty allowlist allows every symbol not in the denylist
+    pub fn new(allowlist: &[String], denylist: &[String]) -> Self {
+        Self {
+            allowlist: allowlist.iter().map(|s| Self::normalize(s)).collect(),
+            denylist: denylist.iter().map(|s| Self::normalize(s)).collect(),
+        }
+    }
+
+    /// Normalize a symbol for comparison (trimmed, uppercase)
+    pub fn normalize(symbol: &str) -> String {
+        symbol.trim().to_uppercase()
+    }
+
+    /// Check whether a symbol passes the filter
+    pub fn matches(&self, symbol: &str) -> bool {
+        let symbol = Self::normalize(symbol);
+        (self.allowlist.is_empty() || self.allowlist.contains(&symbol)) && !self.denylist.contains(&symbol)
+    }
+}
+
+/// Binance short field names and the full names they are renamed to
+pub const BINANCE_FIELD_RENAMES: &[(&str, &str)] = &[
+    ("e", "event_type"),
+    ("s", "symbol"),
+    ("p", "mark_price"),
+    ("i", "index_price"),
+    ("P", "estimated_settle_price"),
+    ("r", "funding_rate"),
+    ("T", "next_funding_time"),
+    ("E", "event_time"),
+];
+
+/// Default field rename map (the Binance mark-price payload)
+pub fn default_field_renames() -> HashMap<String, String> {
+    BINANCE_FIELD_RENAMES
+        .iter()
+        .map(|(short, full)| (short.to_string(), full.to_string()))
+        .collect()
+}
+
+/// Data-driven validation of normalized mark-price rows
+#[derive(Debug, Clone)]
+pub struct RowValidation {
+    /// Fields that must be present and numeric
+    pub required_fields: Vec<String>,
+    /// Fields that may be missing or null but must be numeric when present
+    pub optional_numeric_fields: Vec<String>,
+    /// Sanity ranges; values outside are flagged (logged and counted) but the row is kept
+    pub field_ranges: HashMap<String, FieldRange>,
+}
+
+impl Default for RowValidation {
+    /// Requires numeric `mark_price` and `funding_rate`, no range checks
+    fn default() -> Self {
+        Self {
+            required_fields: vec!["mark_price".to_string(), "funding_rate".to_string()],
+            optional_numeric_fields: Vec::new(),
+            field_ranges: HashMap::new(),
+        }
+    }
+}
+
+impl RowValidation {
+    /// First field that makes the row invalid, or `None` if the row is valid
+    pub fn invalid_field<'a>(&'a self, row: &DataRow) -> Option<&'a str> {
+        let missing_required = self.required_fields.iter()
+            .find(|field| row.get_f64(field).is_none());
+        let non_numeric_optional = self.optional_numeric_fields.iter()
+            .find(|field| !matches!(row.get(field.as_str()), None | Some(Value::Null)) && row.get_f64(field).is_none());
+        missing_required.or(non_numeric_optional).map(String::as_str)
+    }
+
+    pub fn is_valid(&self, row: &DataRow) -> bool {
+        self.invalid_field(row).is_none()
+    }
+
+    /// Fields whose numeric value falls outside the configured range, with the value
+    pub fn range_violations<'a>(&'a self, row: &DataRow) -> Vec<(&'a str, f64)> {
+        self.field_ranges.iter()
+            .filter_map(|(field, range)| {
+                let value = row.get_f64(field)?;
+                (!range.contains(value)).then_some((field.as_str(), value))
+            })
+            .collect()
+    }
+}
+
+/// Spill activity of a merger run, reported in the run summary
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SpillStats {
+    /// Number of times a symbol's in-memory rows were moved to disk
+    pub spill_events: u64,
+    /// Distinct symbols that were spilled before forward-fill
+    pub spilled_symbols: usize,
+    /// Rows moved to disk before forward-fill
+    pub spilled_rows: u64,
+    /// Estimated size of the rows moved to disk
+    pub spilled_bytes: u64,
+    /// Highest estimated in-memory size reached while adding data
+    pub peak_resident_bytes: usize,
+}
+
+/// Upper bounds on the forward-filled day, checked before forward-fill allocates anything
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ForwardFillLimits {
+    /// Maximum rows (symbols × seconds) the filled day may hold
+    pub max_estimated_rows: Option<u64>,
+    /// Maximum estimated bytes held in memory while filling
+    pub max_memory_bytes: Option<u64>,
+}
+
+/// Row size assumed when no resident row is available to measure (every symbol spilled)
+const DEFAULT_ROW_BYTES: u64 = 512;
+
+/// Distinguishes the spill directories of mergers created by the same process
+static SPILL_DIR_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Memory budget bookkeeping and the on-disk store for spilled symbols
+struct SpillState {
+    budget_bytes: usize,
+    /// Opened on first spill so runs that stay within budget never touch the disk
+    db: Option<sled::Db>,
+    /// Estimated in-memory bytes per symbol
+    resident_bytes: HashMap<String, usize>,
+    total_resident_bytes: usize,
+    /// Logical time of each symbol's last added row, used to pick spill victims
+    last_updated: HashMap<String, u64>,
+    clock: u64,
+    /// Symbols with rows in the spill store
+    spilled_symbols: HashSet<String>,
+    stats: SpillStats,
+}
+
+impl SpillState {
+    fn new(budget_bytes: usize) -> Self {
+        Self {
+            budget_bytes,
+            db: None,
+            resident_bytes: HashMap::new(),
+            total_resident_bytes: 0,
+            last_updated: HashMap::new(),
+            clock: 0,
+            spilled_symbols: HashSet::new(),
+            stats: SpillStats::default(),
+        }
+    }
+
+    /// Rough in-memory size of a row (keys, string payloads and per-entry overhead)
+    fn estimate_row_bytes(row: &DataRow) -> usize {
+        row.iter()
+            .map(|(key, value)| {
+                let value_bytes = match value {
+                    Value::String(s) => s.len(),
+                    _ => 16,
+                };
+                key.len() + value_bytes + 64
+            })
+            .sum()
+    }
+
+    /// Sled keys are big-endian with the sign bit flipped so they sort like the timestamps
+    fn encode_key(dedup_key: i64) -> [u8; 8] {
+        ((dedup_key as u64) ^ (1 << 63)).to_be_bytes()
+    }
+
+    fn decode_key(bytes: &[u8]) -> Result<i64> {
+        let bytes: [u8; 8] = bytes.try_into().context("Invalid spill key length")?;
+        Ok((u64::from_be_bytes(bytes) ^ (1 << 63)) as i64)
+    }
+
+    fn db(&mut self) -> Result<&sled::Db> {
+        if self.db.is_none() {
+            // Use an explicit path: sled's default temporary location may be /dev/shm, i.e. RAM
+            let path: PathBuf = std::env::temp_dir().join(format!(
+                "trade-data-processor-spill-{}-{}",
+                std::process::id(),
+                SPILL_DIR_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            info!("Opening spill store at {:?} (budget {} bytes)", path, self.budget_bytes);
+            let db = sled::Config::new()
+                .path(&path)
+                .temporary(true)
+                .open()
+                .context(format!("Failed to open spill store at {:?}", path))?;
+            self.db = Some(db);
+        }
+        Ok(self.db.as_ref().expect("spill store opened above"))
+    }
+
+    fn tree(&self, symbol: &str) -> Result<Option<sled::Tree>> {
+        match &self.db {
+            Some(db) if self.spilled_symbols.contains(symbol) => Ok(Some(db.open_tree(symbol)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether a row for this symbol and dedup key is already on disk
+    fn contains(&self, symbol: &str, dedup_key: i64) -> Result<bool> {
+        match self.tree(symbol)? {
+            Some(tree) => Ok(tree.contains_key(Self::encode_key(dedup_key))?),
+            None => Ok(false),
+        }
+    }
+
+    /// Number of rows on disk for a symbol
+    fn len(&self, symbol: &str) -> Result<usize> {
+        Ok(self.tree(symbol)?.map(|tree| tree.len()).unwrap_or(0))
+    }
+
+    /// Record that a row was added to memory for a symbol
+    fn track_added(&mut self, symbol: &str, row_bytes: usize) {
+        self.clock += 1;
+        self.last_updated.insert(symbol.to_string(), self.clock);
+        *self.resident_bytes.entry(symbol.to_string()).or_insert(0) += row_bytes;
+        self.total_resident_bytes += row_bytes;
+        self.stats.peak_resident_bytes = self.stats.peak_resident_bytes.max(self.total_resident_bytes);
+    }
+
+    /// Forget the in-memory size of a symbol whose rows left memory
+    fn untrack(&mut self, symbol: &str) -> usize {
+        let bytes = self.resident_bytes.remove(symbol).unwrap_or(0);
+        self.total_resident_bytes -= bytes;
+        bytes
+    }
+
+    /// Write rows to a symbol's tree, keeping rows already on disk
+    fn write_rows(&mut self, symbol: &str, rows: &BTreeMap<i64, DataRow>) -> Result<()> {
+        let tree = self.db()?.open_tree(symbol)?;
+        let mut batch = sled::Batch::default();
+        for (dedup_key, row) in rows {
+            batch.insert(&Self::encode_key(*dedup_key)[..], serde_json::to_vec(row)?);
+        }
+        tree.apply_batch(batch)?;
+        self.spilled_symbols.insert(symbol.to_string());
+        Ok(())
+    }
 
-use crate::writer::DataRow;
+    /// Move least-recently-updated symbols to disk until memory is within budget
+    fn spill_until_within_budget(&mut self, data_by_symbol: &mut HashMap<String, BTreeMap<i64, DataRow>>) -> Result<()> {
+        while self.total_resident_bytes > self.budget_bytes {
+            let Some(symbol) = self
+                .resident_bytes
+                .keys()
+                .min_by_key(|symbol| self.last_updated.get(*symbol).copied().unwrap_or(0))
+                .cloned()
+            else {
+                break;
+            };
+
+            let rows = data_by_symbol.remove(&symbol).unwrap_or_default();
+            let was_spilled = self.spilled_symbols.contains(&symbol);
+            self.write_rows(&symbol, &rows)?;
+            let bytes = self.untrack(&symbol);
+
+            self.stats.spill_events += 1;
+            self.stats.spilled_rows += rows.len() as u64;
+            self.stats.spilled_bytes += bytes as u64;
+            if !was_spilled {
+                self.stats.spilled_symbols += 1;
+            }
+            debug!("Spilled {} rows ({} bytes) of symbol {} to disk", rows.len(), bytes, symbol);
+        }
+        Ok(())
+    }
+
+    /// Move a symbol's rows from disk into `rows`, removing them from the store
+    fn take_rows(&mut self, symbol: &str, rows: &mut BTreeMap<i64, DataRow>) -> Result<()> {
+        let Some(tree) = self.tree(symbol)? else {
+            return Ok(());
+        };
+        for entry in tree.iter() {
+            let (key, value) = entry?;
+            rows.insert(Self::decode_key(&key)?, serde_json::from_slice(&value)?);
+        }
+        tree.clear()?;
+        self.spilled_symbols.remove(symbol);
+        Ok(())
+    }
+
+    /// Read a symbol's rows from disk, merged with `resident` rows
+    fn read_rows(&self, symbol: &str, resident: Option<&BTreeMap<i64, DataRow>>) -> Result<Vec<DataRow>> {
+        let mut rows = resident.cloned().unwrap_or_default();
+        if let Some(tree) = self.tree(symbol)? {
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                rows.insert(Self::decode_key(&key)?, serde_json::from_slice(&value)?);
+            }
+        }
+        Ok(rows.into_values().collect())
+    }
+
+    /// Drop all rows of a symbol from disk, returning whether any existed
+    fn remove(&mut self, symbol: &str) -> Result<bool> {
+        self.untrack(symbol);
+        self.last_updated.remove(symbol);
+        if !self.spilled_symbols.remove(symbol) {
+            return Ok(false);
+        }
+        if let Some(db) = &self.db {
+            db.drop_tree(symbol)?;
+        }
+        Ok(true)
+    }
+}
 
 /// Mark price data merger with forward-fill capability
 /// Specifically handles Binance mark-price data with validation
@@ -29,6 +361,22 @@ pub struct MarkPriceMerger {
     data_by_symbol: HashMap<String, BTreeMap<i64, DataRow>>,
     /// The date being processed
     date: NaiveDate,
+    /// Symbols outside this filter are never stored
+    symbol_filter: SymbolFilter,
+    /// Grace window (seconds) around the UTC day; `None` accepts any timestamp
+    date_range_grace_secs: Option<u64>,
+    /// On-disk spill of symbols over the memory budget; `None` keeps everything in memory
+    spill: Option<SpillState>,
+    /// Payload field name -> full field name, applied before validation
+    field_renames: HashMap<String, String>,
+    /// Required/optional fields and sanity ranges checked on normalized rows
+    validation: RowValidation,
+    /// Stored rows that had at least one value outside its sanity range
+    range_violations: u64,
+    /// Store the source name in `SOURCE_COLUMN` of every added row
+    add_source_column: bool,
+    /// Bounds checked before forward-fill; the default checks nothing
+    forward_fill_limits: ForwardFillLimits,
 }
 
 impl MarkPriceMerger {
@@ -37,51 +385,95 @@ impl MarkPriceMerger {
         Self {
             data_by_symbol: HashMap::new(),
             date,
+            symbol_filter: SymbolFilter::default(),
+            date_range_grace_secs: None,
+            spill: None,
+            field_renames: default_field_renames(),
+            validation: RowValidation::default(),
+            range_violations: 0,
+            add_source_column: false,
+            forward_fill_limits: ForwardFillLimits::default(),
         }
     }
 
+    /// Replace the default Binance field rename map, e.g. for OKX/Bybit payloads
+    /// The map must produce `symbol`, `event_time`, `mark_price` and `funding_rate`
+    pub fn with_field_renames(mut self, field_renames: HashMap<String, String>) -> Self {
+        self.field_renames = field_renames;
+        self
+    }
+
+    /// Replace the default validation (numeric `mark_price` and `funding_rate` required)
+    pub fn with_validation(mut self, validation: RowValidation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Number of stored rows flagged by the sanity range checks
+    pub fn range_violations(&self) -> u64 {
+        self.range_violations
+    }
+
+    /// Only store data for symbols that pass the filter
+    pub fn with_symbol_filter(mut self, symbol_filter: SymbolFilter) -> Self {
+        self.symbol_filter = symbol_filter;
+        self
+    }
+
+    /// Reject records outside the date's UTC day (plus `grace_secs` on both sides)
+    pub fn with_date_range_check(mut self, grace_secs: u64) -> Self {
+        self.date_range_grace_secs = Some(grace_secs);
+        self
+    }
+
+    /// Record the source of each row in a `_source` field
+    /// Forward-filled rows keep the source of the row they were filled from
+    pub fn with_source_column(mut self) -> Self {
+        self.add_source_column = true;
+        self
+    }
+
+    /// Spill least-recently-updated symbols to a temporary disk store once the
+    /// estimated in-memory size of the rows exceeds `budget_bytes`
+    pub fn with_spill_budget(mut self, budget_bytes: usize) -> Self {
+        self.spill = Some(SpillState::new(budget_bytes));
+        self
+    }
+
+    /// Refuse to forward-fill when the filled day would exceed `limits`
+    pub fn with_forward_fill_limits(mut self, limits: ForwardFillLimits) -> Self {
+        self.forward_fill_limits = limits;
+        self
+    }
+
+    /// Spill activity so far, if spilling is enabled
+    pub fn spill_stats(&self) -> Option<SpillStats> {
+        self.spill.as_ref().map(|spill| spill.stats.clone())
+    }
+
     /// Extract symbol from a data row
     /// Tries new field name first ("symbol"), then falls back to short name ("s")
     pub fn extract_symbol(row: &DataRow) -> Option<String> {
-        row.get("symbol")
-            .or_else(|| row.get("s"))
-            .and_then(|v| match v {
-                Value::String(s) => Some(s.clone()),
-                _ => None,
-            })
+        row.get_str("symbol")
+            .or_else(|| row.get_str("s"))
+            .map(str::to_string)
     }
 
     /// Extract deduplication key from a data row (timestamp in seconds)
     /// Tries new field name first ("event_time"), then falls back to short name ("E")
     /// Converts milliseconds to seconds for deduplication
     pub fn extract_dedup_key(row: &DataRow) -> Option<i64> {
-        row.get("event_time")
-            .or_else(|| row.get("E"))
-            .and_then(|v| match v {
-                Value::Number(n) => n.as_i64(),
-                Value::String(s) => s.parse::<i64>().ok(),
-                _ => None,
-            })
+        row.get_i64("event_time")
+            .or_else(|| row.get_i64("E"))
             .map(|millis| millis / 1000) // Convert milliseconds to seconds
     }
 
-    /// Normalize field names from short form to full names
-    /// Maps: e->event_type, s->symbol, p->mark_price, i->index_price, P->estimated_settle_price, r->funding_rate, T->next_funding_time
-    fn normalize_field_names(row: &mut DataRow) {
-        let replacements = vec![
-            ("e", "event_type"),
-            ("s", "symbol"),
-            ("p", "mark_price"),
-            ("i", "index_price"),
-            ("P", "estimated_settle_price"),
-            ("r", "funding_rate"),
-            ("T", "next_funding_time"),
-            ("E", "event_time"),
-        ];
-        
-        for (short, full) in replacements {
+    /// Normalize field names to full names using the configured rename map
+    /// Defaults to the Binance map (see `BINANCE_FIELD_RENAMES`)
+    fn normalize_field_names(&self, row: &mut DataRow) {
+        for (short, full) in &self.field_renames {
             if let Some(value) = row.remove(short) {
-                row.insert(full.to_string(), value);
+                row.insert(full.clone(), value);
             }
         }
     }
@@ -90,14 +482,39 @@ impl MarkPriceMerger {
     /// Required fields: event_time/E (timestamp), symbol/s (symbol), mark_price/p, funding_rate/r
     /// Field names are normalized to full names during processing
     /// Original event_time value is preserved; deduplication uses a separate key
-    pub fn add_jsonl_data(&mut self, jsonl_content: &str, source_name: &str) -> Result<usize> {
+    pub fn add_jsonl_data(&mut self, jsonl_content: &str, source_name: &str) -> ProcessorResult<usize> {
+        self.add_jsonl_reader(jsonl_content.as_bytes(), source_name)
+    }
+
+    /// Add mark-price JSONL data parsed line by line from a reader
+    /// Same validation as `add_jsonl_data`; read errors (including invalid UTF-8) abort with the line number
+    /// Failures are returned as `ProcessorError::Merge`
+    pub fn add_jsonl_reader<R: BufRead>(&mut self, reader: R, source_name: &str) -> ProcessorResult<usize> {
+        self.add_jsonl_reader_impl(reader, source_name)
+            .map_err(|source| ProcessorError::Merge { source_name: source_name.to_string(), source })
+    }
+
+    fn add_jsonl_reader_impl<R: BufRead>(&mut self, mut reader: R, source_name: &str) -> Result<usize> {
         let mut added_count = 0;
         let mut skipped_count = 0;
         let mut invalid_count = 0;
         let mut missing_symbol_count = 0;
+        let mut filtered_count = 0;
+        let mut out_of_range_count = 0;
+        let mut flagged_count = 0;
+        let allowed_range = self.date_range_grace_secs.map(|grace| utc_day_range(self.date, grace));
 
-        for (line_num, line) in jsonl_content.lines().enumerate() {
-            let line = line.trim();
+        // Reuse one buffer for every line instead of allocating a String per line
+        let mut buffer = String::new();
+        for line_num in 0usize.. {
+            buffer.clear();
+            let read = reader
+                .read_line(&mut buffer)
+                .with_context(|| format!("Failed to read line {} from {}", line_num + 1, source_name))?;
+            if read == 0 {
+                break;
+            }
+            let line = buffer.trim();
             if line.is_empty() {
                 continue;
             }
@@ -111,97 +528,117 @@ impl MarkPriceMerger {
                 }
             };
 
-            // Convert to DataRow
-            if let Value::Object(obj) = value {
-                let mut row: DataRow = obj.into_iter().collect();
+            // A line holds either one object or an array of objects (batched updates)
+            let records = match value {
+                Value::Array(items) => items,
+                other => vec![other],
+            };
 
-                // Normalize field names to full names
-                Self::normalize_field_names(&mut row);
+            // Convert each record to DataRow
+            for value in records {
+                if let Value::Object(obj) = value {
+                    let mut row: DataRow = obj.into_iter().collect();
 
-                // Extract symbol - required for grouping by trading pair
-                let symbol = match Self::extract_symbol(&row) {
-                    Some(s) => s,
-                    None => {
-                        missing_symbol_count += 1;
-                        warn!("Line {} from {} missing 'symbol' field, skipping", line_num + 1, source_name);
+                    // Normalize field names to full names
+                    self.normalize_field_names(&mut row);
+
+                    // Extract symbol - required for grouping by trading pair
+                    let symbol = match Self::extract_symbol(&row) {
+                        Some(s) => s,
+                        None => {
+                            missing_symbol_count += 1;
+                            warn!("Line {} from {} missing 'symbol' field, skipping", line_num + 1, source_name);
+                            continue;
+                        }
+                    };
+
+                    // Drop symbols excluded by the allow/deny lists before storing anything
+                    if !self.symbol_filter.matches(&symbol) {
+                        filtered_count += 1;
                         continue;
                     }
-                };
 
-                // Extract dedup key (timestamp in seconds)
-                if let Some(dedup_key) = Self::extract_dedup_key(&row) {
-                    // Validate mark-price specific fields
-                    if Self::is_valid_mark_price_row(&row) {
-                        // Get or create the BTreeMap for this symbol
-                        let symbol_data = self.data_by_symbol.entry(symbol.clone()).or_insert_with(BTreeMap::new);
-                        
-                        // Check if this dedup key already has data for this symbol
-                        if symbol_data.contains_key(&dedup_key) {
-                            skipped_count += 1;
-                            debug!("Skipping duplicate mark-price data for symbol {} dedup_key {} from {}", symbol, dedup_key, source_name);
+                    // Extract dedup key (timestamp in seconds)
+                    if let Some(dedup_key) = Self::extract_dedup_key(&row) {
+                        // Reject timestamps outside the processing date so forward-fill can't spread them
+                        if allowed_range.as_ref().is_some_and(|range| !range.contains(&dedup_key)) {
+                            out_of_range_count += 1;
+                            debug!("Skipping out-of-range timestamp {} for symbol {} from {}", dedup_key, symbol, source_name);
+                        } else if let Some(field) = self.validation.invalid_field(&row) {
+                            invalid_count += 1;
+                            warn!("Line {} from {} has missing or non-numeric field '{}', skipping", line_num + 1, source_name, field);
                         } else {
-                            // Add timestamp field for reference (in milliseconds, last 3 digits are 000)
-                            row.insert("timestamp".to_string(), Value::Number((dedup_key * 1000).into()));
+                            // Mark-price specific fields are valid
+                            // Get or create the BTreeMap for this symbol
+                            let symbol_data = self.data_by_symbol.entry(symbol.clone()).or_default();
+                        
+                            // Check if this dedup key already has data for this symbol (in memory or spilled)
+                            let spilled = match &self.spill {
+                                Some(spill) => spill.contains(&symbol, dedup_key)?,
+                                None => false,
+                            };
+                            if symbol_data.contains_key(&dedup_key) || spilled {
+                                skipped_count += 1;
+                                debug!("Skipping duplicate mark-price data for symbol {} dedup_key {} from {}", symbol, dedup_key, source_name);
+                            } else {
+                                let violations = self.validation.range_violations(&row);
+                                if !violations.is_empty() {
+                                    flagged_count += 1;
+                                    warn!("Line {} from {} for symbol {} has values outside their sanity range: {:?}", line_num + 1, source_name, symbol, violations);
+                                }
+
+                                // Add timestamp field for reference (in milliseconds, last 3 digits are 000)
+                                row.insert("timestamp".to_string(), Value::Number((dedup_key * 1000).into()));
+                                if self.add_source_column {
+                                    row.insert(SOURCE_COLUMN.to_string(), Value::String(source_name.to_string()));
+                                }
                             
-                            symbol_data.insert(dedup_key, row);
-                            added_count += 1;
+                                let row_bytes = self.spill.as_ref().map(|_| SpillState::estimate_row_bytes(&row));
+                                symbol_data.insert(dedup_key, row);
+                                added_count += 1;
+
+                                if let (Some(spill), Some(row_bytes)) = (self.spill.as_mut(), row_bytes) {
+                                    spill.track_added(&symbol, row_bytes);
+                                    spill.spill_until_within_budget(&mut self.data_by_symbol)?;
+                                }
+                            }
                         }
                     } else {
-                        invalid_count += 1;
-                        warn!("Line {} from {} has invalid mark-price fields (missing mark_price or funding_rate), skipping", line_num + 1, source_name);
+                        warn!("Line {} from {} missing 'event_time' field, skipping", line_num + 1, source_name);
                     }
                 } else {
-                    warn!("Line {} from {} missing 'event_time' field, skipping", line_num + 1, source_name);
+                    warn!("Line {} from {} has a record that is not a JSON object, skipping", line_num + 1, source_name);
                 }
-            } else {
-                warn!("Line {} from {} is not a JSON object, skipping", line_num + 1, source_name);
             }
         }
 
-        info!("Added {} mark-price records from {} ({} skipped as duplicates, {} invalid, {} missing symbol)", 
-              added_count, source_name, skipped_count, invalid_count, missing_symbol_count);
+        self.range_violations += flagged_count;
+        info!("Added {} mark-price records from {} ({} skipped as duplicates, {} invalid, {} missing symbol, {} filtered by symbol, {} out of range, {} flagged by sanity ranges)", 
+              added_count, source_name, skipped_count, invalid_count, missing_symbol_count, filtered_count, out_of_range_count, flagged_count);
         Ok(added_count)
     }
 
-    /// Validate that a row contains required mark-price fields
-    /// Required: mark_price and funding_rate (with backward compatibility for short names)
-    fn is_valid_mark_price_row(row: &DataRow) -> bool {
-        // Check for required mark-price fields (try full names first, then short names)
-        let has_price = row.get("mark_price").is_some() || row.get("p").is_some();
-        let has_funding_rate = row.get("funding_rate").is_some() || row.get("r").is_some();
-        
-        // Price should be numeric (check full name first)
-        let price_value = row.get("mark_price").or_else(|| row.get("p"));
-        let price_valid = price_value
-            .map(|v| matches!(v, Value::Number(_) | Value::String(_)))
-            .unwrap_or(false);
-        
-        // Funding rate should be numeric (check full name first)
-        let rate_value = row.get("funding_rate").or_else(|| row.get("r"));
-        let rate_valid = rate_value
-            .map(|v| matches!(v, Value::Number(_) | Value::String(_)))
-            .unwrap_or(false);
-        
-        has_price && has_funding_rate && price_valid && rate_valid
-    }
-
     /// Apply forward-fill to ensure every second in the UTC day has data for each symbol
     /// Fills the entire day (00:00:00 to 23:59:59 UTC) based on the date
     /// - If data starts after 00:00:00, backfill with the first data point
     /// - If data ends before 23:59:59, forward-fill with the last data point
     /// - For missing seconds in between, use the previous second's data
     /// 
-    /// This method processes symbols in parallel for better performance
-    pub fn apply_forward_fill(&mut self) -> Result<()> {
+    /// This method processes symbols in parallel for better performance,
+    /// or one symbol at a time through the spill store when spilling is enabled
+    /// Failures, including the `ForwardFillLimits` guard, are returned as `ProcessorError::ForwardFill`
+    pub fn apply_forward_fill(&mut self) -> ProcessorResult<()> {
+        self.apply_forward_fill_impl().map_err(ProcessorError::ForwardFill)
+    }
+
+    fn apply_forward_fill_impl(&mut self) -> Result<()> {
         use rayon::prelude::*;
 
-        if self.data_by_symbol.is_empty() {
+        if self.is_empty() {
             warn!("No data to forward-fill");
             return Ok(());
         }
 
-        info!("Applying forward-fill for date {} (mark-price, full UTC day) across {} symbols in parallel", self.date, self.data_by_symbol.len());
-
         // Calculate the start and end timestamps for the UTC day
         // Explicitly use UTC timezone to ensure correct day boundaries
         let day_start_naive = self.date.and_hms_opt(0, 0, 0)
@@ -215,6 +652,14 @@ impl MarkPriceMerger {
 
         debug!("UTC day range: {} to {} ({} seconds)", day_start, day_end, day_end - day_start + 1);
 
+        self.check_forward_fill_limits((day_end - day_start + 1) as u64)?;
+
+        if self.spill.is_some() {
+            return self.apply_forward_fill_spilled(day_start, day_end);
+        }
+
+        info!("Applying forward-fill for date {} (mark-price, full UTC day) across {} symbols in parallel", self.date, self.data_by_symbol.len());
+
         // Collect symbols to process
         let symbols: Vec<String> = self.data_by_symbol.keys().cloned().collect();
         
@@ -222,95 +667,216 @@ impl MarkPriceMerger {
         let results: Vec<(String, BTreeMap<i64, DataRow>, usize)> = symbols
             .par_iter()
             .filter_map(|symbol| {
-                let symbol_data = match self.data_by_symbol.get(symbol) {
-                    Some(data) if !data.is_empty() => data,
-                    _ => return None,
-                };
+                let symbol_data = self.data_by_symbol.get(symbol)?;
+                let (filled_symbol_data, filled_count) = Self::forward_fill_symbol(symbol, symbol_data, day_start, day_end)?;
+                Some((symbol.clone(), filled_symbol_data, filled_count))
+            })
+            .collect();
 
-                debug!("Applying forward-fill for symbol: {}", symbol);
+        // Update the data_by_symbol map with the filled data
+        for (symbol, filled_data, _filled_count) in results {
+            self.data_by_symbol.insert(symbol, filled_data);
+        }
 
-                // Find the first and last actual data timestamp for this symbol
-                let first_data_timestamp = *symbol_data.keys().next()?;
-                let last_data_timestamp = *symbol_data.keys().last()?;
+        Ok(())
+    }
 
-                debug!("Symbol {} actual data range: {} to {}", symbol, first_data_timestamp, last_data_timestamp);
+    /// Estimate the filled day (every symbol × `seconds` rows) and bail if it exceeds the limits
+    /// Filled rows are copies of existing ones, so the row size is averaged over one resident
+    /// row per symbol; spilled runs only hold one symbol's day in memory at a time
+    fn check_forward_fill_limits(&self, seconds: u64) -> Result<()> {
+        let symbols = self.symbol_count() as u64;
+        let estimated_rows = symbols * seconds;
 
-                // Get the first and last available data points for this symbol
-                let first_data = symbol_data.get(&first_data_timestamp)?.clone();
-                let last_data = symbol_data.get(&last_data_timestamp)?.clone();
+        let sampled: Vec<u64> = self
+            .data_by_symbol
+            .values()
+            .filter_map(|rows| rows.values().next())
+            .map(|row| SpillState::estimate_row_bytes(row) as u64)
+            .collect();
+        let row_bytes = match sampled.len() as u64 {
+            0 => DEFAULT_ROW_BYTES,
+            count => sampled.iter().sum::<u64>() / count,
+        };
+        let resident_rows = if self.spill.is_some() { seconds } else { estimated_rows };
+        let estimated_bytes = resident_rows * row_bytes;
 
-                let mut filled_symbol_data = symbol_data.clone();
-                let mut current_data = first_data.clone();
-                let mut filled_count = 0;
+        info!(
+            "Forward-fill estimate for {}: {} symbols × {} seconds = {} rows, ~{} bytes per row, ~{} bytes in memory",
+            self.date, symbols, seconds, estimated_rows, row_bytes, estimated_bytes
+        );
 
-                // Iterate through every second of the UTC day
-                for timestamp in day_start..=day_end {
-                    if let Some(data) = filled_symbol_data.get(&timestamp) {
-                        // Data exists for this second, use it as the current data
-                        current_data = data.clone();
-                    } else {
-                        // No data for this second, need to fill
-                        let filled_data = if timestamp < first_data_timestamp {
-                            // Before first data point: backfill with first data
-                            first_data.clone()
-                        } else if timestamp > last_data_timestamp {
-                            // After last data point: forward-fill with last data
-                            last_data.clone()
-                        } else {
-                            // Between data points: forward-fill with previous data
-                            current_data.clone()
-                        };
-
-                        // Update timestamp for forward-filled entries (preserve original event_time)
-                        let mut filled_data = filled_data;
-                        let filled_timestamp = timestamp * 1000; // in milliseconds (last 3 digits are 000)
-                        // Keep original event_time, only update timestamp to the filled second
-                        filled_data.insert("timestamp".to_string(), Value::Number(filled_timestamp.into()));
-                        
-                        filled_symbol_data.insert(timestamp, filled_data);
-                        filled_count += 1;
-                    }
-                }
+        if let Some(max_rows) = self.forward_fill_limits.max_estimated_rows {
+            if estimated_rows > max_rows {
+                anyhow::bail!(
+                    "Forward-fill for {} would produce {} rows ({} symbols × {} seconds), over the limit of {}; \
+                     restrict the symbols with symbols_allowlist or use a coarser granularity",
+                    self.date, estimated_rows, symbols, seconds, max_rows
+                );
+            }
+        }
+        if let Some(max_bytes) = self.forward_fill_limits.max_memory_bytes {
+            if estimated_bytes > max_bytes {
+                anyhow::bail!(
+                    "Forward-fill for {} would hold ~{} bytes in memory ({} symbols × {} seconds × ~{} bytes), over the limit of {}; \
+                     restrict the symbols with symbols_allowlist, use a coarser granularity or set spill_memory_budget_bytes",
+                    self.date, estimated_bytes, symbols, seconds, row_bytes, max_bytes
+                );
+            }
+        }
+        Ok(())
+    }
 
-                info!("Forward-filled {} missing seconds for symbol {} (full UTC day: {} to {})", filled_count, symbol, day_start, day_end);
-                
-                Some((symbol.clone(), filled_symbol_data, filled_count))
-            })
-            .collect();
+    /// Forward-fill symbols one at a time, loading each from the spill store and
+    /// writing the filled day back so only one symbol is resident at a time
+    fn apply_forward_fill_spilled(&mut self, day_start: i64, day_end: i64) -> Result<()> {
+        let symbols = self.get_symbols();
+        info!("Applying forward-fill for date {} (mark-price, full UTC day) across {} symbols one at a time (spill enabled)", self.date, symbols.len());
 
-        // Update the data_by_symbol map with the filled data
-        for (symbol, filled_data, _filled_count) in results {
-            self.data_by_symbol.insert(symbol, filled_data);
+        let spill = self.spill.as_mut().context("Spill store not enabled")?;
+        for symbol in symbols {
+            let mut symbol_data = self.data_by_symbol.remove(&symbol).unwrap_or_default();
+            spill.untrack(&symbol);
+            spill.take_rows(&symbol, &mut symbol_data)?;
+
+            if let Some((filled_symbol_data, _filled_count)) = Self::forward_fill_symbol(&symbol, &symbol_data, day_start, day_end) {
+                spill.write_rows(&symbol, &filled_symbol_data)?;
+            }
         }
 
         Ok(())
     }
 
-    /// Get all symbols that have data
+    /// Fill every second of `day_start..=day_end` for one symbol's data
+    /// Returns the filled data and the number of filled seconds, or `None` if there is no data
+    fn forward_fill_symbol(
+        symbol: &str,
+        symbol_data: &BTreeMap<i64, DataRow>,
+        day_start: i64,
+        day_end: i64,
+    ) -> Option<(BTreeMap<i64, DataRow>, usize)> {
+        if symbol_data.is_empty() {
+            return None;
+        }
+
+        debug!("Applying forward-fill for symbol: {}", symbol);
+
+        // Find the first and last actual data timestamp for this symbol
+        let first_data_timestamp = *symbol_data.keys().next()?;
+        let last_data_timestamp = *symbol_data.keys().last()?;
+
+        debug!("Symbol {} actual data range: {} to {}", symbol, first_data_timestamp, last_data_timestamp);
+
+        // Get the first and last available data points for this symbol
+        let first_data = symbol_data.get(&first_data_timestamp)?.clone();
+        let last_data = symbol_data.get(&last_data_timestamp)?.clone();
+
+        let mut filled_symbol_data = symbol_data.clone();
+        let mut current_data = first_data.clone();
+        let mut filled_count = 0;
+
+        // Iterate through every second of the UTC day
+        for timestamp in day_start..=day_end {
+            if let Some(data) = filled_symbol_data.get(&timestamp) {
+                // Data exists for this second, use it as the current data
+                current_data = data.clone();
+            } else {
+                // No data for this second, need to fill
+                let filled_data = if timestamp < first_data_timestamp {
+                    // Before first data point: backfill with first data
+                    first_data.clone()
+                } else if timestamp > last_data_timestamp {
+                    // After last data point: forward-fill with last data
+                    last_data.clone()
+                } else {
+                    // Between data points: forward-fill with previous data
+                    current_data.clone()
+                };
+
+                // Update timestamp for forward-filled entries (preserve original event_time)
+                let mut filled_data = filled_data;
+                let filled_timestamp = timestamp * 1000; // in milliseconds (last 3 digits are 000)
+                // Keep original event_time, only update timestamp to the filled second
+                filled_data.insert("timestamp".to_string(), Value::Number(filled_timestamp.into()));
+                
+                filled_symbol_data.insert(timestamp, filled_data);
+                filled_count += 1;
+            }
+        }
+
+        info!("Forward-filled {} missing seconds for symbol {} (full UTC day: {} to {})", filled_count, symbol, day_start, day_end);
+        
+        Some((filled_symbol_data, filled_count))
+    }
+
+    /// Get all symbols that have data (in memory or spilled)
     pub fn get_symbols(&self) -> Vec<String> {
-        self.data_by_symbol.keys().cloned().collect()
+        let mut symbols: Vec<String> = self.data_by_symbol.keys().cloned().collect();
+        if let Some(spill) = &self.spill {
+            symbols.extend(
+                spill
+                    .spilled_symbols
+                    .iter()
+                    .filter(|symbol| !self.data_by_symbol.contains_key(*symbol))
+                    .cloned(),
+            );
+        }
+        symbols
     }
 
     /// Get all data rows for a specific symbol, sorted by timestamp
     pub fn get_sorted_rows_for_symbol(&self, symbol: &str) -> Option<Vec<DataRow>> {
-        self.data_by_symbol.get(symbol).map(|data| {
-            data.values().cloned().collect()
-        })
+        let resident = self.data_by_symbol.get(symbol);
+        match &self.spill {
+            Some(spill) if spill.spilled_symbols.contains(symbol) => match spill.read_rows(symbol, resident) {
+                Ok(rows) => Some(rows),
+                Err(e) => {
+                    error!("Failed to read spilled rows for symbol {}: {:#}", symbol, e);
+                    None
+                }
+            },
+            _ => resident.map(|data| data.values().cloned().collect()),
+        }
     }
 
     /// Get the number of unique seconds with data for a specific symbol
     pub fn len_for_symbol(&self, symbol: &str) -> usize {
-        self.data_by_symbol.get(symbol).map(|data| data.len()).unwrap_or(0)
+        let resident = self.data_by_symbol.get(symbol).map(|data| data.len()).unwrap_or(0);
+        let spilled = match &self.spill {
+            Some(spill) => spill.len(symbol).unwrap_or_else(|e| {
+                error!("Failed to count spilled rows for symbol {}: {:#}", symbol, e);
+                0
+            }),
+            None => 0,
+        };
+        resident + spilled
+    }
+
+    /// Remove all data for a symbol, returning whether it was present
+    pub fn remove_symbol(&mut self, symbol: &str) -> bool {
+        let resident = self.data_by_symbol.remove(symbol).is_some();
+        let spilled = match self.spill.as_mut() {
+            Some(spill) => spill.remove(symbol).unwrap_or_else(|e| {
+                error!("Failed to remove spilled rows for symbol {}: {:#}", symbol, e);
+                false
+            }),
+            None => false,
+        };
+        resident || spilled
     }
 
     /// Get the total number of symbols
     pub fn symbol_count(&self) -> usize {
-        self.data_by_symbol.len()
+        self.get_symbols().len()
     }
 
     /// Check if the merger is empty (has no symbols)
     pub fn is_empty(&self) -> bool {
         self.data_by_symbol.is_empty()
+            && match &self.spill {
+                Some(spill) => spill.spilled_symbols.is_empty(),
+                None => true,
+            }
     }
 }
 
@@ -341,29 +907,93 @@ mod tests {
     fn test_valid_mark_price_row() {
         let mut row = DataRow::new();
         row.insert("event_time".to_string(), Value::Number(1762411870001i64.into()));
-        row.insert("mark_price".to_string(), Value::Number(103308.50797101.into()));
-        row.insert("funding_rate".to_string(), Value::Number(0.0001.into()));
+        row.insert("mark_price".to_string(), serde_json::json!(103308.50797101));
+        row.insert("funding_rate".to_string(), serde_json::json!(0.0001));
         
-        assert!(MarkPriceMerger::is_valid_mark_price_row(&row));
+        assert!(RowValidation::default().is_valid(&row));
     }
 
     #[test]
     fn test_valid_mark_price_row_with_short_names() {
+        let merger = MarkPriceMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap());
         let mut row = DataRow::new();
         row.insert("E".to_string(), Value::Number(1762411870001i64.into()));
-        row.insert("P".to_string(), Value::Number(103308.50797101.into()));
-        row.insert("r".to_string(), Value::Number(0.0001.into()));
-        
-        assert!(MarkPriceMerger::is_valid_mark_price_row(&row));
+        row.insert("p".to_string(), serde_json::json!(103308.50797101));
+        row.insert("r".to_string(), serde_json::json!(0.0001));
+
+        // Validation keys off the normalized names
+        assert!(!RowValidation::default().is_valid(&row));
+        merger.normalize_field_names(&mut row);
+        assert!(RowValidation::default().is_valid(&row));
+    }
+
+    #[test]
+    fn test_custom_field_renames() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let field_renames: HashMap<String, String> = [
+            ("instId", "symbol"),
+            ("ts", "event_time"),
+            ("mp", "mark_price"),
+            ("fr", "funding_rate"),
+        ]
+        .iter()
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect();
+        let mut merger = MarkPriceMerger::new(date).with_field_renames(field_renames);
+
+        let mut row: DataRow = serde_json::from_str(r#"{"instId":"BTCUSDT","ts":1762411870001,"mp":"103308.5","fr":"0.0001"}"#).unwrap();
+        merger.normalize_field_names(&mut row);
+        assert!(RowValidation::default().is_valid(&row));
+
+        let jsonl = r#"{"instId":"BTCUSDT","ts":1762411870001,"mp":"103308.5","fr":"0.0001"}"#;
+        assert_eq!(merger.add_jsonl_data(jsonl, "okx").unwrap(), 1);
+        let rows = merger.get_sorted_rows_for_symbol("BTCUSDT").unwrap();
+        assert_eq!(rows[0].get("mark_price"), Some(&serde_json::json!("103308.5")));
+        assert!(!rows[0].contains_key("mp"));
     }
 
     #[test]
     fn test_invalid_mark_price_row_missing_price() {
         let mut row = DataRow::new();
         row.insert("event_time".to_string(), Value::Number(1762411870001i64.into()));
-        row.insert("funding_rate".to_string(), Value::Number(0.0001.into()));
+        row.insert("funding_rate".to_string(), serde_json::json!(0.0001));
         
-        assert!(!MarkPriceMerger::is_valid_mark_price_row(&row));
+        assert!(!RowValidation::default().is_valid(&row));
+    }
+
+    #[test]
+    fn test_missing_funding_rate_optional_vs_required() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5"}
+{"E":1762411871001,"s":"BTCUSDT","p":"103309.5","r":"not-a-number"}"#;
+
+        // Default: funding_rate is required
+        let mut required = MarkPriceMerger::new(date);
+        assert_eq!(required.add_jsonl_data(jsonl, "source1").unwrap(), 0);
+
+        // Optional: a missing funding_rate passes, a non-numeric one still fails
+        let validation = RowValidation {
+            required_fields: vec!["mark_price".to_string()],
+            optional_numeric_fields: vec!["funding_rate".to_string()],
+            ..Default::default()
+        };
+        let mut optional = MarkPriceMerger::new(date).with_validation(validation);
+        assert_eq!(optional.add_jsonl_data(jsonl, "source1").unwrap(), 1);
+        let rows = optional.get_sorted_rows_for_symbol("BTCUSDT").unwrap();
+        assert!(!rows[0].contains_key("funding_rate"));
+    }
+
+    #[test]
+    fn test_range_violation_flags_but_keeps_row() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut validation = RowValidation::default();
+        validation.field_ranges.insert("mark_price".to_string(), FieldRange { min: Some(0.000001), max: None });
+        let mut merger = MarkPriceMerger::new(date).with_validation(validation);
+
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"0","r":"0.0001"}
+{"E":1762411871001,"s":"BTCUSDT","p":"103309.5","r":"0.0001"}"#;
+        assert_eq!(merger.add_jsonl_data(jsonl, "source1").unwrap(), 2);
+        assert_eq!(merger.range_violations(), 1);
     }
 
     #[test]
@@ -429,7 +1059,7 @@ mod tests {
         row.insert("r".to_string(), Value::String("0.00030000".to_string()));
         row.insert("T".to_string(), Value::Number(1562306400000i64.into()));
 
-        MarkPriceMerger::normalize_field_names(&mut row);
+        MarkPriceMerger::new(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()).normalize_field_names(&mut row);
 
         // Check that short names were replaced with full names
         assert!(row.contains_key("event_type"));
@@ -451,5 +1081,193 @@ mod tests {
         assert!(!row.contains_key("r"));
         assert!(!row.contains_key("T"));
     }
-}
 
+    #[test]
+    fn test_symbol_allowlist() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let filter = SymbolFilter::new(&[" ethusdt ".to_string()], &[]);
+        let mut merger = MarkPriceMerger::new(date).with_symbol_filter(filter);
+
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.50797101","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.55407203","r":"0.00005065"}
+{"E":1762411870001,"s":"SOLUSDT","p":"160.1","r":"0.0001"}"#;
+
+        assert_eq!(merger.add_jsonl_data(jsonl, "source1").unwrap(), 1);
+        assert_eq!(merger.get_symbols(), vec!["ETHUSDT".to_string()]);
+    }
+
+    #[test]
+    fn test_symbol_denylist() {
+        let filter = SymbolFilter::new(&[], &["btcusdt".to_string()]);
+        assert!(!filter.matches("BTCUSDT"));
+        assert!(filter.matches("ETHUSDT"));
+
+        let filter = SymbolFilter::new(&["BTCUSDT".to_string(), "ETHUSDT".to_string()], &["ETHUSDT".to_string()]);
+        assert!(filter.matches("btcusdt"));
+        assert!(!filter.matches("ETHUSDT"));
+    }
+
+    #[test]
+    fn test_array_line_adds_each_element() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut merger = MarkPriceMerger::new(date);
+
+        let jsonl = r#"[{"E":1762411870001,"s":"BTCUSDT","p":"103308.50797101","r":"0.0001"},{"E":1762411870001,"s":"ETHUSDT","p":"3377.55407203","r":"0.00005065"}]
+{"E":1762411871001,"s":"BTCUSDT","p":"103309.50797101","r":"0.0001"}"#;
+
+        assert_eq!(merger.add_jsonl_data(jsonl, "source1").unwrap(), 3);
+        assert_eq!(merger.len_for_symbol("BTCUSDT"), 2);
+        assert_eq!(merger.len_for_symbol("ETHUSDT"), 1);
+    }
+
+    #[test]
+    fn test_date_range_check_rejects_wrong_day() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut merger = MarkPriceMerger::new(date).with_date_range_check(60);
+
+        // 2025-11-06 12:11:10, 2025-11-05 23:59:30 (within grace) and 2025-11-05 12:11:10
+        let jsonl = r#"{"E":1762431070001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762387170000,"s":"BTCUSDT","p":"103300.0","r":"0.0001"}
+{"E":1762344670001,"s":"BTCUSDT","p":"1.0","r":"0.0001"}"#;
+
+        assert_eq!(merger.add_jsonl_data(jsonl, "source1").unwrap(), 2);
+        assert_eq!(merger.len_for_symbol("BTCUSDT"), 2);
+
+        // Without the check the wrong-day record is kept
+        let mut unchecked = MarkPriceMerger::new(date);
+        assert_eq!(unchecked.add_jsonl_data(jsonl, "source1").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_spill_produces_same_output() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.5","r":"0.00005"}
+{"E":1762411880001,"s":"BTCUSDT","p":"103309.5","r":"0.0001"}
+{"E":1762411890001,"s":"ETHUSDT","p":"3378.5","r":"0.00005"}
+{"E":1762411900001,"s":"BTCUSDT","p":"103310.5","r":"0.0001"}"#;
+        let duplicate = r#"{"E":1762411870001,"s":"BTCUSDT","p":"999999.99","r":"0.0001"}"#;
+
+        let mut in_memory = MarkPriceMerger::new(date);
+        in_memory.add_jsonl_data(jsonl, "source1").unwrap();
+        in_memory.add_jsonl_data(duplicate, "source2").unwrap();
+        in_memory.apply_forward_fill().unwrap();
+
+        // A budget smaller than one row spills after every add
+        let mut spilled = MarkPriceMerger::new(date).with_spill_budget(1);
+        spilled.add_jsonl_data(jsonl, "source1").unwrap();
+        assert_eq!(spilled.add_jsonl_data(duplicate, "source2").unwrap(), 0);
+        assert_eq!(spilled.len_for_symbol("BTCUSDT"), 3);
+
+        let stats = spilled.spill_stats().unwrap();
+        assert_eq!(stats.spill_events, 5);
+        assert_eq!(stats.spilled_symbols, 2);
+        assert_eq!(stats.spilled_rows, 5);
+
+        spilled.apply_forward_fill().unwrap();
+
+        let mut symbols = spilled.get_symbols();
+        symbols.sort();
+        assert_eq!(symbols, vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()]);
+        for symbol in &symbols {
+            assert_eq!(spilled.len_for_symbol(symbol), 86400);
+            assert_eq!(
+                spilled.get_sorted_rows_for_symbol(symbol),
+                in_memory.get_sorted_rows_for_symbol(symbol)
+            );
+        }
+
+        assert!(spilled.remove_symbol("ETHUSDT"));
+        assert_eq!(spilled.get_symbols(), vec!["BTCUSDT".to_string()]);
+    }
+
+    #[test]
+    fn test_spill_keeps_recently_updated_symbols_in_memory() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let row = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}"#;
+        let mut parsed: DataRow = serde_json::from_str(row).unwrap();
+        MarkPriceMerger::new(date).normalize_field_names(&mut parsed);
+        parsed.insert("timestamp".to_string(), Value::Number(1762411870000i64.into()));
+        let row_bytes = SpillState::estimate_row_bytes(&parsed);
+
+        // Room for two rows: adding a third evicts the least recently updated symbol
+        let mut merger = MarkPriceMerger::new(date).with_spill_budget(row_bytes * 2);
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.5","r":"0.0001"}
+{"E":1762411871001,"s":"BTCUSDT","p":"103309.5","r":"0.0001"}"#;
+        merger.add_jsonl_data(jsonl, "source1").unwrap();
+
+        assert!(merger.data_by_symbol.contains_key("BTCUSDT"));
+        assert!(!merger.data_by_symbol.contains_key("ETHUSDT"));
+        assert_eq!(merger.len_for_symbol("ETHUSDT"), 1);
+        assert_eq!(merger.spill_stats().unwrap().spill_events, 1);
+    }
+
+    #[test]
+    fn test_forward_fill_limits_bail_before_filling() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let jsonl = |symbols: usize| -> String {
+            (0..symbols)
+                .map(|i| format!("{{\"E\":1762411870001,\"s\":\"SYM{}USDT\",\"p\":\"1.5\",\"r\":\"0.0001\"}}\n", i))
+                .collect()
+        };
+
+        // 50 symbols × 86400 seconds is over a 1M row limit
+        let limits = ForwardFillLimits { max_estimated_rows: Some(1_000_000), max_memory_bytes: None };
+        let mut merger = MarkPriceMerger::new(date).with_forward_fill_limits(limits);
+        merger.add_jsonl_data(&jsonl(50), "source1").unwrap();
+        let error = merger.apply_forward_fill().unwrap_err().to_string();
+        assert!(error.contains("4320000 rows (50 symbols × 86400 seconds)"), "{}", error);
+        assert!(error.contains("symbols_allowlist"), "{}", error);
+        // The guard fires before anything is filled
+        assert_eq!(merger.len_for_symbol("SYM0USDT"), 1);
+
+        // Rows are ~400 bytes, so two symbols' full day is over 50 MiB but one symbol's is not
+        let limits = ForwardFillLimits { max_estimated_rows: None, max_memory_bytes: Some(50 << 20) };
+        let mut merger = MarkPriceMerger::new(date).with_forward_fill_limits(limits);
+        merger.add_jsonl_data(&jsonl(2), "source1").unwrap();
+        let error = merger.apply_forward_fill().unwrap_err().to_string();
+        assert!(error.contains("over the limit of 52428800"), "{}", error);
+        assert_eq!(merger.len_for_symbol("SYM0USDT"), 1);
+
+        // Spilled runs fill one symbol at a time, so only one symbol's day counts
+        let mut merger = MarkPriceMerger::new(date)
+            .with_forward_fill_limits(limits)
+            .with_spill_budget(usize::MAX);
+        merger.add_jsonl_data(&jsonl(2), "source1").unwrap();
+        merger.apply_forward_fill().unwrap();
+        assert_eq!(merger.len_for_symbol("SYM0USDT"), 86400);
+    }
+
+    #[test]
+    fn test_reader_matches_string_path() {
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+
+[{"E":1762411871001,"s":"BTCUSDT","p":"103309.5","r":"0.0001"},{"E":1762411871001,"s":"ETHUSDT","p":"3377.5","r":"0.00005"}]
+not json
+{"E":1762411871001,"s":"BTCUSDT","p":"999999.9","r":"0.0001"}
+{"E":1762411872001,"s":"ETHUSDT","p":"3377.6"}"#;
+
+        let mut from_string = MarkPriceMerger::new(date);
+        let string_count = from_string.add_jsonl_data(jsonl, "source1").unwrap();
+
+        let mut from_reader = MarkPriceMerger::new(date);
+        let reader_count = from_reader.add_jsonl_reader(std::io::Cursor::new(jsonl.as_bytes()), "source1").unwrap();
+
+        assert_eq!(reader_count, 3);
+        assert_eq!(reader_count, string_count);
+        // Symbols come out of a HashMap, so compare them in a fixed order
+        let mut symbols = from_string.get_symbols();
+        symbols.sort();
+        let mut reader_symbols = from_reader.get_symbols();
+        reader_symbols.sort();
+        assert_eq!(reader_symbols, symbols);
+        for symbol in symbols {
+            assert_eq!(
+                from_reader.get_sorted_rows_for_symbol(&symbol),
+                from_string.get_sorted_rows_for_symbol(&symbol)
+            );
+        }
+    }
+}
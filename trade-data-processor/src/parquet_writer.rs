@@ -8,17 +8,20 @@ use arrow::array::{
     ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, RecordBatch, StringBuilder,
     UInt64Builder,
 };
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::datatypes::{DataType, Field, FieldRef, Schema};
 use async_trait::async_trait;
 use chrono::NaiveDate;
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 use parquet::arrow::ArrowWriter;
-use parquet::file::properties::WriterProperties;
+use parquet::basic::Encoding;
+use parquet::file::properties::{WriterProperties, WriterPropertiesBuilder};
+use parquet::schema::types::ColumnPath;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{info, warn};
@@ -26,7 +29,8 @@ use tracing::{info, warn};
 #[cfg(unix)]
 use std::os::unix::fs::{OpenOptionsExt, DirBuilderExt};
 
-use crate::writer::{Writer, DataRow};
+use crate::writer::{Writer, DataRow, DataRowExt};
+use crate::error::{ProcessorError, ProcessorResult};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ColumnType {
@@ -58,6 +62,9 @@ pub struct FilterCondition {
     pub value: Value,
 }
 
+/// Default maximum number of rows per row group
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 128 * 1024;
+
 /// Configuration for ParquetWriter
 #[derive(Debug, Clone)]
 pub struct ParquetWriterConfig {
@@ -74,9 +81,168 @@ pub struct ParquetWriterConfig {
     pub filter: Vec<FilterCondition>,
     /// Optional date to write data to - if None, uses current date
     pub date: Option<NaiveDate>,
+    /// Maximum number of rows per row group within a file
+    pub row_group_size: usize,
+    /// Columns written first, in this order; remaining columns follow sorted by name
+    /// If None, all columns are sorted by name
+    pub column_order: Option<Vec<String>>,
+    /// Whether to write a `<file>.sha256` sidecar next to each parquet file
+    pub write_checksum: bool,
+    /// Whether to point `<path>/<name>_latest.parquet` at each newly written file
+    pub write_latest_pointer: bool,
     /// Whether to write to /tmp first and then copy to output directory
     /// This can improve performance by writing to faster storage first
     pub use_temp_dir: bool,
+    /// Per-column encodings; unlisted columns use the writer default
+    /// With dictionary encoding enabled for a column this is the fallback encoding
+    pub column_encodings: HashMap<String, Encoding>,
+    /// Columns that use dictionary encoding; all others have it disabled
+    /// If None, dictionary encoding is enabled for every column (writer default)
+    pub dictionary_columns: Option<Vec<String>>,
+}
+
+/// Parse a per-column encoding name such as `DELTA_BINARY_PACKED` (case-insensitive)
+/// Dictionary encodings are rejected since they are controlled by `dictionary_columns`
+pub fn parse_column_encoding(name: &str) -> Result<Encoding> {
+    match name.to_ascii_uppercase().as_str() {
+        "PLAIN" => Ok(Encoding::PLAIN),
+        "RLE" => Ok(Encoding::RLE),
+        "DELTA_BINARY_PACKED" => Ok(Encoding::DELTA_BINARY_PACKED),
+        "DELTA_LENGTH_BYTE_ARRAY" => Ok(Encoding::DELTA_LENGTH_BYTE_ARRAY),
+        "DELTA_BYTE_ARRAY" => Ok(Encoding::DELTA_BYTE_ARRAY),
+        "BYTE_STREAM_SPLIT" => Ok(Encoding::BYTE_STREAM_SPLIT),
+        "PLAIN_DICTIONARY" | "RLE_DICTIONARY" => {
+            anyhow::bail!("{} cannot be set per column, list the column in dictionary_columns instead", name)
+        }
+        _ => anyhow::bail!("Unknown parquet encoding: {}", name),
+    }
+}
+
+/// Serde adapter for `column -> encoding` maps written as encoding names in config files
+pub mod column_encodings_serde {
+    use super::{parse_column_encoding, Encoding};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+
+    pub fn serialize<S: Serializer>(
+        encodings: &HashMap<String, Encoding>,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let names: BTreeMap<&String, String> = encodings
+            .iter()
+            .map(|(column, encoding)| (column, format!("{:?}", encoding)))
+            .collect();
+        names.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<HashMap<String, Encoding>, D::Error> {
+        HashMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(column, name)| {
+                parse_column_encoding(&name)
+                    .map(|encoding| (column, encoding))
+                    .map_err(serde::de::Error::custom)
+            })
+            .collect()
+    }
+}
+
+impl ParquetWriterConfig {
+    /// Get directory path for a given date
+    pub fn parquet_dir(&self, date: NaiveDate) -> PathBuf {
+        let year = date.format("%Y").to_string();
+        let month = date.format("%m").to_string();
+        let day = date.format("%d").to_string();
+
+        PathBuf::from(&self.path)
+            .join(year)
+            .join(month)
+            .join(day)
+    }
+
+    /// Build the parquet file path for a date and file sequence number
+    /// The sequence is only part of the filename when batch_size was explicitly configured
+    pub fn parquet_path(&self, date: NaiveDate, sequence: u64) -> PathBuf {
+        let dir = self.parquet_dir(date);
+        let clean_name = self.file_prefix();
+
+        // Generate filename: only add sequence if batch_size was explicitly configured
+        // If no batch config (None), use simple format: symbol_date.parquet
+        // If batch_size is configured, add sequence: symbol_000001_date.parquet
+        let filename = if self.has_batch_config {
+            format!("{}_{:06}_{}.parquet", clean_name, sequence, date.format("%Y-%m-%d"))
+        } else {
+            format!("{}_{}.parquet", clean_name, date.format("%Y-%m-%d"))
+        };
+        dir.join(filename)
+    }
+
+    /// Path of the first file a writer with this configuration produces for a date
+    /// Used to detect output that has already been written
+    pub fn expected_parquet_path(&self, date: NaiveDate) -> PathBuf {
+        self.parquet_path(date, 1)
+    }
+
+    /// Highest sequence number among existing `<name>_<sequence>_<date>.parquet` files
+    /// for a date, or 0 if there are none
+    /// Files of other names or dates and names without a numeric sequence are ignored
+    pub fn max_existing_sequence(&self, date: NaiveDate) -> u64 {
+        let Ok(entries) = fs::read_dir(self.parquet_dir(date)) else {
+            return 0;
+        };
+        let prefix = format!("{}_", self.file_prefix());
+        let suffix = format!("_{}.parquet", date.format("%Y-%m-%d"));
+
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok()?;
+                let sequence = file_name.strip_prefix(&prefix)?.strip_suffix(&suffix)?;
+                if sequence.is_empty() || !sequence.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                sequence.parse::<u64>().ok()
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// File name prefix: the symbol name (last part after the last dash if it contains one)
+    fn file_prefix(&self) -> String {
+        match self.name.rfind('-') {
+            Some(last_dash_idx) => self.name[last_dash_idx + 1..].to_string(),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Size and layout of a written parquet file, read back from its footer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WrittenFileInfo {
+    pub path: PathBuf,
+    /// File size on disk
+    pub bytes: u64,
+    pub num_rows: i64,
+    pub num_row_groups: usize,
+}
+
+impl WrittenFileInfo {
+    /// Read the size and footer of a parquet file
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = File::open(path).context(format!("Failed to open {:?}", path))?;
+        let bytes = file.metadata().context(format!("Failed to stat {:?}", path))?.len();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+            .context(format!("Failed to read parquet footer: {:?}", path))?;
+        let metadata = builder.metadata();
+        Ok(Self {
+            path: path.to_path_buf(),
+            bytes,
+            num_rows: metadata.file_metadata().num_rows(),
+            num_row_groups: metadata.num_row_groups(),
+        })
+    }
 }
 
 /// Main struct for writing data to Parquet files
@@ -87,6 +253,9 @@ pub struct ParquetWriter {
     column_types: HashMap<String, ColumnType>,
     buffer: Vec<DataRow>,
     file_sequence: u64, // Sequence number for unique file naming
+    sequence_date: Option<NaiveDate>, // Date whose existing files file_sequence was resumed from
+    written_files: Vec<PathBuf>,
+    written_file_info: Vec<WrittenFileInfo>,
 }
 
 impl ParquetWriter {
@@ -98,8 +267,21 @@ impl ParquetWriter {
             column_types: HashMap::new(),
             buffer: Vec::new(),
             file_sequence: 0,
+            sequence_date: None,
+            written_files: Vec::new(),
+            written_file_info: Vec::new(),
         }
     }
+
+    /// Paths of the parquet files written so far
+    pub fn written_files(&self) -> &[PathBuf] {
+        &self.written_files
+    }
+
+    /// Size, row count and row group count of the parquet files written so far
+    pub fn written_file_info(&self) -> &[WrittenFileInfo] {
+        &self.written_file_info
+    }
     /// Infer column type from JSON value
     pub fn infer_type(value: &Value) -> ColumnType {
         match value {
@@ -130,6 +312,8 @@ impl ParquetWriter {
     }
 
     /// Infer schema from data objects
+    /// Columns are the union of all objects' fields; a column's type comes from the
+    /// first object that has it, and rows without it are written as null
     pub fn infer_schema_from_data(data: &[Value]) -> Result<(Arc<Schema>, HashMap<String, ColumnType>)> {
         if data.is_empty() {
             anyhow::bail!("Cannot infer schema from empty data");
@@ -138,15 +322,16 @@ impl ParquetWriter {
         let mut column_types: HashMap<String, ColumnType> = HashMap::new();
         let mut field_order: Vec<String> = Vec::new();
 
-        // Analyze first object to get field names and types
-        if let Value::Object(obj) = &data[0] {
+        for item in data {
+            let Value::Object(obj) = item else {
+                anyhow::bail!("Expected object in data array");
+            };
             for (key, value) in obj.iter() {
-                let col_type = Self::infer_type(value);
-                column_types.insert(key.clone(), col_type);
-                field_order.push(key.clone());
+                if !column_types.contains_key(key) {
+                    column_types.insert(key.clone(), Self::infer_type(value));
+                    field_order.push(key.clone());
+                }
             }
-        } else {
-            anyhow::bail!("Expected object in data array");
         }
 
         // Sort field names for consistent schema
@@ -171,6 +356,34 @@ impl ParquetWriter {
         Ok((schema, column_types))
     }
 
+    /// Reorder schema fields so `column_order` columns come first in the declared order,
+    /// followed by the remaining columns sorted by name
+    /// Configured columns that are not in the data are skipped with a warning
+    fn apply_column_order(&self, schema: Arc<Schema>) -> Arc<Schema> {
+        let Some(ref column_order) = self.config.column_order else {
+            return schema;
+        };
+
+        let mut fields: Vec<FieldRef> = Vec::with_capacity(schema.fields().len());
+        for name in column_order {
+            if fields.iter().any(|f| f.name() == name) {
+                continue;
+            }
+            match schema.field_with_name(name) {
+                Ok(field) => fields.push(Arc::new(field.clone())),
+                Err(_) => warn!("Column '{}' from column_order is not present in the data, skipping", name),
+            }
+        }
+        let mut rest: Vec<FieldRef> = schema.fields().iter()
+            .filter(|f| !column_order.contains(f.name()))
+            .cloned()
+            .collect();
+        rest.sort_by(|a, b| a.name().cmp(b.name()));
+        fields.extend(rest);
+
+        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+    }
+
     /// Check if a row matches a filter condition
     pub fn row_matches_condition(row: &DataRow, condition: &FilterCondition) -> bool {
         let row_value = match row.get(&condition.field) {
@@ -260,43 +473,24 @@ impl ParquetWriter {
 
     /// Get directory path for a given date
     pub fn get_parquet_dir(&self, date: NaiveDate) -> PathBuf {
-        let year = date.format("%Y").to_string();
-        let month = date.format("%m").to_string();
-        let day = date.format("%d").to_string();
-
-        PathBuf::from(&self.config.path)
-            .join(year)
-            .join(month)
-            .join(day)
+        self.config.parquet_dir(date)
     }
 
     /// Generate unique parquet file path with timestamp and sequence
     pub fn get_unique_parquet_path(&mut self, date: NaiveDate) -> PathBuf {
-        let dir = self.get_parquet_dir(date);
-        
-        // Extract symbol name (last part after the last dash if it contains one)
-        let clean_name = if self.config.name.contains('-') {
-            if let Some(last_dash_idx) = self.config.name.rfind('-') {
-                self.config.name[last_dash_idx + 1..].to_string()
-            } else {
-                self.config.name.clone()
+        if self.config.has_batch_config {
+            // Continue after files left by earlier runs instead of overwriting them
+            if self.sequence_date != Some(date) {
+                let existing = self.config.max_existing_sequence(date);
+                if existing > self.file_sequence {
+                    info!("Found existing files up to sequence {} for {}, continuing from there", existing, date);
+                    self.file_sequence = existing;
+                }
+                self.sequence_date = Some(date);
             }
-        } else {
-            self.config.name.clone()
-        };
-        
-        // Generate filename: only add sequence if batch_size was explicitly configured
-        // If no batch config (None), use simple format: symbol_date.parquet
-        // If batch_size is configured, add sequence: symbol_000001_date.parquet
-        let filename = if self.config.has_batch_config {
-            // Has batch_size configured, add sequence number
             self.file_sequence += 1;
-            format!("{}_{:06}_{}.parquet", clean_name, self.file_sequence, date.format("%Y-%m-%d"))
-        } else {
-            // No batch_size configured (single file mode), no sequence needed
-            format!("{}_{}.parquet", clean_name, date.format("%Y-%m-%d"))
-        };
-        dir.join(filename)
+        }
+        self.config.parquet_path(date, self.file_sequence)
     }
 
     /// Validate schema of existing files in directory (optional, for startup check)
@@ -358,10 +552,10 @@ impl ParquetWriter {
                         if let Some(value) = row.get(col_name) {
                             match value {
                                 Value::String(s) => builder.append_value(s),
-                                Value::Number(n) => builder.append_value(&n.to_string()),
-                                Value::Bool(b) => builder.append_value(&b.to_string()),
+                                Value::Number(n) => builder.append_value(n.to_string()),
+                                Value::Bool(b) => builder.append_value(b.to_string()),
                                 Value::Null => builder.append_null(),
-                                _ => builder.append_value(&value.to_string()),
+                                _ => builder.append_value(value.to_string()),
                             }
                         } else {
                             builder.append_null();
@@ -372,13 +566,8 @@ impl ParquetWriter {
                 ColumnType::UInt64 => {
                     let mut builder = UInt64Builder::new();
                     for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let num = match value {
-                                Value::Number(n) => n.as_u64().unwrap_or(0),
-                                Value::String(s) => s.parse::<u64>().unwrap_or(0),
-                                _ => 0,
-                            };
-                            builder.append_value(num);
+                        if row.contains_key(col_name) {
+                            builder.append_value(row.get_u64(col_name).unwrap_or(0));
                         } else {
                             builder.append_null();
                         }
@@ -388,13 +577,8 @@ impl ParquetWriter {
                 ColumnType::Int64 => {
                     let mut builder = Int64Builder::new();
                     for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let num = match value {
-                                Value::Number(n) => n.as_i64().unwrap_or(0),
-                                Value::String(s) => s.parse::<i64>().unwrap_or(0),
-                                _ => 0,
-                            };
-                            builder.append_value(num);
+                        if row.contains_key(col_name) {
+                            builder.append_value(row.get_i64(col_name).unwrap_or(0));
                         } else {
                             builder.append_null();
                         }
@@ -404,13 +588,8 @@ impl ParquetWriter {
                 ColumnType::Float64 => {
                     let mut builder = Float64Builder::new();
                     for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let num = match value {
-                                Value::Number(n) => n.as_f64().unwrap_or(0.0),
-                                Value::String(s) => s.parse::<f64>().unwrap_or(0.0),
-                                _ => 0.0,
-                            };
-                            builder.append_value(num);
+                        if row.contains_key(col_name) {
+                            builder.append_value(row.get_f64(col_name).unwrap_or(0.0));
                         } else {
                             builder.append_null();
                         }
@@ -420,13 +599,8 @@ impl ParquetWriter {
                 ColumnType::Boolean => {
                     let mut builder = BooleanBuilder::new();
                     for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let b = match value {
-                                Value::Bool(b) => *b,
-                                Value::String(s) => s.parse::<bool>().unwrap_or(false),
-                                _ => false,
-                            };
-                            builder.append_value(b);
+                        if row.contains_key(col_name) {
+                            builder.append_value(row.get_bool(col_name).unwrap_or(false));
                         } else {
                             builder.append_null();
                         }
@@ -443,7 +617,7 @@ impl ParquetWriter {
     }
 
     /// Generate a unique temporary file path in /tmp
-    fn generate_temp_path(&self, final_path: &PathBuf) -> Result<PathBuf> {
+    fn generate_temp_path(&self, final_path: &Path) -> Result<PathBuf> {
         let filename = final_path.file_name()
             .and_then(|n| n.to_str())
             .context("Failed to get filename from path")?;
@@ -461,7 +635,13 @@ impl ParquetWriter {
 
     /// Write a single RecordBatch to a new parquet file
     /// If use_temp_dir is enabled, writes to /tmp first and then copies to final destination
-    pub fn write_batch_to_file(&self, path: &PathBuf, batch: &RecordBatch) -> Result<()> {
+    /// Returns the final file's size and layout, read back from its footer
+    pub fn write_batch_to_file(&self, path: &PathBuf, batch: &RecordBatch) -> ProcessorResult<WrittenFileInfo> {
+        self.write_batch_to_file_impl(path, batch)
+            .map_err(|source| ProcessorError::Write { path: path.clone(), source })
+    }
+
+    fn write_batch_to_file_impl(&self, path: &PathBuf, batch: &RecordBatch) -> Result<WrittenFileInfo> {
         let Some(ref schema) = self.schema else {
             anyhow::bail!("Schema not initialized");
         };
@@ -511,11 +691,21 @@ impl ParquetWriter {
             .open(&write_path)
             .context(format!("Failed to open parquet file: {:?}", write_path))?;
 
-        let props = WriterProperties::builder().build();
+        let row_group_size = self.config.row_group_size.max(1);
+        let props = self.column_properties(WriterProperties::builder())
+            .set_max_row_group_size(row_group_size)
+            .build();
         let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(props))
             .context("Failed to create ArrowWriter")?;
 
-        writer.write(batch).context("Failed to write batch to parquet")?;
+        // Write one row group per slice so large buffers stay readable with predicate pushdown
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let len = row_group_size.min(batch.num_rows() - offset);
+            writer.write(&batch.slice(offset, len)).context("Failed to write batch to parquet")?;
+            writer.flush().context("Failed to flush row group")?;
+            offset += len;
+        }
         writer.close().context("Failed to close writer")?;
 
         // If using temp dir, copy file to final destination
@@ -559,9 +749,61 @@ impl ParquetWriter {
             info!("Wrote {} records to {:?}", batch.num_rows(), path);
         }
 
+        // Checksum the final file (after any copy from the temp dir)
+        if self.config.write_checksum {
+            let checksum_path = write_checksum_file(path)?;
+            info!("Wrote checksum to {:?}", checksum_path);
+        }
+
+        if self.config.write_latest_pointer {
+            let pointer = update_latest_pointer(Path::new(&self.config.path), &self.config.file_prefix(), path)?;
+            info!("Updated latest pointer {:?}", pointer);
+        }
+
+        WrittenFileInfo::read(path)
+    }
+
+    /// Infer the schema from `rows` unless it is already set
+    /// Called with the first written batch; call it up front with all rows when later
+    /// batches may carry columns the first one lacks
+    pub fn init_schema(&mut self, rows: &[DataRow]) -> Result<()> {
+        if self.schema.is_some() || rows.is_empty() {
+            return Ok(());
+        }
+
+        // Convert rows to Value array for schema inference
+        let data_array: Vec<Value> = rows.iter()
+            .map(|row| Value::Object(row.clone().into_iter().collect()))
+            .collect();
+
+        let (schema, column_types) = Self::infer_schema_from_data(&data_array)
+            .context("Failed to infer schema")?;
+        let schema = self.apply_column_order(schema);
+
+        info!("Inferred schema with {} columns:", schema.fields().len());
+        for field in schema.fields() {
+            info!("  - {}: {:?}", field.name(), field.data_type());
+        }
+
+        self.schema = Some(schema);
+        self.column_types = column_types;
         Ok(())
     }
 
+    /// Apply the per-column encoding and dictionary settings
+    fn column_properties(&self, mut builder: WriterPropertiesBuilder) -> WriterPropertiesBuilder {
+        if let Some(ref dictionary_columns) = self.config.dictionary_columns {
+            builder = builder.set_dictionary_enabled(false);
+            for column in dictionary_columns {
+                builder = builder.set_column_dictionary_enabled(ColumnPath::from(column.as_str()), true);
+            }
+        }
+        for (column, encoding) in &self.config.column_encodings {
+            builder = builder.set_column_encoding(ColumnPath::from(column.as_str()), *encoding);
+        }
+        builder
+    }
+
     /// Write data rows directly (called by Writer trait implementation)
     async fn write_rows_impl(&mut self, rows: Vec<DataRow>) -> Result<()> {
         if rows.is_empty() {
@@ -572,23 +814,7 @@ impl ParquetWriter {
         let today = self.config.date.unwrap_or_else(|| chrono::Utc::now().date_naive());
 
         // If schema not initialized, infer it from the first batch
-        if self.schema.is_none() {
-            // Convert rows to Value array for schema inference
-            let data_array: Vec<Value> = rows.iter()
-                .map(|row| Value::Object(row.clone().into_iter().collect()))
-                .collect();
-            
-            let (schema, column_types) = Self::infer_schema_from_data(&data_array)
-                .context("Failed to infer schema")?;
-            
-            info!("Inferred schema with {} columns:", schema.fields().len());
-            for field in schema.fields() {
-                info!("  - {}: {:?}", field.name(), field.data_type());
-            }
-            
-            self.schema = Some(schema);
-            self.column_types = column_types;
-        }
+        self.init_schema(&rows)?;
 
         // Check if we need to flush data for a new day
         if self.current_date.is_some() && self.current_date != Some(today) {
@@ -637,7 +863,19 @@ impl ParquetWriter {
         let path = self.get_unique_parquet_path(date);
 
         // Write to new file (no reading of old data!)
-        self.write_batch_to_file(&path, &batch)?;
+        let file_info = self.write_batch_to_file(&path, &batch)?;
+        info!(
+            path = %file_info.path.display(),
+            bytes = file_info.bytes,
+            num_rows = file_info.num_rows,
+            num_row_groups = file_info.num_row_groups as u64,
+            "Parquet file is {} bytes with {} rows in {} row groups",
+            file_info.bytes,
+            file_info.num_rows,
+            file_info.num_row_groups
+        );
+        self.written_files.push(path);
+        self.written_file_info.push(file_info);
 
         // Clear buffer
         self.buffer.clear();
@@ -665,6 +903,93 @@ impl Drop for ParquetWriter {
     }
 }
 
+/// Compute the hex SHA256 digest of a file
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)
+        .context(format!("Failed to open file for hashing: {:?}", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .context(format!("Failed to hash file: {:?}", path))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Write a `<file>.sha256` sidecar in `sha256sum` format ("<digest>  <filename>")
+/// Returns the sidecar path
+pub fn write_checksum_file(path: &Path) -> Result<PathBuf> {
+    let digest = sha256_file(path)?;
+    let file_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .context(format!("Invalid file name: {:?}", path))?;
+
+    let mut checksum_name = path.as_os_str().to_owned();
+    checksum_name.push(".sha256");
+    let checksum_path = PathBuf::from(checksum_name);
+
+    fs::write(&checksum_path, format!("{}  {}\n", digest, file_name))
+        .context(format!("Failed to write checksum file: {:?}", checksum_path))?;
+    Ok(checksum_path)
+}
+
+/// Where the pointer to the newest output file was written
+#[derive(Debug, Clone, PartialEq)]
+pub enum LatestPointer {
+    /// `<prefix>_latest.parquet` symlink to the file
+    Symlink(PathBuf),
+    /// `<prefix>_latest.json` holding the file's path, where symlinks are unavailable
+    File(PathBuf),
+}
+
+/// Point `<dir>/<prefix>_latest.parquet` at `target` with a relative symlink, replaced atomically
+/// Falls back to `<dir>/<prefix>_latest.json` (`{"path": ...}`, relative to `dir` like the
+/// symlink) on Windows or when the filesystem refuses symlinks
+pub fn update_latest_pointer(dir: &Path, prefix: &str, target: &Path) -> Result<LatestPointer> {
+    let relative = target.strip_prefix(dir).unwrap_or(target);
+
+    #[cfg(unix)]
+    {
+        let link = dir.join(format!("{}_latest.parquet", prefix));
+        match replace_symlink(&link, relative) {
+            Ok(()) => {
+                let _ = fs::remove_file(dir.join(format!("{}_latest.json", prefix)));
+                return Ok(LatestPointer::Symlink(link));
+            }
+            Err(e) => warn!("Cannot symlink {:?}, writing a pointer file instead: {:#}", link, e),
+        }
+    }
+
+    write_latest_pointer_file(dir, prefix, relative).map(LatestPointer::File)
+}
+
+#[cfg(unix)]
+fn replace_symlink(link: &Path, target: &Path) -> Result<()> {
+    let mut temp_name = link.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp = PathBuf::from(temp_name);
+
+    let _ = fs::remove_file(&temp);
+    std::os::unix::fs::symlink(target, &temp)
+        .context(format!("Failed to create symlink: {:?}", temp))?;
+    fs::rename(&temp, link).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        anyhow::Error::new(e).context(format!("Failed to replace {:?}", link))
+    })
+}
+
+/// Write `<dir>/<prefix>_latest.json` with the newest file's path, replaced atomically
+pub fn write_latest_pointer_file(dir: &Path, prefix: &str, target: &Path) -> Result<PathBuf> {
+    let pointer_path = dir.join(format!("{}_latest.json", prefix));
+    let mut temp_name = pointer_path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp = PathBuf::from(temp_name);
+
+    let content = serde_json::json!({ "path": target.to_string_lossy() });
+    fs::write(&temp, format!("{}\n", content))
+        .context(format!("Failed to write pointer file: {:?}", temp))?;
+    fs::rename(&temp, &pointer_path)
+        .context(format!("Failed to replace pointer file: {:?}", pointer_path))?;
+    Ok(pointer_path)
+}
+
 /// Check if two schemas are compatible (same fields and types)
 pub fn schemas_compatible(schema1: &Schema, schema2: &Schema) -> bool {
     if schema1.fields().len() != schema2.fields().len() {
@@ -679,3 +1004,400 @@ pub fn schemas_compatible(schema1: &Schema, schema2: &Schema) -> bool {
 
     true
 }
+
+/// Result of re-reading the parquet files in a directory
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Readable files and their row counts
+    pub files: Vec<(PathBuf, usize)>,
+    /// Files that could not be read or whose schema differs from the first readable file
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl VerifyReport {
+    /// Total rows across readable files
+    pub fn total_rows(&self) -> usize {
+        self.files.iter().map(|(_, rows)| rows).sum()
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Read every row of a parquet file, returning the schema and row count
+fn read_parquet_file(path: &Path) -> Result<(Arc<Schema>, usize)> {
+    let file = File::open(path)
+        .context(format!("Failed to open {:?}", path))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+        .context("Failed to read parquet metadata")?;
+    let schema = builder.schema().clone();
+    let expected_rows = builder.metadata().file_metadata().num_rows();
+
+    let mut rows = 0;
+    for batch in builder.build().context("Failed to create record batch reader")? {
+        rows += batch.context("Failed to decode record batch")?.num_rows();
+    }
+    if rows as i64 != expected_rows {
+        anyhow::bail!("Read {} rows but metadata declares {}", rows, expected_rows);
+    }
+    Ok((schema, rows))
+}
+
+/// Re-read all `.parquet` files in a directory to catch disk or copy corruption
+/// Every file is fully decoded and its schema compared against the first readable file
+pub fn verify_parquet_dir(dir: &Path) -> Result<VerifyReport> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .context(format!("Failed to read directory {:?}", dir))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("parquet"))
+        .collect();
+    paths.sort();
+
+    let mut report = VerifyReport::default();
+    let mut reference: Option<(PathBuf, Arc<Schema>)> = None;
+    for path in paths {
+        match read_parquet_file(&path) {
+            Ok((schema, rows)) => {
+                if let Some((reference_path, reference_schema)) = &reference {
+                    if !schemas_compatible(&schema, reference_schema) {
+                        report.failures.push((path, format!("Schema differs from {:?}", reference_path)));
+                        continue;
+                    }
+                }
+                if reference.is_none() {
+                    reference = Some((path.clone(), schema));
+                }
+                report.files.push((path, rows));
+            }
+            Err(e) => report.failures.push((path, format!("{:#}", e))),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checksum_sidecar_matches_final_file() {
+        let dir = std::env::temp_dir().join(format!("tdp-checksum-{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let config = ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "mark-price".to_string(),
+            batch_size: 100,
+            has_batch_config: false,
+            filter: Vec::new(),
+            date: Some(date),
+            use_temp_dir: true,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            write_checksum: true,
+            write_latest_pointer: false,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        };
+        let mut writer = ParquetWriter::new(config.clone());
+
+        let rows: Vec<DataRow> = (0..3)
+            .map(|i| [("E".to_string(), Value::from(1762387200000u64 + i * 1000))].into_iter().collect())
+            .collect();
+        writer.write_rows(rows).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+
+        let parquet_path = config.expected_parquet_path(date);
+        assert_eq!(writer.written_files(), std::slice::from_ref(&parquet_path));
+
+        // Parquet bytes vary between library versions, so the digest itself is pinned in
+        // test_checksum_file_known_digest; this checks it covers the final file
+        let checksum_path = PathBuf::from(format!("{}.sha256", parquet_path.display()));
+        let content = fs::read_to_string(&checksum_path).unwrap();
+        let expected = format!(
+            "{}  {}\n",
+            sha256_file(&parquet_path).unwrap(),
+            parquet_path.file_name().unwrap().to_str().unwrap()
+        );
+        assert_eq!(content, expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_checksum_file_known_digest() {
+        let dir = std::env::temp_dir().join(format!("tdp-checksum-digest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("abc.parquet");
+        fs::write(&path, b"abc").unwrap();
+
+        // SHA256("abc") from FIPS 180-2
+        let checksum_path = write_checksum_file(&path).unwrap();
+        assert_eq!(checksum_path, dir.join("abc.parquet.sha256"));
+        assert_eq!(
+            fs::read_to_string(&checksum_path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  abc.parquet\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_latest_symlink_follows_newest_file() {
+        let dir = std::env::temp_dir().join(format!("tdp-latest-{}", std::process::id()));
+        let config = ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "mark-price-BTCUSDT".to_string(),
+            batch_size: 2,
+            has_batch_config: true,
+            filter: Vec::new(),
+            date: Some(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()),
+            use_temp_dir: false,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            write_checksum: false,
+            write_latest_pointer: true,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        };
+        let mut writer = ParquetWriter::new(config);
+
+        let rows = |start: u64, count: u64| -> Vec<DataRow> {
+            (start..start + count)
+                .map(|i| [("E".to_string(), Value::from(1762387200000u64 + i * 1000))].into_iter().collect())
+                .collect()
+        };
+        // The first batch fills a file on its own, the second is written on flush
+        writer.write_rows(rows(0, 2)).await.unwrap();
+        writer.write_rows(rows(2, 1)).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+        assert_eq!(writer.written_files().len(), 2);
+
+        let link = dir.join("BTCUSDT_latest.parquet");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("2025/11/06/BTCUSDT_000002_2025-11-06.parquet"));
+        assert_eq!(fs::canonicalize(&link).unwrap(), fs::canonicalize(&writer.written_files()[1]).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_latest_pointer_file_when_symlink_fails() {
+        let dir = std::env::temp_dir().join(format!("tdp-latest-fallback-{}", std::process::id()));
+        let target = dir.join("2025/11/06/BTCUSDT_2025-11-06.parquet");
+        // A non-empty directory where the symlink would go cannot be replaced by it
+        fs::create_dir_all(dir.join("BTCUSDT_latest.parquet/keep")).unwrap();
+
+        let expected = dir.join("BTCUSDT_latest.json");
+        let pointer = update_latest_pointer(&dir, "BTCUSDT", &target).unwrap();
+        assert_eq!(pointer, LatestPointer::File(expected.clone()));
+
+        let content: Value = serde_json::from_str(&fs::read_to_string(&expected).unwrap()).unwrap();
+        assert_eq!(content["path"], "2025/11/06/BTCUSDT_2025-11-06.parquet");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_sequence_resumes_after_existing_files() {
+        let dir = std::env::temp_dir().join(format!("tdp-sequence-{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let config = ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "mark-price".to_string(),
+            batch_size: 100,
+            has_batch_config: true,
+            filter: Vec::new(),
+            date: Some(date),
+            use_temp_dir: false,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            write_checksum: false,
+            write_latest_pointer: false,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        };
+
+        // A previous run's file plus names that must not be read as sequences
+        let day_dir = config.parquet_dir(date);
+        fs::create_dir_all(&day_dir).unwrap();
+        for name in [
+            "price_000005_2025-11-06.parquet",
+            "price_000009_2025-11-07.parquet",
+            "other_000010_2025-11-06.parquet",
+            "price_latest_2025-11-06.parquet",
+            "price_2025-11-06.parquet",
+        ] {
+            fs::write(day_dir.join(name), b"").unwrap();
+        }
+        assert_eq!(config.max_existing_sequence(date), 5);
+
+        let mut writer = ParquetWriter::new(config.clone());
+        let rows: Vec<DataRow> = (0..3)
+            .map(|i| [("E".to_string(), Value::from(1762387200000u64 + i * 1000))].into_iter().collect())
+            .collect();
+        writer.write_rows(rows).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+
+        assert_eq!(writer.written_files(), [day_dir.join("price_000006_2025-11-06.parquet")]);
+        assert_eq!(fs::metadata(day_dir.join("price_000005_2025-11-06.parquet")).unwrap().len(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_written_file_info_matches_footer() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let dir = std::env::temp_dir().join(format!("tdp-file-info-{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let config = ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "price".to_string(),
+            batch_size: 100,
+            has_batch_config: false,
+            filter: Vec::new(),
+            date: Some(date),
+            use_temp_dir: false,
+            row_group_size: 4,
+            write_checksum: false,
+            write_latest_pointer: false,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        };
+        let mut writer = ParquetWriter::new(config.clone());
+
+        let rows: Vec<DataRow> = (0..10)
+            .map(|i| [("E".to_string(), Value::from(1762387200000u64 + i * 1000))].into_iter().collect())
+            .collect();
+        writer.write_rows(rows).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+
+        let info = &writer.written_file_info()[0];
+        let path = config.expected_parquet_path(date);
+        assert_eq!(info.path, path);
+        assert_eq!(info.bytes, fs::metadata(&path).unwrap().len());
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(info.num_rows, reader.metadata().file_metadata().num_rows());
+        assert_eq!(info.num_row_groups, reader.metadata().num_row_groups());
+        assert_eq!((info.num_rows, info.num_row_groups), (10, 3));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_per_column_dictionary_and_encoding() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let dir = std::env::temp_dir().join(format!("tdp-encodings-{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let config = ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "trades".to_string(),
+            batch_size: 100,
+            has_batch_config: false,
+            filter: Vec::new(),
+            date: Some(date),
+            use_temp_dir: false,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            write_checksum: false,
+            write_latest_pointer: false,
+            column_order: None,
+            column_encodings: HashMap::from([(
+                "order_id".to_string(),
+                parse_column_encoding("delta_binary_packed").unwrap(),
+            )]),
+            dictionary_columns: Some(vec!["symbol".to_string()]),
+        };
+        let mut writer = ParquetWriter::new(config.clone());
+
+        let rows: Vec<DataRow> = (0..50u64)
+            .map(|i| {
+                [
+                    ("order_id".to_string(), Value::from(9_000_000_000 + i * 7)),
+                    ("symbol".to_string(), Value::from(if i % 2 == 0 { "BTCUSDT" } else { "ETHUSDT" })),
+                ]
+                .into_iter()
+                .collect()
+            })
+            .collect();
+        writer.write_rows(rows).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+
+        let reader = SerializedFileReader::new(File::open(config.expected_parquet_path(date)).unwrap()).unwrap();
+        let row_group = reader.metadata().row_group(0);
+        let column = |name: &str| {
+            row_group
+                .columns()
+                .iter()
+                .find(|c| c.column_path().string() == name)
+                .unwrap()
+        };
+
+        let is_dictionary = |e: &Encoding| matches!(e, Encoding::PLAIN_DICTIONARY | Encoding::RLE_DICTIONARY);
+
+        let symbol = column("symbol");
+        assert!(symbol.dictionary_page_offset().is_some());
+        assert!(symbol.encodings().iter().any(is_dictionary));
+
+        let order_id = column("order_id");
+        assert!(order_id.dictionary_page_offset().is_none());
+        assert!(order_id.encodings().contains(&Encoding::DELTA_BINARY_PACKED));
+        assert!(!order_id.encodings().iter().any(is_dictionary));
+
+        assert!(parse_column_encoding("rle_dictionary").is_err());
+        assert!(parse_column_encoding("zigzag").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_column_order_prefix() {
+        let config = ParquetWriterConfig {
+            path: std::env::temp_dir().to_string_lossy().to_string(),
+            name: "mark-price".to_string(),
+            batch_size: 100,
+            has_batch_config: false,
+            filter: Vec::new(),
+            date: None,
+            use_temp_dir: false,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            write_checksum: false,
+            write_latest_pointer: false,
+            column_order: Some(vec!["timestamp".to_string(), "symbol".to_string(), "missing".to_string()]),
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        };
+        let writer = ParquetWriter::new(config);
+
+        let data = vec![serde_json::json!({
+            "mark_price": "103308.5",
+            "symbol": "BTCUSDT",
+            "event_time": 1762411870001u64,
+            "timestamp": 1762411870000u64,
+            "funding_rate": "0.0001",
+        })];
+        let (schema, _) = ParquetWriter::infer_schema_from_data(&data).unwrap();
+        let schema = writer.apply_column_order(schema);
+
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["timestamp", "symbol", "event_time", "funding_rate", "mark_price"]);
+    }
+
+    #[test]
+    fn test_schema_is_union_of_all_rows() {
+        let data = vec![
+            serde_json::json!({"symbol": "BTCUSDT", "mark_price": "103308.5"}),
+            serde_json::json!({"symbol": "ETHUSDT", "mark_price": "3377.5", "index_price": 3377.1}),
+        ];
+        let (schema, column_types) = ParquetWriter::infer_schema_from_data(&data).unwrap();
+
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["index_price", "mark_price", "symbol"]);
+        assert_eq!(column_types["index_price"], ColumnType::Float64);
+    }
+}
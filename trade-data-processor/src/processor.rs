@@ -0,0 +1,339 @@
+//! Data processor module
+//!
+//! This module defines the DataProcessor trait implemented by the mergers and a
+//! registry that maps data type names (e.g., "mark-price") to processor constructors,
+//! so new specialized mergers can be added without editing the processing loop.
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::config::DataSourceConfig;
+use crate::data_merger::DataMerger;
+use crate::mark_price_merger::{ForwardFillLimits, MarkPriceMerger, RowValidation, SpillStats, SymbolFilter};
+use crate::writer::DataRow;
+
+/// Symbol under which processors that don't split data by symbol expose all their rows
+pub const ALL_ROWS_SYMBOL: &str = "";
+
+/// Common interface of the per-day data mergers
+pub trait DataProcessor: Send {
+    /// Add data from a JSONL reader, returning the number of records added
+    fn add_jsonl_reader(&mut self, reader: &mut dyn BufRead, source_name: &str) -> Result<usize>;
+
+    /// Add data from a JSONL string, returning the number of records added
+    fn add_jsonl(&mut self, jsonl_content: &str, source_name: &str) -> Result<usize> {
+        self.add_jsonl_reader(&mut jsonl_content.as_bytes(), source_name)
+    }
+
+    /// Apply forward-fill so every second of the UTC day has data
+    fn forward_fill(&mut self) -> Result<()>;
+
+    /// Symbols that have data
+    /// Processors that don't split by symbol return `ALL_ROWS_SYMBOL` when non-empty
+    fn symbols(&self) -> Vec<String>;
+
+    /// Data rows for a symbol, sorted by timestamp
+    fn rows_for_symbol(&self, symbol: &str) -> Option<Vec<DataRow>>;
+
+    /// Number of unique seconds with data for a symbol
+    fn len_for_symbol(&self, symbol: &str) -> usize;
+
+    /// Remove all data for a symbol, returning whether it was present
+    fn remove_symbol(&mut self, symbol: &str) -> bool;
+
+    /// Whether the processor has no data
+    fn is_empty(&self) -> bool;
+
+    /// Whether output is written as one file per symbol
+    fn splits_by_symbol(&self) -> bool {
+        true
+    }
+
+    /// Spill activity, for processors that can spill data to disk
+    fn spill_stats(&self) -> Option<SpillStats> {
+        None
+    }
+}
+
+impl DataProcessor for MarkPriceMerger {
+    fn add_jsonl_reader(&mut self, reader: &mut dyn BufRead, source_name: &str) -> Result<usize> {
+        Ok(MarkPriceMerger::add_jsonl_reader(self, reader, source_name)?)
+    }
+
+    fn forward_fill(&mut self) -> Result<()> {
+        Ok(self.apply_forward_fill()?)
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        self.get_symbols()
+    }
+
+    fn rows_for_symbol(&self, symbol: &str) -> Option<Vec<DataRow>> {
+        self.get_sorted_rows_for_symbol(symbol)
+    }
+
+    fn len_for_symbol(&self, symbol: &str) -> usize {
+        MarkPriceMerger::len_for_symbol(self, symbol)
+    }
+
+    fn remove_symbol(&mut self, symbol: &str) -> bool {
+        MarkPriceMerger::remove_symbol(self, symbol)
+    }
+
+    fn is_empty(&self) -> bool {
+        MarkPriceMerger::is_empty(self)
+    }
+
+    fn spill_stats(&self) -> Option<SpillStats> {
+        MarkPriceMerger::spill_stats(self)
+    }
+}
+
+impl DataProcessor for DataMerger {
+    fn add_jsonl_reader(&mut self, reader: &mut dyn BufRead, source_name: &str) -> Result<usize> {
+        Ok(DataMerger::add_jsonl_reader(self, reader, source_name)?)
+    }
+
+    fn forward_fill(&mut self) -> Result<()> {
+        Ok(self.apply_forward_fill()?)
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        if DataMerger::is_empty(self) {
+            Vec::new()
+        } else {
+            vec![ALL_ROWS_SYMBOL.to_string()]
+        }
+    }
+
+    fn rows_for_symbol(&self, symbol: &str) -> Option<Vec<DataRow>> {
+        (symbol == ALL_ROWS_SYMBOL).then(|| self.get_sorted_rows())
+    }
+
+    fn len_for_symbol(&self, symbol: &str) -> usize {
+        if symbol == ALL_ROWS_SYMBOL {
+            self.len()
+        } else {
+            0
+        }
+    }
+
+    fn remove_symbol(&mut self, symbol: &str) -> bool {
+        if symbol != ALL_ROWS_SYMBOL || DataMerger::is_empty(self) {
+            return false;
+        }
+        self.clear();
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        DataMerger::is_empty(self)
+    }
+
+    fn splits_by_symbol(&self) -> bool {
+        false
+    }
+}
+
+/// Constructor creating a processor for a date and data source configuration
+pub type ProcessorConstructor = fn(NaiveDate, &DataSourceConfig) -> Box<dyn DataProcessor>;
+
+/// Registry mapping data type names to processor constructors
+/// Data types are matched case-insensitively; unknown types use the fallback processor
+pub struct ProcessorRegistry {
+    constructors: HashMap<String, ProcessorConstructor>,
+    fallback: ProcessorConstructor,
+}
+
+impl ProcessorRegistry {
+    /// Create an empty registry that uses `fallback` for every data type
+    pub fn new(fallback: ProcessorConstructor) -> Self {
+        Self {
+            constructors: HashMap::new(),
+            fallback,
+        }
+    }
+
+    /// Register a constructor for a data type, replacing any previous one
+    pub fn register(&mut self, data_type: &str, constructor: ProcessorConstructor) {
+        self.constructors.insert(data_type.to_lowercase(), constructor);
+    }
+
+    /// Whether a specialized processor is registered for a data type
+    pub fn is_registered(&self, data_type: &str) -> bool {
+        self.constructors.contains_key(&data_type.to_lowercase())
+    }
+
+    /// Create the processor for a data source's data type and a date
+    pub fn create(&self, data_source: &DataSourceConfig, date: NaiveDate) -> Box<dyn DataProcessor> {
+        let constructor = self
+            .constructors
+            .get(&data_source.data_type.to_lowercase())
+            .unwrap_or(&self.fallback);
+        constructor(date, data_source)
+    }
+}
+
+impl Default for ProcessorRegistry {
+    /// Registry with MarkPriceMerger for "mark-price" and DataMerger for everything else
+    fn default() -> Self {
+        let mut registry = Self::new(|date, data_source| {
+            let mut merger = DataMerger::new(date);
+            if data_source.reject_out_of_range {
+                merger = merger.with_date_range_check(data_source.out_of_range_grace_secs);
+            }
+            if data_source.add_source_column {
+                merger = merger.with_source_column();
+            }
+            if !data_source.dedup_key_fields.is_empty() {
+                merger = merger.with_dedup_key_fields(data_source.dedup_key_fields.clone());
+            }
+            Box::new(merger)
+        });
+        registry.register("mark-price", |date, data_source| {
+            let symbol_filter = SymbolFilter::new(&data_source.symbols_allowlist, &data_source.symbols_denylist);
+            let mut merger = MarkPriceMerger::new(date).with_symbol_filter(symbol_filter);
+            if data_source.reject_out_of_range {
+                merger = merger.with_date_range_check(data_source.out_of_range_grace_secs);
+            }
+            if data_source.add_source_column {
+                merger = merger.with_source_column();
+            }
+            if let Some(budget_bytes) = data_source.spill_memory_budget_bytes {
+                merger = merger.with_spill_budget(budget_bytes);
+            }
+            merger = merger.with_forward_fill_limits(ForwardFillLimits {
+                max_estimated_rows: data_source.forward_fill_max_rows,
+                max_memory_bytes: data_source.forward_fill_max_memory_bytes,
+            });
+            if let Some(field_renames) = &data_source.field_renames {
+                merger = merger.with_field_renames(field_renames.clone());
+            }
+            let mut validation = RowValidation::default();
+            if let Some(required_fields) = &data_source.required_fields {
+                validation.required_fields = required_fields.clone();
+            }
+            validation.optional_numeric_fields = data_source.optional_numeric_fields.clone();
+            validation.field_ranges = data_source.field_ranges.clone();
+            merger = merger.with_validation(validation);
+            Box::new(merger)
+        });
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Processor that records how much data it was given
+    struct DummyProcessor {
+        lines: usize,
+    }
+
+    impl DataProcessor for DummyProcessor {
+        fn add_jsonl_reader(&mut self, reader: &mut dyn BufRead, _source_name: &str) -> Result<usize> {
+            let added = reader.lines().count();
+            self.lines += added;
+            Ok(added)
+        }
+
+        fn forward_fill(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn symbols(&self) -> Vec<String> {
+            vec!["DUMMY".to_string()]
+        }
+
+        fn rows_for_symbol(&self, _symbol: &str) -> Option<Vec<DataRow>> {
+            Some(Vec::new())
+        }
+
+        fn len_for_symbol(&self, _symbol: &str) -> usize {
+            self.lines
+        }
+
+        fn remove_symbol(&mut self, _symbol: &str) -> bool {
+            false
+        }
+
+        fn is_empty(&self) -> bool {
+            self.lines == 0
+        }
+    }
+
+    fn date() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()
+    }
+
+    fn data_source(data_type: &str) -> DataSourceConfig {
+        DataSourceConfig {
+            data_type: data_type.to_string(),
+            ssh_servers: Vec::new(),
+            http_servers: Vec::new(),
+            local_files: Vec::new(),
+            symbols_allowlist: Vec::new(),
+            symbols_denylist: Vec::new(),
+            file_extensions: crate::config::default_file_extensions(),
+            reject_out_of_range: false,
+            out_of_range_grace_secs: 0,
+            add_source_column: false,
+            dedup_key_fields: Vec::new(),
+            spill_memory_budget_bytes: None,
+            forward_fill_max_rows: None,
+            forward_fill_max_memory_bytes: None,
+            field_renames: None,
+            required_fields: None,
+            optional_numeric_fields: Vec::new(),
+            field_ranges: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_registry_routes_custom_data_type() {
+        let mut registry = ProcessorRegistry::default();
+        registry.register("made-up", |_date, _data_source| Box::new(DummyProcessor { lines: 0 }));
+
+        let mut processor = registry.create(&data_source("Made-Up"), date());
+        assert_eq!(processor.add_jsonl("a\nb\n", "source1").unwrap(), 2);
+        assert_eq!(processor.symbols(), vec!["DUMMY".to_string()]);
+        assert_eq!(processor.len_for_symbol("DUMMY"), 2);
+    }
+
+    #[test]
+    fn test_registry_defaults() {
+        let registry = ProcessorRegistry::default();
+        assert!(registry.is_registered("mark-price"));
+        assert!(!registry.is_registered("trades"));
+
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}"#;
+
+        let mut mark_price = registry.create(&data_source("mark-price"), date());
+        mark_price.add_jsonl(jsonl, "source1").unwrap();
+        assert!(mark_price.splits_by_symbol());
+        assert_eq!(mark_price.symbols(), vec!["BTCUSDT".to_string()]);
+
+        let mut generic = registry.create(&data_source("trades"), date());
+        generic.add_jsonl(jsonl, "source1").unwrap();
+        assert!(!generic.splits_by_symbol());
+        assert_eq!(generic.symbols(), vec![ALL_ROWS_SYMBOL.to_string()]);
+        assert_eq!(generic.rows_for_symbol(ALL_ROWS_SYMBOL).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_mark_price_uses_symbol_allowlist() {
+        let registry = ProcessorRegistry::default();
+        let mut source = data_source("mark-price");
+        source.symbols_allowlist = vec!["btcusdt".to_string()];
+
+        let jsonl = r#"{"E":1762411870001,"s":"BTCUSDT","p":"103308.5","r":"0.0001"}
+{"E":1762411870001,"s":"ETHUSDT","p":"3377.5","r":"0.00005"}"#;
+
+        let mut processor = registry.create(&source, date());
+        assert_eq!(processor.add_jsonl(jsonl, "source1").unwrap(), 1);
+        assert_eq!(processor.symbols(), vec!["BTCUSDT".to_string()]);
+    }
+}
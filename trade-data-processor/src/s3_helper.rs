@@ -22,21 +22,82 @@ use aws_sdk_s3::config::{Credentials, Region};
 use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::Client as S3Client;
 use bytes::Bytes;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{info, debug, warn, error};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 use walkdir::WalkDir;
+
+use crate::error::{ProcessorError, ProcessorResult};
 use std::fs;
 
 use crate::config::S3Config;
 
 /// S3-compatible storage client
+#[derive(Clone)]
 pub struct S3Helper {
     client: S3Client,
     config: S3Config,
 }
 
+/// Result of a HEAD request (see `S3Helper::get_object_metadata`)
+struct ObjectHead {
+    size: i64,
+    last_modified: String,
+    /// SHA256 of the original file, set on objects uploaded by a sync
+    sha256: Option<String>,
+}
+
+/// Behaviour of `upload_files_batch_with_options` when an upload fails
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatchUploadOptions {
+    /// Delete the files that did upload when any file in the batch fails
+    /// Only keys the batch created are deleted; keys that already existed keep the new
+    /// content, since the previous content is not kept (see `BatchUploadResult::overwritten`)
+    pub atomic: bool,
+    /// What to do when several files of the batch map to the same key
+    pub duplicate_keys: DuplicateKeyPolicy,
+}
+
+/// Handling of batch entries that share an S3 key
+///
+/// Concurrent uploads to one key race and the last one silently wins, so duplicates
+/// (usually a path normalization bug) are caught before anything is uploaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    /// Fail the batch without uploading anything, listing the duplicate keys
+    #[default]
+    Error,
+    /// Upload the first file for each key, skip later ones with a warning
+    KeepFirst,
+}
+
+/// Outcome of a batch upload
+#[derive(Debug, Clone, Default)]
+pub struct BatchUploadResult {
+    /// Keys uploaded and left in place
+    pub uploaded: Vec<String>,
+    /// Keys that failed to upload, with the error
+    pub failed: Vec<(String, String)>,
+    /// Keys that uploaded but were deleted again because the batch was atomic
+    pub rolled_back: Vec<String>,
+    /// Keys that already existed before an atomic batch and were overwritten
+    /// A rollback leaves them in `uploaded`: their previous content is lost
+    pub overwritten: Vec<String>,
+    /// Files skipped because an earlier file in the batch had the same key (`KeepFirst`)
+    pub skipped_duplicates: Vec<(String, PathBuf)>,
+}
+
+impl BatchUploadResult {
+    /// Whether every file in the batch was uploaded
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
 /// S3 provider types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum S3Provider {
@@ -52,6 +113,8 @@ pub enum S3Provider {
 
 impl S3Provider {
     /// Parse provider from string
+    /// Unknown names fall back to `Generic` rather than failing, so this is not `FromStr`
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         match s.to_lowercase().as_str() {
             "aws" | "s3" | "aws-s3" => S3Provider::AwsS3,
@@ -77,19 +140,26 @@ impl S3Helper {
         Ok(Self { client, config })
     }
 
+    /// Inline credentials from the config, or `None` to fall back to the credential chain
+    /// Setting only one of the two keys is an error rather than a silent fallback
+    fn static_credentials(config: &S3Config) -> Result<Option<Credentials>> {
+        match (config.access_key_id.is_empty(), config.secret_access_key.is_empty()) {
+            (true, true) => Ok(None),
+            (false, false) => Ok(Some(Credentials::new(
+                &config.access_key_id,
+                &config.secret_access_key,
+                None,  // session token
+                None,  // expiration
+                "custom"  // provider name
+            ))),
+            _ => bail!("S3 access_key_id and secret_access_key must both be set, or both be empty to use the AWS credential chain"),
+        }
+    }
+
     /// Create S3 client with custom configuration
     async fn create_client(config: &S3Config) -> Result<S3Client> {
         let provider = S3Provider::from_str(&config.provider);
         
-        // Create credentials
-        let credentials = Credentials::new(
-            &config.access_key_id,
-            &config.secret_access_key,
-            None,  // session token
-            None,  // expiration
-            "custom"  // provider name
-        );
-
         // Determine region
         let region = config.region.clone()
             .unwrap_or_else(|| match provider {
@@ -101,12 +171,16 @@ impl S3Helper {
 
         let region_provider = RegionProviderChain::first_try(Region::new(region));
 
-        // Build AWS config
-        let aws_config = aws_config::defaults(BehaviorVersion::latest())
-            .region(region_provider)
-            .credentials_provider(credentials)
-            .load()
-            .await;
+        // Build AWS config; without inline keys the default credential chain applies
+        let mut loader = aws_config::defaults(BehaviorVersion::latest()).region(region_provider);
+        if let Some(profile) = &config.profile {
+            loader = loader.profile_name(profile);
+        }
+        match Self::static_credentials(config)? {
+            Some(credentials) => loader = loader.credentials_provider(credentials),
+            None => info!("No access keys configured, using the AWS credential chain (profile: {:?})", config.profile),
+        }
+        let aws_config = loader.load().await;
 
         // Build S3 config with custom endpoint if provided
         let mut s3_config_builder = aws_sdk_s3::config::Builder::from(&aws_config);
@@ -159,17 +233,46 @@ impl S3Helper {
     /// # Returns
     /// The uploaded object's ETag
     pub async fn upload_file<P: AsRef<Path>>(&self, local_path: P, key: &str) -> Result<String> {
+        self.upload_file_with_attributes(local_path, key, &HashMap::new(), &HashMap::new()).await
+    }
+
+    /// Upload a file to S3 with object tags and user metadata
+    ///
+    /// # Arguments
+    /// * `local_path` - Path to the local file
+    /// * `key` - S3 object key (path in bucket)
+    /// * `tags` - Object tags (e.g., for lifecycle rules or cost allocation)
+    /// * `metadata` - User metadata, stored as `x-amz-meta-*` headers
+    ///
+    /// # Returns
+    /// The uploaded object's ETag
+    pub async fn upload_file_with_attributes<P: AsRef<Path>>(
+        &self,
+        local_path: P,
+        key: &str,
+        tags: &HashMap<String, String>,
+        metadata: &HashMap<String, String>,
+    ) -> Result<String> {
         let local_path = local_path.as_ref();
         info!("Uploading file {:?} to s3://{}/{}", local_path, self.config.bucket, key);
 
         let body = ByteStream::from_path(local_path).await
             .context(format!("Failed to read local file: {:?}", local_path))?;
 
-        let response = self.client
+        let mut request = self.client
             .put_object()
             .bucket(&self.config.bucket)
             .key(key)
-            .body(body)
+            .body(body);
+
+        if let Some(tagging) = encode_tagging(tags) {
+            request = request.tagging(tagging);
+        }
+        if !metadata.is_empty() {
+            request = request.set_metadata(Some(metadata.clone()));
+        }
+
+        let response = request
             .send()
             .await
             .context(format!("Failed to upload file to S3: {}", key))?;
@@ -217,21 +320,13 @@ impl S3Helper {
     /// # Arguments
     /// * `key` - S3 object key (path in bucket)
     /// * `local_path` - Path where to save the downloaded file
-    pub async fn download_file<P: AsRef<Path>>(&self, key: &str, local_path: P) -> Result<()> {
+    ///
+    /// Returns `ProcessorError::S3NotFound` if the object does not exist
+    pub async fn download_file<P: AsRef<Path>>(&self, key: &str, local_path: P) -> ProcessorResult<()> {
         let local_path = local_path.as_ref();
         info!("Downloading s3://{}/{} to {:?}", self.config.bucket, key, local_path);
 
-        let response = self.client
-            .get_object()
-            .bucket(&self.config.bucket)
-            .key(key)
-            .send()
-            .await
-            .context(format!("Failed to download file from S3: {}", key))?;
-
-        let data = response.body.collect().await
-            .context("Failed to read response body")?
-            .into_bytes();
+        let data = self.get_object_bytes(key).await?;
 
         // Create parent directory if it doesn't exist
         if let Some(parent) = local_path.parent() {
@@ -252,24 +347,50 @@ impl S3Helper {
     /// * `key` - S3 object key (path in bucket)
     ///
     /// # Returns
-    /// The object's contents as bytes
-    pub async fn download_bytes(&self, key: &str) -> Result<Bytes> {
+    /// The object's contents as bytes, or `ProcessorError::S3NotFound` if the object does not exist
+    pub async fn download_bytes(&self, key: &str) -> ProcessorResult<Bytes> {
         debug!("Downloading s3://{}/{} as bytes", self.config.bucket, key);
 
-        let response = self.client
+        let data = self.get_object_bytes(key).await?;
+
+        debug!("Successfully downloaded {} ({} bytes)", key, data.len());
+        Ok(data)
+    }
+
+    /// Fetch an object's contents, mapping a missing key to `ProcessorError::S3NotFound`
+    async fn get_object_bytes(&self, key: &str) -> ProcessorResult<Bytes> {
+        let (data, _) = self.get_object_with_sha256(key).await?;
+        Ok(data)
+    }
+
+    /// Fetch an object's contents together with its `sha256` user metadata, if any
+    async fn get_object_with_sha256(&self, key: &str) -> ProcessorResult<(Bytes, Option<String>)> {
+        let response = match self.client
             .get_object()
             .bucket(&self.config.bucket)
             .key(key)
             .send()
             .await
-            .context(format!("Failed to download object from S3: {}", key))?;
+        {
+            Ok(response) => response,
+            Err(e) if e.as_service_error().is_some_and(|se| se.is_no_such_key()) => {
+                return Err(ProcessorError::S3NotFound {
+                    bucket: self.config.bucket.clone(),
+                    key: key.to_string(),
+                });
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Failed to download object from S3: {}", key))
+                    .into());
+            }
+        };
 
+        let sha256 = response.metadata().and_then(|m| m.get("sha256")).cloned();
         let data = response.body.collect().await
             .context("Failed to read response body")?
             .into_bytes();
-
-        debug!("Successfully downloaded {} ({} bytes)", key, data.len());
-        Ok(data)
+        Ok((data, sha256))
     }
 
     /// List objects in the bucket with a given prefix
@@ -484,6 +605,12 @@ impl S3Helper {
     /// # Returns
     /// Tuple of (size_bytes, last_modified)
     pub async fn get_object_metadata(&self, key: &str) -> Result<(i64, String)> {
+        let head = self.object_head(key).await?;
+        Ok((head.size, head.last_modified))
+    }
+
+    /// HEAD an object, keeping the `sha256` user metadata set by sync uploads
+    async fn object_head(&self, key: &str) -> Result<ObjectHead> {
         debug!("Getting metadata for s3://{}/{}", self.config.bucket, key);
 
         let response = self.client
@@ -499,8 +626,10 @@ impl S3Helper {
             .map(|dt| dt.to_string())
             .unwrap_or_else(|| "unknown".to_string());
 
+        let sha256 = response.metadata().and_then(|m| m.get("sha256")).cloned();
+
         debug!("Object {} - Size: {} bytes, Last Modified: {}", key, size, last_modified);
-        Ok((size, last_modified))
+        Ok(ObjectHead { size, last_modified, sha256 })
     }
 
     /// Upload multiple files in parallel
@@ -511,10 +640,38 @@ impl S3Helper {
     /// # Returns
     /// Number of successfully uploaded files
     pub async fn upload_files_batch<P: AsRef<Path>>(&self, files: Vec<(P, String)>) -> Result<usize> {
+        let result = self.upload_files_batch_with_options(files, BatchUploadOptions::default()).await?;
+        Ok(result.uploaded.len())
+    }
+
+    /// Upload multiple files in parallel, cleaning up after failures
+    ///
+    /// If any upload fails, in-progress multipart uploads for the failed keys are aborted
+    /// so orphaned parts don't accumulate. With `atomic` set, the objects that did upload
+    /// are deleted as well, making the batch all-or-nothing.
+    ///
+    /// Files sharing a key are rejected before any upload starts, unless
+    /// `duplicate_keys` is `KeepFirst` (see `DuplicateKeyPolicy`).
+    ///
+    /// # Arguments
+    /// * `files` - Vector of (local_path, s3_key) tuples
+    /// * `options` - Batch behaviour on failure
+    ///
+    /// # Returns
+    /// Uploaded, failed and rolled-back keys
+    pub async fn upload_files_batch_with_options<P: AsRef<Path>>(
+        &self,
+        files: Vec<(P, String)>,
+        options: BatchUploadOptions,
+    ) -> Result<BatchUploadResult> {
+        let mut result = BatchUploadResult::default();
         if files.is_empty() {
-            return Ok(0);
+            return Ok(result);
         }
 
+        let (files, skipped_duplicates) = dedupe_batch_keys(files, options.duplicate_keys)?;
+        result.skipped_duplicates = skipped_duplicates;
+
         info!("Uploading {} files in batch to s3://{}", files.len(), self.config.bucket);
 
         let mut tasks = Vec::new();
@@ -522,33 +679,137 @@ impl S3Helper {
         for (local_path, key) in files {
             let client = self.client.clone();
             let bucket = self.config.bucket.clone();
-            let local_path = local_path.as_ref().to_path_buf();
+            let task_key = key.clone();
+            let atomic = options.atomic;
             
             let task = tokio::spawn(async move {
-                let body = ByteStream::from_path(&local_path).await?;
+                // A rollback may only delete keys this batch created
+                let existed = atomic && match client.head_object().bucket(&bucket).key(&task_key).send().await {
+                    Ok(_) => true,
+                    Err(e) if e.as_service_error().is_some_and(|se| se.is_not_found()) => false,
+                    Err(e) => {
+                        warn!("Failed to check whether {} exists, it will not be rolled back: {}", task_key, e);
+                        true
+                    }
+                };
+
+                let body = ByteStream::from_path(&local_path).await
+                    .context(format!("Failed to read local file: {:?}", local_path))?;
                 
                 client
                     .put_object()
                     .bucket(&bucket)
-                    .key(&key)
+                    .key(&task_key)
                     .body(body)
                     .send()
                     .await
                     .context(format!("Failed to upload file: {:?}", local_path))?;
                 
-                Ok::<_, anyhow::Error>(())
+                Ok::<_, anyhow::Error>(existed)
             });
 
-            tasks.push(task);
+            tasks.push((key, task));
+        }
+
+        let total = tasks.len();
+        for (key, task) in tasks {
+            match task.await {
+                Ok(Ok(existed)) => {
+                    if existed {
+                        result.overwritten.push(key.clone());
+                    }
+                    result.uploaded.push(key);
+                }
+                Ok(Err(e)) => result.failed.push((key, format!("{:#}", e))),
+                Err(e) => result.failed.push((key, format!("Upload task panicked: {}", e))),
+            }
+        }
+
+        info!("Successfully uploaded {} out of {} files", result.uploaded.len(), total);
+        if result.failed.is_empty() {
+            return Ok(result);
+        }
+
+        for (key, error) in &result.failed {
+            warn!("Failed to upload {}: {}", key, error);
+            match self.abort_multipart_uploads(key).await {
+                Ok(0) => {}
+                Ok(aborted) => info!("Aborted {} in-progress multipart upload(s) for {}", aborted, key),
+                Err(e) => warn!("Failed to abort multipart uploads for {}: {:#}", key, e),
+            }
+        }
+
+        if options.atomic {
+            warn!("Rolling back {} uploaded file(s) of the failed batch", result.uploaded.len() - result.overwritten.len());
+            for key in std::mem::take(&mut result.uploaded) {
+                if result.overwritten.contains(&key) {
+                    warn!("Not rolling back {}: it existed before the batch and its previous content is lost", key);
+                    result.uploaded.push(key);
+                    continue;
+                }
+                match self.delete_object(&key).await {
+                    Ok(()) => result.rolled_back.push(key),
+                    Err(e) => {
+                        error!("Failed to roll back {}: {:#}", key, e);
+                        result.uploaded.push(key);
+                    }
+                }
+            }
         }
 
-        let results = futures::future::join_all(tasks).await;
-        let success_count = results.iter()
-            .filter(|r| r.is_ok() && r.as_ref().unwrap().is_ok())
-            .count();
+        Ok(result)
+    }
+
+    /// Abort in-progress multipart uploads for exactly this key
+    ///
+    /// # Returns
+    /// Number of aborted uploads
+    pub async fn abort_multipart_uploads(&self, key: &str) -> Result<usize> {
+        let mut aborted = 0;
+        let mut key_marker: Option<String> = None;
+        let mut upload_id_marker: Option<String> = None;
+
+        loop {
+            let response = self.client
+                .list_multipart_uploads()
+                .bucket(&self.config.bucket)
+                .prefix(key)
+                .set_key_marker(key_marker.take())
+                .set_upload_id_marker(upload_id_marker.take())
+                .send()
+                .await
+                .context(format!("Failed to list multipart uploads for {}", key))?;
+
+            for upload in response.uploads() {
+                let (Some(upload_key), Some(upload_id)) = (upload.key(), upload.upload_id()) else {
+                    continue;
+                };
+                if upload_key != key {
+                    continue;
+                }
+                self.client
+                    .abort_multipart_upload()
+                    .bucket(&self.config.bucket)
+                    .key(upload_key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                    .context(format!("Failed to abort multipart upload {} for {}", upload_id, key))?;
+                aborted += 1;
+            }
 
-        info!("Successfully uploaded {} out of {} files", success_count, results.len());
-        Ok(success_count)
+            // Check if there are more results
+            if response.is_truncated() == Some(true) {
+                key_marker = response.next_key_marker().map(|s| s.to_string());
+                upload_id_marker = response.next_upload_id_marker().map(|s| s.to_string());
+                if key_marker.is_none() && upload_id_marker.is_none() {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+        Ok(aborted)
     }
 
     /// Get bucket name
@@ -626,8 +887,23 @@ impl SyncDatabase {
     /// # Arguments
     /// * `db_path` - Path to the database directory
     pub fn open<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let db = sled::open(db_path.as_ref())
-            .context(format!("Failed to open sync database: {:?}", db_path.as_ref()))?;
+        let db_path = db_path.as_ref();
+        
+        // sled releases the file lock of a dropped handle from its background threads, so
+        // reopening right after (e.g. the download half of a bidirectional sync) can
+        // briefly find the database still locked
+        let mut attempts = 1;
+        let db = loop {
+            match sled::open(db_path) {
+                Ok(db) => break db,
+                Err(sled::Error::Io(e)) if attempts < SYNC_DB_OPEN_ATTEMPTS && e.to_string().contains("could not acquire lock") => {
+                    debug!("Sync database {:?} still locked, retrying", db_path);
+                    attempts += 1;
+                    std::thread::sleep(Duration::from_millis(20));
+                }
+                Err(e) => return Err(e).context(format!("Failed to open sync database: {:?}", db_path)),
+            }
+        };
         
         Ok(Self { db })
     }
@@ -685,6 +961,35 @@ impl SyncDatabase {
     }
 }
 
+/// Attempts to open a sync database whose lock is still held, 20ms apart
+const SYNC_DB_OPEN_ATTEMPTS: usize = 50;
+
+/// Periodic flush of the sync database during a sync
+struct Checkpoint {
+    every: usize,
+    pending: usize,
+}
+
+impl Checkpoint {
+    fn new(every: usize) -> Self {
+        Self { every, pending: 0 }
+    }
+
+    /// Count a recorded file and flush once `every` files are pending
+    fn record(&mut self, db: &SyncDatabase) -> Result<()> {
+        self.pending += 1;
+        if self.every > 0 && self.pending >= self.every {
+            db.flush()?;
+            debug!("Sync checkpoint: flushed {} recorded files", self.pending);
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Downloads of a restore are attempted this many times when the SHA256 does not match
+const RESTORE_ATTEMPTS: usize = 2;
+
 /// Sync direction
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SyncDirection {
@@ -713,6 +1018,18 @@ pub struct SyncOptions {
     pub max_parallel: usize,
     /// Use zstd compression for S3 storage (local files remain uncompressed)
     pub use_compression: bool,
+    /// Tags applied to every uploaded object (e.g., {"dataset": "mark-price"})
+    pub object_tags: HashMap<String, String>,
+    /// User metadata applied to every uploaded object
+    /// The SHA256 of the original file is always added as `sha256`
+    pub user_metadata: HashMap<String, String>,
+    /// Flush the sync database after this many recorded files, so an interrupted
+    /// sync resumes near where it stopped (0 = only flush at the end)
+    /// Lower values are more durable but cause more sled write amplification
+    pub checkpoint_every: usize,
+    /// Record path, size, duration and direction of every transferred file in
+    /// `SyncStats::timings` (off by default to avoid the overhead on huge syncs)
+    pub collect_timings: bool,
 }
 
 impl Default for SyncOptions {
@@ -730,6 +1047,10 @@ impl Default for SyncOptions {
             ],
             max_parallel: 4,
             use_compression: true,
+            object_tags: HashMap::new(),
+            user_metadata: HashMap::new(),
+            checkpoint_every: 100,
+            collect_timings: false,
         }
     }
 }
@@ -745,6 +1066,44 @@ pub struct SyncStats {
     pub bytes_uploaded: u64,
     pub bytes_downloaded: u64,
     pub errors: usize,
+    /// Per-file transfer timings, only collected with `SyncOptions::collect_timings`
+    pub timings: Vec<TransferTiming>,
+}
+
+impl SyncStats {
+    /// The `n` slowest transfers, slowest first
+    pub fn slowest_transfers(&self, n: usize) -> Vec<&TransferTiming> {
+        let mut timings: Vec<&TransferTiming> = self.timings.iter().collect();
+        timings.sort_by_key(|timing| std::cmp::Reverse(timing.duration));
+        timings.truncate(n);
+        timings
+    }
+}
+
+/// Time taken to transfer one file during a sync
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferTiming {
+    /// Relative path of the file
+    pub path: String,
+    /// Size of the original (uncompressed) file
+    pub bytes: u64,
+    /// Wall time including compression/decompression
+    pub duration: Duration,
+    /// `LocalToS3` for uploads, `S3ToLocal` for downloads
+    pub direction: SyncDirection,
+}
+
+/// What a sync would do, as relative paths (see `S3Helper::plan_sync`)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SyncPlan {
+    /// Local files that would be uploaded
+    pub to_upload: Vec<String>,
+    /// S3 objects that would be downloaded
+    pub to_download: Vec<String>,
+    /// Files that would be deleted (from S3 for local -> S3, locally for S3 -> local)
+    pub to_delete: Vec<String>,
+    /// Files already in sync
+    pub unchanged: Vec<String>,
 }
 
 impl S3Helper {
@@ -765,6 +1124,10 @@ impl S3Helper {
         db_path: P,
         options: SyncOptions,
     ) -> Result<SyncStats> {
+        if options.dry_run {
+            return self.dry_run_sync(local_folder, s3_prefix, db_path, &options).await;
+        }
+        
         match options.direction {
             SyncDirection::LocalToS3 => {
                 self.sync_local_to_s3(local_folder, s3_prefix, db_path, options).await
@@ -778,6 +1141,157 @@ impl S3Helper {
         }
     }
 
+    /// Work out what `sync_folder` would transfer and delete, without changing anything
+    ///
+    /// Uses the same change detection as a real sync (sync database and S3 metadata).
+    /// For bidirectional syncs, files already planned by the upload phase are not
+    /// listed again by the download phase, and nothing is deleted.
+    ///
+    /// # Arguments
+    /// * `local_folder` - Local folder path to sync
+    /// * `s3_prefix` - S3 prefix (folder path in bucket)
+    /// * `db_path` - Path to sync database
+    /// * `options` - Sync options (including direction)
+    pub async fn plan_sync<P: AsRef<Path>>(
+        &self,
+        local_folder: P,
+        s3_prefix: &str,
+        db_path: P,
+        options: &SyncOptions,
+    ) -> Result<SyncPlan> {
+        let local_folder = local_folder.as_ref();
+        let db = SyncDatabase::open(db_path)?;
+        let mut plan = SyncPlan::default();
+        
+        match options.direction {
+            SyncDirection::LocalToS3 => {
+                self.plan_upload(&db, local_folder, s3_prefix, options, &mut plan)?;
+            }
+            SyncDirection::S3ToLocal => {
+                self.plan_download(&db, local_folder, s3_prefix, options, &mut plan).await?;
+            }
+            SyncDirection::Bidirectional => {
+                let mut upload_options = options.clone();
+                upload_options.delete = false;
+                self.plan_upload(&db, local_folder, s3_prefix, &upload_options, &mut plan)?;
+                
+                let mut download_plan = SyncPlan::default();
+                self.plan_download(&db, local_folder, s3_prefix, &upload_options, &mut download_plan).await?;
+                let planned: std::collections::HashSet<_> =
+                    plan.to_upload.iter().chain(&plan.unchanged).cloned().collect();
+                plan.to_download.extend(download_plan.to_download.into_iter().filter(|p| !planned.contains(p)));
+                plan.unchanged.extend(download_plan.unchanged.into_iter().filter(|p| !planned.contains(p)));
+            }
+        }
+        
+        Ok(plan)
+    }
+    
+    /// Upload half of `plan_sync`
+    fn plan_upload(
+        &self,
+        db: &SyncDatabase,
+        local_folder: &Path,
+        s3_prefix: &str,
+        options: &SyncOptions,
+        plan: &mut SyncPlan,
+    ) -> Result<()> {
+        let local_files = scan_local_files(local_folder, &options.exclude_patterns)?;
+        
+        for (local_path, relative_path) in &local_files {
+            match local_needs_upload(db, local_path, relative_path, options.force) {
+                Ok(true) => plan.to_upload.push(relative_path.clone()),
+                Ok(false) => plan.unchanged.push(relative_path.clone()),
+                Err(e) => warn!("Failed to read file metadata for {:?}: {}", local_path, e),
+            }
+        }
+        
+        if options.delete {
+            let local_paths: std::collections::HashSet<_> =
+                local_files.iter().map(|(_, p)| p.as_str()).collect();
+            for cached in db.list_all()? {
+                if !local_paths.contains(cached.path.as_str()) {
+                    debug!("Would delete {}", s3_key_for(s3_prefix, &cached.path, options.use_compression));
+                    plan.to_delete.push(cached.path);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Download half of `plan_sync`
+    async fn plan_download(
+        &self,
+        db: &SyncDatabase,
+        local_folder: &Path,
+        s3_prefix: &str,
+        options: &SyncOptions,
+        plan: &mut SyncPlan,
+    ) -> Result<()> {
+        let mut remote_paths = std::collections::HashSet::new();
+        
+        for s3_key in self.list_all_objects(s3_prefix).await? {
+            let Some((relative_path, local_path)) = local_target_for_key(&s3_key, s3_prefix, local_folder, options) else {
+                continue;
+            };
+            remote_paths.insert(relative_path.clone());
+            
+            match self.remote_needs_download(db, &s3_key, &local_path, &relative_path, options.force).await {
+                Ok(true) => plan.to_download.push(relative_path),
+                Ok(false) => plan.unchanged.push(relative_path),
+                Err(e) => warn!("Failed to get S3 metadata for {}: {}", s3_key, e),
+            }
+        }
+        
+        if options.delete {
+            for cached in db.list_all()? {
+                if !remote_paths.contains(&cached.path) && local_folder.join(&cached.path).exists() {
+                    plan.to_delete.push(cached.path);
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Log the plan of a dry run and report it as sync statistics
+    async fn dry_run_sync<P: AsRef<Path>>(
+        &self,
+        local_folder: P,
+        s3_prefix: &str,
+        db_path: P,
+        options: &SyncOptions,
+    ) -> Result<SyncStats> {
+        let plan = self.plan_sync(local_folder, s3_prefix, db_path, options).await?;
+        let deletes_remote = options.direction == SyncDirection::LocalToS3;
+        
+        for path in &plan.to_upload {
+            info!("[DRY RUN] Would upload: {}", path);
+        }
+        for path in &plan.to_download {
+            info!("[DRY RUN] Would download: {}", path);
+        }
+        for path in &plan.to_delete {
+            if deletes_remote {
+                info!("[DRY RUN] Would delete from S3: {}", path);
+            } else {
+                info!("[DRY RUN] Would delete local file: {}", path);
+            }
+        }
+        info!("[DRY RUN] upload={}, download={}, delete={}, unchanged={}",
+            plan.to_upload.len(), plan.to_download.len(), plan.to_delete.len(), plan.unchanged.len());
+        
+        Ok(SyncStats {
+            files_scanned: plan.to_upload.len() + plan.to_download.len() + plan.unchanged.len(),
+            files_uploaded: plan.to_upload.len(),
+            files_downloaded: plan.to_download.len(),
+            files_deleted: plan.to_delete.len(),
+            files_skipped: plan.unchanged.len(),
+            ..Default::default()
+        })
+    }
+    
     /// Sync from local folder to S3 (one-way: local -> S3)
     ///
     /// # Arguments
@@ -799,167 +1313,144 @@ impl S3Helper {
         let mut stats = SyncStats::default();
         
         info!("Starting folder sync: {:?} -> s3://{}/{}", local_folder, self.config.bucket, s3_prefix);
-        info!("Sync options: force={}, delete={}, dry_run={}", options.force, options.delete, options.dry_run);
+        info!("Sync options: force={}, delete={}", options.force, options.delete);
         
         // Open sync database
         let db = SyncDatabase::open(db_path)?;
+        let mut checkpoint = Checkpoint::new(options.checkpoint_every);
         
         // Scan local files
-        let mut local_files = Vec::new();
-        for entry in WalkDir::new(local_folder)
-            .follow_links(false)
-            .into_iter()
-            .filter_entry(|e| !should_exclude(e.path(), &options.exclude_patterns))
-        {
-            let entry = entry.context("Failed to read directory entry")?;
-            
-            if entry.file_type().is_file() {
-                let relative_path = entry.path()
-                    .strip_prefix(local_folder)
-                    .context("Failed to get relative path")?
-                    .to_string_lossy()
-                    .replace('\\', "/");
-                
-                local_files.push((entry.path().to_path_buf(), relative_path));
-                stats.files_scanned += 1;
-            }
-        }
+        let local_files = scan_local_files(local_folder, &options.exclude_patterns)?;
+        stats.files_scanned += local_files.len();
         
         info!("Found {} local files to check", local_files.len());
         
         // Process files
         for (local_path, relative_path) in &local_files {
-            // Add .zst extension to S3 key if compression is enabled
-            let s3_key = if options.use_compression {
-                format!("{}/{}.zst", s3_prefix.trim_end_matches('/'), relative_path)
-            } else {
-                format!("{}/{}", s3_prefix.trim_end_matches('/'), relative_path)
-            };
+            let s3_key = s3_key_for(s3_prefix, relative_path, options.use_compression);
             
             // Check if file needs to be uploaded
-            let needs_upload = if options.force {
-                true
-            } else {
-                match db.get_metadata(&relative_path)? {
-                    Some(cached) => {
-                        // Compare with current file state
-                        match FileMetadata::from_file(&local_path, &relative_path) {
-                            Ok(current) => {
-                                current.hash != cached.hash || current.size != cached.size
-                            }
-                            Err(e) => {
-                                warn!("Failed to read file metadata for {:?}: {}", local_path, e);
-                                stats.errors += 1;
-                                continue;
-                            }
-                        }
-                    }
-                    None => true, // Not in cache, need to upload
+            let needs_upload = match local_needs_upload(&db, local_path, relative_path, options.force) {
+                Ok(needs_upload) => needs_upload,
+                Err(e) => {
+                    warn!("Failed to read file metadata for {:?}: {}", local_path, e);
+                    stats.errors += 1;
+                    continue;
                 }
             };
             
             if needs_upload {
-                if options.dry_run {
-                    info!("[DRY RUN] Would upload: {:?} -> {}", local_path, s3_key);
-                    stats.files_uploaded += 1;
-                } else {
-                    // Check if S3 already has the same file (optimization for first sync)
-                    let should_actually_upload = match self.object_exists(&s3_key).await {
-                        Ok(true) => {
-                            // S3 file exists, compare with local file to avoid unnecessary upload
-                            debug!("S3 file exists, checking if content matches: {}", s3_key);
-                            
-                            match self.get_object_metadata(&s3_key).await {
-                                Ok((s3_size, _s3_modified)) => {
-                                    match FileMetadata::from_file(&local_path, &relative_path) {
-                                        Ok(local_meta) => {
-                                            // If compression is enabled, we can't directly compare sizes
-                                            // so we need to download and compare, or just re-upload
-                                            // For now, if sizes match (when not compressed), skip upload
-                                            if !options.use_compression && s3_size as u64 == local_meta.size {
-                                                // Sizes match, assume content is the same
-                                                // Just update database without uploading
-                                                info!("✓ File already in S3 (skipped upload): {}", relative_path);
-                                                db.set_metadata(&local_meta)?;
-                                                stats.files_skipped += 1;
-                                                false
-                                            } else if options.use_compression {
-                                                // With compression, we can't easily compare sizes
-                                                // Could download and compare hashes, but for now just re-upload
-                                                // TODO: Implement hash comparison for compressed files
-                                                true
-                                            } else {
-                                                true // Sizes don't match, need to upload
-                                            }
-                                        }
-                                        Err(e) => {
-                                            warn!("Failed to read local file metadata: {}", e);
-                                            true // Upload to be safe
+                // Check if S3 already has the same file (optimization for first sync)
+                let should_actually_upload = match self.object_exists(&s3_key).await {
+                    Ok(true) => {
+                        // S3 file exists, compare with local file to avoid unnecessary upload
+                        debug!("S3 file exists, checking if content matches: {}", s3_key);
+                        
+                        match self.get_object_metadata(&s3_key).await {
+                            Ok((s3_size, _s3_modified)) => {
+                                match FileMetadata::from_file(local_path, relative_path) {
+                                    Ok(local_meta) => {
+                                        // If compression is enabled, we can't directly compare sizes
+                                        // so we need to download and compare, or just re-upload
+                                        // For now, if sizes match (when not compressed), skip upload
+                                        if !options.use_compression && s3_size as u64 == local_meta.size {
+                                            // Sizes match, assume content is the same
+                                            // Just update database without uploading
+                                            info!("✓ File already in S3 (skipped upload): {}", relative_path);
+                                            db.set_metadata(&local_meta)?;
+                                            checkpoint.record(&db)?;
+                                            stats.files_skipped += 1;
+                                            false
+                                        } else if options.use_compression {
+                                            // With compression, we can't easily compare sizes
+                                            // Could download and compare hashes, but for now just re-upload
+                                            // TODO: Implement hash comparison for compressed files
+                                            true
+                                        } else {
+                                            true // Sizes don't match, need to upload
                                         }
                                     }
-                                }
-                                Err(e) => {
-                                    warn!("Failed to get S3 metadata for {}: {}", s3_key, e);
-                                    true // Upload to be safe
+                                    Err(e) => {
+                                        warn!("Failed to read local file metadata: {}", e);
+                                        true // Upload to be safe
+                                    }
                                 }
                             }
+                            Err(e) => {
+                                warn!("Failed to get S3 metadata for {}: {}", s3_key, e);
+                                true // Upload to be safe
+                            }
                         }
-                        Ok(false) => true, // S3 file doesn't exist, need to upload
-                        Err(e) => {
-                            warn!("Failed to check if S3 object exists {}: {}", s3_key, e);
-                            true // Upload to be safe
-                        }
-                    };
+                    }
+                    Ok(false) => true, // S3 file doesn't exist, need to upload
+                    Err(e) => {
+                        warn!("Failed to check if S3 object exists {}: {}", s3_key, e);
+                        true // Upload to be safe
+                    }
+                };
+                
+                if should_actually_upload {
+                    debug!("Uploading: {:?} -> {}", local_path, s3_key);
+                    let transfer_started = Instant::now();
                     
-                    if should_actually_upload {
-                        debug!("Uploading: {:?} -> {}", local_path, s3_key);
-                        
-                        // Compress file if compression is enabled
-                        let (upload_path, temp_file) = if options.use_compression {
-                            let temp_dir = std::env::temp_dir();
-                            let temp_file_name = format!("s3sync_{}_{}.zst", 
-                                std::process::id(),
-                                local_path.file_name().unwrap_or_default().to_string_lossy()
-                            );
-                            let temp_path = temp_dir.join(temp_file_name);
-                            
-                            match compress_file(&local_path, &temp_path) {
-                                Ok(_) => (temp_path.clone(), Some(temp_path)),
-                                Err(e) => {
-                                    error!("Failed to compress {:?}: {}", local_path, e);
-                                    stats.errors += 1;
-                                    continue;
-                                }
-                            }
-                        } else {
-                            (local_path.clone(), None)
-                        };
+                    // Compress file if compression is enabled
+                    let (upload_path, temp_file) = if options.use_compression {
+                        let temp_dir = std::env::temp_dir();
+                        let temp_file_name = format!("s3sync_{}_{}.zst", 
+                            std::process::id(),
+                            local_path.file_name().unwrap_or_default().to_string_lossy()
+                        );
+                        let temp_path = temp_dir.join(temp_file_name);
                         
-                        match self.upload_file(&upload_path, &s3_key).await {
-                            Ok(etag) => {
-                                // Clean up temp file if it exists
-                                if let Some(temp_path) = temp_file {
-                                    let _ = fs::remove_file(&temp_path);
-                                }
-                                
-                                // Update database (store original file metadata)
-                                let mut metadata = FileMetadata::from_file(&local_path, &relative_path)?;
-                                metadata.etag = Some(etag);
-                                db.set_metadata(&metadata)?;
-                                
-                                stats.files_uploaded += 1;
-                                stats.bytes_uploaded += metadata.size;
-                                
-                                info!("✓ Uploaded{}: {}", 
-                                    if options.use_compression { " (compressed)" } else { "" },
-                                    relative_path
-                                );
-                            }
+                        match compress_file(local_path, &temp_path) {
+                            Ok(_) => (temp_path.clone(), Some(temp_path)),
                             Err(e) => {
-                                error!("Failed to upload {:?}: {}", local_path, e);
+                                error!("Failed to compress {:?}: {}", local_path, e);
                                 stats.errors += 1;
+                                continue;
                             }
                         }
+                    } else {
+                        (local_path.clone(), None)
+                    };
+                    
+                    // Store original file metadata, and its hash on the object for integrity checks
+                    let mut metadata = FileMetadata::from_file(local_path, relative_path)?;
+                    let mut user_metadata = options.user_metadata.clone();
+                    user_metadata.insert("sha256".to_string(), metadata.hash.clone());
+                    
+                    match self.upload_file_with_attributes(&upload_path, &s3_key, &options.object_tags, &user_metadata).await {
+                        Ok(etag) => {
+                            // Clean up temp file if it exists
+                            if let Some(temp_path) = temp_file {
+                                let _ = fs::remove_file(&temp_path);
+                            }
+                            
+                            // Update database
+                            metadata.etag = Some(etag);
+                            db.set_metadata(&metadata)?;
+                            checkpoint.record(&db)?;
+                            
+                            stats.files_uploaded += 1;
+                            stats.bytes_uploaded += metadata.size;
+                            if options.collect_timings {
+                                stats.timings.push(TransferTiming {
+                                    path: relative_path.clone(),
+                                    bytes: metadata.size,
+                                    duration: transfer_started.elapsed(),
+                                    direction: SyncDirection::LocalToS3,
+                                });
+                            }
+                            
+                            info!("✓ Uploaded{}: {}", 
+                                if options.use_compression { " (compressed)" } else { "" },
+                                relative_path
+                            );
+                        }
+                        Err(e) => {
+                            error!("Failed to upload {:?}: {}", local_path, e);
+                            stats.errors += 1;
+                        }
                     }
                 }
             } else {
@@ -976,29 +1467,19 @@ impl S3Helper {
             
             for cached in tracked_files {
                 if !local_paths.contains(cached.path.as_str()) {
-                    // Add .zst extension to S3 key if compression is enabled
-                    let s3_key = if options.use_compression {
-                        format!("{}/{}.zst", s3_prefix.trim_end_matches('/'), cached.path)
-                    } else {
-                        format!("{}/{}", s3_prefix.trim_end_matches('/'), cached.path)
-                    };
+                    let s3_key = s3_key_for(s3_prefix, &cached.path, options.use_compression);
                     
-                    if options.dry_run {
-                        info!("[DRY RUN] Would delete from S3: {}", s3_key);
-                        stats.files_deleted += 1;
-                    } else {
-                        debug!("Deleting from S3: {}", s3_key);
-                        
-                        match self.delete_object(&s3_key).await {
-                            Ok(_) => {
-                                db.remove_metadata(&cached.path)?;
-                                stats.files_deleted += 1;
-                                info!("✗ Deleted from S3: {}", cached.path);
-                            }
-                            Err(e) => {
-                                error!("Failed to delete {}: {}", s3_key, e);
-                                stats.errors += 1;
-                            }
+                    debug!("Deleting from S3: {}", s3_key);
+                    
+                    match self.delete_object(&s3_key).await {
+                        Ok(_) => {
+                            db.remove_metadata(&cached.path)?;
+                            stats.files_deleted += 1;
+                            info!("✗ Deleted from S3: {}", cached.path);
+                        }
+                        Err(e) => {
+                            error!("Failed to delete {}: {}", s3_key, e);
+                            stats.errors += 1;
                         }
                     }
                 }
@@ -1013,6 +1494,119 @@ impl S3Helper {
         Ok(stats)
     }
     
+    /// Check if an S3 object differs from its local copy (always true when forced or missing locally)
+    async fn remote_needs_download(
+        &self,
+        db: &SyncDatabase,
+        s3_key: &str,
+        local_path: &Path,
+        relative_path: &str,
+        force: bool,
+    ) -> Result<bool> {
+        if force || !local_path.exists() {
+            return Ok(true);
+        }
+        
+        // Get S3 metadata and compare with local file
+        let head = self.object_head(s3_key).await?;
+        
+        // Objects uploaded by a sync carry the original file's hash, which also works for
+        // compressed objects and for files restored before the last checkpoint
+        if let Some(sha256) = &head.sha256 {
+            return match FileMetadata::from_file(local_path, relative_path) {
+                Ok(current) => Ok(current.hash != *sha256),
+                Err(e) => {
+                    warn!("Failed to read local file metadata for {:?}: {}", local_path, e);
+                    Ok(true) // Download to be safe
+                }
+            };
+        }
+        
+        let (s3_size, s3_modified) = (head.size, head.last_modified);
+        match db.get_metadata(relative_path)? {
+            Some(cached) => {
+                // Compare with current local file
+                match FileMetadata::from_file(local_path, relative_path) {
+                    Ok(current) => {
+                        // Check if S3 file is different
+                        Ok(s3_size as u64 != current.size || s3_modified > cached.last_sync.to_string())
+                    }
+                    Err(e) => {
+                        warn!("Failed to read local file metadata for {:?}: {}", local_path, e);
+                        Ok(true) // Download to be safe
+                    }
+                }
+            }
+            None => {
+                // Not in cache, compare with local file directly
+                match fs::metadata(local_path) {
+                    Ok(local_meta) => Ok(s3_size as u64 != local_meta.len()),
+                    Err(_) => Ok(true),
+                }
+            }
+        }
+    }
+    
+    /// Download one object to `local_path`, verifying it against the object's `sha256` metadata
+    ///
+    /// The file is written to a temporary sibling and only moved into place once it
+    /// verifies, so a corrupt or interrupted download never replaces the local copy.
+    /// A hash mismatch is retried once before it is reported as an error; objects
+    /// without `sha256` metadata are restored unverified.
+    async fn restore_object(
+        &self,
+        s3_key: &str,
+        local_path: &Path,
+        relative_path: &str,
+        use_compression: bool,
+    ) -> Result<FileMetadata> {
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent)
+                .context(format!("Failed to create directory: {:?}", parent))?;
+        }
+        let partial_path = temp_sibling(local_path, ".s3sync.tmp");
+        
+        let mut attempt = 1;
+        loop {
+            let (data, expected_hash) = self.get_object_with_sha256(s3_key).await?;
+            if use_compression {
+                let compressed_path = temp_sibling(local_path, ".s3sync.zst.tmp");
+                fs::write(&compressed_path, &data)
+                    .context(format!("Failed to write file: {:?}", compressed_path))?;
+                let decompressed = decompress_file(&compressed_path, &partial_path);
+                let _ = fs::remove_file(&compressed_path);
+                if let Err(e) = decompressed {
+                    let _ = fs::remove_file(&partial_path);
+                    return Err(e);
+                }
+            } else {
+                fs::write(&partial_path, &data)
+                    .context(format!("Failed to write file: {:?}", partial_path))?;
+            }
+            
+            let metadata = FileMetadata::from_file(&partial_path, relative_path)?;
+            match expected_hash {
+                Some(expected) if expected != metadata.hash => {
+                    let _ = fs::remove_file(&partial_path);
+                    if attempt >= RESTORE_ATTEMPTS {
+                        bail!("SHA256 mismatch for {} after {} attempts: expected {}, got {}",
+                            s3_key, attempt, expected, metadata.hash);
+                    }
+                    warn!("SHA256 mismatch for {} (expected {}, got {}), retrying", s3_key, expected, metadata.hash);
+                    attempt += 1;
+                }
+                expected => {
+                    if expected.is_none() {
+                        debug!("No sha256 metadata on {}, restored without verification", s3_key);
+                    }
+                    fs::rename(&partial_path, local_path)
+                        .context(format!("Failed to move {:?} to {:?}", partial_path, local_path))?;
+                    return Ok(metadata);
+                }
+            }
+        }
+    }
+    
     /// Sync from S3 to local folder (one-way: S3 -> local)
     ///
     /// # Arguments
@@ -1034,10 +1628,11 @@ impl S3Helper {
         let mut stats = SyncStats::default();
         
         info!("Starting folder sync: s3://{}/{} -> {:?}", self.config.bucket, s3_prefix, local_folder);
-        info!("Sync options: force={}, delete={}, dry_run={}", options.force, options.delete, options.dry_run);
+        info!("Sync options: force={}, delete={}", options.force, options.delete);
         
         // Open sync database
         let db = SyncDatabase::open(db_path)?;
+        let mut checkpoint = Checkpoint::new(options.checkpoint_every);
         
         // List all S3 objects with the prefix
         let s3_objects = self.list_all_objects(s3_prefix).await?;
@@ -1045,201 +1640,71 @@ impl S3Helper {
         
         // Track downloaded files for deletion check
         let mut downloaded_paths = std::collections::HashSet::new();
+        // (s3 key, relative path, local path) of the files to download
+        let mut pending = Vec::new();
         
         for s3_key in s3_objects {
             stats.files_scanned += 1;
             
-            // Skip if doesn't match our prefix
-            if !s3_key.starts_with(s3_prefix) {
+            let Some((relative_path, local_path)) = local_target_for_key(&s3_key, s3_prefix, local_folder, &options) else {
                 continue;
-            }
-            
-            // Get relative path (remove .zst extension if compression is enabled)
-            let mut relative_path = s3_key.strip_prefix(s3_prefix)
-                .unwrap_or(&s3_key)
-                .trim_start_matches('/')
-                .to_string();
-            
-            if relative_path.is_empty() {
-                continue;
-            }
-            
-            // Remove .zst extension if compression is enabled
-            if options.use_compression && relative_path.ends_with(".zst") {
-                relative_path = relative_path.trim_end_matches(".zst").to_string();
-            } else if options.use_compression {
-                // If compression is enabled but file doesn't have .zst extension, skip it
-                debug!("Skipping non-compressed file in compression mode: {}", s3_key);
-                continue;
-            }
-            
-            // Check if should exclude
-            let local_path = local_folder.join(&relative_path);
-            if should_exclude(&local_path, &options.exclude_patterns) {
-                debug!("Excluding: {}", relative_path);
-                continue;
-            }
+            };
             
             downloaded_paths.insert(relative_path.clone());
             
             // Check if file needs to be downloaded
-            let needs_download = if options.force {
-                true
-            } else if !local_path.exists() {
-                true
-            } else {
-                // Get S3 metadata and compare with local file
-                match self.get_object_metadata(&s3_key).await {
-                    Ok((s3_size, s3_modified)) => {
-                        match db.get_metadata(&relative_path)? {
-                            Some(cached) => {
-                                // Compare with current local file
-                                match FileMetadata::from_file(&local_path, &relative_path) {
-                                    Ok(current) => {
-                                        // Check if S3 file is different
-                                        s3_size as u64 != current.size || 
-                                        s3_modified > cached.last_sync.to_string()
-                                    }
-                                    Err(e) => {
-                                        warn!("Failed to read local file metadata for {:?}: {}", local_path, e);
-                                        true // Download to be safe
-                                    }
-                                }
-                            }
-                            None => {
-                                // Not in cache, compare with local file directly
-                                match fs::metadata(&local_path) {
-                                    Ok(local_meta) => s3_size as u64 != local_meta.len(),
-                                    Err(_) => true,
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Failed to get S3 metadata for {}: {}", s3_key, e);
-                        stats.errors += 1;
-                        continue;
-                    }
+            let needs_download = match self.remote_needs_download(&db, &s3_key, &local_path, &relative_path, options.force).await {
+                Ok(needs_download) => needs_download,
+                Err(e) => {
+                    warn!("Failed to get S3 metadata for {}: {}", s3_key, e);
+                    stats.errors += 1;
+                    continue;
                 }
             };
             
             if needs_download {
-                if options.dry_run {
-                    info!("[DRY RUN] Would download: {} -> {:?}", s3_key, local_path);
-                    stats.files_downloaded += 1;
-                } else {
-                    // Check if local file already exists and matches S3 content
-                    let should_actually_download = if local_path.exists() {
-                        debug!("Local file exists, checking if content matches: {:?}", local_path);
-                        
-                        match self.get_object_metadata(&s3_key).await {
-                            Ok((s3_size, _s3_modified)) => {
-                                match FileMetadata::from_file(&local_path, &relative_path) {
-                                    Ok(local_meta) => {
-                                        // If not using compression, compare sizes directly
-                                        if !options.use_compression && s3_size as u64 == local_meta.size {
-                                            // Sizes match, assume content is the same
-                                            // Just update database without downloading
-                                            info!("✓ File already exists locally (skipped download): {}", relative_path);
-                                            db.set_metadata(&local_meta)?;
-                                            stats.files_skipped += 1;
-                                            false
-                                        } else if options.use_compression {
-                                            // With compression, S3 size is compressed size
-                                            // We could download and compare, but for now just re-download
-                                            // TODO: Implement hash comparison for compressed files
-                                            true
-                                        } else {
-                                            true // Sizes don't match, need to download
-                                        }
-                                    }
-                                    Err(e) => {
-                                        warn!("Failed to read local file metadata for {:?}: {}", local_path, e);
-                                        true // Download to be safe
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                warn!("Failed to get S3 metadata for {}: {}", s3_key, e);
-                                true // Download to be safe
-                            }
-                        }
-                    } else {
-                        true // Local file doesn't exist, need to download
-                    };
+                // Check if local file already exists and matches S3 content
+                let should_actually_download = if local_path.exists() {
+                    debug!("Local file exists, checking if content matches: {:?}", local_path);
                     
-                    if should_actually_download {
-                        debug!("Downloading: {} -> {:?}", s3_key, local_path);
-                        
-                        // Download and decompress if compression is enabled
-                        if options.use_compression {
-                        // Download to temporary compressed file
-                        let temp_dir = std::env::temp_dir();
-                        let temp_file_name = format!("s3sync_{}_{}.zst", 
-                            std::process::id(),
-                            local_path.file_name().unwrap_or_default().to_string_lossy()
-                        );
-                        let temp_path = temp_dir.join(temp_file_name);
-                        
-                        match self.download_file(&s3_key, &temp_path).await {
-                            Ok(_) => {
-                                // Decompress the file
-                                match decompress_file(&temp_path, &local_path) {
-                                    Ok(_) => {
-                                        // Clean up temp file
-                                        let _ = fs::remove_file(&temp_path);
-                                        
-                                        // Update database
-                                        match FileMetadata::from_file(&local_path, &relative_path) {
-                                            Ok(metadata) => {
-                                                db.set_metadata(&metadata)?;
-                                                stats.files_downloaded += 1;
-                                                stats.bytes_downloaded += metadata.size;
-                                                info!("⬇ Downloaded (decompressed): {}", relative_path);
-                                            }
-                                            Err(e) => {
-                                                error!("Failed to update metadata for {:?}: {}", local_path, e);
-                                                stats.errors += 1;
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to decompress {:?}: {}", temp_path, e);
-                                        let _ = fs::remove_file(&temp_path);
-                                        stats.errors += 1;
+                    match self.get_object_metadata(&s3_key).await {
+                        Ok((s3_size, _s3_modified)) => {
+                            match FileMetadata::from_file(&local_path, &relative_path) {
+                                Ok(local_meta) => {
+                                    // If not using compression, compare sizes directly
+                                    if !options.use_compression && s3_size as u64 == local_meta.size {
+                                        // Sizes match, assume content is the same
+                                        // Just update database without downloading
+                                        info!("✓ File already exists locally (skipped download): {}", relative_path);
+                                        db.set_metadata(&local_meta)?;
+                                        checkpoint.record(&db)?;
+                                        stats.files_skipped += 1;
+                                        false
+                                    } else if options.use_compression {
+                                        // With compression, S3 size is compressed size; objects with
+                                        // `sha256` metadata were already compared by content
+                                        true
+                                    } else {
+                                        true // Sizes don't match, need to download
                                     }
                                 }
-                            }
-                            Err(e) => {
-                                error!("Failed to download {}: {}", s3_key, e);
-                                stats.errors += 1;
-                            }
-                        }
-                    } else {
-                        // Direct download without compression
-                        match self.download_file(&s3_key, &local_path).await {
-                            Ok(_) => {
-                                // Update database
-                                match FileMetadata::from_file(&local_path, &relative_path) {
-                                    Ok(metadata) => {
-                                        db.set_metadata(&metadata)?;
-                                        stats.files_downloaded += 1;
-                                        stats.bytes_downloaded += metadata.size;
-                                        info!("⬇ Downloaded: {}", relative_path);
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to update metadata for {:?}: {}", local_path, e);
-                                        stats.errors += 1;
-                                    }
+                                Err(e) => {
+                                    warn!("Failed to read local file metadata for {:?}: {}", local_path, e);
+                                    true // Download to be safe
                                 }
                             }
-                            Err(e) => {
-                                error!("Failed to download {}: {}", s3_key, e);
-                                stats.errors += 1;
-                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to get S3 metadata for {}: {}", s3_key, e);
+                            true // Download to be safe
                         }
                     }
-                    }
+                } else {
+                    true // Local file doesn't exist, need to download
+                };
+                
+                if should_actually_download {
+                    pending.push((s3_key, relative_path, local_path));
                 }
             } else {
                 debug!("Skipping (unchanged): {}", relative_path);
@@ -1247,6 +1712,63 @@ impl S3Helper {
             }
         }
         
+        // Download in parallel, recording each file as it completes so an interrupted
+        // restore resumes from the last checkpoint
+        let max_parallel = options.max_parallel.max(1);
+        if !pending.is_empty() {
+            info!("Downloading {} files, up to {} in parallel", pending.len(), max_parallel);
+        }
+        let semaphore = Arc::new(tokio::sync::Semaphore::new(max_parallel));
+        let mut downloads = tokio::task::JoinSet::new();
+        for (s3_key, relative_path, local_path) in pending {
+            let helper = self.clone();
+            let semaphore = semaphore.clone();
+            let use_compression = options.use_compression;
+            downloads.spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("download semaphore closed");
+                debug!("Downloading: {} -> {:?}", s3_key, local_path);
+                let transfer_started = Instant::now();
+                let result = helper.restore_object(&s3_key, &local_path, &relative_path, use_compression).await;
+                (s3_key, relative_path, result, transfer_started.elapsed())
+            });
+        }
+        
+        while let Some(joined) = downloads.join_next().await {
+            let (s3_key, relative_path, result, duration) = match joined {
+                Ok(done) => done,
+                Err(e) => {
+                    error!("Download task panicked: {}", e);
+                    stats.errors += 1;
+                    continue;
+                }
+            };
+            
+            match result {
+                Ok(metadata) => {
+                    db.set_metadata(&metadata)?;
+                    checkpoint.record(&db)?;
+                    stats.files_downloaded += 1;
+                    stats.bytes_downloaded += metadata.size;
+                    if options.collect_timings {
+                        stats.timings.push(TransferTiming {
+                            path: relative_path.clone(),
+                            bytes: metadata.size,
+                            duration,
+                            direction: SyncDirection::S3ToLocal,
+                        });
+                    }
+                    info!("⬇ Downloaded{}: {}",
+                        if options.use_compression { " (decompressed)" } else { "" },
+                        relative_path
+                    );
+                }
+                Err(e) => {
+                    error!("Failed to download {}: {:#}", s3_key, e);
+                    stats.errors += 1;
+                }
+            }
+        }
+        
         // Handle local file deletions if requested
         if options.delete {
             let tracked_files = db.list_all()?;
@@ -1256,22 +1778,17 @@ impl S3Helper {
                     let local_path = local_folder.join(&cached.path);
                     
                     if local_path.exists() {
-                        if options.dry_run {
-                            info!("[DRY RUN] Would delete local file: {:?}", local_path);
-                            stats.files_deleted += 1;
-                        } else {
-                            debug!("Deleting local file: {:?}", local_path);
-                            
-                            match fs::remove_file(&local_path) {
-                                Ok(_) => {
-                                    db.remove_metadata(&cached.path)?;
-                                    stats.files_deleted += 1;
-                                    info!("✗ Deleted local file: {}", cached.path);
-                                }
-                                Err(e) => {
-                                    error!("Failed to delete {:?}: {}", local_path, e);
-                                    stats.errors += 1;
-                                }
+                        debug!("Deleting local file: {:?}", local_path);
+                        
+                        match fs::remove_file(&local_path) {
+                            Ok(_) => {
+                                db.remove_metadata(&cached.path)?;
+                                stats.files_deleted += 1;
+                                info!("✗ Deleted local file: {}", cached.path);
+                            }
+                            Err(e) => {
+                                error!("Failed to delete {:?}: {}", local_path, e);
+                                stats.errors += 1;
                             }
                         }
                     } else {
@@ -1321,6 +1838,7 @@ impl S3Helper {
         stats.files_skipped = upload_stats.files_skipped;
         stats.bytes_uploaded = upload_stats.bytes_uploaded;
         stats.errors = upload_stats.errors;
+        stats.timings = upload_stats.timings;
         
         // Then, sync S3 -> local (but without deletion to avoid conflicts)
         let mut download_options = options.clone();
@@ -1331,6 +1849,7 @@ impl S3Helper {
         stats.bytes_downloaded = download_stats.bytes_downloaded;
         stats.files_skipped += download_stats.files_skipped;
         stats.errors += download_stats.errors;
+        stats.timings.extend(download_stats.timings);
         
         info!("Bidirectional sync completed: uploaded={}, downloaded={}, skipped={}, errors={}", 
             stats.files_uploaded, stats.files_downloaded, stats.files_skipped, stats.errors);
@@ -1339,6 +1858,161 @@ impl S3Helper {
     }
 }
 
+/// Encode object tags as an `x-amz-tagging` value ("k1=v1&k2=v2", sorted by key)
+/// Returns `None` when there are no tags
+fn encode_tagging(tags: &HashMap<String, String>) -> Option<String> {
+    if tags.is_empty() {
+        return None;
+    }
+    let mut pairs: Vec<_> = tags.iter().collect();
+    pairs.sort();
+    Some(
+        pairs
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", urlencoding::encode(k), urlencoding::encode(v)))
+            .collect::<Vec<_>>()
+            .join("&"),
+    )
+}
+
+/// Files to upload as (path, key), and the skipped duplicates as (key, path)
+type DedupedBatch = (Vec<(PathBuf, String)>, Vec<(String, PathBuf)>);
+
+/// Split a batch into the first file for each key and the later files sharing a key
+/// With `DuplicateKeyPolicy::Error`, any duplicate fails the whole batch instead
+fn dedupe_batch_keys<P: AsRef<Path>>(
+    files: Vec<(P, String)>,
+    policy: DuplicateKeyPolicy,
+) -> Result<DedupedBatch> {
+    let mut first_paths: HashMap<String, PathBuf> = HashMap::new();
+    let mut unique = Vec::with_capacity(files.len());
+    let mut duplicates: Vec<(String, PathBuf, PathBuf)> = Vec::new();
+    for (local_path, key) in files {
+        let local_path = local_path.as_ref().to_path_buf();
+        match first_paths.get(&key) {
+            Some(first) => duplicates.push((key, first.clone(), local_path)),
+            None => {
+                first_paths.insert(key.clone(), local_path.clone());
+                unique.push((local_path, key));
+            }
+        }
+    }
+
+    if duplicates.is_empty() {
+        return Ok((unique, Vec::new()));
+    }
+    match policy {
+        DuplicateKeyPolicy::Error => {
+            let listed: Vec<String> = duplicates
+                .iter()
+                .map(|(key, first, duplicate)| format!("{} ({:?} and {:?})", key, first, duplicate))
+                .collect();
+            bail!(
+                "{} file(s) in the batch share a key with an earlier file, nothing was uploaded: {}",
+                duplicates.len(),
+                listed.join(", ")
+            )
+        }
+        DuplicateKeyPolicy::KeepFirst => {
+            for (key, first, duplicate) in &duplicates {
+                warn!("Skipping {:?}: key {} is already uploaded from {:?} in this batch", duplicate, key, first);
+            }
+            Ok((unique, duplicates.into_iter().map(|(key, _, duplicate)| (key, duplicate)).collect()))
+        }
+    }
+}
+
+/// Scan a local folder for files to sync, returning (absolute path, relative path) pairs
+/// Relative paths always use `/` as separator
+fn scan_local_files(local_folder: &Path, exclude_patterns: &[String]) -> Result<Vec<(PathBuf, String)>> {
+    let mut local_files = Vec::new();
+    for entry in WalkDir::new(local_folder)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| !should_exclude(e.path(), exclude_patterns))
+    {
+        let entry = entry.context("Failed to read directory entry")?;
+        
+        if entry.file_type().is_file() {
+            let relative_path = entry.path()
+                .strip_prefix(local_folder)
+                .context("Failed to get relative path")?
+                .to_string_lossy()
+                .replace('\\', "/");
+            
+            local_files.push((entry.path().to_path_buf(), relative_path));
+        }
+    }
+    Ok(local_files)
+}
+
+/// S3 key for a relative path (with `.zst` extension if compression is enabled)
+fn s3_key_for(s3_prefix: &str, relative_path: &str, use_compression: bool) -> String {
+    if use_compression {
+        format!("{}/{}.zst", s3_prefix.trim_end_matches('/'), relative_path)
+    } else {
+        format!("{}/{}", s3_prefix.trim_end_matches('/'), relative_path)
+    }
+}
+
+/// Check if a local file changed since it was last synced (always true when forced or untracked)
+fn local_needs_upload(db: &SyncDatabase, local_path: &Path, relative_path: &str, force: bool) -> Result<bool> {
+    if force {
+        return Ok(true);
+    }
+    match db.get_metadata(relative_path)? {
+        Some(cached) => {
+            // Compare with current file state
+            let current = FileMetadata::from_file(local_path, relative_path)?;
+            Ok(current.hash != cached.hash || current.size != cached.size)
+        }
+        None => Ok(true), // Not in cache, need to upload
+    }
+}
+
+/// Temporary file next to `path`, named with `suffix` appended
+/// The default `*.tmp` exclude keeps leftovers of an interrupted restore out of uploads
+fn temp_sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Map an S3 key to its (relative path, local path), or `None` if the key should not be synced
+fn local_target_for_key(s3_key: &str, s3_prefix: &str, local_folder: &Path, options: &SyncOptions) -> Option<(String, PathBuf)> {
+    // Skip if doesn't match our prefix
+    if !s3_key.starts_with(s3_prefix) {
+        return None;
+    }
+    
+    let mut relative_path = s3_key.strip_prefix(s3_prefix)
+        .unwrap_or(s3_key)
+        .trim_start_matches('/')
+        .to_string();
+    
+    if relative_path.is_empty() {
+        return None;
+    }
+    
+    // Remove .zst extension if compression is enabled
+    if options.use_compression && relative_path.ends_with(".zst") {
+        relative_path = relative_path.trim_end_matches(".zst").to_string();
+    } else if options.use_compression {
+        // If compression is enabled but file doesn't have .zst extension, skip it
+        debug!("Skipping non-compressed file in compression mode: {}", s3_key);
+        return None;
+    }
+    
+    // Check if should exclude
+    let local_path = local_folder.join(&relative_path);
+    if should_exclude(&local_path, &options.exclude_patterns) {
+        debug!("Excluding: {}", relative_path);
+        return None;
+    }
+    
+    Some((relative_path, local_path))
+}
+
 /// Check if path should be excluded based on patterns
 fn should_exclude(path: &Path, patterns: &[String]) -> bool {
     let path_str = path.to_string_lossy();
@@ -1350,13 +2024,11 @@ fn should_exclude(path: &Path, patterns: &[String]) -> bool {
             if path_str.contains(middle) {
                 return true;
             }
-        } else if pattern.starts_with('*') {
-            let suffix = &pattern[1..];
+        } else if let Some(suffix) = pattern.strip_prefix('*') {
             if path_str.ends_with(suffix) {
                 return true;
             }
-        } else if pattern.ends_with('*') {
-            let prefix = &pattern[..pattern.len()-1];
+        } else if let Some(prefix) = pattern.strip_suffix('*') {
             if path_str.starts_with(prefix) {
                 return true;
             }
@@ -1461,6 +2133,7 @@ fn decompress_file(input_path: &Path, output_path: &Path) -> Result<u64> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Mutex;
 
     #[test]
     fn test_provider_from_str() {
@@ -1479,5 +2152,580 @@ mod tests {
         assert_eq!(S3Provider::from_str("minio"), S3Provider::Generic);
         assert_eq!(S3Provider::from_str("other"), S3Provider::Generic);
     }
-}
 
+    /// Empty ListMultipartUploads result
+    const LIST_MULTIPART_UPLOADS_RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Type: application/xml\r\nContent-Length: 128\r\nConnection: close\r\n\r\n<ListMultipartUploadsResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Bucket>bucket</Bucket></ListMultipartUploadsResult>";
+
+    /// Mock S3 endpoint recording request headers
+    /// HEAD requests get 404, multipart listings are empty, everything else 200;
+    /// PUTs after `hang_after_puts` never get a response
+    async fn mock_s3_endpoint(hang_after_puts: Option<usize>) -> (String, Arc<Mutex<Vec<String>>>) {
+        mock_s3_endpoint_with_existing(hang_after_puts, &[]).await
+    }
+
+    /// `mock_s3_endpoint` where HEAD requests for the `existing` keys get 200
+    async fn mock_s3_endpoint_with_existing(
+        hang_after_puts: Option<usize>,
+        existing: &[&str],
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let existing: Arc<Vec<String>> = Arc::new(existing.iter().map(|key| format!("/bucket/{}", key)).collect());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let requests = recorded.clone();
+                let existing = existing.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+
+                    // Read the headers, then the body announced by Content-Length
+                    let header_end = loop {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..n]);
+                        if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                            break pos + 4;
+                        }
+                    };
+                    let headers = String::from_utf8_lossy(&request[..header_end]).to_string();
+                    let content_length = headers
+                        .lines()
+                        .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    while request.len() < header_end + content_length {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                    }
+
+                    let puts = {
+                        let mut requests = requests.lock().unwrap();
+                        requests.push(headers.clone());
+                        requests.iter().filter(|r| r.starts_with("PUT")).count()
+                    };
+                    let path = headers.split_whitespace().nth(1).unwrap_or_default().split('?').next().unwrap_or_default();
+                    let response: &[u8] = if headers.starts_with("HEAD") && existing.iter().any(|key| key == path) {
+                        b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    } else if headers.starts_with("HEAD") {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    } else if headers.starts_with("GET") && headers.contains("?uploads") {
+                        LIST_MULTIPART_UPLOADS_RESPONSE
+                    } else if hang_after_puts.is_some_and(|limit| puts > limit) {
+                        std::future::pending::<()>().await;
+                        unreachable!()
+                    } else {
+                        b"HTTP/1.1 200 OK\r\nETag: \"mock-etag\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    };
+                    stream.write_all(response).await.unwrap();
+                });
+            }
+        });
+        (endpoint, requests)
+    }
+
+    /// Mock S3 bucket serving `objects` as (key, body, sha256 metadata) to listings, HEAD and GET
+    /// The first `corrupt_gets[key]` GETs of a key return `corrupt_body` instead;
+    /// the returned counter holds the peak number of GETs in flight
+    async fn mock_s3_objects(
+        objects: Vec<(String, Vec<u8>, String)>,
+        corrupt_gets: HashMap<String, usize>,
+        corrupt_body: Vec<u8>,
+    ) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let objects = Arc::new(objects);
+        let corrupt_gets = Arc::new(Mutex::new(corrupt_gets));
+        let corrupt_body = Arc::new(corrupt_body);
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let peak_gets = peak.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let (objects, corrupt_gets, corrupt_body) = (objects.clone(), corrupt_gets.clone(), corrupt_body.clone());
+                let (in_flight, peak) = (in_flight.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..n]);
+                    }
+                    let request = String::from_utf8_lossy(&request).to_string();
+                    let mut request_line = request.split_whitespace();
+                    let method = request_line.next().unwrap_or_default().to_string();
+                    let target = request_line.next().unwrap_or_default().to_string();
+                    let path = target.split('?').next().unwrap_or_default();
+
+                    let mut response = Vec::new();
+                    if path.trim_end_matches('/') == "/bucket" {
+                        let contents: String = objects
+                            .iter()
+                            .map(|(key, body, _)| format!("<Contents><Key>{}</Key><Size>{}</Size></Contents>", key, body.len()))
+                            .collect();
+                        let body = format!(
+                            "<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\"><Name>bucket</Name><KeyCount>{}</KeyCount><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                            objects.len(),
+                            contents
+                        );
+                        response.extend_from_slice(
+                            format!("HTTP/1.1 200 OK\r\nContent-Type: application/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).as_bytes(),
+                        );
+                        response.extend_from_slice(body.as_bytes());
+                    } else if let Some((key, body, sha256)) = objects.iter().find(|(key, _, _)| path == format!("/bucket/{}", key)) {
+                        let mut body = body;
+                        if method == "GET" {
+                            let count = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(count, Ordering::SeqCst);
+                            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                            if let Some(remaining) = corrupt_gets.lock().unwrap().get_mut(key).filter(|n| **n > 0) {
+                                *remaining -= 1;
+                                body = &*corrupt_body;
+                            }
+                        }
+                        response.extend_from_slice(
+                            format!(
+                                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nLast-Modified: Wed, 01 Jan 2025 00:00:00 GMT\r\nx-amz-meta-sha256: {}\r\nConnection: close\r\n\r\n",
+                                body.len(),
+                                sha256
+                            )
+                            .as_bytes(),
+                        );
+                        if method == "GET" {
+                            response.extend_from_slice(body);
+                        }
+                    } else {
+                        response.extend_from_slice(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                    }
+                    stream.write_all(&response).await.unwrap();
+                });
+            }
+        });
+        (endpoint, peak_gets)
+    }
+
+    /// Fresh directory for one test, unique across the tests of this run
+    fn test_root(name: &str) -> PathBuf {
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let id = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!("{}-{}-{}", name, std::process::id(), id));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn mock_config(endpoint: String) -> S3Config {
+        S3Config {
+            provider: "generic".to_string(),
+            bucket: "bucket".to_string(),
+            access_key_id: "key".to_string(),
+            secret_access_key: "secret".to_string(),
+            region: Some("us-east-1".to_string()),
+            endpoint: Some(endpoint),
+            force_path_style: Some(true),
+            base_path: None,
+            local_path: None,
+            remote_prefix: None,
+            cache_db_path: None,
+            sync_direction: None,
+            profile: None,
+        }
+    }
+
+    async fn mock_helper(endpoint: String) -> S3Helper {
+        S3Helper::new(mock_config(endpoint)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_credential_chain_used_without_inline_keys() {
+        let (endpoint, requests) = mock_s3_endpoint(None).await;
+        let dir = test_root("s3-credentials");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("data.parquet");
+        fs::write(&file, b"parquet bytes").unwrap();
+        let signed_key = |index: usize| -> String {
+            let headers = requests.lock().unwrap()[index].to_lowercase();
+            let credential = headers.split("credential=").nth(1).unwrap_or_else(|| panic!("unsigned request: {}", headers));
+            credential.split('/').next().unwrap().to_string()
+        };
+
+        let mut config = mock_config(endpoint);
+        config.access_key_id.clear();
+        config.secret_access_key.clear();
+
+        // Environment variables come first in the chain
+        std::env::set_var("AWS_ACCESS_KEY_ID", "env-key");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "env-secret");
+        S3Helper::new(config.clone()).await.unwrap().upload_file(&file, "a.parquet").await.unwrap();
+        std::env::remove_var("AWS_ACCESS_KEY_ID");
+        std::env::remove_var("AWS_SECRET_ACCESS_KEY");
+        assert_eq!(signed_key(0), "env-key");
+
+        // Then the named profile from the shared credentials file
+        let credentials_file = dir.join("credentials");
+        fs::write(&credentials_file, "[archive]\naws_access_key_id = profile-key\naws_secret_access_key = profile-secret\n").unwrap();
+        std::env::set_var("AWS_SHARED_CREDENTIALS_FILE", &credentials_file);
+        std::env::set_var("AWS_CONFIG_FILE", dir.join("config"));
+        config.profile = Some("archive".to_string());
+        S3Helper::new(config.clone()).await.unwrap().upload_file(&file, "b.parquet").await.unwrap();
+        std::env::remove_var("AWS_SHARED_CREDENTIALS_FILE");
+        std::env::remove_var("AWS_CONFIG_FILE");
+        assert_eq!(signed_key(1), "profile-key");
+
+        // Half-configured inline keys are rejected instead of silently using the chain
+        config.access_key_id = "key".to_string();
+        assert!(S3Helper::new(config).await.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upload_sends_tags_and_metadata() {
+        let (endpoint, requests) = mock_s3_endpoint(None).await;
+        let helper = mock_helper(endpoint).await;
+
+        let file = test_root("s3-tags").join("data.parquet");
+        fs::write(&file, b"parquet bytes").unwrap();
+
+        let tags = HashMap::from([
+            ("dataset".to_string(), "mark-price".to_string()),
+            ("upload-date".to_string(), "2025-11-06".to_string()),
+        ]);
+        let metadata = HashMap::from([("sha256".to_string(), "abc123".to_string())]);
+        let etag = helper
+            .upload_file_with_attributes(&file, "mark-price/2025-11-06.parquet", &tags, &metadata)
+            .await
+            .unwrap();
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+
+        assert_eq!(etag, "\"mock-etag\"");
+        let headers = requests.lock().unwrap()[0].to_lowercase();
+        assert!(headers.starts_with("put /bucket/mark-price/2025-11-06.parquet"), "{}", headers);
+        assert!(headers.contains("x-amz-tagging: dataset=mark-price&upload-date=2025-11-06"), "{}", headers);
+        assert!(headers.contains("x-amz-meta-sha256: abc123"), "{}", headers);
+    }
+
+    #[tokio::test]
+    async fn test_atomic_batch_rolls_back_uploaded_siblings() {
+        let root = test_root("s3-atomic");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.parquet"), b"a").unwrap();
+        fs::write(root.join("b.parquet"), b"b").unwrap();
+        let files = vec![
+            (root.join("a.parquet"), "batch/a.parquet".to_string()),
+            (root.join("missing.parquet"), "batch/missing.parquet".to_string()),
+            (root.join("b.parquet"), "batch/b.parquet".to_string()),
+        ];
+
+        let (endpoint, requests) = mock_s3_endpoint(None).await;
+        let helper = mock_helper(endpoint).await;
+        let result = helper
+            .upload_files_batch_with_options(files, BatchUploadOptions { atomic: true, ..Default::default() })
+            .await
+            .unwrap();
+
+        assert!(!result.is_complete());
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].0, "batch/missing.parquet");
+        assert!(result.uploaded.is_empty());
+        assert_eq!(result.rolled_back, vec!["batch/a.parquet".to_string(), "batch/b.parquet".to_string()]);
+
+        let requests = requests.lock().unwrap();
+        let mut deleted: Vec<&str> = requests.iter()
+            .filter(|r| r.starts_with("DELETE"))
+            .map(|r| r.split_whitespace().nth(1).unwrap().split('?').next().unwrap())
+            .collect();
+        deleted.sort();
+        assert_eq!(deleted, ["/bucket/batch/a.parquet", "/bucket/batch/b.parquet"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_atomic_rollback_keeps_overwritten_keys() {
+        let root = test_root("s3-atomic-overwrite");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.parquet"), b"a").unwrap();
+        fs::write(root.join("b.parquet"), b"b").unwrap();
+        let files = vec![
+            (root.join("a.parquet"), "batch/a.parquet".to_string()),
+            (root.join("missing.parquet"), "batch/missing.parquet".to_string()),
+            (root.join("b.parquet"), "batch/b.parquet".to_string()),
+        ];
+
+        // b.parquet was in the bucket before the batch
+        let (endpoint, requests) = mock_s3_endpoint_with_existing(None, &["batch/b.parquet"]).await;
+        let helper = mock_helper(endpoint).await;
+        let result = helper
+            .upload_files_batch_with_options(files, BatchUploadOptions { atomic: true, ..Default::default() })
+            .await
+            .unwrap();
+
+        assert_eq!(result.rolled_back, vec!["batch/a.parquet".to_string()]);
+        assert_eq!(result.uploaded, vec!["batch/b.parquet".to_string()]);
+        assert_eq!(result.overwritten, vec!["batch/b.parquet".to_string()]);
+        let requests = requests.lock().unwrap();
+        let deleted: Vec<&str> = requests.iter()
+            .filter(|r| r.starts_with("DELETE"))
+            .map(|r| r.split_whitespace().nth(1).unwrap().split('?').next().unwrap())
+            .collect();
+        assert_eq!(deleted, ["/bucket/batch/a.parquet"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_batch_with_duplicate_keys_is_reported() {
+        let root = test_root("s3-duplicate-keys");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.parquet"), b"a").unwrap();
+        fs::write(root.join("a-copy.parquet"), b"a copy").unwrap();
+        fs::write(root.join("b.parquet"), b"b").unwrap();
+        let files = vec![
+            (root.join("a.parquet"), "batch/a.parquet".to_string()),
+            (root.join("b.parquet"), "batch/b.parquet".to_string()),
+            (root.join("a-copy.parquet"), "batch/a.parquet".to_string()),
+        ];
+
+        let (endpoint, requests) = mock_s3_endpoint(None).await;
+        let helper = mock_helper(endpoint).await;
+
+        // The default rejects the batch before anything is uploaded
+        let error = helper
+            .upload_files_batch_with_options(files.clone(), BatchUploadOptions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("batch/a.parquet"), "{}", error);
+        assert!(error.contains("a-copy.parquet"), "{}", error);
+        assert!(requests.lock().unwrap().is_empty());
+
+        let options = BatchUploadOptions { duplicate_keys: DuplicateKeyPolicy::KeepFirst, ..Default::default() };
+        let result = helper.upload_files_batch_with_options(files, options).await.unwrap();
+        assert_eq!(result.uploaded, vec!["batch/a.parquet".to_string(), "batch/b.parquet".to_string()]);
+        assert_eq!(result.skipped_duplicates, vec![("batch/a.parquet".to_string(), root.join("a-copy.parquet"))]);
+        assert_eq!(requests.lock().unwrap().iter().filter(|r| r.starts_with("PUT")).count(), 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_non_atomic_batch_keeps_uploaded_files() {
+        let root = test_root("s3-non-atomic");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.parquet"), b"a").unwrap();
+        let files = vec![
+            (root.join("a.parquet"), "batch/a.parquet".to_string()),
+            (root.join("missing.parquet"), "batch/missing.parquet".to_string()),
+        ];
+
+        let (endpoint, requests) = mock_s3_endpoint(None).await;
+        let helper = mock_helper(endpoint).await;
+        let result = helper
+            .upload_files_batch_with_options(files, BatchUploadOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(result.uploaded, vec!["batch/a.parquet".to_string()]);
+        assert!(result.rolled_back.is_empty());
+        assert!(!requests.lock().unwrap().iter().any(|r| r.starts_with("DELETE")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_sync_resumes_from_checkpoint() {
+        let root = test_root("s3-checkpoint");
+        let local_folder = root.join("data");
+        let db_path = root.join("sync-db");
+        fs::create_dir_all(&local_folder).unwrap();
+        for name in ["a", "b", "c", "d", "e"] {
+            fs::write(local_folder.join(format!("{}.parquet", name)), name).unwrap();
+        }
+        let options = SyncOptions {
+            use_compression: false,
+            checkpoint_every: 1,
+            ..Default::default()
+        };
+
+        // The fourth upload never completes; the sync is dropped mid-run
+        let (endpoint, _) = mock_s3_endpoint(Some(3)).await;
+        let helper = mock_helper(endpoint).await;
+        let interrupted = tokio::time::timeout(
+            std::time::Duration::from_secs(2),
+            helper.sync_folder(local_folder.as_path(), "backup", db_path.as_path(), options.clone()),
+        )
+        .await;
+        assert!(interrupted.is_err());
+        drop(helper);
+
+        // The next run only uploads the files that were not recorded
+        let (endpoint, requests) = mock_s3_endpoint(None).await;
+        let helper = mock_helper(endpoint).await;
+        let stats = helper
+            .sync_folder(local_folder.as_path(), "backup", db_path.as_path(), options)
+            .await
+            .unwrap();
+        assert_eq!(stats.files_skipped, 3);
+        assert_eq!(stats.files_uploaded, 2);
+        assert_eq!(requests.lock().unwrap().iter().filter(|r| r.starts_with("PUT")).count(), 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_collect_timings_records_each_upload() {
+        let root = test_root("s3-timings");
+        let local_folder = root.join("data");
+        fs::create_dir_all(&local_folder).unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(local_folder.join(name), name.repeat(10)).unwrap();
+        }
+        let (endpoint, _) = mock_s3_endpoint(None).await;
+        let helper = mock_helper(endpoint).await;
+
+        let options = SyncOptions {
+            use_compression: false,
+            collect_timings: true,
+            ..Default::default()
+        };
+        let stats = helper
+            .sync_folder(local_folder.as_path(), "backup", root.join("db-on").as_path(), options)
+            .await
+            .unwrap();
+        let mut paths: Vec<&str> = stats.timings.iter().map(|t| t.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, ["a", "b", "c"]);
+        assert!(stats.timings.iter().all(|t| t.bytes == 10 && t.direction == SyncDirection::LocalToS3));
+        assert_eq!(stats.slowest_transfers(2).len(), 2);
+
+        // Off by default
+        let options = SyncOptions { use_compression: false, ..Default::default() };
+        let stats = helper
+            .sync_folder(local_folder.as_path(), "backup", root.join("db-off").as_path(), options)
+            .await
+            .unwrap();
+        assert_eq!(stats.files_uploaded, 3);
+        assert!(stats.timings.is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_plan_sync_lists_changes_without_transferring() {
+        let root = test_root("s3-plan");
+        let local_folder = root.join("data");
+        let db_path = root.join("sync-db");
+        fs::create_dir_all(&local_folder).unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(local_folder.join(name), name).unwrap();
+        }
+
+        // a and b were synced before, b changed since; d was synced and then removed locally
+        let db = SyncDatabase::open(&db_path).unwrap();
+        for name in ["a", "b"] {
+            db.set_metadata(&FileMetadata::from_file(&local_folder.join(name), name).unwrap()).unwrap();
+        }
+        db.set_metadata(&FileMetadata::from_file(&local_folder.join("a"), "d").unwrap()).unwrap();
+        drop(db);
+        fs::write(local_folder.join("b"), "b changed").unwrap();
+
+        let options = SyncOptions {
+            delete: true,
+            dry_run: true,
+            use_compression: false,
+            ..Default::default()
+        };
+        let (endpoint, requests) = mock_s3_endpoint(None).await;
+        let helper = mock_helper(endpoint).await;
+        let mut plan = helper
+            .plan_sync(local_folder.as_path(), "backup", db_path.as_path(), &options)
+            .await
+            .unwrap();
+        plan.to_upload.sort();
+        assert_eq!(plan.to_upload, vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(plan.to_delete, vec!["d".to_string()]);
+        assert_eq!(plan.unchanged, vec!["a".to_string()]);
+        assert!(plan.to_download.is_empty());
+
+        // A dry run reports the plan and sends nothing to S3
+        let stats = helper
+            .sync_folder(local_folder.as_path(), "backup", db_path.as_path(), options)
+            .await
+            .unwrap();
+        assert_eq!((stats.files_uploaded, stats.files_deleted, stats.files_skipped), (2, 1, 1));
+        assert!(requests.lock().unwrap().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_parallel_restore_verifies_hashes() {
+        let root = test_root("s3-restore");
+        let local_folder = root.join("data");
+        let db_path = root.join("sync-db");
+        let names = ["a", "b", "c", "d", "e", "flaky", "corrupt"];
+        let original = |name: &str| name.repeat(100).into_bytes();
+        let objects = names
+            .iter()
+            .map(|name| {
+                let compressed = zstd::encode_all(&original(name)[..], 3).unwrap();
+                (format!("restore/{}.parquet.zst", name), compressed, format!("{:x}", Sha256::digest(original(name))))
+            })
+            .collect();
+
+        // "flaky" is served wrong content once and verifies on the retry, "corrupt" never verifies
+        let corrupt_gets = HashMap::from([
+            ("restore/flaky.parquet.zst".to_string(), 1),
+            ("restore/corrupt.parquet.zst".to_string(), usize::MAX),
+        ]);
+        let corrupt_body = zstd::encode_all(&b"bit rot"[..], 3).unwrap();
+        let (endpoint, peak_gets) = mock_s3_objects(objects, corrupt_gets, corrupt_body).await;
+        let helper = mock_helper(endpoint).await;
+
+        let options = SyncOptions {
+            direction: SyncDirection::S3ToLocal,
+            max_parallel: 3,
+            checkpoint_every: 1,
+            ..Default::default()
+        };
+        let stats = helper
+            .sync_folder(local_folder.as_path(), "restore", db_path.as_path(), options.clone())
+            .await
+            .unwrap();
+        assert_eq!((stats.files_downloaded, stats.errors), (6, 1));
+        let peak = peak_gets.load(std::sync::atomic::Ordering::SeqCst);
+        assert!(peak > 1 && peak <= 3, "peak concurrent downloads: {}", peak);
+
+        for name in &names[..6] {
+            assert_eq!(fs::read(local_folder.join(format!("{}.parquet", name))).unwrap(), original(name));
+        }
+        // The corrupt object never replaces a local file and no temporary files are left
+        assert!(!local_folder.join("corrupt.parquet").exists());
+        assert_eq!(fs::read_dir(&local_folder).unwrap().count(), 6);
+
+        // Restored files match their hashes, so the next run only retries the corrupt object
+        let stats = helper
+            .sync_folder(local_folder.as_path(), "restore", db_path.as_path(), options)
+            .await
+            .unwrap();
+        assert_eq!((stats.files_downloaded, stats.files_skipped, stats.errors), (0, 6, 1));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -6,7 +6,7 @@
 //! for optimal bandwidth usage and transfer speed.
 
 use anyhow::{Result, bail};
-use tracing::{info, debug, error};
+use tracing::{info, debug};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::net::TcpStream;
@@ -14,21 +14,45 @@ use std::io::Read;
 use ssh2::Session;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::compression::{decompress_auto, Compression};
 use crate::config::SshConfig;
+use crate::download::{collect_download_results, format_throughput, log_transfer, read_to_end_throttled, BandwidthLimiter, DownloadOrder, RequestLimiter, TransferStats};
+use crate::error::{ProcessorError, ProcessorResult};
 
 /// SSH client wrapper with connection pooling for file operations
 pub struct SshClient {
     config: SshConfig,
     /// Shared connection (reused across operations on the same server)
     connection: Arc<Mutex<Option<Arc<Session>>>>,
+    /// Shared by all downloads of this client when `max_bytes_per_sec` is set
+    limiter: Option<Arc<BandwidthLimiter>>,
+    /// Shared by all commands of this client when `requests_per_sec` is set
+    rate_limiter: Option<Arc<RequestLimiter>>,
 }
 
 impl SshClient {
     /// Create a new SSH client
     pub fn new(config: SshConfig) -> Self {
+        let limiter = config.max_bytes_per_sec.map(|rate| {
+            info!("SSH downloads from {} limited to {} bytes/s", config.host, rate);
+            Arc::new(BandwidthLimiter::new(rate))
+        });
+        let rate_limiter = config.requests_per_sec.map(|rate| {
+            info!("SSH commands on {} limited to {} per second", config.host, rate);
+            Arc::new(RequestLimiter::new(rate))
+        });
         Self {
             config,
             connection: Arc::new(Mutex::new(None)),
+            limiter,
+            rate_limiter,
+        }
+    }
+
+    /// Wait for the request-rate limit, if set, before executing a command
+    async fn throttle(rate_limiter: Option<&RequestLimiter>) {
+        if let Some(rate_limiter) = rate_limiter {
+            rate_limiter.acquire().await;
         }
     }
 
@@ -77,11 +101,12 @@ impl SshClient {
         // Authenticate
         if let Some(ref key_path) = config.private_key_path {
             debug!("Using private key authentication: {}", key_path);
+            let passphrase = config.key_passphrase();
             session.userauth_pubkey_file(
                 &config.username,
                 None,
                 std::path::Path::new(key_path),
-                config.password.as_deref(),
+                passphrase.as_deref(),
             )
             .map_err(|e| anyhow::anyhow!("Failed to authenticate with key: {:?}", e))?;
         } else if let Some(ref password) = config.password {
@@ -96,33 +121,57 @@ impl SshClient {
         Ok(session)
     }
 
+    /// Quote a string as a single POSIX shell word
+    pub fn shell_quote(value: &str) -> String {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+
+    /// Listing command for a directory from the configured template
+    pub fn list_command(&self, remote_path: &str) -> String {
+        self.config.list_command.replace("{path}", &Self::shell_quote(remote_path))
+    }
+
+    /// File names from the listing output, one per line
+    /// Names are kept verbatim (spaces included); only blank lines and `\r` are dropped
+    pub fn parse_file_list(output: &str) -> Vec<String> {
+        output
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
     /// List files in a remote directory
+    /// An empty directory yields an empty list
     pub async fn list_files(&self, remote_path: &str) -> Result<Vec<String>> {
         let session = self.get_connection().await?;
-        let remote_path = remote_path.to_string();
-        let remote_path_log = remote_path.clone();
+        let remote_path_log = remote_path.to_string();
+        let command = self.list_command(remote_path);
         
         debug!("Listing files in remote directory: {}", remote_path_log);
+        Self::throttle(self.rate_limiter.as_deref()).await;
         
         let files = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
             let mut channel = session.channel_session()
                 .map_err(|e| anyhow::anyhow!("Failed to open channel: {:?}", e))?;
             
-            let command = format!("ls -1 {}", remote_path);
             channel.exec(&command)
                 .map_err(|e| anyhow::anyhow!("Failed to execute command: {:?}", e))?;
             
             let mut output = String::new();
             channel.read_to_string(&mut output)
                 .map_err(|e| anyhow::anyhow!("Failed to read command output: {:?}", e))?;
+
+            channel.wait_close()
+                .map_err(|e| anyhow::anyhow!("Failed to close channel: {:?}", e))?;
+            let exit_status = channel.exit_status()
+                .map_err(|e| anyhow::anyhow!("Failed to get exit status: {:?}", e))?;
+            if exit_status != 0 {
+                bail!("List command `{}` failed: exit code {}", command, exit_status);
+            }
             
-            let files: Vec<String> = output
-                .lines()
-                .filter(|line| !line.trim().is_empty())
-                .map(|line| line.trim().to_string())
-                .collect();
-            
-            Ok(files)
+            Ok(Self::parse_file_list(&output))
         })
         .await
         .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??;
@@ -131,24 +180,6 @@ impl SshClient {
         Ok(files)
     }
 
-    /// Decompress zstd data
-    fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>> {
-        // Check if data looks like zstd format (starts with 0x28, 0xB5, 0x2F, 0xFD)
-        let is_zstd = data.len() >= 4 && &data[0..4] == &[0x28, 0xB5, 0x2F, 0xFD];
-        
-        if !is_zstd {
-            // Not zstd format, return as-is
-            info!("*** Data is NOT zstd format, returning as-is ({} bytes) ***", data.len());
-            return Ok(data.to_vec());
-        }
-        
-        info!("*** Attempting zstd decompression on {} bytes ***", data.len());
-        let decompressed = zstd::decode_all(data)
-            .map_err(|e| anyhow::anyhow!("Zstd decompression failed: {}", e))?;
-        info!("*** Successfully decompressed zstd data to {} bytes ***", decompressed.len());
-        Ok(decompressed)
-    }
-
     /// Download a file from the remote server via SFTP with zstd compression
     /// 
     /// Strategy:
@@ -156,7 +187,13 @@ impl SshClient {
     /// 2. Download the compressed file via SFTP
     /// 3. Decompress the data locally
     /// 4. Clean up temporary file on remote server
-    pub async fn download_file(&self, remote_path: &str) -> Result<Vec<u8>> {
+    pub async fn download_file(&self, remote_path: &str) -> ProcessorResult<Vec<u8>> {
+        self.download_file_impl(remote_path)
+            .await
+            .map_err(|source| ProcessorError::Download { path: remote_path.to_string(), source })
+    }
+
+    async fn download_file_impl(&self, remote_path: &str) -> Result<Vec<u8>> {
         let session = self.get_connection().await?;
         let remote_path = remote_path.to_string();
         
@@ -175,6 +212,7 @@ impl SshClient {
         let remote_path_clone = remote_path.clone();
         let temp_path_clone = temp_path.clone();
         let session_clone = session.clone();
+        Self::throttle(self.rate_limiter.as_deref()).await;
         
         tokio::task::spawn_blocking(move || {
             let mut channel = session_clone.channel_session()
@@ -199,6 +237,7 @@ impl SshClient {
         debug!("Downloading compressed file via SFTP: {}", temp_path);
         
         let session_clone = session.clone();
+        let limiter = self.limiter.clone();
         let compressed_data = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
             let sftp = session_clone.sftp()
                 .map_err(|e| anyhow::anyhow!("Failed to open SFTP channel: {:?}", e))?;
@@ -206,8 +245,7 @@ impl SshClient {
             let mut file = sftp.open(std::path::Path::new(&temp_path_clone))
                 .map_err(|e| anyhow::anyhow!("Failed to open file via SFTP: {:?}", e))?;
             
-            let mut data = Vec::new();
-            file.read_to_end(&mut data)
+            let data = read_to_end_throttled(&mut file, limiter.as_deref())
                 .map_err(|e| anyhow::anyhow!("Failed to read file: {:?}", e))?;
             
             Ok(data)
@@ -215,19 +253,10 @@ impl SshClient {
         .await
         .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??;
         
-        info!("Downloaded {} bytes from {} via SFTP", compressed_data.len(), remote_path_clone);
-        
         // Decompress locally
-        let decompressed = Self::decompress_zstd(&compressed_data)
+        let decompressed = decompress_auto(&compressed_data)
             .map_err(|e| anyhow::anyhow!("Decompression failed: {}", e))?;
-        
-        if decompressed.len() != compressed_data.len() {
-            info!("Decompressed to {} bytes (compression ratio: {:.1}%)", 
-                decompressed.len(), 
-                (compressed_data.len() as f64 / decompressed.len() as f64) * 100.0);
-        } else {
-            debug!("File was not compressed, downloaded as-is");
-        }
+        log_transfer("SFTP", &remote_path_clone, compressed_data.len(), decompressed.len());
         
         // Clean up temporary file on remote server
         debug!("Cleaning up temporary file: {}", temp_path);
@@ -249,9 +278,22 @@ impl SshClient {
     /// 
     /// Downloads multiple files in parallel with zstd compression using SFTP.
     /// Compresses files in /tmp, downloads via SFTP, decompresses locally.
-    pub async fn download_files_parallel(&self, file_paths: Vec<String>) -> Result<Vec<(String, Vec<u8>)>> {
+    /// Results are returned in the order of `file_paths`, with the transfer sizes summed over all files.
+    pub async fn download_files_parallel(&self, file_paths: Vec<String>) -> Result<(Vec<(String, Vec<u8>)>, TransferStats)> {
+        self.download_files_parallel_ordered(file_paths, DownloadOrder::Input).await
+    }
+
+    /// Download multiple files concurrently, returning results in the given order
+    /// `DownloadOrder::Completion` skips the reordering step
+    pub async fn download_files_parallel_ordered(
+        &self,
+        file_paths: Vec<String>,
+        order: DownloadOrder,
+    ) -> Result<(Vec<(String, Vec<u8>)>, TransferStats)> {
         let total_files = file_paths.len();
         let completed = Arc::new(AtomicUsize::new(0));
+        let transferred = Arc::new(AtomicUsize::new(0));
+        let started = std::time::Instant::now();
         let mut handles = vec![];
         
         info!("Starting parallel download of {} files (SFTP method)", total_files);
@@ -259,6 +301,9 @@ impl SshClient {
         for file_path in file_paths {
             let session = self.get_connection().await?;
             let completed = Arc::clone(&completed);
+            let transferred = Arc::clone(&transferred);
+            let limiter = self.limiter.clone();
+            let rate_limiter = self.rate_limiter.clone();
             
             let handle = tokio::spawn(async move {
                 debug!("Downloading file in parallel: {}", file_path);
@@ -275,6 +320,7 @@ impl SshClient {
                 let file_path_for_error = file_path.clone();
                 let file_path_for_info = file_path.clone();
                 let session_clone = session.clone();
+                Self::throttle(rate_limiter.as_deref()).await;
                 tokio::task::spawn_blocking(move || {
                     let mut channel = session_clone.channel_session()
                         .map_err(|e| anyhow::anyhow!("Failed to open channel: {:?}", e))?;
@@ -306,8 +352,7 @@ impl SshClient {
                     let mut file = sftp.open(std::path::Path::new(&temp_path_clone))
                         .map_err(|e| anyhow::anyhow!("Failed to open file via SFTP: {:?}", e))?;
                     
-                    let mut data = Vec::new();
-                    file.read_to_end(&mut data)
+                    let data = read_to_end_throttled(&mut file, limiter.as_deref())
                         .map_err(|e| anyhow::anyhow!("Failed to read file: {:?}", e))?;
                     
                     Ok(data)
@@ -316,12 +361,8 @@ impl SshClient {
                 .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??;
                 
                 let count = completed.fetch_add(1, Ordering::SeqCst) + 1;
-                info!("Downloaded {} bytes from {} via SFTP [{}/{} files completed]", 
-                    compressed_data.len(), 
-                    file_path_for_info.split('/').last().unwrap_or(&file_path_for_info),
-                    count,
-                    total_files
-                );
+                transferred.fetch_add(compressed_data.len(), Ordering::Relaxed);
+                debug!("Downloaded {} via SFTP [{}/{} files completed]", file_path_for_info, count, total_files);
                 
                 // Debug: Check first few bytes
                 let first_bytes = if compressed_data.len() >= 4 {
@@ -332,21 +373,14 @@ impl SshClient {
                 };
                 info!("*** First 4 bytes of downloaded data (HEX): {} ***", first_bytes);
                 
-                // Check if it's zstd format
-                let is_zstd = compressed_data.len() >= 4 && &compressed_data[0..4] == &[0x28, 0xB5, 0x2F, 0xFD];
-                info!("*** Is zstd format: {} ***", is_zstd);
+                info!("*** Detected compression: {:?} ***", Compression::detect(&compressed_data));
                 
                 // Decompress locally
-                let decompressed = Self::decompress_zstd(&compressed_data)
+                let decompressed = decompress_auto(&compressed_data)
                     .map_err(|e| anyhow::anyhow!("Decompression failed for {}: {}", file_path, e))?;
                 
-                if decompressed.len() != compressed_data.len() {
-                    info!("Decompressed to {} bytes (ratio: {:.1}%)", 
-                        decompressed.len(), 
-                        (compressed_data.len() as f64 / decompressed.len() as f64) * 100.0);
-                } else {
-                    debug!("File was not compressed (same size: {} bytes)", decompressed.len());
-                }
+                let file_name = file_path.rsplit('/').next().unwrap_or(&file_path);
+                log_transfer("SFTP", file_name, compressed_data.len(), decompressed.len());
                 
                 // Log first 100 bytes for debugging
                 let preview = String::from_utf8_lossy(&decompressed[..decompressed.len().min(100)]);
@@ -385,30 +419,37 @@ impl SshClient {
                 })
                 .await;
                 
-                Ok((file_path, decompressed))
+                Ok((file_path, decompressed, compressed_data.len()))
             });
             
             handles.push(handle);
         }
         
-        let mut results = vec![];
-        for handle in handles {
-            match handle.await {
-                Ok(Ok(result)) => results.push(result),
-                Ok(Err(e)) => {
-                    // Log failures and return error
-                    error!("Parallel download FAILED: {}", e);
-                    return Err(e);
-                }
-                Err(e) => {
-                    // Task panic or join error
-                    return Err(anyhow::anyhow!("Download task failed: {}", e));
-                }
-            }
-        }
+        let results = collect_download_results(handles, order).await?;
         
-        info!("Completed parallel download: {} files succeeded", results.len());
-        Ok(results)
+        let mut stats = TransferStats::default();
+        let results: Vec<(String, Vec<u8>)> = results
+            .into_iter()
+            .map(|(file_path, data, compressed_size)| {
+                stats.record(compressed_size, data.len());
+                (file_path, data)
+            })
+            .collect();
+        let transferred = transferred.load(Ordering::Relaxed);
+        info!(
+            source = self.config.host.as_str(),
+            files = stats.files as u64,
+            bytes_compressed = stats.bytes_compressed,
+            bytes_decompressed = stats.bytes_decompressed,
+            bytes_saved = stats.bytes_saved(),
+            ratio = stats.ratio(),
+            "Completed parallel download: {} files succeeded ({} bytes in {:.1?}, {})",
+            results.len(),
+            transferred,
+            started.elapsed(),
+            format_throughput(transferred, started.elapsed())
+        );
+        Ok((results, stats))
     }
 
     /// Check if a remote path exists
@@ -418,11 +459,12 @@ impl SshClient {
         let remote_path_log = remote_path.clone();
         
         // Use test command to check if path exists
+        Self::throttle(self.rate_limiter.as_deref()).await;
         let exists = tokio::task::spawn_blocking(move || -> Result<bool> {
             let mut channel = session.channel_session()
                 .map_err(|e| anyhow::anyhow!("Failed to open channel: {:?}", e))?;
             
-            let command = format!("test -e {} && echo 'exists' || echo 'not_exists'", remote_path);
+            let command = format!("test -e {} && echo 'exists' || echo 'not_exists'", Self::shell_quote(&remote_path));
             channel.exec(&command)
                 .map_err(|e| anyhow::anyhow!("Failed to execute command: {:?}", e))?;
             
@@ -458,9 +500,28 @@ mod tests {
             username: "user".to_string(),
             password: Some("pass".to_string()),
             private_key_path: None,
+            private_key_passphrase: None,
+            max_bytes_per_sec: None,
+            requests_per_sec: None,
+            list_command: crate::config::DEFAULT_SSH_LIST_COMMAND.to_string(),
+            input_base_path: "/data/mark-price".to_string(),
         };
         
         let client = SshClient::new(config);
         assert_eq!(client.host_identifier(), "user@localhost");
+        assert_eq!(
+            client.list_command("/data/it's here; rm -rf /"),
+            r#"find '/data/it'\''s here; rm -rf /' -maxdepth 1 -type f -printf '%f\n'"#
+        );
+    }
+
+    #[test]
+    fn test_parse_file_list_keeps_spaces_and_dotfiles() {
+        let output = "data 01.jsonl\r\n.hidden.jsonl\n\n  trailing space.jsonl \n";
+        assert_eq!(
+            SshClient::parse_file_list(output),
+            vec!["data 01.jsonl", ".hidden.jsonl", "  trailing space.jsonl "]
+        );
+        assert!(SshClient::parse_file_list("").is_empty());
     }
 }
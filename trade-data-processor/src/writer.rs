@@ -10,6 +10,57 @@ use std::collections::HashMap;
 /// Type alias for a data row (map of field names to JSON values)
 pub type DataRow = HashMap<String, Value>;
 
+/// Typed accessors for `DataRow` fields
+///
+/// Exchange payloads send numbers both as JSON numbers and as numeric strings, so the
+/// numeric and boolean accessors accept either form. All accessors return `None` for
+/// missing fields, `null` and values that cannot be coerced.
+pub trait DataRowExt {
+    fn get_i64(&self, key: &str) -> Option<i64>;
+    fn get_u64(&self, key: &str) -> Option<u64>;
+    fn get_f64(&self, key: &str) -> Option<f64>;
+    fn get_str(&self, key: &str) -> Option<&str>;
+    fn get_bool(&self, key: &str) -> Option<bool>;
+}
+
+impl DataRowExt for DataRow {
+    fn get_i64(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn get_u64(&self, key: &str) -> Option<u64> {
+        match self.get(key)? {
+            Value::Number(n) => n.as_u64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.get(key)? {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key)?.as_str()
+    }
+
+    fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            Value::Bool(b) => Some(*b),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
 /// Trait for writing data rows to a storage backend
 #[async_trait]
 pub trait Writer: Send {
@@ -20,3 +71,80 @@ pub trait Writer: Send {
     async fn flush_buffer(&mut self) -> Result<()>;
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample_row() -> DataRow {
+        let mut row = DataRow::new();
+        row.insert("num".to_string(), json!(42));
+        row.insert("neg".to_string(), json!(-7));
+        row.insert("float".to_string(), json!(1.5));
+        row.insert("num_str".to_string(), json!("123"));
+        row.insert("float_str".to_string(), json!("0.0001"));
+        row.insert("text".to_string(), json!("BTCUSDT"));
+        row.insert("flag".to_string(), json!(true));
+        row.insert("flag_str".to_string(), json!("false"));
+        row.insert("null".to_string(), Value::Null);
+        row
+    }
+
+    #[test]
+    fn test_get_i64() {
+        let row = sample_row();
+        assert_eq!(row.get_i64("num"), Some(42));
+        assert_eq!(row.get_i64("neg"), Some(-7));
+        assert_eq!(row.get_i64("num_str"), Some(123));
+        assert_eq!(row.get_i64("float"), None);
+        assert_eq!(row.get_i64("text"), None);
+        assert_eq!(row.get_i64("flag"), None);
+        assert_eq!(row.get_i64("null"), None);
+        assert_eq!(row.get_i64("missing"), None);
+    }
+
+    #[test]
+    fn test_get_u64() {
+        let row = sample_row();
+        assert_eq!(row.get_u64("num"), Some(42));
+        assert_eq!(row.get_u64("num_str"), Some(123));
+        assert_eq!(row.get_u64("neg"), None);
+        assert_eq!(row.get_u64("text"), None);
+        assert_eq!(row.get_u64("flag"), None);
+        assert_eq!(row.get_u64("null"), None);
+    }
+
+    #[test]
+    fn test_get_f64() {
+        let row = sample_row();
+        assert_eq!(row.get_f64("float"), Some(1.5));
+        assert_eq!(row.get_f64("num"), Some(42.0));
+        assert_eq!(row.get_f64("float_str"), Some(0.0001));
+        assert_eq!(row.get_f64("text"), None);
+        assert_eq!(row.get_f64("flag"), None);
+        assert_eq!(row.get_f64("null"), None);
+        assert_eq!(row.get_f64("missing"), None);
+    }
+
+    #[test]
+    fn test_get_str() {
+        let row = sample_row();
+        assert_eq!(row.get_str("text"), Some("BTCUSDT"));
+        assert_eq!(row.get_str("num_str"), Some("123"));
+        assert_eq!(row.get_str("num"), None);
+        assert_eq!(row.get_str("flag"), None);
+        assert_eq!(row.get_str("null"), None);
+        assert_eq!(row.get_str("missing"), None);
+    }
+
+    #[test]
+    fn test_get_bool() {
+        let row = sample_row();
+        assert_eq!(row.get_bool("flag"), Some(true));
+        assert_eq!(row.get_bool("flag_str"), Some(false));
+        assert_eq!(row.get_bool("num"), None);
+        assert_eq!(row.get_bool("text"), None);
+        assert_eq!(row.get_bool("null"), None);
+        assert_eq!(row.get_bool("missing"), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! Throughput self-test of the writer pipeline
+//!
+//! `run_bench` feeds synthetic WebSocket messages through the same path as live data
+//! (`extract_data_array_ignoring`, `convert_to_rows`, the write queue and the writer task)
+//! as fast as the writer accepts them, without connecting anywhere. The report tells how
+//! many rows per second the configured writer sustains on this host.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::collector::{run_writer, Config, WriterCommand};
+use crate::data_extract::{convert_to_rows, extract_data_array_ignoring};
+use crate::write_queue::write_queue;
+use crate::writer::{DataRow, Writer};
+
+/// Bench parameters
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// How long to generate messages
+    pub duration: Duration,
+    /// Rows in each synthetic message
+    pub rows_per_message: usize,
+    /// Flush the writer this often, so partial batches reach the disk as in a long run
+    pub flush_interval: Duration,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(10),
+            rows_per_message: 10,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Result of a bench run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchReport {
+    /// Time from the first message to the writer task finishing its final flush
+    pub elapsed: Duration,
+    /// Messages generated and parsed
+    pub messages: u64,
+    /// Rows handed to the writer
+    pub rows: u64,
+    /// Messages dropped by the write queue (`drop_oldest` policy only)
+    pub dropped_messages: u64,
+    /// `flush_buffer` calls, periodic and final
+    pub flushes: u64,
+    /// Growth of the output directories during the run
+    pub bytes_written: u64,
+}
+
+impl BenchReport {
+    pub fn rows_per_sec(&self) -> f64 {
+        self.rows as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows from {} messages in {:.2}s ({:.0} rows/sec), {} dropped messages, {} flushes, {} bytes written",
+            self.rows,
+            self.messages,
+            self.elapsed.as_secs_f64(),
+            self.rows_per_sec(),
+            self.dropped_messages,
+            self.flushes,
+            self.bytes_written
+        )
+    }
+}
+
+/// Writer wrapper counting the rows and flushes that reach the real writer
+struct CountingWriter {
+    inner: Box<dyn Writer>,
+    rows: Arc<AtomicU64>,
+    flushes: Arc<AtomicU64>,
+}
+
+#[async_trait]
+impl Writer for CountingWriter {
+    async fn write_rows(&mut self, rows: Vec<DataRow>) -> Result<()> {
+        self.rows.fetch_add(rows.len() as u64, Ordering::Relaxed);
+        self.inner.write_rows(rows).await
+    }
+
+    async fn flush_buffer(&mut self) -> Result<()> {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.inner.flush_buffer().await
+    }
+}
+
+/// Synthetic mark-price style message with `rows` records
+fn synthetic_message(sequence: u64, rows: usize) -> String {
+    let time = 1_700_000_000_000 + sequence;
+    let data: Vec<String> = (0..rows)
+        .map(|i| {
+            format!(
+                r#"{{"e":"markPriceUpdate","E":{},"s":"BENCH{}USDT","p":"{}.{}","r":"0.00010000","T":{}}}"#,
+                time,
+                i,
+                1000 + i,
+                sequence % 1000,
+                time
+            )
+        })
+        .collect();
+    format!(r#"{{"stream":"bench","data":[{}]}}"#, data.join(","))
+}
+
+/// Total size of the files below `path` (0 if it does not exist)
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Output directories of the configured writers
+fn output_dirs(config: &Config) -> Vec<String> {
+    let mut dirs = vec![config.path.clone()];
+    for spec in &config.writers {
+        if let Some(path) = &spec.path {
+            if !dirs.contains(path) {
+                dirs.push(path.clone());
+            }
+        }
+    }
+    dirs
+}
+
+/// Generate messages for `options.duration` through `writer` and report the throughput
+/// `config` supplies the write queue, ignored message shapes and the output directories
+pub async fn run_bench(config: &Config, writer: Box<dyn Writer>, options: &BenchOptions) -> Result<BenchReport> {
+    let rows = Arc::new(AtomicU64::new(0));
+    let flushes = Arc::new(AtomicU64::new(0));
+    let writer = Box::new(CountingWriter { inner: writer, rows: rows.clone(), flushes: flushes.clone() });
+
+    let dirs = output_dirs(config);
+    let size_before: u64 = dirs.iter().map(|dir| dir_size(Path::new(dir))).sum();
+
+    info!(
+        "Benchmarking writer for {:?} with {} rows per message",
+        options.duration, options.rows_per_message
+    );
+    let (writer_queue, writer_commands) = write_queue(&config.write_queue);
+    let writer_handle = tokio::spawn(run_writer(writer, writer_commands));
+
+    let started = Instant::now();
+    let mut last_flush = started;
+    let mut messages = 0;
+    while started.elapsed() < options.duration {
+        let text = synthetic_message(messages, options.rows_per_message);
+        let data = extract_data_array_ignoring(&text, &config.ignore_messages)?;
+        writer_queue.send(WriterCommand::Rows(convert_to_rows(data))).await?;
+        messages += 1;
+
+        if last_flush.elapsed() >= options.flush_interval {
+            writer_queue.send(WriterCommand::Flush).await?;
+            last_flush = Instant::now();
+        }
+        // Let the writer task run on single-threaded runtimes too
+        tokio::task::yield_now().await;
+    }
+
+    // Dropping the queue makes the writer task drain it and flush once more
+    let dropped_messages = writer_queue.dropped();
+    drop(writer_queue);
+    writer_handle.await?;
+    let elapsed = started.elapsed();
+
+    let size_after: u64 = dirs.iter().map(|dir| dir_size(Path::new(dir))).sum();
+    Ok(BenchReport {
+        elapsed,
+        messages,
+        rows: rows.load(Ordering::Relaxed),
+        dropped_messages,
+        flushes: flushes.load(Ordering::Relaxed),
+        bytes_written: size_after.saturating_sub(size_before),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Writer keeping only the row count
+    struct MockWriter {
+        rows: Arc<Mutex<u64>>,
+    }
+
+    #[async_trait]
+    impl Writer for MockWriter {
+        async fn write_rows(&mut self, rows: Vec<DataRow>) -> Result<()> {
+            *self.rows.lock().unwrap() += rows.len() as u64;
+            Ok(())
+        }
+
+        async fn flush_buffer(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_short_bench_reports_written_rows() {
+        let config: Config = serde_yaml::from_str("wss_url: ws://unused\npath: /nonexistent/bench\nname: bench\n").unwrap();
+        let written = Arc::new(Mutex::new(0));
+        let options = BenchOptions {
+            duration: Duration::from_millis(200),
+            rows_per_message: 5,
+            flush_interval: Duration::from_millis(50),
+        };
+
+        let report = run_bench(&config, Box::new(MockWriter { rows: written.clone() }), &options).await.unwrap();
+
+        assert!(report.messages > 0);
+        assert_eq!(report.rows, report.messages * 5);
+        assert_eq!(report.rows, *written.lock().unwrap());
+        assert_eq!(report.dropped_messages, 0);
+        // Periodic flushes plus the final one
+        assert!(report.flushes >= 2, "{:?}", report);
+        assert_eq!(report.bytes_written, 0);
+        assert!(report.elapsed >= options.duration);
+        assert!(report.rows_per_sec() > 0.0);
+    }
+}
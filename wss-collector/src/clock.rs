@@ -0,0 +1,21 @@
+//! Injectable source of the current time
+//!
+//! Writers without a fixed `date` pick the output day from the clock. Tests swap in their
+//! own clock to cross midnight without waiting for it.
+
+use chrono::{DateTime, Utc};
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
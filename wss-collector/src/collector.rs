@@ -0,0 +1,726 @@
+//! Collector loop, usable as a library
+//!
+//! `run_collector` connects to the configured stream, hands parsed rows to a writer task
+//! and reconnects with backoff until it is cancelled. The `wss-collector` binary is a thin
+//! wrapper that loads the config and cancels on Ctrl-C; services can embed the same loop
+//! and inject their own `Writer`.
+
+use futures_util::{SinkExt, StreamExt};
+use parquet::basic::Encoding;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::composite_writer::CompositeWriter;
+use crate::data_extract::{convert_to_rows, default_ignore_messages, extract_binary_data_array, extract_data_array_ignoring, MessageCodec};
+use crate::error::{CollectorError, CollectorResult};
+use crate::feather_writer::{FeatherWriter, FeatherWriterConfig};
+use crate::json_writer::{JsonWriter, JsonWriterConfig};
+use crate::parquet_writer::{Filter, ParquetWriter, ParquetWriterConfig, DEFAULT_ROW_GROUP_SIZE};
+use crate::quarantine::{Quarantine, DEFAULT_QUARANTINE_MAX_BYTES};
+use crate::reconnect::ReconnectBackoff;
+use crate::subscribe::{send_subscribe_frames, SubscribeRateLimit};
+use crate::token_refresh::{TokenRefreshConfig, TokenRefresher};
+use crate::write_queue::{write_queue, Evictable, WriteQueueConfig, WriteQueueReceiver};
+use crate::writer::{DataRow, Writer, WriterSpec, WriterType};
+use crate::wss_stream::{connect_wss_stream_verbose, WsConfig};
+
+/// Collector configuration, usually loaded from YAML
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// WebSocket URL to connect to; `ws+unix:///path/to/socket` connects over a Unix socket
+    pub wss_url: String,
+    /// Base path for storing parquet/json files
+    pub path: String,
+    /// Name prefix for output files
+    pub name: String,
+    /// Optional HTTP proxy URL (e.g., http://proxy.example.com:8080)
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Writer type: "parquet" (default), "json" or "feather"
+    #[serde(default)]
+    pub writer_type: WriterType,
+    /// Sinks that all receive every row, e.g. parquet plus json for replay
+    /// When non-empty this replaces `writer_type`
+    #[serde(default)]
+    pub writers: Vec<WriterSpec>,
+    /// With several `writers`, keep writing to the others when one sink fails
+    /// (default false: the failing sink aborts the write and the error is logged)
+    #[serde(default)]
+    pub continue_on_writer_error: bool,
+    /// Batch size - number of records to buffer before writing to file (parquet and feather)
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Rows per parquet file; batches are appended to the open file until it is reached
+    /// If not specified, every batch is written to its own file (only for parquet)
+    #[serde(default)]
+    pub file_target_rows: Option<usize>,
+    /// Maximum rows per parquet row group within a file (only for parquet)
+    #[serde(default = "default_row_group_size")]
+    pub row_group_size: usize,
+    /// Parquet columns written first, in this order (rest sorted by name)
+    #[serde(default)]
+    pub column_order: Option<Vec<String>>,
+    /// Per-column parquet encodings by name, e.g. `order_id: DELTA_BINARY_PACKED` (only for parquet)
+    #[serde(default, with = "crate::parquet_writer::column_encodings_serde")]
+    pub column_encodings: HashMap<String, Encoding>,
+    /// Columns that use dictionary encoding; all others have it disabled (only for parquet)
+    /// If not specified, every column uses dictionary encoding
+    #[serde(default)]
+    pub dictionary_columns: Option<Vec<String>>,
+    /// Optional row filter - a flat list of conditions (OR) or `{ logic, groups }`;
+    /// if not specified, all data is written
+    #[serde(default)]
+    pub filter: Filter,
+    /// Offer permessage-deflate compression (servers without it are read uncompressed)
+    #[serde(default)]
+    pub compression: bool,
+    /// Maximum WebSocket message size in bytes (default 64 MiB)
+    #[serde(default)]
+    pub max_message_size: Option<usize>,
+    /// Maximum WebSocket frame size in bytes (default 16 MiB)
+    #[serde(default)]
+    pub max_frame_size: Option<usize>,
+    /// PEM CA certificate trusted for `wss://` in addition to the system roots
+    #[serde(default)]
+    pub ca_cert_path: Option<String>,
+    /// Accept any server certificate (INSECURE, for testing only)
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    /// Frames sent after every (re)connect, e.g. subscribe requests
+    #[serde(default)]
+    pub subscribe: Vec<String>,
+    /// Optional pacing for subscribe frames
+    #[serde(default)]
+    pub subscribe_rate_limit: Option<SubscribeRateLimit>,
+    /// Message shapes skipped without logging an error (pongs, subscribe acks, ...)
+    /// An object shape matches messages containing all of its key/value pairs
+    #[serde(default = "default_ignore_messages")]
+    pub ignore_messages: Vec<serde_json::Value>,
+    /// Encoding of binary frames: "json" (default, must be UTF-8) or "msgpack"
+    #[serde(default)]
+    pub message_codec: MessageCodec,
+    /// Queue between the read loop and the writer task
+    #[serde(default)]
+    pub write_queue: WriteQueueConfig,
+    /// Optional token refresh for authenticated streams (listen keys etc.)
+    #[serde(default)]
+    pub token_refresh: Option<TokenRefreshConfig>,
+    /// Optional JSONL file that receives messages which failed to parse, with time and error
+    #[serde(default)]
+    pub quarantine_path: Option<String>,
+    /// Size cap of the quarantine file before it is rotated to `<path>.1`
+    #[serde(default = "default_quarantine_max_bytes")]
+    pub quarantine_max_bytes: u64,
+    /// Consecutive reconnect attempts before giving up; unset retries forever
+    #[serde(default)]
+    pub max_reconnect_attempts: Option<u32>,
+}
+
+fn default_batch_size() -> usize {
+    1000
+}
+
+fn default_quarantine_max_bytes() -> u64 {
+    DEFAULT_QUARANTINE_MAX_BYTES
+}
+
+fn default_row_group_size() -> usize {
+    DEFAULT_ROW_GROUP_SIZE
+}
+
+impl Config {
+    /// Convert generic Config to ParquetWriterConfig
+    pub fn to_parquet_config(&self) -> ParquetWriterConfig {
+        ParquetWriterConfig {
+            path: self.path.clone(),
+            name: self.name.clone(),
+            batch_size: self.batch_size,
+            file_target_rows: self.file_target_rows,
+            filter: self.filter.clone(),
+            date: None,
+            row_group_size: self.row_group_size,
+            column_order: self.column_order.clone(),
+            column_encodings: self.column_encodings.clone(),
+            dictionary_columns: self.dictionary_columns.clone(),
+        }
+    }
+
+    /// WebSocket options for connecting
+    pub fn ws_config(&self) -> WsConfig {
+        WsConfig {
+            compression: self.compression,
+            max_message_size: self.max_message_size,
+            max_frame_size: self.max_frame_size,
+            ca_cert_path: self.ca_cert_path.clone(),
+            accept_invalid_certs: self.accept_invalid_certs,
+        }
+    }
+
+    /// Convert generic Config to JsonWriterConfig
+    pub fn to_json_config(&self) -> JsonWriterConfig {
+        JsonWriterConfig {
+            path: self.path.clone(),
+            name: self.name.clone(),
+            filter: self.filter.clone(),
+        }
+    }
+
+    /// Convert generic Config to FeatherWriterConfig
+    pub fn to_feather_config(&self) -> FeatherWriterConfig {
+        FeatherWriterConfig {
+            path: self.path.clone(),
+            name: self.name.clone(),
+            batch_size: self.batch_size,
+            filter: self.filter.clone(),
+            date: None,
+        }
+    }
+
+    /// Create appropriate writer based on configuration
+    /// Several `writers` are combined into a `CompositeWriter`
+    pub fn create_writer(&self) -> Box<dyn Writer> {
+        if self.writers.is_empty() {
+            return self.create_single_writer();
+        }
+
+        info!("Writing to {} sinks (continue on error: {})", self.writers.len(), self.continue_on_writer_error);
+        let writers = self
+            .writers
+            .iter()
+            .map(|spec| {
+                let mut config = self.clone();
+                config.writer_type = spec.writer_type.clone();
+                if let Some(path) = &spec.path {
+                    config.path = path.clone();
+                }
+                if let Some(name) = &spec.name {
+                    config.name = name.clone();
+                }
+                config.create_single_writer()
+            })
+            .collect();
+        Box::new(CompositeWriter::new(writers).with_continue_on_error(self.continue_on_writer_error))
+    }
+
+    fn create_single_writer(&self) -> Box<dyn Writer> {
+        match self.writer_type {
+            WriterType::Parquet => {
+                info!("Using Parquet writer with batch size: {}", self.batch_size);
+                Box::new(ParquetWriter::new(self.to_parquet_config()))
+            }
+            WriterType::Json => {
+                info!("Using JSON writer (line-by-line append mode)");
+                Box::new(JsonWriter::new(self.to_json_config()))
+            }
+            WriterType::Feather => {
+                info!("Using Feather (Arrow IPC) writer with batch size: {}", self.batch_size);
+                Box::new(FeatherWriter::new(self.to_feather_config()))
+            }
+        }
+    }
+}
+
+/// First 200 characters of a message for logging (never splits a UTF-8 character)
+fn excerpt(text: &str) -> String {
+    text.chars().take(200).collect()
+}
+
+/// Log a message that could not be turned into rows and keep it in the quarantine file
+fn reject_message(quarantine: &mut Option<Quarantine>, kind: &str, text: &str, error: &str) {
+    error!("Failed to parse {}: {} (content: {})", kind, error, excerpt(text));
+    if let Some(quarantine) = quarantine {
+        if let Err(e) = quarantine.record(text, error) {
+            error!("Failed to write quarantine file {:?}: {:#}", quarantine.path(), e);
+        }
+    }
+}
+
+/// Log a binary frame that could not be turned into rows and keep it (base64) in the quarantine file
+fn reject_binary_message(quarantine: &mut Option<Quarantine>, data: &[u8], error: &str) {
+    error!("Failed to parse binary message: {} ({} bytes)", error, data.len());
+    if let Some(quarantine) = quarantine {
+        if let Err(e) = quarantine.record_binary(data, error) {
+            error!("Failed to write quarantine file {:?}: {:#}", quarantine.path(), e);
+        }
+    }
+}
+
+/// Work sent from the read loop to the writer task
+pub(crate) enum WriterCommand {
+    Rows(Vec<DataRow>),
+    Flush,
+}
+
+impl Evictable for WriterCommand {
+    /// Rows may be dropped under `DropOldest`; a flush must always reach the writer
+    fn evictable(&self) -> bool {
+        matches!(self, WriterCommand::Rows(_))
+    }
+}
+
+/// Own the writer and apply queued commands until the read loop stops
+pub(crate) async fn run_writer(mut writer: Box<dyn Writer>, mut commands: WriteQueueReceiver<WriterCommand>) {
+    while let Some(command) = commands.recv().await {
+        match command {
+            WriterCommand::Rows(rows) => {
+                if let Err(e) = writer.write_rows(rows).await {
+                    error!("Failed to write rows: {}", e);
+                }
+            }
+            WriterCommand::Flush => {
+                if let Err(e) = writer.flush_buffer().await {
+                    error!("Failed to flush buffer: {}", e);
+                }
+            }
+        }
+    }
+
+    if let Err(e) = writer.flush_buffer().await {
+        error!("Failed to flush buffer on shutdown: {}", e);
+    }
+}
+
+/// Collect from the stream into the configured writer until `shutdown` is cancelled
+///
+/// Reconnects with jittered backoff. Returns `Ok` after flushing the writer once shutdown
+/// is requested, or `CollectorError::ReconnectExhausted` once `max_reconnect_attempts`
+/// consecutive attempts have failed.
+pub async fn run_collector(config: Config, shutdown: CancellationToken) -> CollectorResult<()> {
+    let writer = config.create_writer();
+    run_collector_with_writer(config, writer, shutdown).await
+}
+
+/// Same as `run_collector`, writing rows to `writer` instead of the configured one
+pub async fn run_collector_with_writer(config: Config, writer: Box<dyn Writer>, shutdown: CancellationToken) -> CollectorResult<()> {
+    let (writer_queue, writer_commands) = write_queue(&config.write_queue);
+    info!(
+        "Write queue capacity: {} (overflow policy: {:?})",
+        config.write_queue.capacity, config.write_queue.overflow
+    );
+    let writer_handle = tokio::spawn(run_writer(writer, writer_commands));
+    let token_refresher = match &config.token_refresh {
+        Some(token_config) => match TokenRefresher::new(token_config.clone(), config.proxy.as_deref()) {
+            Ok(refresher) => Some(Arc::new(refresher)),
+            Err(e) => {
+                error!("Invalid token refresh configuration, continuing without it: {:#}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    let mut quarantine = config.quarantine_path.as_ref().map(|path| {
+        info!("Quarantining unparseable messages to {} (max {} bytes)", path, config.quarantine_max_bytes);
+        Quarantine::new(path, config.quarantine_max_bytes)
+    });
+    let mut backoff = ReconnectBackoff::new(config.max_reconnect_attempts);
+    // Failure of the latest connect attempt, set by every attempt below
+    let mut last_error: Option<Box<CollectorError>>;
+
+    loop {
+        info!("Attempting to connect to WebSocket: {}", config.wss_url);
+        if let Some(ref proxy) = config.proxy {
+            info!("Using proxy: {}", proxy);
+        }
+
+        let ws_config = config.ws_config();
+        let connect_result = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            result = connect_wss_stream_verbose(
+                &config.wss_url,
+                config.proxy.as_deref(),
+                &ws_config,
+            ) => result,
+        };
+
+        match connect_result {
+            Ok((ws_stream, connection_info)) => {
+                info!(
+                    "Successfully connected to WebSocket in {:?} (proxy: {}, protocol: {}, compressed: {})",
+                    connection_info.handshake_duration,
+                    connection_info.via_proxy.as_deref().unwrap_or("none"),
+                    connection_info.negotiated_protocol.as_deref().unwrap_or("none"),
+                    connection_info.compressed,
+                );
+                backoff.reset();
+                last_error = None;
+
+                let (write, mut read) = ws_stream.split();
+                let write = Arc::new(Mutex::new(write));
+
+                // Fetch a fresh token and send the renew frame before resubscribing
+                let mut token_handle = None;
+                if let Some(refresher) = &token_refresher {
+                    if let Err(e) = refresher.renew(&write).await {
+                        error!("Failed to fetch token after connecting: {:#}", e);
+                    }
+                    token_handle = Some(refresher.clone().spawn(write.clone()));
+                }
+
+                // (Re)subscribe before reading
+                if let Err(e) = send_subscribe_frames(&write, &config.subscribe, config.subscribe_rate_limit.as_ref()).await {
+                    error!("Failed to send subscribe frames: {:#}", e);
+                }
+
+                // Optional: Send a ping periodically to keep connection alive
+                let ping_write = write.clone();
+                let ping_handle = tokio::spawn(async move {
+                    loop {
+                        tokio::time::sleep(Duration::from_secs(30)).await;
+                        if ping_write.lock().await.send(tokio_tungstenite::tungstenite::Message::Ping(vec![])).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                // Read messages from WebSocket until it ends or shutdown is requested
+                loop {
+                    let message = tokio::select! {
+                        _ = shutdown.cancelled() => {
+                            info!("Shutdown requested, closing WebSocket connection");
+                            let _ = write.lock().await.close().await;
+                            break;
+                        }
+                        message = read.next() => match message {
+                            Some(message) => message,
+                            None => break,
+                        },
+                    };
+                    match message {
+                        Ok(tokio_tungstenite::tungstenite::Message::Text(text)) => {
+                            info!("Received message: {} bytes", text.len());
+                            
+                            // Parse WebSocket message and extract data
+                            match extract_data_array_ignoring(&text, &config.ignore_messages) {
+                                Ok(data_array) if data_array.is_empty() => {
+                                    debug!("Skipping message without data: {}", excerpt(&text));
+                                }
+                                Ok(data_array) => {
+                                    // Convert to data rows
+                                    let rows = convert_to_rows(data_array);
+                                    
+                                    if rows.is_empty() {
+                                        reject_message(&mut quarantine, "message", &text, "No valid rows extracted from message");
+                                        continue;
+                                    }
+                                    
+                                    // Hand rows to the writer task
+                                    if let Err(e) = writer_queue.send(WriterCommand::Rows(rows)).await {
+                                        error!("Failed to queue rows: {}", e);
+                                        error!("Message content: {}", excerpt(&text));
+                                    }
+                                }
+                                Err(e) => {
+                                    reject_message(&mut quarantine, "message", &text, &format!("{:#}", e));
+                                }
+                            }
+                        }
+                        Ok(tokio_tungstenite::tungstenite::Message::Binary(data)) => {
+                            info!("Received binary message: {} bytes", data.len());
+                            
+                            // Decode with the configured codec and extract data
+                            match extract_binary_data_array(&data, config.message_codec, &config.ignore_messages) {
+                                Ok(data_array) if data_array.is_empty() => {
+                                    debug!("Skipping binary message without data: {} bytes", data.len());
+                                }
+                                Ok(data_array) => {
+                                    // Convert to data rows
+                                    let rows = convert_to_rows(data_array);
+                                    
+                                    if rows.is_empty() {
+                                        reject_binary_message(&mut quarantine, &data, "No valid rows extracted from binary message");
+                                        continue;
+                                    }
+                                    
+                                    // Hand rows to the writer task
+                                    if let Err(e) = writer_queue.send(WriterCommand::Rows(rows)).await {
+                                        error!("Failed to queue rows from binary message: {}", e);
+                                    }
+                                }
+                                Err(e) => {
+                                    reject_binary_message(&mut quarantine, &data, &format!("{:#}", e));
+                                }
+                            }
+                        }
+                        Ok(tokio_tungstenite::tungstenite::Message::Ping(_)) | Ok(tokio_tungstenite::tungstenite::Message::Pong(_)) => {
+                            // Ignore ping/pong messages
+                        }
+                        Ok(tokio_tungstenite::tungstenite::Message::Close(_)) => {
+                            warn!("WebSocket connection closed by server");
+                            // Flush buffer before reconnecting
+                            if let Err(e) = writer_queue.send(WriterCommand::Flush).await {
+                                error!("Failed to queue flush on close: {}", e);
+                            }
+                            break;
+                        }
+                        Err(e) => {
+                            error!("WebSocket error: {}", e);
+                            // Flush buffer before reconnecting
+                            if let Err(e) = writer_queue.send(WriterCommand::Flush).await {
+                                error!("Failed to queue flush on error: {}", e);
+                            }
+                            break;
+                        }
+                        Ok(tokio_tungstenite::tungstenite::Message::Frame(frame)) => {
+                            // Fragmented messages are reassembled into Text/Binary before they get
+                            // here; a raw frame means the stream is not delivering whole messages
+                            warn!(
+                                "Skipping raw WebSocket frame ({}, {} bytes, final: {})",
+                                frame.header().opcode,
+                                frame.payload().len(),
+                                frame.header().is_final
+                            );
+                        }
+                    }
+                }
+
+                ping_handle.abort();
+                if writer_queue.dropped() > 0 {
+                    warn!("{} messages dropped so far because the write queue was full", writer_queue.dropped());
+                }
+                if let Some(handle) = token_handle {
+                    handle.abort();
+                }
+                if shutdown.is_cancelled() {
+                    break;
+                }
+                warn!("WebSocket stream ended, will reconnect...");
+            }
+            Err(e) => {
+                error!("Failed to connect to WebSocket (attempt {}): {}", backoff.attempts() + 1, e);
+                last_error = Some(Box::new(e));
+            }
+        }
+
+        // Exponential backoff capped at 60 seconds, jittered by ±50%
+        let Some(sleep_duration) = backoff.next_delay() else {
+            error!("Giving up after {} reconnect attempts", backoff.attempts());
+            // Let the writer flush what it has before returning
+            drop(writer_queue);
+            if let Err(e) = writer_handle.await {
+                error!("Writer task failed: {}", e);
+            }
+            return Err(CollectorError::ReconnectExhausted {
+                url: config.wss_url.clone(),
+                attempts: backoff.attempts(),
+                last_error,
+            });
+        };
+        warn!("Retrying in {:.1} seconds...", sleep_duration.as_secs_f64());
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(sleep_duration) => {}
+        }
+    }
+
+    // Let the writer flush what it has before returning
+    info!("Collector stopped, flushing writer");
+    drop(writer_queue);
+    if let Err(e) = writer_handle.await {
+        error!("Writer task failed: {}", e);
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// Writer keeping rows in memory
+    struct MemoryWriter {
+        rows: Arc<std::sync::Mutex<Vec<DataRow>>>,
+    }
+
+    #[async_trait]
+    impl Writer for MemoryWriter {
+        async fn write_rows(&mut self, rows: Vec<DataRow>) -> Result<()> {
+            self.rows.lock().unwrap().extend(rows);
+            Ok(())
+        }
+
+        async fn flush_buffer(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run the collector against a server sending `messages`, cancel it once `expected_rows`
+    /// rows arrived and return the symbols of the written rows
+    async fn collect_symbols(messages: Vec<Message>, expected_rows: usize) -> Vec<Value> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Server keeping the connection open until the client closes it
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            for message in messages {
+                ws.send(message).await.unwrap();
+            }
+            while ws.next().await.is_some() {}
+        });
+
+        let config: Config = serde_yaml::from_str(&format!("wss_url: ws://{}\npath: unused\nname: test\n", addr)).unwrap();
+        let rows = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let writer = Box::new(MemoryWriter { rows: rows.clone() });
+        let shutdown = CancellationToken::new();
+        let collector = tokio::spawn(run_collector_with_writer(config, writer, shutdown.clone()));
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while rows.lock().unwrap().len() < expected_rows {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Cancelling stops the collector cleanly instead of reconnecting
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(5), collector).await.unwrap().unwrap().unwrap();
+
+        let rows = rows.lock().unwrap();
+        rows.iter().map(|row| row["s"].clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_run_collector_writes_rows_until_cancelled() {
+        let symbols = collect_symbols(
+            vec![
+                Message::Text(r#"{"data":[{"s":"BTCUSDT"}]}"#.to_string()),
+                Message::Text(r#"{"data":[{"s":"ETHUSDT"}]}"#.to_string()),
+            ],
+            2,
+        )
+        .await;
+        assert_eq!(symbols, [Value::from("BTCUSDT"), Value::from("ETHUSDT")]);
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_message_is_parsed_once() {
+        use tokio_tungstenite::tungstenite::protocol::frame::coding::{Data, OpCode};
+        use tokio_tungstenite::tungstenite::protocol::frame::Frame;
+
+        // One text message split over a first frame and a final continuation frame
+        let first = Frame::message(br#"{"data":[{"s":"BTC"#.to_vec(), OpCode::Data(Data::Text), false);
+        let last = Frame::message(br#"USDT"}]}"#.to_vec(), OpCode::Data(Data::Continue), true);
+        let symbols = collect_symbols(
+            vec![
+                Message::Frame(first),
+                Message::Frame(last),
+                Message::Text(r#"{"data":[{"s":"ETHUSDT"}]}"#.to_string()),
+            ],
+            2,
+        )
+        .await;
+        assert_eq!(symbols, [Value::from("BTCUSDT"), Value::from("ETHUSDT")]);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_with_last_connect_error() {
+        // Nothing listens on port 1, so every attempt fails at the proxy
+        let config: Config = serde_yaml::from_str(
+            "wss_url: ws://127.0.0.1:9\nproxy: http://127.0.0.1:1\nmax_reconnect_attempts: 1\npath: unused\nname: test\n",
+        )
+        .unwrap();
+        let writer = Box::new(MemoryWriter { rows: Arc::new(std::sync::Mutex::new(Vec::new())) });
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            run_collector_with_writer(config, writer, CancellationToken::new()),
+        )
+        .await
+        .unwrap();
+        match result {
+            Err(CollectorError::ReconnectExhausted { attempts, last_error, .. }) => {
+                assert_eq!(attempts, 1);
+                assert!(matches!(last_error.as_deref(), Some(CollectorError::ProxyUnavailable { .. })), "{:?}", last_error);
+            }
+            other => panic!("expected ReconnectExhausted, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_renew_precedes_subscribe_on_every_connect() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Token endpoint handing out a new listen key per request
+        let token_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let token_addr = token_listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for key in ["key1", "key2"] {
+                let (mut stream, _) = token_listener.accept().await.unwrap();
+                let _ = stream.read(&mut [0u8; 1024]).await.unwrap();
+                let body = format!(r#"{{"listenKey":"{}"}}"#, key);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        // Server recording the first three frames of two connections, dropping the first
+        // one to force a reconnect
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut connections = Vec::new();
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let mut frames = Vec::new();
+                while frames.len() < 3 {
+                    if let Message::Text(text) = ws.next().await.unwrap().unwrap() {
+                        frames.push(text);
+                    }
+                }
+                connections.push(frames);
+            }
+            connections
+        });
+
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+wss_url: ws://{}
+path: unused
+name: test
+subscribe: ["sub-a", "sub-b"]
+token_refresh:
+  url: http://{}/listenKey
+  interval_secs: 3600
+  message_template: "renew-{{token}}"
+"#,
+            addr, token_addr
+        ))
+        .unwrap();
+        let writer = Box::new(MemoryWriter { rows: Arc::new(std::sync::Mutex::new(Vec::new())) });
+        let shutdown = CancellationToken::new();
+        let collector = tokio::spawn(run_collector_with_writer(config, writer, shutdown.clone()));
+
+        let connections = tokio::time::timeout(Duration::from_secs(10), server).await.unwrap().unwrap();
+        assert_eq!(
+            connections,
+            [["renew-key1", "sub-a", "sub-b"], ["renew-key2", "sub-a", "sub-b"]]
+        );
+
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_secs(5), collector).await.unwrap().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_excerpt_keeps_multibyte_characters_whole() {
+        // Byte 200 falls inside the three-byte "比"
+        let text = format!("{}比特币", "a".repeat(199));
+        assert_eq!(excerpt(&text), format!("{}比", "a".repeat(199)));
+        assert_eq!(excerpt("short"), "short");
+    }
+}
@@ -0,0 +1,178 @@
+//! Fan-out writer for collecting into several sinks at once
+//!
+//! `CompositeWriter` hands every batch of rows to each of its writers, e.g. parquet for
+//! analytics and JSON for replay. By default the first failing sink aborts the call;
+//! with `continue_on_error` failures are logged and the remaining sinks still get the rows.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tracing::error;
+
+use crate::writer::{DataRow, Writer};
+
+/// Writer that forwards rows and flushes to every inner writer in order
+pub struct CompositeWriter {
+    writers: Vec<Box<dyn Writer>>,
+    continue_on_error: bool,
+}
+
+impl CompositeWriter {
+    pub fn new(writers: Vec<Box<dyn Writer>>) -> Self {
+        Self { writers, continue_on_error: false }
+    }
+
+    /// Keep writing to the other sinks when one fails; the call only fails if every sink did
+    pub fn with_continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// Number of inner writers
+    pub fn len(&self) -> usize {
+        self.writers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writers.is_empty()
+    }
+
+    /// Log a sink failure and decide whether the call continues with the next sink
+    fn handle_error(&self, index: usize, action: &str, e: anyhow::Error) -> Result<()> {
+        error!("Sink {} failed to {}: {:#}", index, action, e);
+        if self.continue_on_error {
+            Ok(())
+        } else {
+            Err(e).context(format!("Sink {} failed to {}", index, action))
+        }
+    }
+
+    fn all_failed(&self, failed: usize, action: &str) -> Result<()> {
+        if failed > 0 && failed == self.writers.len() {
+            anyhow::bail!("All {} sinks failed to {}", failed, action);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Writer for CompositeWriter {
+    async fn write_rows(&mut self, rows: Vec<DataRow>) -> Result<()> {
+        let mut failed = 0;
+        let last = self.writers.len().saturating_sub(1);
+        let mut rows = Some(rows);
+        for index in 0..self.writers.len() {
+            // The last sink takes the rows, the others get a copy
+            let batch = if index == last {
+                rows.take().unwrap_or_default()
+            } else {
+                rows.clone().unwrap_or_default()
+            };
+            if let Err(e) = self.writers[index].write_rows(batch).await {
+                failed += 1;
+                self.handle_error(index, "write rows", e)?;
+            }
+        }
+        self.all_failed(failed, "write rows")
+    }
+
+    async fn flush_buffer(&mut self) -> Result<()> {
+        let mut failed = 0;
+        for index in 0..self.writers.len() {
+            if let Err(e) = self.writers[index].flush_buffer().await {
+                failed += 1;
+                self.handle_error(index, "flush", e)?;
+            }
+        }
+        self.all_failed(failed, "flush")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    type SharedRows = Arc<Mutex<Vec<DataRow>>>;
+
+    /// Writer recording rows and flushes, optionally failing every call
+    struct MockWriter {
+        rows: SharedRows,
+        flushes: Arc<Mutex<usize>>,
+        fail: bool,
+    }
+
+    impl MockWriter {
+        fn new(fail: bool) -> (Self, SharedRows, Arc<Mutex<usize>>) {
+            let rows = Arc::new(Mutex::new(Vec::new()));
+            let flushes = Arc::new(Mutex::new(0));
+            (Self { rows: rows.clone(), flushes: flushes.clone(), fail }, rows, flushes)
+        }
+    }
+
+    #[async_trait]
+    impl Writer for MockWriter {
+        async fn write_rows(&mut self, rows: Vec<DataRow>) -> Result<()> {
+            if self.fail {
+                anyhow::bail!("disk full");
+            }
+            self.rows.lock().unwrap().extend(rows);
+            Ok(())
+        }
+
+        async fn flush_buffer(&mut self) -> Result<()> {
+            if self.fail {
+                anyhow::bail!("disk full");
+            }
+            *self.flushes.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    fn rows(symbols: &[&str]) -> Vec<DataRow> {
+        symbols
+            .iter()
+            .map(|symbol| [("s".to_string(), Value::from(*symbol))].into_iter().collect())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_every_sink_receives_every_row() {
+        let (first, first_rows, first_flushes) = MockWriter::new(false);
+        let (second, second_rows, second_flushes) = MockWriter::new(false);
+        let mut writer = CompositeWriter::new(vec![Box::new(first), Box::new(second)]);
+
+        writer.write_rows(rows(&["BTCUSDT", "ETHUSDT"])).await.unwrap();
+        writer.write_rows(rows(&["SOLUSDT"])).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+
+        assert_eq!(*first_rows.lock().unwrap(), rows(&["BTCUSDT", "ETHUSDT", "SOLUSDT"]));
+        assert_eq!(*second_rows.lock().unwrap(), rows(&["BTCUSDT", "ETHUSDT", "SOLUSDT"]));
+        assert_eq!((*first_flushes.lock().unwrap(), *second_flushes.lock().unwrap()), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_failing_sink_aborts_or_is_skipped() {
+        // By default the failing first sink stops the call before the second one
+        let (failing, _, _) = MockWriter::new(true);
+        let (healthy, healthy_rows, _) = MockWriter::new(false);
+        let mut writer = CompositeWriter::new(vec![Box::new(failing), Box::new(healthy)]);
+        let error = writer.write_rows(rows(&["BTCUSDT"])).await.unwrap_err();
+        assert_eq!(error.to_string(), "Sink 0 failed to write rows");
+        assert!(healthy_rows.lock().unwrap().is_empty());
+
+        let (failing, _, _) = MockWriter::new(true);
+        let (healthy, healthy_rows, healthy_flushes) = MockWriter::new(false);
+        let mut writer = CompositeWriter::new(vec![Box::new(failing), Box::new(healthy)]).with_continue_on_error(true);
+        writer.write_rows(rows(&["BTCUSDT"])).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+        assert_eq!(*healthy_rows.lock().unwrap(), rows(&["BTCUSDT"]));
+        assert_eq!(*healthy_flushes.lock().unwrap(), 1);
+
+        // Still an error when no sink succeeded
+        let (failing, _, _) = MockWriter::new(true);
+        let mut writer = CompositeWriter::new(vec![Box::new(failing)]).with_continue_on_error(true);
+        let error = writer.write_rows(rows(&["BTCUSDT"])).await.unwrap_err();
+        assert_eq!(error.to_string(), "All 1 sinks failed to write rows");
+    }
+}
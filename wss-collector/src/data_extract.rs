@@ -1,16 +1,274 @@
 //! Data extraction utilities for WebSocket messages
 
 use anyhow::{Context, Result};
-use serde_json::Value;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::OnceLock;
 use crate::writer::DataRow;
 
+/// Encoding of binary WebSocket frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageCodec {
+    /// UTF-8 JSON sent in binary frames
+    #[default]
+    Json,
+    /// MessagePack
+    Msgpack,
+}
+
+/// Control message shapes that carry no data: pongs and subscribe acknowledgements
+pub fn default_ignore_messages() -> Vec<Value> {
+    vec![
+        json!({"type": "pong"}),
+        json!({"event": "pong"}),
+        json!({"result": null}),
+    ]
+}
+
+/// Check whether a message matches an ignore shape
+/// An object shape matches when every key in it is present in the message with an equal value;
+/// any other shape must equal the message exactly
+pub fn matches_shape(message: &Value, shape: &Value) -> bool {
+    match (message, shape) {
+        (Value::Object(message), Value::Object(shape)) => shape
+            .iter()
+            .all(|(key, value)| message.get(key) == Some(value)),
+        _ => message == shape,
+    }
+}
+
 /// Extract data array from WebSocket message
 /// Handles formats like: {"data": [...]} or direct array [...]
+/// Empty/whitespace messages and the default control messages return an empty array
 /// This is a utility function that should be called in main.rs before passing data to writers
 pub fn extract_data_array(message: &str) -> Result<Vec<Value>> {
+    static DEFAULT_IGNORE: OnceLock<Vec<Value>> = OnceLock::new();
+    extract_data_array_ignoring(message, DEFAULT_IGNORE.get_or_init(default_ignore_messages))
+}
+
+/// Same as `extract_data_array` with a custom list of ignored message shapes
+/// Malformed JSON is still an error
+pub fn extract_data_array_ignoring(message: &str, ignore: &[Value]) -> Result<Vec<Value>> {
+    if message.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
     let parsed: Value = serde_json::from_str(message)
         .context("Failed to parse JSON message")?;
 
+    extract_value_ignoring(parsed, ignore)
+}
+
+/// Streaming counterpart of `extract_data_array_ignoring` for large messages
+/// Elements of the data array are parsed one at a time and passed to `on_item`, so a
+/// multi-megabyte snapshot never exists as a single string or `Value` tree; returns the
+/// number of elements passed. Accepts the same message formats as the DOM path, but ignore
+/// shapes are only checked against messages whose data is not an array (control messages),
+/// since array elements are handed out before the rest of the message has been read
+pub fn extract_data_array_streaming<R: Read, F: FnMut(Value)>(reader: R, ignore: &[Value], mut on_item: F) -> Result<usize> {
+    let mut reader = BufReader::new(reader);
+    // Empty/whitespace messages carry no data, as for text messages
+    loop {
+        let buf = reader.fill_buf().context("Failed to read message")?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(pos) => {
+                reader.consume(pos);
+                break;
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+
+    let mut count = 0;
+    let mut counting = |value: Value| {
+        count += 1;
+        on_item(value);
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer
+        .deserialize_any(MessageVisitor { ignore, on_item: &mut counting })
+        .and_then(|()| deserializer.end())
+        .context("Failed to parse JSON message")?;
+    Ok(count)
+}
+
+/// Top level of a streamed message: an array, or an object with or without `data`
+struct MessageVisitor<'a, F> {
+    ignore: &'a [Value],
+    on_item: &'a mut F,
+}
+
+impl<'de, F: FnMut(Value)> Visitor<'de> for MessageVisitor<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON array or object")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> std::result::Result<(), A::Error> {
+        stream_elements(seq, self.on_item)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+        let mut message = Map::new();
+        let mut streamed = false;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "data" {
+                match map.next_value_seed(DataSeed { on_item: &mut *self.on_item, nested: true })? {
+                    DataField::Streamed => streamed = true,
+                    DataField::Value(value) => {
+                        message.insert(key, value);
+                    }
+                }
+            } else {
+                message.insert(key, map.next_value()?);
+            }
+        }
+        if streamed {
+            return Ok(());
+        }
+
+        // Same rules as `extract_value_ignoring` for a message without a data array
+        let message = Value::Object(message);
+        if self.ignore.iter().any(|shape| matches_shape(&message, shape)) {
+            return Ok(());
+        }
+        if let Value::Object(mut obj) = message {
+            match obj.remove("data") {
+                Some(data) => (self.on_item)(data),
+                None => (self.on_item)(Value::Object(obj)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of reading a `data` field: its array elements were streamed, or it was buffered
+enum DataField {
+    Streamed,
+    Value(Value),
+}
+
+/// Reads a `data` field, streaming it if it is an array
+/// With `nested`, an object's own `data` array (`{"data": {"data": [...]}}`) is streamed too
+struct DataSeed<'a, F> {
+    on_item: &'a mut F,
+    nested: bool,
+}
+
+impl<'de, F: FnMut(Value)> DeserializeSeed<'de> for DataSeed<'_, F> {
+    type Value = DataField;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<DataField, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, F: FnMut(Value)> Visitor<'de> for DataSeed<'_, F> {
+    type Value = DataField;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> std::result::Result<DataField, A::Error> {
+        stream_elements(seq, self.on_item)?;
+        Ok(DataField::Streamed)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<DataField, A::Error> {
+        let mut obj = Map::new();
+        let mut streamed = false;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "data" && self.nested {
+                match map.next_value_seed(DataSeed { on_item: &mut *self.on_item, nested: false })? {
+                    DataField::Streamed => streamed = true,
+                    DataField::Value(value) => {
+                        obj.insert(key, value);
+                    }
+                }
+            } else {
+                obj.insert(key, map.next_value()?);
+            }
+        }
+        // An object without a nested data array is kept whole, as a single record
+        Ok(if streamed { DataField::Streamed } else { DataField::Value(Value::Object(obj)) })
+    }
+
+    fn visit_bool<E: serde::de::Error>(self, v: bool) -> std::result::Result<DataField, E> {
+        Ok(DataField::Value(Value::Bool(v)))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> std::result::Result<DataField, E> {
+        Ok(DataField::Value(Value::from(v)))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> std::result::Result<DataField, E> {
+        Ok(DataField::Value(Value::from(v)))
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> std::result::Result<DataField, E> {
+        Ok(DataField::Value(Value::from(v)))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<DataField, E> {
+        Ok(DataField::Value(Value::String(v.to_string())))
+    }
+
+    fn visit_string<E: serde::de::Error>(self, v: String) -> std::result::Result<DataField, E> {
+        Ok(DataField::Value(Value::String(v)))
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> std::result::Result<DataField, E> {
+        Ok(DataField::Value(Value::Null))
+    }
+}
+
+/// Pass each element of an array to `on_item` as soon as it is parsed
+fn stream_elements<'de, A: SeqAccess<'de>, F: FnMut(Value)>(mut seq: A, on_item: &mut F) -> std::result::Result<(), A::Error> {
+    while let Some(value) = seq.next_element::<Value>()? {
+        on_item(value);
+    }
+    Ok(())
+}
+
+/// Decode a binary frame with the configured codec and extract its data array
+/// JSON frames must be valid UTF-8; MessagePack frames are decoded into JSON values
+/// and then go through the same extraction as text messages
+pub fn extract_binary_data_array(data: &[u8], codec: MessageCodec, ignore: &[Value]) -> Result<Vec<Value>> {
+    match codec {
+        MessageCodec::Json => {
+            let text = std::str::from_utf8(data)
+                .context("Binary message is not valid UTF-8")?;
+            extract_data_array_ignoring(text, ignore)
+        }
+        MessageCodec::Msgpack => {
+            if data.is_empty() {
+                return Ok(Vec::new());
+            }
+            let parsed: Value = rmp_serde::from_slice(data)
+                .context("Failed to decode MessagePack message")?;
+            extract_value_ignoring(parsed, ignore)
+        }
+    }
+}
+
+/// Extract the data array from an already decoded message
+fn extract_value_ignoring(parsed: Value, ignore: &[Value]) -> Result<Vec<Value>> {
+    if ignore.iter().any(|shape| matches_shape(&parsed, shape)) {
+        return Ok(Vec::new());
+    }
+
     match parsed {
         // If it's already an array
         Value::Array(arr) => Ok(arr),
@@ -55,3 +313,84 @@ pub fn convert_to_rows(data: Vec<Value>) -> Vec<DataRow> {
         .collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_message_is_no_data() {
+        assert!(extract_data_array("").unwrap().is_empty());
+        assert!(extract_data_array("  \r\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_control_messages_are_no_data() {
+        assert!(extract_data_array(r#"{"type":"pong"}"#).unwrap().is_empty());
+        assert!(extract_data_array(r#"{"result":null,"id":1}"#).unwrap().is_empty());
+
+        // Custom shapes replace the defaults
+        let ignore = vec![json!({"op": "heartbeat"})];
+        assert!(extract_data_array_ignoring(r#"{"op":"heartbeat","ts":1}"#, &ignore).unwrap().is_empty());
+        assert_eq!(extract_data_array_ignoring(r#"{"type":"pong"}"#, &ignore).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_data_and_broken_json() {
+        assert_eq!(extract_data_array(r#"{"data":[{"s":"BTCUSDT"},{"s":"ETHUSDT"}]}"#).unwrap().len(), 2);
+        assert!(extract_data_array(r#"{"data":[{"s":"BTC"#).is_err());
+    }
+
+    #[test]
+    fn test_streaming_matches_dom_extraction() {
+        let streamed = |message: &str| -> Result<Vec<Value>> {
+            let mut items = Vec::new();
+            let count = extract_data_array_streaming(message.as_bytes(), &default_ignore_messages(), |value| items.push(value))?;
+            assert_eq!(count, items.len());
+            Ok(items)
+        };
+
+        // A megabyte-scale snapshot yields the same rows as the DOM path
+        let rows: Vec<Value> = (0..20_000)
+            .map(|i| json!({"s": format!("SYM{}USDT", i), "p": format!("{}.5", i), "T": 1700000000000u64 + i, "q": [i, null]}))
+            .collect();
+        let snapshot = json!({"stream": "!markPrice@arr", "data": rows}).to_string();
+        assert!(snapshot.len() > 1 << 20);
+        assert_eq!(streamed(&snapshot).unwrap(), extract_data_array(&snapshot).unwrap());
+
+        for message in [
+            r#"[{"s":"BTCUSDT"},{"s":"ETHUSDT"}]"#,
+            r#"{"data":{"data":[{"s":"BTCUSDT"}],"ts":1}}"#,
+            r#"{"data":{"s":"BTCUSDT","data":{"x":1}}}"#,
+            r#"{"data":"scalar","id":1}"#,
+            r#"{"s":"BTCUSDT","p":"1.5"}"#,
+            r#"{"type":"pong"}"#,
+            " \r\n",
+        ] {
+            assert_eq!(streamed(message).unwrap(), extract_data_array(message).unwrap(), "{}", message);
+        }
+
+        assert!(streamed(r#"{"data":[{"s":"BTC"#).is_err());
+        assert!(streamed(r#"[{"s":"BTCUSDT"}] trailing"#).is_err());
+        assert!(streamed("42").is_err());
+    }
+
+    #[test]
+    fn test_msgpack_binary_frame_to_rows() {
+        let message = json!({"data": [
+            {"s": "BTCUSDT", "p": "67000.5", "T": 1700000000000u64},
+            {"s": "ETHUSDT", "p": "3500.1", "T": 1700000000001u64},
+        ]});
+        let encoded = rmp_serde::to_vec_named(&message).unwrap();
+
+        let data = extract_binary_data_array(&encoded, MessageCodec::Msgpack, &default_ignore_messages()).unwrap();
+        let rows = convert_to_rows(data);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["s"], json!("BTCUSDT"));
+        assert_eq!(rows[1]["T"], json!(1700000000001u64));
+
+        // Garbage is an error rather than a lossy conversion
+        assert!(extract_binary_data_array(&[0xc1], MessageCodec::Msgpack, &[]).is_err());
+        assert!(extract_binary_data_array(&[b'[', 0xff, b']'], MessageCodec::Json, &[]).is_err());
+        assert_eq!(extract_binary_data_array(br#"[{"s":"BTCUSDT"}]"#, MessageCodec::Json, &[]).unwrap().len(), 1);
+    }
+}
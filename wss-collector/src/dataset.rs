@@ -0,0 +1,191 @@
+//! Read all parquet files of a day under one schema
+//!
+//! Schema widening rotates to a new file whenever new fields appear, so a day can hold
+//! files with different column sets. `ParquetDataset` computes the union of their schemas
+//! and yields every batch with the columns a file lacks filled with nulls, so downstream
+//! readers see a single consistent schema.
+
+use anyhow::{Context, Result};
+use arrow::array::{new_null_array, ArrayRef, RecordBatch};
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use chrono::NaiveDate;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::parquet_writer::date_dir;
+
+/// The parquet files written for one date, read under their union schema
+pub struct ParquetDataset {
+    files: Vec<PathBuf>,
+    schema: SchemaRef,
+}
+
+impl ParquetDataset {
+    /// Open the files in `<dir>/<year>/<month>/<day>` (the collector's layout for `path`)
+    /// Files are read in name order, which is write order for the collector's file names
+    /// Fails if two files disagree on the type of a column, naming the column and files
+    pub fn open(dir: &Path, date: NaiveDate) -> Result<Self> {
+        let day_dir = date_dir(dir, date);
+        let mut files: Vec<PathBuf> = fs::read_dir(&day_dir)
+            .context(format!("Failed to read directory: {:?}", day_dir))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("parquet"))
+            .collect();
+        files.sort();
+
+        // Columns in order of first appearance; the first file to have a column fixes its type
+        let mut fields: Vec<(Field, PathBuf)> = Vec::new();
+        for path in &files {
+            for field in Self::file_schema(path)?.fields() {
+                match fields.iter().find(|(existing, _)| existing.name() == field.name()) {
+                    Some((existing, first_path)) if existing.data_type() != field.data_type() => {
+                        anyhow::bail!(
+                            "Column {} is {:?} in {:?} but {:?} in {:?}",
+                            field.name(),
+                            existing.data_type(),
+                            first_path,
+                            field.data_type(),
+                            path
+                        );
+                    }
+                    Some(_) => {}
+                    None => fields.push((field.as_ref().clone().with_nullable(true), path.clone())),
+                }
+            }
+        }
+
+        let schema = Arc::new(Schema::new(fields.into_iter().map(|(field, _)| field).collect::<Vec<_>>()));
+        Ok(Self { files, schema })
+    }
+
+    /// Union schema of all files
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Files in read order
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Batches of every file in order, conformed to the union schema
+    pub fn batches(&self) -> impl Iterator<Item = Result<RecordBatch>> + '_ {
+        self.files.iter().flat_map(move |path| self.file_batches(path))
+    }
+
+    fn file_batches<'a>(&'a self, path: &'a Path) -> Box<dyn Iterator<Item = Result<RecordBatch>> + 'a> {
+        let reader = File::open(path)
+            .context(format!("Failed to open parquet file: {:?}", path))
+            .and_then(|file| {
+                ParquetRecordBatchReaderBuilder::try_new(file)
+                    .and_then(|builder| builder.build())
+                    .context(format!("Failed to read parquet file: {:?}", path))
+            });
+        match reader {
+            Ok(reader) => Box::new(reader.map(move |batch| {
+                let batch = batch.context(format!("Failed to read batch from {:?}", path))?;
+                self.conform(&batch)
+            })),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+
+    /// Reorder a batch's columns to the union schema, adding null columns it lacks
+    fn conform(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| {
+                batch
+                    .column_by_name(field.name())
+                    .cloned()
+                    .unwrap_or_else(|| new_null_array(field.data_type(), batch.num_rows()))
+            })
+            .collect();
+        RecordBatch::try_new(self.schema.clone(), columns).context("Failed to conform batch to dataset schema")
+    }
+
+    fn file_schema(path: &Path) -> Result<SchemaRef> {
+        let file = File::open(path).context(format!("Failed to open parquet file: {:?}", path))?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+            .context(format!("Failed to read parquet metadata: {:?}", path))?;
+        Ok(builder.schema().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parquet_writer::{ParquetWriter, ParquetWriterConfig, DEFAULT_ROW_GROUP_SIZE};
+    use crate::writer::{DataRow, Writer};
+    use crate::Filter;
+    use arrow::array::{Array, StringArray};
+    use arrow::datatypes::DataType;
+    use parquet::arrow::ArrowWriter;
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_union_schema_across_widened_files() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-dataset-{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut writer = ParquetWriter::new(ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 100,
+            file_target_rows: None,
+            filter: Filter::default(),
+            date: Some(date),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        });
+
+        // The second row adds a field, so the writer rotates to a wider file
+        let first: DataRow = [("s".to_string(), Value::from("BTCUSDT"))].into_iter().collect();
+        writer.write_rows(vec![first]).await.unwrap();
+        let second: DataRow = [
+            ("s".to_string(), Value::from("ETHUSDT")),
+            ("r".to_string(), Value::from("funding")),
+        ]
+        .into_iter()
+        .collect();
+        writer.write_rows(vec![second]).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+
+        let dataset = ParquetDataset::open(&dir, date).unwrap();
+        assert_eq!(dataset.files().len(), 2);
+        let schema = dataset.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["s", "r"]);
+
+        let batches: Vec<RecordBatch> = dataset.batches().collect::<Result<_>>().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert!(batches.iter().all(|b| b.schema() == schema));
+        let r = |batch: &RecordBatch| batch.column(1).as_any().downcast_ref::<StringArray>().unwrap().clone();
+        assert!(r(&batches[0]).is_null(0));
+        assert_eq!(r(&batches[1]).value(0), "funding");
+
+        // A file with a different type for an existing column is rejected, naming the column
+        let conflict_schema = Arc::new(Schema::new(vec![Field::new("s", DataType::Int64, true)]));
+        let batch = RecordBatch::try_new(
+            conflict_schema.clone(),
+            vec![Arc::new(arrow::array::Int64Array::from(vec![1]))],
+        )
+        .unwrap();
+        let file = File::create(date_dir(&dir, date).join("zz_conflict.parquet")).unwrap();
+        let mut conflict = ArrowWriter::try_new(file, conflict_schema, None).unwrap();
+        conflict.write(&batch).unwrap();
+        conflict.close().unwrap();
+
+        let error = ParquetDataset::open(&dir, date).err().unwrap().to_string();
+        assert!(error.starts_with("Column s is Utf8"), "{}", error);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
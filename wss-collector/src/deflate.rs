@@ -0,0 +1,417 @@
+//! permessage-deflate (RFC 7692) for incoming WebSocket messages
+//!
+//! tungstenite 0.24 has no WebSocket extension support and rejects frames with the RSV1
+//! bit set. `DeflateStream` sits between the transport (TCP, TLS or Unix socket) and
+//! tungstenite: it reads the handshake response to see whether the server accepted
+//! `permessage-deflate`, and if so inflates every compressed message into plain frames
+//! before tungstenite parses them. Outgoing messages are never compressed, which the
+//! extension allows, so writes pass through unchanged.
+
+use flate2::{Decompress, FlushDecompress, Status};
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+/// `Sec-WebSocket-Extensions` request header value offering compression
+pub const PERMESSAGE_DEFLATE: &str = "permessage-deflate";
+
+/// Trailer the sender strips from every compressed message (RFC 7692 section 7.2.1)
+const DEFLATE_TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Bytes read from the transport at a time
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Handshake responses longer than this are passed through without looking for the extension
+const MAX_HANDSHAKE_RESPONSE: usize = 64 * 1024;
+
+/// Limits used when the `WebSocketConfig` leaves them unset (the tungstenite defaults)
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 << 20;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16 << 20;
+
+/// Whether a handshake response accepted permessage-deflate
+///
+/// Returns `Some(server_no_context_takeover)` when the extension was accepted.
+pub fn accepted_permessage_deflate<'a>(extension_headers: impl IntoIterator<Item = &'a str>) -> Option<bool> {
+    extension_headers
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .find_map(|extension| {
+            let mut params = extension.split(';').map(str::trim);
+            if !params.next()?.eq_ignore_ascii_case(PERMESSAGE_DEFLATE) {
+                return None;
+            }
+            Some(params.any(|param| param.eq_ignore_ascii_case("server_no_context_takeover")))
+        })
+}
+
+/// Transport wrapper that inflates permessage-deflate messages for tungstenite
+///
+/// Without an accepted extension in the handshake response every byte passes through.
+pub struct DeflateStream<S> {
+    inner: S,
+    /// Still reading the HTTP handshake response
+    in_handshake: bool,
+    /// Set once the server accepted permessage-deflate
+    inflater: Option<Inflater>,
+    max_message_size: usize,
+    max_frame_size: usize,
+    /// Bytes read from `inner` and not processed yet
+    input: Vec<u8>,
+    /// Processed bytes waiting to be read by tungstenite
+    output: Vec<u8>,
+    output_pos: usize,
+    /// Payload bytes of the current uncompressed frame still to pass through
+    passthrough: u64,
+    eof: bool,
+}
+
+struct Inflater {
+    decompress: Decompress,
+    /// The server resets its compression context after every message
+    no_context_takeover: bool,
+    /// Opcode and compressed payload of the message being reassembled
+    message: Option<(u8, Vec<u8>)>,
+}
+
+/// Header of a WebSocket frame
+struct FrameHeader {
+    fin: bool,
+    rsv1: bool,
+    opcode: u8,
+    masked: bool,
+    header_len: usize,
+    payload_len: u64,
+}
+
+impl<S> DeflateStream<S> {
+    /// Wrap `inner`, inflating messages up to the limits of `config`
+    pub fn new(inner: S, config: &WebSocketConfig) -> Self {
+        Self {
+            inner,
+            in_handshake: true,
+            inflater: None,
+            max_message_size: config.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE),
+            max_frame_size: config.max_frame_size.unwrap_or(DEFAULT_MAX_FRAME_SIZE).max(1),
+            input: Vec::new(),
+            output: Vec::new(),
+            output_pos: 0,
+            passthrough: 0,
+            eof: false,
+        }
+    }
+
+    /// Move processed bytes from `input` to `output`
+    fn process(&mut self) -> io::Result<()> {
+        if self.in_handshake {
+            match find(&self.input, b"\r\n\r\n") {
+                Some(end) => {
+                    let head: Vec<u8> = self.input.drain(..end + 4).collect();
+                    let head_str = String::from_utf8_lossy(&head);
+                    let extensions = head_str.lines().filter_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.trim().eq_ignore_ascii_case("sec-websocket-extensions").then_some(value)
+                    });
+                    self.inflater = accepted_permessage_deflate(extensions).map(|no_context_takeover| Inflater {
+                        decompress: Decompress::new(false),
+                        no_context_takeover,
+                        message: None,
+                    });
+                    self.output.extend_from_slice(&head);
+                    self.in_handshake = false;
+                }
+                None if self.input.len() > MAX_HANDSHAKE_RESPONSE => self.in_handshake = false,
+                None => return Ok(()),
+            }
+        }
+
+        let Some(inflater) = &mut self.inflater else {
+            self.output.append(&mut self.input);
+            return Ok(());
+        };
+
+        let mut consumed = 0;
+        loop {
+            if self.passthrough > 0 {
+                let n = (self.input.len() - consumed).min(self.passthrough as usize);
+                self.output.extend_from_slice(&self.input[consumed..consumed + n]);
+                consumed += n;
+                self.passthrough -= n as u64;
+                if self.passthrough > 0 {
+                    break;
+                }
+                continue;
+            }
+
+            let Some(header) = parse_frame_header(&self.input[consumed..]) else { break };
+            // Control frames may arrive between the fragments of a compressed message;
+            // masked frames are invalid from a server and left for tungstenite to reject
+            let is_control = header.opcode & 0x08 != 0;
+            let compressed = !is_control
+                && !header.masked
+                && if header.opcode == 0 { inflater.message.is_some() } else { header.rsv1 };
+            if !compressed {
+                self.output.extend_from_slice(&self.input[consumed..consumed + header.header_len]);
+                consumed += header.header_len;
+                self.passthrough = header.payload_len;
+                continue;
+            }
+
+            let buffered = inflater.message.as_ref().map_or(0, |(_, payload)| payload.len() as u64);
+            if buffered + header.payload_len > self.max_message_size as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("compressed message exceeds max_message_size ({} bytes)", self.max_message_size),
+                ));
+            }
+            let frame_len = header.header_len + header.payload_len as usize;
+            if self.input.len() - consumed < frame_len {
+                break;
+            }
+            let payload = &self.input[consumed + header.header_len..consumed + frame_len];
+            match &mut inflater.message {
+                Some((_, message)) => message.extend_from_slice(payload),
+                None => inflater.message = Some((header.opcode, payload.to_vec())),
+            }
+            consumed += frame_len;
+
+            if header.fin {
+                let (opcode, message) = inflater.message.take().expect("message started above");
+                let inflated = inflater.inflate(message, self.max_message_size)?;
+                write_frames(&mut self.output, opcode, &inflated, self.max_frame_size);
+            }
+        }
+        self.input.drain(..consumed);
+        Ok(())
+    }
+}
+
+impl Inflater {
+    /// Inflate one complete message, at most `limit` bytes
+    fn inflate(&mut self, mut message: Vec<u8>, limit: usize) -> io::Result<Vec<u8>> {
+        message.extend_from_slice(&DEFLATE_TRAILER);
+        let start = self.decompress.total_in();
+        let mut inflated = Vec::with_capacity(message.len() * 4);
+        loop {
+            inflated.reserve(READ_CHUNK);
+            let offset = (self.decompress.total_in() - start) as usize;
+            let produced = inflated.len();
+            let status = self
+                .decompress
+                .decompress_vec(&message[offset..], &mut inflated, FlushDecompress::Sync)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if inflated.len() > limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("inflated message exceeds max_message_size ({} bytes)", limit),
+                ));
+            }
+            let consumed_all = (self.decompress.total_in() - start) as usize == message.len();
+            if status == Status::StreamEnd || (consumed_all && inflated.len() < inflated.capacity()) {
+                break;
+            }
+            if (self.decompress.total_in() - start) as usize == offset && inflated.len() == produced {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated compressed message"));
+            }
+        }
+        if self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Ok(inflated)
+    }
+}
+
+/// Parse a frame header, `None` until enough bytes have arrived
+fn parse_frame_header(data: &[u8]) -> Option<FrameHeader> {
+    if data.len() < 2 {
+        return None;
+    }
+    let (header_len, payload_len) = match data[1] & 0x7f {
+        126 => (4, u16::from_be_bytes(data.get(2..4)?.try_into().ok()?) as u64),
+        127 => (10, u64::from_be_bytes(data.get(2..10)?.try_into().ok()?)),
+        len => (2, len as u64),
+    };
+    let masked = data[1] & 0x80 != 0;
+    let header_len = if masked { header_len + 4 } else { header_len };
+    if data.len() < header_len {
+        return None;
+    }
+    Some(FrameHeader {
+        fin: data[0] & 0x80 != 0,
+        rsv1: data[0] & 0x40 != 0,
+        opcode: data[0] & 0x0f,
+        masked,
+        header_len,
+        payload_len,
+    })
+}
+
+/// Append `payload` as unmasked, uncompressed frames of at most `max_frame_size` bytes
+fn write_frames(output: &mut Vec<u8>, opcode: u8, payload: &[u8], max_frame_size: usize) {
+    let mut chunks = payload.chunks(max_frame_size).peekable();
+    let mut first = true;
+    // An empty message is still one frame
+    if chunks.peek().is_none() {
+        output.extend_from_slice(&[0x80 | opcode, 0]);
+        return;
+    }
+    while let Some(chunk) = chunks.next() {
+        let fin = if chunks.peek().is_none() { 0x80 } else { 0 };
+        output.push(fin | if first { opcode } else { 0 });
+        first = false;
+        match chunk.len() {
+            len if len < 126 => output.push(len as u8),
+            len if len <= u16::MAX as usize => {
+                output.push(126);
+                output.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                output.push(127);
+                output.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        output.extend_from_slice(chunk);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if this.output_pos < this.output.len() {
+                let n = buf.remaining().min(this.output.len() - this.output_pos);
+                buf.put_slice(&this.output[this.output_pos..this.output_pos + n]);
+                this.output_pos += n;
+                if this.output_pos == this.output.len() {
+                    this.output.clear();
+                    this.output_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                // Hand over a partial frame so tungstenite reports the truncated stream
+                this.eof = true;
+                this.output.append(&mut this.input);
+            } else {
+                this.input.extend_from_slice(chunk_buf.filled());
+                this.process()?;
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compress, Compression, FlushCompress};
+    use tokio::io::AsyncReadExt;
+
+    /// Compress one message the way a permessage-deflate sender does
+    fn deflate(compress: &mut Compress, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 64);
+        compress.compress_vec(data, &mut out, FlushCompress::Sync).unwrap();
+        assert!(out.ends_with(&DEFLATE_TRAILER));
+        out.truncate(out.len() - DEFLATE_TRAILER.len());
+        out
+    }
+
+    fn frame(first_byte: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![first_byte];
+        assert!(payload.len() < 126);
+        out.push(payload.len() as u8);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    const RESPONSE: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nSec-WebSocket-Extensions: permessage-deflate\r\n\r\n";
+
+    async fn read_all(input: Vec<u8>, config: &WebSocketConfig) -> io::Result<Vec<u8>> {
+        let mut stream = DeflateStream::new(&input[..], config);
+        let mut output = Vec::new();
+        stream.read_to_end(&mut output).await?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_accepted_permessage_deflate() {
+        assert_eq!(accepted_permessage_deflate(["permessage-deflate"]), Some(false));
+        assert_eq!(
+            accepted_permessage_deflate(["x-webkit-deflate-frame", " permessage-deflate; server_no_context_takeover"]),
+            Some(true)
+        );
+        assert_eq!(accepted_permessage_deflate(["x-webkit-deflate-frame"]), None);
+        assert_eq!(accepted_permessage_deflate([]), None);
+    }
+
+    #[tokio::test]
+    async fn test_inflates_fragmented_messages_with_context_takeover() {
+        let mut compress = Compress::new(Compression::default(), false);
+        let first = deflate(&mut compress, br#"{"data":[{"s":"BTCUSDT"}]}"#);
+        // Refers back to the first message through the shared window
+        let second = deflate(&mut compress, br#"{"data":[{"s":"BTCUSDT"}]}"#);
+
+        let mut input = RESPONSE.to_vec();
+        input.extend(frame(0x80 | 0x40 | 0x1, &first));
+        // Second message split in two fragments with a ping in between
+        let (head, tail) = second.split_at(second.len() / 2);
+        input.extend(frame(0x40 | 0x1, head));
+        input.extend(frame(0x80 | 0x9, b"hi"));
+        input.extend(frame(0x00, tail));
+        input.extend(frame(0x80, b""));
+        // Uncompressed messages pass through
+        input.extend(frame(0x80 | 0x1, b"plain"));
+
+        let output = read_all(input, &WebSocketConfig::default()).await.unwrap();
+        let mut expected = RESPONSE.to_vec();
+        expected.extend(frame(0x80 | 0x1, br#"{"data":[{"s":"BTCUSDT"}]}"#));
+        expected.extend(frame(0x80 | 0x9, b"hi"));
+        expected.extend(frame(0x80 | 0x1, br#"{"data":[{"s":"BTCUSDT"}]}"#));
+        expected.extend(frame(0x80 | 0x1, b"plain"));
+        assert_eq!(output, expected);
+    }
+
+    #[tokio::test]
+    async fn test_passes_through_without_extension() {
+        let mut input = b"HTTP/1.1 101 Switching Protocols\r\n\r\n".to_vec();
+        input.extend(frame(0x80 | 0x1, b"plain"));
+        assert_eq!(read_all(input.clone(), &WebSocketConfig::default()).await.unwrap(), input);
+    }
+
+    #[tokio::test]
+    async fn test_inflated_size_is_limited() {
+        let mut compress = Compress::new(Compression::default(), false);
+        let payload = deflate(&mut compress, &[b'a'; 2000]);
+        let mut input = RESPONSE.to_vec();
+        input.extend(frame(0x80 | 0x40 | 0x1, &payload));
+
+        let config = WebSocketConfig { max_message_size: Some(1024), ..Default::default() };
+        let error = read_all(input, &config).await.unwrap_err();
+        assert!(error.to_string().contains("max_message_size"), "{}", error);
+    }
+}
@@ -0,0 +1,80 @@
+//! Error module
+//!
+//! This module defines the error type returned at the public API boundary
+//! (connecting and running the collector), so callers can match on specific
+//! failure kinds such as a proxy being down. Internals keep using anyhow.
+
+use thiserror::Error;
+
+/// Errors returned by the public connect and collector functions
+#[derive(Debug, Error)]
+pub enum CollectorError {
+    /// The HTTP proxy could not tunnel to the WebSocket host
+    #[error("proxy {proxy} is unavailable: {source:#}")]
+    ProxyUnavailable {
+        proxy: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// The WebSocket connection or handshake failed
+    #[error("failed to connect to {url}: {source:#}")]
+    Connect {
+        url: String,
+        #[source]
+        source: anyhow::Error,
+    },
+
+    /// The collector gave up after `max_reconnect_attempts` consecutive failed attempts
+    /// `last_error` is the failure of the final attempt
+    #[error("giving up on {url} after {attempts} reconnect attempts")]
+    ReconnectExhausted {
+        url: String,
+        attempts: u32,
+        #[source]
+        last_error: Option<Box<CollectorError>>,
+    },
+
+    /// Any other failure
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Result type for the public API
+pub type CollectorResult<T> = std::result::Result<T, CollectorError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wss_stream::{connect_wss_stream, WsConfig};
+
+    #[tokio::test]
+    async fn test_proxy_failure_is_proxy_unavailable() {
+        // Nothing listens on port 1, so the proxy connection is refused
+        let err = connect_wss_stream("ws://127.0.0.1:9", Some("http://127.0.0.1:1"), &WsConfig::default())
+            .await
+            .err()
+            .unwrap();
+        match err {
+            CollectorError::ProxyUnavailable { proxy, .. } => assert_eq!(proxy, "http://127.0.0.1:1"),
+            other => panic!("expected ProxyUnavailable, got {:?}", other),
+        }
+
+        let err = connect_wss_stream("ws://127.0.0.1:1", None, &WsConfig::default()).await.err().unwrap();
+        assert!(matches!(err, CollectorError::Connect { ref url, .. } if url == "ws://127.0.0.1:1"), "{:?}", err);
+    }
+
+    #[test]
+    fn test_converts_to_and_from_anyhow() {
+        let err: CollectorError = anyhow::anyhow!("boom").into();
+        assert!(matches!(err, CollectorError::Other(_)));
+
+        let err: anyhow::Error = CollectorError::ReconnectExhausted {
+            url: "wss://example.com".to_string(),
+            attempts: 3,
+            last_error: None,
+        }
+        .into();
+        assert!(matches!(err.downcast_ref::<CollectorError>(), Some(CollectorError::ReconnectExhausted { attempts: 3, .. })));
+    }
+}
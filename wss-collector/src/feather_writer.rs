@@ -0,0 +1,243 @@
+//! Arrow IPC (Feather v2) file writing module
+//!
+//! An alternative to Parquet for consumers that memory-map files directly (pyarrow,
+//! polars). Schema inference, schema widening and value coercion are shared with
+//! `ParquetWriter`, so both formats produce the same columns for the same rows; only the
+//! serialization differs. Each flush writes one `.arrow` file under the same
+//! `<path>/<year>/<month>/<day>` layout.
+
+use anyhow::{Context, Result};
+use arrow::datatypes::Schema;
+use arrow::ipc::writer::FileWriter;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::parquet_writer::{
+    create_output_file, date_dir, find_new_columns, is_degenerate_row, rows_to_batch, ColumnType, Filter, ParquetWriter,
+};
+use crate::writer::{DataRow, Writer};
+
+/// Configuration for FeatherWriter
+#[derive(Debug, Clone)]
+pub struct FeatherWriterConfig {
+    /// Base path for storing arrow files
+    pub path: String,
+    /// Name prefix for arrow files
+    pub name: String,
+    /// Batch size - number of records to buffer before writing to file
+    pub batch_size: usize,
+    /// Optional row filter - if empty, all data is written
+    pub filter: Filter,
+    /// Optional date to write data to - if None, uses current date
+    pub date: Option<NaiveDate>,
+}
+
+/// Writer that flushes buffered rows to Arrow IPC files
+pub struct FeatherWriter {
+    config: FeatherWriterConfig,
+    current_date: Option<NaiveDate>,
+    schema: Option<Arc<Schema>>,
+    column_types: HashMap<String, ColumnType>,
+    buffer: Vec<DataRow>,
+    file_sequence: u64, // Sequence number for unique file naming
+}
+
+impl FeatherWriter {
+    pub fn new(config: FeatherWriterConfig) -> Self {
+        Self {
+            config,
+            current_date: None,
+            schema: None,
+            column_types: HashMap::new(),
+            buffer: Vec::new(),
+            file_sequence: 0,
+        }
+    }
+
+    /// Generate unique arrow file path with timestamp and sequence
+    pub fn get_unique_arrow_path(&mut self, date: NaiveDate) -> PathBuf {
+        let dir = date_dir(Path::new(&self.config.path), date);
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        self.file_sequence += 1;
+        let filename = format!(
+            "{}_{}_{}_{:06}.arrow",
+            self.config.name,
+            date.format("%Y-%m-%d"),
+            timestamp,
+            self.file_sequence
+        );
+        dir.join(filename)
+    }
+
+    async fn write_rows_impl(&mut self, rows: Vec<DataRow>) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let today = self.config.date.unwrap_or_else(|| chrono::Utc::now().date_naive());
+
+        // Rows without any value cannot be inferred from; hold them until one arrives
+        if self.schema.is_none() && rows.iter().all(is_degenerate_row) {
+            self.buffer.extend(rows.into_iter().filter(|row| self.config.filter.matches(row)));
+            if !self.buffer.is_empty() {
+                self.current_date = Some(today);
+            }
+            return Ok(());
+        }
+
+        if self.schema.is_none() {
+            let data_array: Vec<Value> = rows.iter()
+                .map(|row| Value::Object(row.clone().into_iter().collect()))
+                .collect();
+            let (schema, column_types) = ParquetWriter::infer_schema_from_data(&data_array)
+                .context("Failed to infer schema")?;
+            info!("Inferred schema with {} columns", schema.fields().len());
+            self.schema = Some(schema);
+            self.column_types = column_types;
+        }
+
+        if self.current_date.is_some() && self.current_date != Some(today) {
+            self.write_buffer()?;
+        }
+
+        let filtered_rows: Vec<DataRow> = rows.into_iter()
+            .filter(|row| self.config.filter.matches(row))
+            .collect();
+
+        // Widen the schema when new fields appear; buffered rows go to a file with the old schema
+        let new_columns = find_new_columns(&self.column_types, &filtered_rows);
+        if !new_columns.is_empty() {
+            self.write_buffer()?;
+
+            let mut added: Vec<&String> = new_columns.keys().collect();
+            added.sort();
+            info!("New fields {:?} detected, subsequent rows go to a new file", added);
+
+            self.column_types.extend(new_columns);
+            self.schema = Some(ParquetWriter::schema_from_column_types(&self.column_types));
+        }
+
+        if !filtered_rows.is_empty() {
+            self.buffer.extend(filtered_rows);
+            self.current_date = Some(today);
+        }
+
+        if self.buffer.len() >= self.config.batch_size {
+            self.write_buffer()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the buffer to a new arrow file
+    fn write_buffer(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let Some(schema) = self.schema.clone() else {
+            warn!("Schema not initialized, cannot flush buffer");
+            return Ok(());
+        };
+
+        let date = self.current_date.context("No current date set")?;
+        let batch = rows_to_batch(&schema, &self.column_types, &self.buffer)?;
+        let path = self.get_unique_arrow_path(date);
+
+        let file = create_output_file(&path)?;
+        let mut writer = FileWriter::try_new(file, &schema)
+            .context(format!("Failed to create arrow file writer: {:?}", path))?;
+        writer.write(&batch).context("Failed to write batch to arrow file")?;
+        writer.finish().context(format!("Failed to finish arrow file: {:?}", path))?;
+
+        info!("Wrote {} records to {:?}", batch.num_rows(), path);
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Writer for FeatherWriter {
+    async fn write_rows(&mut self, rows: Vec<DataRow>) -> Result<()> {
+        self.write_rows_impl(rows).await
+    }
+
+    async fn flush_buffer(&mut self) -> Result<()> {
+        self.write_buffer()
+    }
+}
+
+impl Drop for FeatherWriter {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            warn!("Buffer not empty on drop, {} records will be lost", self.buffer.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Float64Array, RecordBatch, StringArray, UInt64Array};
+    use arrow::ipc::reader::FileReader;
+    use std::fs::{self, File};
+
+    #[tokio::test]
+    async fn test_rows_round_trip_through_arrow_file() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-feather-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let date = NaiveDate::from_ymd_opt(2025, 11, 6).unwrap();
+        let mut writer = FeatherWriter::new(FeatherWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 100,
+            filter: Filter::default(),
+            date: Some(date),
+        });
+
+        let row = |symbol: &str, price: Value, time: u64| -> DataRow {
+            [
+                ("s".to_string(), Value::from(symbol)),
+                ("p".to_string(), price),
+                ("T".to_string(), Value::from(time)),
+            ]
+            .into_iter()
+            .collect()
+        };
+        // The string price is coerced to the inferred Float64 column, as for parquet
+        writer.write_rows(vec![row("BTCUSDT", Value::from(1.5), 1), row("ETHUSDT", Value::from("2.5"), 2)])
+            .await
+            .unwrap();
+        writer.flush_buffer().await.unwrap();
+
+        let files: Vec<PathBuf> = fs::read_dir(date_dir(&dir, date)).unwrap()
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().and_then(|s| s.to_str()), Some("arrow"));
+
+        let reader = FileReader::try_new(File::open(&files[0]).unwrap(), None).unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<std::result::Result<_, _>>().unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let symbols = column("s");
+        let symbols = symbols.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(symbols.value(1), "ETHUSDT");
+        let prices = column("p");
+        let prices = prices.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!((prices.value(0), prices.value(1)), (1.5, 2.5));
+        let times = column("T");
+        assert_eq!(times.as_any().downcast_ref::<UInt64Array>().unwrap().value(1), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -14,7 +14,7 @@ use tracing::info;
 #[cfg(unix)]
 use std::os::unix::fs::{OpenOptionsExt, DirBuilderExt};
 
-use crate::parquet_writer::FilterCondition;
+use crate::parquet_writer::Filter;
 use crate::writer::{Writer, DataRow};
 
 /// Configuration for JsonWriter
@@ -24,8 +24,8 @@ pub struct JsonWriterConfig {
     pub path: String,
     /// Name prefix for JSON files
     pub name: String,
-    /// Optional filter conditions - if empty, all data is written
-    pub filter: Vec<FilterCondition>,
+    /// Optional row filter - if empty, all data is written
+    pub filter: Filter,
 }
 
 /// JSON writer that appends records line-by-line to daily files
@@ -121,15 +121,7 @@ impl JsonWriter {
     /// Apply filters to data rows - returns true if row should be included
     /// If no filters are configured, all rows pass through
     fn apply_filters(&self, row: &DataRow) -> bool {
-        // If no filters configured, include all data
-        if self.config.filter.is_empty() {
-            return true;
-        }
-
-        // Check if row matches ANY of the filter conditions (OR logic)
-        self.config.filter.iter().any(|condition| {
-            crate::parquet_writer::ParquetWriter::row_matches_condition(row, condition)
-        })
+        self.config.filter.matches(row)
     }
 
     /// Write a single row to the JSON file
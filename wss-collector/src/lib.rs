@@ -4,14 +4,41 @@
 //! with support for HTTP proxy connections.
 
 pub mod wss_stream;
+pub mod deflate;
 pub mod parquet_writer;
 pub mod json_writer;
+pub mod feather_writer;
 pub mod writer;
+pub mod composite_writer;
 pub mod data_extract;
+pub mod logging;
+pub mod subscribe;
+pub mod token_refresh;
+pub mod write_queue;
+pub mod quarantine;
+pub mod reconnect;
+pub mod dataset;
+pub mod clock;
+pub mod collector;
+pub mod bench;
+pub mod error;
 
 // Re-export public items for convenient access
-pub use wss_stream::{connect_wss_stream, ProxyStream};
-pub use parquet_writer::{ParquetWriter, ParquetWriterConfig, ColumnType, FilterCondition, FilterOperator};
+pub use wss_stream::{connect_wss_stream, connect_wss_stream_verbose, ConnectionInfo, ProxyStream, WsConfig, UNIX_SOCKET_SCHEME};
+pub use parquet_writer::{parse_column_encoding, ParquetWriter, ParquetWriterConfig, WrittenFileInfo, DEFAULT_ROW_GROUP_SIZE, ColumnType, Filter, FilterCondition, FilterGroup, FilterLogic, FilterOperator, FilterStats};
 pub use json_writer::{JsonWriter, JsonWriterConfig};
-pub use writer::{Writer, WriterType, DataRow};
-pub use data_extract::{extract_data_array, convert_to_rows};
+pub use feather_writer::{FeatherWriter, FeatherWriterConfig};
+pub use writer::{Writer, WriterSpec, WriterType, DataRow};
+pub use composite_writer::CompositeWriter;
+pub use data_extract::{extract_data_array, extract_data_array_ignoring, extract_data_array_streaming, extract_binary_data_array, default_ignore_messages, convert_to_rows, MessageCodec};
+pub use logging::{init_tracing, LogFormat};
+pub use subscribe::{send_subscribe_frames, SubscribeRateLimit};
+pub use token_refresh::{TokenRefreshConfig, TokenRefresher};
+pub use write_queue::{write_queue, Evictable, OverflowPolicy, WriteQueueConfig, WriteQueueReceiver, WriteQueueSender};
+pub use quarantine::{Quarantine, DEFAULT_QUARANTINE_MAX_BYTES};
+pub use reconnect::ReconnectBackoff;
+pub use dataset::ParquetDataset;
+pub use clock::{Clock, SystemClock};
+pub use collector::{run_collector, run_collector_with_writer, Config};
+pub use bench::{run_bench, BenchOptions, BenchReport};
+pub use error::{CollectorError, CollectorResult};
@@ -8,23 +8,27 @@ use arrow::array::{
     ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, RecordBatch, StringBuilder,
     UInt64Builder,
 };
-use arrow::datatypes::{DataType, Field, Schema};
+use arrow::datatypes::{DataType, Field, FieldRef, Schema};
 use async_trait::async_trait;
 use chrono::NaiveDate;
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 use parquet::arrow::ArrowWriter;
-use parquet::file::properties::WriterProperties;
+use parquet::basic::Encoding;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::{WriterProperties, WriterPropertiesBuilder};
+use parquet::schema::types::ColumnPath;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::{info, warn};
 
 #[cfg(unix)]
 use std::os::unix::fs::{OpenOptionsExt, DirBuilderExt};
 
+use crate::clock::{Clock, SystemClock};
 use crate::writer::{Writer, DataRow};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -57,6 +61,133 @@ pub struct FilterCondition {
     pub value: Value,
 }
 
+/// How filter conditions (or groups) are combined
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterLogic {
+    /// Every item must match
+    And,
+    /// At least one item must match
+    #[default]
+    Or,
+}
+
+impl FilterLogic {
+    fn combine<I: IntoIterator<Item = bool>>(self, results: I) -> bool {
+        let mut results = results.into_iter();
+        match self {
+            FilterLogic::And => results.all(|matched| matched),
+            FilterLogic::Or => results.any(|matched| matched),
+        }
+    }
+}
+
+/// Conditions combined with a single logic, e.g. `(a AND b)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterGroup {
+    #[serde(default)]
+    pub logic: FilterLogic,
+    pub conditions: Vec<FilterCondition>,
+}
+
+impl FilterGroup {
+    /// Check if a row matches the group's conditions
+    pub fn matches(&self, row: &DataRow) -> bool {
+        self.logic.combine(
+            self.conditions
+                .iter()
+                .map(|condition| ParquetWriter::row_matches_condition(row, condition)),
+        )
+    }
+}
+
+/// Row filter: groups combined with an outer logic, e.g. `(a AND b) OR (c)`
+///
+/// Deserializes from either a flat list of conditions (one OR group, the original format)
+/// or `{ logic, groups }`. An empty filter lets every row through.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "FilterRepr", into = "FilterRepr")]
+pub struct Filter {
+    pub logic: FilterLogic,
+    pub groups: Vec<FilterGroup>,
+}
+
+/// Accepted config shapes for `Filter`
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum FilterRepr {
+    Flat(Vec<FilterCondition>),
+    Groups {
+        #[serde(default)]
+        logic: FilterLogic,
+        groups: Vec<FilterGroup>,
+    },
+}
+
+impl From<FilterRepr> for Filter {
+    fn from(repr: FilterRepr) -> Self {
+        match repr {
+            FilterRepr::Flat(conditions) => Filter::from(conditions),
+            FilterRepr::Groups { logic, groups } => Filter { logic, groups },
+        }
+    }
+}
+
+impl From<Filter> for FilterRepr {
+    fn from(filter: Filter) -> Self {
+        FilterRepr::Groups {
+            logic: filter.logic,
+            groups: filter.groups,
+        }
+    }
+}
+
+impl From<Vec<FilterCondition>> for Filter {
+    /// A flat condition list is a single group with OR logic
+    fn from(conditions: Vec<FilterCondition>) -> Self {
+        if conditions.is_empty() {
+            return Filter::default();
+        }
+        Filter {
+            logic: FilterLogic::Or,
+            groups: vec![FilterGroup {
+                logic: FilterLogic::Or,
+                conditions,
+            }],
+        }
+    }
+}
+
+impl Filter {
+    /// Whether no conditions are configured
+    pub fn is_empty(&self) -> bool {
+        self.groups.iter().all(|group| group.conditions.is_empty())
+    }
+
+    /// Check if a row passes the filter; an empty filter passes every row
+    pub fn matches(&self, row: &DataRow) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        self.logic.combine(self.groups.iter().map(|group| group.matches(row)))
+    }
+}
+
+/// Cumulative count of rows kept and dropped by a writer's filter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FilterStats {
+    /// Rows that matched the filter and were buffered for writing
+    pub passed: u64,
+    /// Rows dropped because they did not match the filter
+    pub filtered: u64,
+}
+
+/// Key under which the schema version is stored in each file's key-value metadata
+pub const SCHEMA_VERSION_METADATA_KEY: &str = "wss_collector.schema_version";
+
+/// Default maximum number of rows per row group
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 128 * 1024;
+
 /// Configuration for ParquetWriter
 #[derive(Debug, Clone)]
 pub struct ParquetWriterConfig {
@@ -66,10 +197,167 @@ pub struct ParquetWriterConfig {
     pub name: String,
     /// Batch size - number of records to buffer before writing to file
     pub batch_size: usize,
-    /// Optional filter conditions - if empty, all data is written
-    pub filter: Vec<FilterCondition>,
+    /// Rows per file; batches are appended to the open file until it holds this many rows
+    /// If None, every batch is written to its own file
+    pub file_target_rows: Option<usize>,
+    /// Optional row filter - if empty, all data is written
+    pub filter: Filter,
     /// Optional date to write data to - if None, uses current date
     pub date: Option<NaiveDate>,
+    /// Maximum number of rows per row group within a file
+    pub row_group_size: usize,
+    /// Columns written first, in this order; remaining columns follow sorted by name
+    /// If None, all columns are sorted by name
+    pub column_order: Option<Vec<String>>,
+    /// Per-column encodings; unlisted columns use the writer default
+    /// With dictionary encoding enabled for a column this is the fallback encoding
+    pub column_encodings: HashMap<String, Encoding>,
+    /// Columns that use dictionary encoding; all others have it disabled
+    /// If None, dictionary encoding is enabled for every column (writer default)
+    pub dictionary_columns: Option<Vec<String>>,
+}
+
+/// Parse a per-column encoding name such as `DELTA_BINARY_PACKED` (case-insensitive)
+/// Dictionary encodings are rejected since they are controlled by `dictionary_columns`
+pub fn parse_column_encoding(name: &str) -> Result<Encoding> {
+    match name.to_ascii_uppercase().as_str() {
+        "PLAIN" => Ok(Encoding::PLAIN),
+        "RLE" => Ok(Encoding::RLE),
+        "DELTA_BINARY_PACKED" => Ok(Encoding::DELTA_BINARY_PACKED),
+        "DELTA_LENGTH_BYTE_ARRAY" => Ok(Encoding::DELTA_LENGTH_BYTE_ARRAY),
+        "DELTA_BYTE_ARRAY" => Ok(Encoding::DELTA_BYTE_ARRAY),
+        "BYTE_STREAM_SPLIT" => Ok(Encoding::BYTE_STREAM_SPLIT),
+        "PLAIN_DICTIONARY" | "RLE_DICTIONARY" => {
+            anyhow::bail!("{} cannot be set per column, list the column in dictionary_columns instead", name)
+        }
+        _ => anyhow::bail!("Unknown parquet encoding: {}", name),
+    }
+}
+
+/// Serde adapter for `column -> encoding` maps written as encoding names in config files
+pub mod column_encodings_serde {
+    use super::{parse_column_encoding, Encoding};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::{BTreeMap, HashMap};
+
+    pub fn serialize<S: Serializer>(
+        encodings: &HashMap<String, Encoding>,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let names: BTreeMap<&String, String> = encodings
+            .iter()
+            .map(|(column, encoding)| (column, format!("{:?}", encoding)))
+            .collect();
+        names.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<HashMap<String, Encoding>, D::Error> {
+        HashMap::<String, String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(column, name)| {
+                parse_column_encoding(&name)
+                    .map(|encoding| (column, encoding))
+                    .map_err(serde::de::Error::custom)
+            })
+            .collect()
+    }
+}
+
+/// Directory holding a date's files under a base path: `<base>/<year>/<month>/<day>`
+pub fn date_dir(base: &Path, date: NaiveDate) -> PathBuf {
+    base.join(date.format("%Y").to_string())
+        .join(date.format("%m").to_string())
+        .join(date.format("%d").to_string())
+}
+
+/// Create `path` and its parent directories with group-writable permissions
+/// (0775 for directories, 0664 for the file on unix), truncating an existing file
+pub fn create_output_file(path: &Path) -> Result<File> {
+    // Create parent directories with explicit permissions (0775 - rwxrwxr-x)
+    if let Some(parent) = path.parent() {
+        #[cfg(unix)]
+        {
+            let mut builder = std::fs::DirBuilder::new();
+            builder.recursive(true).mode(0o775);
+            builder.create(parent)
+                .context(format!("Failed to create directory: {:?}", parent))?;
+        }
+        
+        #[cfg(not(unix))]
+        fs::create_dir_all(parent)
+            .context(format!("Failed to create directory: {:?}", parent))?;
+    }
+
+    // Open file with explicit permissions (0664 - rw-rw-r--)
+    #[cfg(unix)]
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o664)
+        .open(path)
+        .context(format!("Failed to open output file: {:?}", path))?;
+    
+    #[cfg(not(unix))]
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .context(format!("Failed to open output file: {:?}", path))?;
+
+    Ok(file)
+}
+
+/// Size and layout of a written parquet file, read back from its footer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WrittenFileInfo {
+    pub path: PathBuf,
+    /// File size on disk
+    pub bytes: u64,
+    pub num_rows: i64,
+    pub num_row_groups: usize,
+}
+
+impl WrittenFileInfo {
+    /// Read the size and footer of a parquet file
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = File::open(path).context(format!("Failed to open {:?}", path))?;
+        let bytes = file.metadata().context(format!("Failed to stat {:?}", path))?.len();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+            .context(format!("Failed to read parquet footer: {:?}", path))?;
+        let metadata = builder.metadata();
+        Ok(Self {
+            path: path.to_path_buf(),
+            bytes,
+            num_rows: metadata.file_metadata().num_rows(),
+            num_row_groups: metadata.num_row_groups(),
+        })
+    }
+
+    /// Log the file's size and layout as structured fields
+    fn log(&self) {
+        info!(
+            path = %self.path.display(),
+            bytes = self.bytes,
+            num_rows = self.num_rows,
+            num_row_groups = self.num_row_groups as u64,
+            "Parquet file is {} bytes with {} rows in {} row groups",
+            self.bytes,
+            self.num_rows,
+            self.num_row_groups
+        );
+    }
+}
+
+/// File kept open across batches while it is below `file_target_rows`
+struct OpenFile {
+    writer: ArrowWriter<File>,
+    path: PathBuf,
+    date: NaiveDate,
+    rows: usize,
 }
 
 /// Main struct for writing data to Parquet files
@@ -80,10 +368,19 @@ pub struct ParquetWriter {
     column_types: HashMap<String, ColumnType>,
     buffer: Vec<DataRow>,
     file_sequence: u64, // Sequence number for unique file naming
+    schema_version: u32, // Bumped each time new fields widen the schema
+    open_file: Option<OpenFile>,
+    filter_stats: FilterStats,
+    clock: Arc<dyn Clock>, // Picks the day when `config.date` is not set
 }
 
 impl ParquetWriter {
     pub fn new(config: ParquetWriterConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create a writer that reads the current time from `clock` instead of the system clock
+    pub fn with_clock(config: ParquetWriterConfig, clock: Arc<dyn Clock>) -> Self {
         Self {
             config,
             current_date: None,
@@ -91,6 +388,10 @@ impl ParquetWriter {
             column_types: HashMap::new(),
             buffer: Vec::new(),
             file_sequence: 0,
+            schema_version: 0,
+            open_file: None,
+            filter_stats: FilterStats::default(),
+            clock,
         }
     }
     /// Infer column type from JSON value
@@ -123,6 +424,8 @@ impl ParquetWriter {
     }
 
     /// Infer schema from data objects
+    /// Types come from the first object with a non-null value; leading `{}` or all-null
+    /// objects are skipped so they cannot lock in an empty schema
     pub fn infer_schema_from_data(data: &[Value]) -> Result<(Arc<Schema>, HashMap<String, ColumnType>)> {
         if data.is_empty() {
             anyhow::bail!("Cannot infer schema from empty data");
@@ -131,15 +434,24 @@ impl ParquetWriter {
         let mut column_types: HashMap<String, ColumnType> = HashMap::new();
         let mut field_order: Vec<String> = Vec::new();
 
-        // Analyze first object to get field names and types
-        if let Value::Object(obj) = &data[0] {
-            for (key, value) in obj.iter() {
-                let col_type = Self::infer_type(value);
-                column_types.insert(key.clone(), col_type);
-                field_order.push(key.clone());
+        // Analyze the first representative object to get field names and types
+        let mut representative = None;
+        for item in data {
+            let Value::Object(obj) = item else {
+                anyhow::bail!("Expected object in data array");
+            };
+            if obj.values().any(|value| !value.is_null()) {
+                representative = Some(obj);
+                break;
             }
-        } else {
-            anyhow::bail!("Expected object in data array");
+        }
+        let Some(obj) = representative else {
+            anyhow::bail!("Cannot infer schema from {} empty or all-null objects", data.len());
+        };
+        for (key, value) in obj.iter() {
+            let col_type = Self::infer_type(value);
+            column_types.insert(key.clone(), col_type);
+            field_order.push(key.clone());
         }
 
         // Sort field names for consistent schema
@@ -164,6 +476,71 @@ impl ParquetWriter {
         Ok((schema, column_types))
     }
 
+    /// Build a schema from column types, with fields sorted by name
+    pub fn schema_from_column_types(column_types: &HashMap<String, ColumnType>) -> Arc<Schema> {
+        let mut names: Vec<&String> = column_types.keys().collect();
+        names.sort();
+
+        let fields: Vec<Field> = names
+            .into_iter()
+            .map(|name| {
+                let data_type = match &column_types[name] {
+                    ColumnType::String => DataType::Utf8,
+                    ColumnType::Int64 => DataType::Int64,
+                    ColumnType::UInt64 => DataType::UInt64,
+                    ColumnType::Float64 => DataType::Float64,
+                    ColumnType::Boolean => DataType::Boolean,
+                };
+                Field::new(name, data_type, true)
+            })
+            .collect();
+
+        Arc::new(Schema::new(fields))
+    }
+
+    /// Reorder schema fields so `column_order` columns come first in the declared order,
+    /// followed by the remaining columns sorted by name
+    /// Configured columns that are not in the data are skipped with a warning
+    fn apply_column_order(&self, schema: Arc<Schema>) -> Arc<Schema> {
+        let Some(ref column_order) = self.config.column_order else {
+            return schema;
+        };
+
+        let mut fields: Vec<FieldRef> = Vec::with_capacity(schema.fields().len());
+        for name in column_order {
+            if fields.iter().any(|f| f.name() == name) {
+                continue;
+            }
+            match schema.field_with_name(name) {
+                Ok(field) => fields.push(Arc::new(field.clone())),
+                Err(_) => warn!("Column '{}' from column_order is not present in the data, skipping", name),
+            }
+        }
+        let mut rest: Vec<FieldRef> = schema.fields().iter()
+            .filter(|f| !column_order.contains(f.name()))
+            .cloned()
+            .collect();
+        rest.sort_by(|a, b| a.name().cmp(b.name()));
+        fields.extend(rest);
+
+        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+    }
+
+    /// Find fields in `rows` that are not part of the current schema, with their inferred types
+    pub fn find_new_columns(&self, rows: &[DataRow]) -> HashMap<String, ColumnType> {
+        find_new_columns(&self.column_types, rows)
+    }
+
+    /// Current schema version, recorded in the metadata of every written file
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Rows passed and dropped by the filter since the writer was created
+    pub fn filter_stats(&self) -> FilterStats {
+        self.filter_stats
+    }
+
     /// Check if a row matches a filter condition
     pub fn row_matches_condition(row: &DataRow, condition: &FilterCondition) -> bool {
         let row_value = match row.get(&condition.field) {
@@ -227,15 +604,16 @@ impl ParquetWriter {
     /// Apply filters to data rows - returns true if row should be included
     /// If no filters are configured, all rows pass through
     pub fn apply_filters(&self, row: &DataRow) -> bool {
-        // If no filters configured, include all data
-        if self.config.filter.is_empty() {
-            return true;
-        }
+        self.config.filter.matches(row)
+    }
 
-        // Check if row matches ANY of the filter conditions (OR logic)
-        self.config.filter.iter().any(|condition| {
-            Self::row_matches_condition(row, condition)
-        })
+    /// Keep the rows passing the filter, counting both outcomes in `filter_stats`
+    fn filter_rows(&mut self, rows: Vec<DataRow>) -> Vec<DataRow> {
+        let total = rows.len() as u64;
+        let rows: Vec<DataRow> = rows.into_iter().filter(|row| self.apply_filters(row)).collect();
+        self.filter_stats.passed += rows.len() as u64;
+        self.filter_stats.filtered += total - rows.len() as u64;
+        rows
     }
 
     /// Convert data to rows
@@ -253,20 +631,13 @@ impl ParquetWriter {
 
     /// Get directory path for a given date
     pub fn get_parquet_dir(&self, date: NaiveDate) -> PathBuf {
-        let year = date.format("%Y").to_string();
-        let month = date.format("%m").to_string();
-        let day = date.format("%d").to_string();
-
-        PathBuf::from(&self.config.path)
-            .join(year)
-            .join(month)
-            .join(day)
+        date_dir(Path::new(&self.config.path), date)
     }
 
     /// Generate unique parquet file path with timestamp and sequence
     pub fn get_unique_parquet_path(&mut self, date: NaiveDate) -> PathBuf {
         let dir = self.get_parquet_dir(date);
-        let timestamp = chrono::Utc::now().timestamp_millis();
+        let timestamp = self.clock.now().timestamp_millis();
         self.file_sequence += 1;
         let filename = format!(
             "{}_{}_{}_{:06}.parquet",
@@ -322,152 +693,103 @@ impl ParquetWriter {
             anyhow::bail!("Schema not initialized");
         };
 
-        // Build arrays dynamically based on schema
-        let mut arrays: Vec<ArrayRef> = Vec::new();
-
-        for field in schema.fields().iter() {
-            let col_name = field.name();
-            let col_type = self.column_types.get(col_name)
-                .context(format!("Column type not found for {}", col_name))?;
-
-            let array: ArrayRef = match col_type {
-                ColumnType::String => {
-                    let mut builder = StringBuilder::new();
-                    for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            match value {
-                                Value::String(s) => builder.append_value(s),
-                                Value::Number(n) => builder.append_value(&n.to_string()),
-                                Value::Bool(b) => builder.append_value(&b.to_string()),
-                                Value::Null => builder.append_null(),
-                                _ => builder.append_value(&value.to_string()),
-                            }
-                        } else {
-                            builder.append_null();
-                        }
-                    }
-                    Arc::new(builder.finish())
-                }
-                ColumnType::UInt64 => {
-                    let mut builder = UInt64Builder::new();
-                    for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let num = match value {
-                                Value::Number(n) => n.as_u64().unwrap_or(0),
-                                Value::String(s) => s.parse::<u64>().unwrap_or(0),
-                                _ => 0,
-                            };
-                            builder.append_value(num);
-                        } else {
-                            builder.append_null();
-                        }
-                    }
-                    Arc::new(builder.finish())
-                }
-                ColumnType::Int64 => {
-                    let mut builder = Int64Builder::new();
-                    for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let num = match value {
-                                Value::Number(n) => n.as_i64().unwrap_or(0),
-                                Value::String(s) => s.parse::<i64>().unwrap_or(0),
-                                _ => 0,
-                            };
-                            builder.append_value(num);
-                        } else {
-                            builder.append_null();
-                        }
-                    }
-                    Arc::new(builder.finish())
-                }
-                ColumnType::Float64 => {
-                    let mut builder = Float64Builder::new();
-                    for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let num = match value {
-                                Value::Number(n) => n.as_f64().unwrap_or(0.0),
-                                Value::String(s) => s.parse::<f64>().unwrap_or(0.0),
-                                _ => 0.0,
-                            };
-                            builder.append_value(num);
-                        } else {
-                            builder.append_null();
-                        }
-                    }
-                    Arc::new(builder.finish())
-                }
-                ColumnType::Boolean => {
-                    let mut builder = BooleanBuilder::new();
-                    for row in data {
-                        if let Some(value) = row.get(col_name) {
-                            let b = match value {
-                                Value::Bool(b) => *b,
-                                Value::String(s) => s.parse::<bool>().unwrap_or(false),
-                                _ => false,
-                            };
-                            builder.append_value(b);
-                        } else {
-                            builder.append_null();
-                        }
-                    }
-                    Arc::new(builder.finish())
-                }
-            };
+        rows_to_batch(schema, &self.column_types, data)
+    }
+
+    /// Write a single RecordBatch to a new parquet file
+    /// Returns the file's size and layout, read back from its footer
+    pub fn write_batch_to_file(&self, path: &PathBuf, batch: &RecordBatch) -> Result<WrittenFileInfo> {
+        let mut writer = self.create_file_writer(path)?;
 
-            arrays.push(array);
+        // Write one row group per slice so large buffers stay readable with predicate pushdown
+        let row_group_size = self.config.row_group_size.max(1);
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let len = row_group_size.min(batch.num_rows() - offset);
+            writer.write(&batch.slice(offset, len)).context("Failed to write batch to parquet")?;
+            writer.flush().context("Failed to flush row group")?;
+            offset += len;
         }
+        writer.close().context("Failed to close writer")?;
 
-        RecordBatch::try_new(schema.clone(), arrays)
-            .context("Failed to create record batch")
+        info!("Wrote {} records to {:?}", batch.num_rows(), path);
+        WrittenFileInfo::read(path)
     }
 
-    /// Write a single RecordBatch to a new parquet file
-    pub fn write_batch_to_file(&self, path: &PathBuf, batch: &RecordBatch) -> Result<()> {
+    /// Create the file at `path` and a parquet writer for it with the current schema
+    fn create_file_writer(&self, path: &Path) -> Result<ArrowWriter<File>> {
         let Some(ref schema) = self.schema else {
             anyhow::bail!("Schema not initialized");
         };
 
-        // Create parent directories with explicit permissions (0775 - rwxrwxr-x)
-        if let Some(parent) = path.parent() {
-            #[cfg(unix)]
-            {
-                let mut builder = std::fs::DirBuilder::new();
-                builder.recursive(true).mode(0o775);
-                builder.create(parent)
-                    .context(format!("Failed to create directory: {:?}", parent))?;
-            }
-            
-            #[cfg(not(unix))]
-            fs::create_dir_all(parent)
-                .context(format!("Failed to create directory: {:?}", parent))?;
+        let file = create_output_file(path)?;
+
+        let row_group_size = self.config.row_group_size.max(1);
+        let props = self.column_properties(WriterProperties::builder())
+            .set_max_row_group_size(row_group_size)
+            .set_key_value_metadata(Some(vec![KeyValue::new(
+                SCHEMA_VERSION_METADATA_KEY.to_string(),
+                self.schema_version.to_string(),
+            )]))
+            .build();
+        ArrowWriter::try_new(file, schema.clone(), Some(props)).context("Failed to create ArrowWriter")
+    }
+
+    /// Append a batch to the open file (opening one if needed) and close it at `file_target_rows`
+    /// Rows accumulate in the writer until `row_group_size`, so row groups span several batches
+    fn append_to_open_file(&mut self, date: NaiveDate, batch: &RecordBatch, target_rows: usize) -> Result<()> {
+        if self.open_file.as_ref().is_some_and(|open| open.date != date) {
+            self.close_open_file()?;
+        }
+        if self.open_file.is_none() {
+            let path = self.get_unique_parquet_path(date);
+            let writer = self.create_file_writer(&path)?;
+            self.open_file = Some(OpenFile { writer, path, date, rows: 0 });
         }
 
-        // Open file with explicit permissions (0664 - rw-rw-r--)
-        #[cfg(unix)]
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .mode(0o664)
-            .open(path)
-            .context(format!("Failed to open parquet file: {:?}", path))?;
-        
-        #[cfg(not(unix))]
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)
-            .context(format!("Failed to open parquet file: {:?}", path))?;
-
-        let props = WriterProperties::builder().build();
-        let mut writer = ArrowWriter::try_new(file, schema.clone(), Some(props))
-            .context("Failed to create ArrowWriter")?;
-
-        writer.write(batch).context("Failed to write batch to parquet")?;
-        writer.close().context("Failed to close writer")?;
+        let open = self.open_file.as_mut().expect("file opened above");
+        open.writer.write(batch).context("Failed to write batch to parquet")?;
+        open.rows += batch.num_rows();
+        if open.rows >= target_rows.max(1) {
+            self.close_open_file()?;
+        }
+        Ok(())
+    }
 
-        info!("Wrote {} records to {:?}", batch.num_rows(), path);
+    /// Finish the open file, if any, so it becomes readable
+    fn close_open_file(&mut self) -> Result<()> {
+        let Some(open) = self.open_file.take() else {
+            return Ok(());
+        };
+        open.writer.close().context(format!("Failed to close parquet file: {:?}", open.path))?;
+        info!("Wrote {} records to {:?}", open.rows, open.path);
+        WrittenFileInfo::read(&open.path)?.log();
+        Ok(())
+    }
+
+    /// Apply the per-column encoding and dictionary settings
+    fn column_properties(&self, mut builder: WriterPropertiesBuilder) -> WriterPropertiesBuilder {
+        if let Some(ref dictionary_columns) = self.config.dictionary_columns {
+            builder = builder.set_dictionary_enabled(false);
+            for column in dictionary_columns {
+                builder = builder.set_column_dictionary_enabled(ColumnPath::from(column.as_str()), true);
+            }
+        }
+        for (column, encoding) in &self.config.column_encodings {
+            builder = builder.set_column_encoding(ColumnPath::from(column.as_str()), *encoding);
+        }
+        builder
+    }
+
+    /// Buffer rows that arrived before the schema could be inferred
+    /// They are written, as nulls, with the first batch after the schema is known
+    fn hold_degenerate_rows(&mut self, rows: Vec<DataRow>, today: NaiveDate) -> Result<()> {
+        let rows = self.filter_rows(rows);
+        if !rows.is_empty() {
+            info!("Holding {} empty or all-null rows until a row to infer the schema from arrives", rows.len());
+            self.buffer.extend(rows);
+            self.current_date = Some(today);
+        }
         Ok(())
     }
 
@@ -478,7 +800,12 @@ impl ParquetWriter {
         }
 
         // Use configured date if provided, otherwise use current date
-        let today = self.config.date.unwrap_or_else(|| chrono::Utc::now().date_naive());
+        let today = self.config.date.unwrap_or_else(|| self.clock.now().date_naive());
+
+        // Rows without any value cannot be inferred from; hold them until one arrives
+        if self.schema.is_none() && rows.iter().all(is_degenerate_row) {
+            return self.hold_degenerate_rows(rows, today);
+        }
 
         // If schema not initialized, infer it from the first batch
         if self.schema.is_none() {
@@ -489,6 +816,7 @@ impl ParquetWriter {
             
             let (schema, column_types) = Self::infer_schema_from_data(&data_array)
                 .context("Failed to infer schema")?;
+            let schema = self.apply_column_order(schema);
             
             info!("Inferred schema with {} columns:", schema.fields().len());
             for field in schema.fields() {
@@ -510,9 +838,26 @@ impl ParquetWriter {
         }
 
         // Apply filters
-        let filtered_rows: Vec<DataRow> = rows.into_iter()
-            .filter(|row| self.apply_filters(row))
-            .collect();
+        let filtered_rows = self.filter_rows(rows);
+
+        // Widen the schema when new fields appear; buffered rows go to a file with the old schema
+        let new_columns = self.find_new_columns(&filtered_rows);
+        if !new_columns.is_empty() {
+            self.flush_buffer_impl().await?;
+
+            let mut added: Vec<&String> = new_columns.keys().collect();
+            added.sort();
+            info!(
+                "New fields {:?} detected, widening schema to version {} ({} columns); subsequent rows go to a new file",
+                added,
+                self.schema_version + 1,
+                self.column_types.len() + new_columns.len()
+            );
+
+            self.column_types.extend(new_columns);
+            self.schema = Some(self.apply_column_order(Self::schema_from_column_types(&self.column_types)));
+            self.schema_version += 1;
+        }
 
         if !filtered_rows.is_empty() {
             self.buffer.extend(filtered_rows);
@@ -522,15 +867,22 @@ impl ParquetWriter {
             info!("All records filtered out by filter conditions");
         }
 
-        // Flush buffer when it reaches configured batch_size
+        // Write the buffer when it reaches configured batch_size; an open file stays open
         if self.buffer.len() >= self.config.batch_size {
-            self.flush_buffer_impl().await?;
+            self.write_buffer()?;
         }
 
         Ok(())
     }
 
+    /// Write the buffer and finish the open file
     async fn flush_buffer_impl(&mut self) -> Result<()> {
+        self.write_buffer()?;
+        self.close_open_file()
+    }
+
+    /// Write the buffer to a new file, or append it to the open one when `file_target_rows` is set
+    fn write_buffer(&mut self) -> Result<()> {
         if self.buffer.is_empty() {
             return Ok(());
         }
@@ -543,15 +895,29 @@ impl ParquetWriter {
         let date = self.current_date.context("No current date set")?;
         
         info!("Flushing {} records to parquet", self.buffer.len());
+        if !self.config.filter.is_empty() {
+            let stats = self.filter_stats;
+            info!(
+                passed = stats.passed,
+                filtered = stats.filtered,
+                "Filter has passed {} and dropped {} rows so far",
+                stats.passed,
+                stats.filtered
+            );
+        }
 
         // Convert buffer to RecordBatch
         let batch = self.buffer_to_batch(&self.buffer)?;
-        
-        // Generate unique file path
-        let path = self.get_unique_parquet_path(date);
 
-        // Write to new file (no reading of old data!)
-        self.write_batch_to_file(&path, &batch)?;
+        if let Some(target_rows) = self.config.file_target_rows {
+            self.append_to_open_file(date, &batch, target_rows)?;
+        } else {
+            // Generate unique file path
+            let path = self.get_unique_parquet_path(date);
+
+            // Write to new file (no reading of old data!)
+            self.write_batch_to_file(&path, &batch)?.log();
+        }
 
         // Clear buffer
         self.buffer.clear();
@@ -576,9 +942,122 @@ impl Drop for ParquetWriter {
         if !self.buffer.is_empty() {
             warn!("Buffer not empty on drop, {} records will be lost", self.buffer.len());
         }
+        if let Err(e) = self.close_open_file() {
+            warn!("Failed to close parquet file on drop: {:#}", e);
+        }
     }
 }
 
+/// Build a RecordBatch with `schema` from rows, coercing values to the column types
+/// Shared by the Parquet and Feather writers so both produce identical columns
+pub fn rows_to_batch(schema: &Arc<Schema>, column_types: &HashMap<String, ColumnType>, data: &[DataRow]) -> Result<RecordBatch> {
+    if data.is_empty() {
+        anyhow::bail!("Cannot create batch from empty data");
+    }
+
+    // Build arrays dynamically based on schema
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+
+    for field in schema.fields().iter() {
+        let col_name = field.name();
+        let col_type = column_types.get(col_name)
+            .context(format!("Column type not found for {}", col_name))?;
+
+        let array: ArrayRef = match col_type {
+            ColumnType::String => {
+                let mut builder = StringBuilder::new();
+                for row in data {
+                    if let Some(value) = row.get(col_name) {
+                        match value {
+                            Value::String(s) => builder.append_value(s),
+                            Value::Number(n) => builder.append_value(n.to_string()),
+                            Value::Bool(b) => builder.append_value(b.to_string()),
+                            Value::Null => builder.append_null(),
+                            _ => builder.append_value(value.to_string()),
+                        }
+                    } else {
+                        builder.append_null();
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            // Missing keys, explicit nulls and unparseable values all become Arrow nulls,
+            // never a 0/false default that would skew aggregates
+            ColumnType::UInt64 => {
+                let mut builder = UInt64Builder::new();
+                for row in data {
+                    let num = row.get(col_name).and_then(|value| match value {
+                        Value::Number(n) => n.as_u64(),
+                        Value::String(s) => s.parse::<u64>().ok(),
+                        _ => None,
+                    });
+                    builder.append_option(num);
+                }
+                Arc::new(builder.finish())
+            }
+            ColumnType::Int64 => {
+                let mut builder = Int64Builder::new();
+                for row in data {
+                    let num = row.get(col_name).and_then(|value| match value {
+                        Value::Number(n) => n.as_i64(),
+                        Value::String(s) => s.parse::<i64>().ok(),
+                        _ => None,
+                    });
+                    builder.append_option(num);
+                }
+                Arc::new(builder.finish())
+            }
+            ColumnType::Float64 => {
+                let mut builder = Float64Builder::new();
+                for row in data {
+                    let num = row.get(col_name).and_then(|value| match value {
+                        Value::Number(n) => n.as_f64(),
+                        Value::String(s) => s.parse::<f64>().ok(),
+                        _ => None,
+                    });
+                    builder.append_option(num);
+                }
+                Arc::new(builder.finish())
+            }
+            ColumnType::Boolean => {
+                let mut builder = BooleanBuilder::new();
+                for row in data {
+                    let b = row.get(col_name).and_then(|value| match value {
+                        Value::Bool(b) => Some(*b),
+                        Value::String(s) => s.parse::<bool>().ok(),
+                        _ => None,
+                    });
+                    builder.append_option(b);
+                }
+                Arc::new(builder.finish())
+            }
+        };
+
+        arrays.push(array);
+    }
+
+    RecordBatch::try_new(schema.clone(), arrays)
+        .context("Failed to create record batch")
+}
+
+/// Whether a row has no non-null value (`{}` or only nulls), so its types cannot be inferred
+pub fn is_degenerate_row(row: &DataRow) -> bool {
+    row.values().all(Value::is_null)
+}
+
+/// Fields in `rows` that are not in `column_types`, with their inferred types
+pub fn find_new_columns(column_types: &HashMap<String, ColumnType>, rows: &[DataRow]) -> HashMap<String, ColumnType> {
+    let mut new_columns = HashMap::new();
+    for row in rows {
+        for (key, value) in row {
+            if !column_types.contains_key(key) && !new_columns.contains_key(key) {
+                new_columns.insert(key.clone(), ParquetWriter::infer_type(value));
+            }
+        }
+    }
+    new_columns
+}
+
 /// Check if two schemas are compatible (same fields and types)
 pub fn schemas_compatible(schema1: &Schema, schema2: &Schema) -> bool {
     if schema1.fields().len() != schema2.fields().len() {
@@ -593,3 +1072,450 @@ pub fn schemas_compatible(schema1: &Schema, schema2: &Schema) -> bool {
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[tokio::test]
+    async fn test_row_group_size_splits_file() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-row-groups-{}", std::process::id()));
+        let mut writer = ParquetWriter::new(ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 10,
+            file_target_rows: None,
+            filter: Filter::default(),
+            date: Some(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()),
+            row_group_size: 3,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        });
+
+        let rows: Vec<DataRow> = (0..10)
+            .map(|i| [("E".to_string(), Value::from(1762411870000u64 + i))].into_iter().collect())
+            .collect();
+        writer.write_rows(rows).await.unwrap();
+
+        let day_dir = dir.join("2025/11/06");
+        let files: Vec<PathBuf> = fs::read_dir(&day_dir).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+
+        let reader = SerializedFileReader::new(File::open(&files[0]).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 4);
+        assert_eq!(metadata.file_metadata().num_rows(), 10);
+
+        let info = WrittenFileInfo::read(&files[0]).unwrap();
+        assert_eq!((info.num_rows, info.num_row_groups), (10, 4));
+        assert_eq!(info.bytes, fs::metadata(&files[0]).unwrap().len());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_small_batches_coalesce_into_one_file() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-file-target-{}", std::process::id()));
+        let config = ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 2,
+            file_target_rows: Some(100),
+            filter: Filter::default(),
+            date: Some(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        };
+        let day_dir = dir.join("2025/11/06");
+        let rows = |start: u64| -> Vec<DataRow> {
+            (start..start + 2)
+                .map(|i| [("E".to_string(), Value::from(1762411870000u64 + i))].into_iter().collect())
+                .collect()
+        };
+
+        // Three batches below the file target end up in one file and one row group
+        let mut writer = ParquetWriter::new(config.clone());
+        for start in [0, 2, 4] {
+            writer.write_rows(rows(start)).await.unwrap();
+        }
+        writer.flush_buffer().await.unwrap();
+
+        let files: Vec<PathBuf> = fs::read_dir(&day_dir).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let reader = SerializedFileReader::new(File::open(&files[0]).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 6);
+        assert_eq!(reader.metadata().num_row_groups(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Without a file target every batch still gets its own file
+        let mut writer = ParquetWriter::new(ParquetWriterConfig { file_target_rows: None, ..config });
+        for start in [0, 2, 4] {
+            writer.write_rows(rows(start)).await.unwrap();
+        }
+        assert_eq!(fs::read_dir(&day_dir).unwrap().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_new_field_widens_schema_into_new_file() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-schema-widening-{}", std::process::id()));
+        let mut writer = ParquetWriter::new(ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 100,
+            file_target_rows: None,
+            filter: Filter::default(),
+            date: Some(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        });
+
+        let first: DataRow = [("s".to_string(), Value::from("BTCUSDT"))].into_iter().collect();
+        writer.write_rows(vec![first]).await.unwrap();
+        let second: DataRow = [
+            ("s".to_string(), Value::from("ETHUSDT")),
+            ("r".to_string(), Value::from("0.0001")),
+        ]
+        .into_iter()
+        .collect();
+        writer.write_rows(vec![second]).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+        assert_eq!(writer.schema_version(), 1);
+
+        let day_dir = dir.join("2025/11/06");
+        let mut files: Vec<PathBuf> = fs::read_dir(&day_dir).unwrap().map(|e| e.unwrap().path()).collect();
+        files.sort();
+        assert_eq!(files.len(), 2);
+
+        let mut columns = Vec::new();
+        for path in &files {
+            let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+            let file_metadata = reader.metadata().file_metadata();
+            let version = file_metadata
+                .key_value_metadata()
+                .and_then(|kv| kv.iter().find(|kv| kv.key == SCHEMA_VERSION_METADATA_KEY))
+                .and_then(|kv| kv.value.clone())
+                .unwrap();
+            let names: Vec<String> = file_metadata
+                .schema_descr()
+                .columns()
+                .iter()
+                .map(|c| c.name().to_string())
+                .collect();
+            columns.push((version, names));
+        }
+        assert_eq!(columns[0], ("0".to_string(), vec!["s".to_string()]));
+        assert_eq!(columns[1], ("1".to_string(), vec!["r".to_string(), "s".to_string()]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn row(fields: &[(&str, Value)]) -> DataRow {
+        fields.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_leading_empty_rows_do_not_lock_in_schema() {
+        let data = vec![
+            serde_json::json!({}),
+            serde_json::json!({"s": null}),
+            serde_json::json!({"s": "BTCUSDT", "p": "1.5"}),
+        ];
+        let (schema, column_types) = ParquetWriter::infer_schema_from_data(&data).unwrap();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["p", "s"]);
+        assert_eq!(column_types["p"], ColumnType::Float64);
+        assert!(ParquetWriter::infer_schema_from_data(&data[..2]).is_err());
+
+        // A batch of only empty rows is held until a rich row arrives
+        let dir = std::env::temp_dir().join(format!("wss-collector-empty-first-{}", std::process::id()));
+        let mut writer = ParquetWriter::new(ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 100,
+            file_target_rows: None,
+            filter: Filter::default(),
+            date: Some(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        });
+        writer.write_rows(vec![row(&[])]).await.unwrap();
+        assert!(writer.schema.is_none());
+        writer.write_rows(vec![row(&[("s", Value::from("BTCUSDT")), ("p", Value::from("1.5"))])]).await.unwrap();
+        writer.flush_buffer().await.unwrap();
+        assert_eq!(writer.schema_version(), 0);
+
+        let files: Vec<PathBuf> = fs::read_dir(dir.join("2025/11/06")).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let reader = SerializedFileReader::new(File::open(&files[0]).unwrap()).unwrap();
+        let file_metadata = reader.metadata().file_metadata();
+        assert_eq!(file_metadata.num_rows(), 2);
+        let names: Vec<&str> = file_metadata.schema_descr().columns().iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["p", "s"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_filter_groups_combine_and_with_or() {
+        // (s == BTCUSDT AND p > 100) OR (s == ETHUSDT)
+        let filter: Filter = serde_yaml::from_str(
+            r#"
+logic: or
+groups:
+  - logic: and
+    conditions:
+      - { field: "s", operator: "eq", value: "BTCUSDT" }
+      - { field: "p", operator: "gt", value: 100 }
+  - conditions:
+      - { field: "s", operator: "eq", value: "ETHUSDT" }
+"#,
+        )
+        .unwrap();
+
+        assert!(filter.matches(&row(&[("s", Value::from("BTCUSDT")), ("p", Value::from(150))])));
+        assert!(!filter.matches(&row(&[("s", Value::from("BTCUSDT")), ("p", Value::from(50))])));
+        assert!(filter.matches(&row(&[("s", Value::from("ETHUSDT")), ("p", Value::from(50))])));
+        assert!(!filter.matches(&row(&[("s", Value::from("SOLUSDT")), ("p", Value::from(150))])));
+    }
+
+    #[test]
+    fn test_flat_filter_list_is_single_or_group() {
+        let filter: Filter = serde_yaml::from_str(
+            r#"
+- { field: "s", operator: "eq", value: "BTCUSDT" }
+- { field: "s", operator: "eq", value: "ETHUSDT" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(filter.groups.len(), 1);
+        assert_eq!(filter.groups[0].logic, FilterLogic::Or);
+
+        assert!(filter.matches(&row(&[("s", Value::from("ETHUSDT"))])));
+        assert!(!filter.matches(&row(&[("s", Value::from("SOLUSDT"))])));
+
+        // No conditions lets everything through
+        let empty: Filter = serde_yaml::from_str("[]").unwrap();
+        assert!(empty.is_empty());
+        assert!(empty.matches(&row(&[("s", Value::from("SOLUSDT"))])));
+    }
+
+    /// Clock that only moves when the test sets it
+    struct ManualClock(std::sync::Mutex<chrono::DateTime<chrono::Utc>>);
+
+    impl ManualClock {
+        fn set(&self, rfc3339: &str) {
+            *self.0.lock().unwrap() = chrono::DateTime::parse_from_rfc3339(rfc3339).unwrap().into();
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> chrono::DateTime<chrono::Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_injected_clock_crossing_midnight_flushes_previous_day() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-clock-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let clock = Arc::new(ManualClock(std::sync::Mutex::new(Default::default())));
+        clock.set("2025-11-06T23:59:58Z");
+        let mut writer = ParquetWriter::with_clock(
+            ParquetWriterConfig {
+                path: dir.to_string_lossy().to_string(),
+                name: "test".to_string(),
+                batch_size: 100,
+                file_target_rows: None,
+                filter: Filter::default(),
+                date: None,
+                row_group_size: DEFAULT_ROW_GROUP_SIZE,
+                column_order: None,
+                column_encodings: HashMap::new(),
+                dictionary_columns: None,
+            },
+            clock.clone(),
+        );
+        let files = |day: &str| -> Vec<PathBuf> {
+            fs::read_dir(dir.join(day)).map_or(Vec::new(), |entries| entries.flatten().map(|e| e.path()).collect())
+        };
+
+        writer.write_rows(vec![row(&[("s", Value::from("BTCUSDT"))])]).await.unwrap();
+        assert!(files("2025/11/06").is_empty(), "below batch_size, nothing is written yet");
+
+        // The first row after midnight flushes the previous day's buffer to its own directory
+        clock.set("2025-11-07T00:00:01Z");
+        writer.write_rows(vec![row(&[("s", Value::from("ETHUSDT"))])]).await.unwrap();
+        let previous = files("2025/11/06");
+        assert_eq!(previous.len(), 1);
+        assert_eq!(WrittenFileInfo::read(&previous[0]).unwrap().num_rows, 1);
+        assert!(previous[0].file_name().unwrap().to_string_lossy().starts_with("test_2025-11-06_"));
+        assert!(files("2025/11/07").is_empty());
+
+        writer.flush_buffer().await.unwrap();
+        let next = files("2025/11/07");
+        assert_eq!(next.len(), 1);
+        assert!(next[0].file_name().unwrap().to_string_lossy().starts_with("test_2025-11-07_"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_filter_stats_count_partially_filtered_batches() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-filter-stats-{}", std::process::id()));
+        let filter: Filter = serde_yaml::from_str(r#"[{ field: "s", operator: "eq", value: "BTCUSDT" }]"#).unwrap();
+        let mut writer = ParquetWriter::new(ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 100,
+            file_target_rows: None,
+            filter,
+            date: Some(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        });
+
+        // Every other row matches, so each batch is only partially filtered
+        let rows = |count: u64| -> Vec<DataRow> {
+            (0..count)
+                .map(|i| {
+                    let symbol = if i % 2 == 0 { "BTCUSDT" } else { "ETHUSDT" };
+                    row(&[("s", Value::from(symbol)), ("T", Value::from(i))])
+                })
+                .collect()
+        };
+        writer.write_rows(rows(10)).await.unwrap();
+        assert_eq!(writer.filter_stats(), FilterStats { passed: 5, filtered: 5 });
+        writer.write_rows(rows(4)).await.unwrap();
+        assert_eq!(writer.filter_stats(), FilterStats { passed: 7, filtered: 7 });
+
+        writer.flush_buffer().await.unwrap();
+        let info = WrittenFileInfo::read(&fs::read_dir(dir.join("2025/11/06")).unwrap().next().unwrap().unwrap().path())
+            .unwrap();
+        assert_eq!(info.num_rows, 7);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_null_and_unparseable_values_become_arrow_nulls() {
+        use arrow::array::{Array, Float64Array, UInt64Array};
+
+        let mut writer = ParquetWriter::new(ParquetWriterConfig {
+            path: std::env::temp_dir().to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 10,
+            file_target_rows: None,
+            filter: Filter::default(),
+            date: None,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: None,
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        });
+
+        let rows = vec![
+            row(&[("price", Value::from(1.5)), ("qty", Value::from(3u64))]),
+            row(&[("price", Value::Null), ("qty", Value::from("n/a"))]),
+            row(&[]),
+        ];
+        let data: Vec<Value> = rows
+            .iter()
+            .map(|row| Value::Object(row.clone().into_iter().collect()))
+            .collect();
+        let (schema, column_types) = ParquetWriter::infer_schema_from_data(&data).unwrap();
+        writer.schema = Some(schema);
+        writer.column_types = column_types;
+
+        let batch = writer.buffer_to_batch(&rows).unwrap();
+        let price = batch.column_by_name("price").unwrap().as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(price.value(0), 1.5);
+        assert!(price.is_null(1));
+        assert!(price.is_null(2));
+
+        let qty = batch.column_by_name("qty").unwrap().as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(qty.value(0), 3);
+        assert!(qty.is_null(1));
+        assert!(qty.is_null(2));
+    }
+
+    #[tokio::test]
+    async fn test_per_column_dictionary_and_encoding() {
+        let dir = std::env::temp_dir().join(format!("wss-collector-encodings-{}", std::process::id()));
+        let mut writer = ParquetWriter::new(ParquetWriterConfig {
+            path: dir.to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 20,
+            file_target_rows: None,
+            filter: Filter::default(),
+            date: Some(NaiveDate::from_ymd_opt(2025, 11, 6).unwrap()),
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: None,
+            column_encodings: HashMap::from([("E".to_string(), Encoding::DELTA_BINARY_PACKED)]),
+            dictionary_columns: Some(vec!["s".to_string()]),
+        });
+
+        let rows: Vec<DataRow> = (0..20)
+            .map(|i| {
+                [
+                    ("E".to_string(), Value::from(1762411870000u64 + i)),
+                    ("s".to_string(), Value::from(if i % 2 == 0 { "BTCUSDT" } else { "ETHUSDT" })),
+                ]
+                .into_iter()
+                .collect()
+            })
+            .collect();
+        writer.write_rows(rows).await.unwrap();
+
+        let day_dir = dir.join("2025/11/06");
+        let files: Vec<PathBuf> = fs::read_dir(&day_dir).unwrap().map(|e| e.unwrap().path()).collect();
+        let reader = SerializedFileReader::new(File::open(&files[0]).unwrap()).unwrap();
+        let row_group = reader.metadata().row_group(0);
+        let column = |name: &str| row_group.columns().iter().find(|c| c.column_path().string() == name).unwrap();
+
+        assert!(column("s").dictionary_page_offset().is_some());
+        assert!(column("E").dictionary_page_offset().is_none());
+        assert!(column("E").encodings().contains(&Encoding::DELTA_BINARY_PACKED));
+        assert!(parse_column_encoding("plain_dictionary").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_column_order_prefix() {
+        let writer = ParquetWriter::new(ParquetWriterConfig {
+            path: std::env::temp_dir().to_string_lossy().to_string(),
+            name: "test".to_string(),
+            batch_size: 10,
+            file_target_rows: None,
+            filter: Filter::default(),
+            date: None,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            column_order: Some(vec!["E".to_string(), "s".to_string(), "missing".to_string()]),
+            column_encodings: HashMap::new(),
+            dictionary_columns: None,
+        });
+
+        let data = vec![serde_json::json!({"p": "1.5", "s": "BTCUSDT", "E": 1762411870001u64, "q": "2"})];
+        let (schema, mut column_types) = ParquetWriter::infer_schema_from_data(&data).unwrap();
+        let schema = writer.apply_column_order(schema);
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["E", "s", "p", "q"]);
+
+        // Widened schemas keep the configured prefix
+        column_types.insert("a".to_string(), ColumnType::String);
+        let schema = writer.apply_column_order(ParquetWriter::schema_from_column_types(&column_types));
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["E", "s", "a", "p", "q"]);
+    }
+}
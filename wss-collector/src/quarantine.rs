@@ -0,0 +1,171 @@
+//! Quarantine file for messages that could not be turned into rows
+//!
+//! Instead of only logging a truncated excerpt, failed messages are appended in full
+//! to a JSONL file together with the time and the error, so parsing can be fixed and
+//! replayed later without re-capturing live data. Binary frames are stored base64-encoded
+//! in `message_base64` instead of `message`. When the file would grow past the
+//! size cap it is rotated to `<path>.1` (replacing any previous rotation).
+
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Default size cap of the quarantine file (64 MiB)
+pub const DEFAULT_QUARANTINE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Append-only JSONL file of failed messages with size-based rotation
+pub struct Quarantine {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Option<File>,
+    size: u64,
+}
+
+impl Quarantine {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: max_bytes.max(1),
+            file: None,
+            size: 0,
+        }
+    }
+
+    /// Path of the active quarantine file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path the active file is rotated to
+    pub fn rotated_path(&self) -> PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        PathBuf::from(rotated)
+    }
+
+    /// Append a failed message with the current time and its error
+    pub fn record(&mut self, message: &str, error: &str) -> Result<()> {
+        self.append(json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "error": error,
+            "message": message,
+        }))
+    }
+
+    /// Append a failed binary frame, base64-encoded in `message_base64`
+    pub fn record_binary(&mut self, data: &[u8], error: &str) -> Result<()> {
+        self.append(json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "error": error,
+            "message_base64": BASE64.encode(data),
+        }))
+    }
+
+    fn append(&mut self, entry: Value) -> Result<()> {
+        let mut line = entry.to_string();
+        line.push('\n');
+
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+
+        let file = self.ensure_file()?;
+        file.write_all(line.as_bytes())
+            .context(format!("Failed to write quarantine file: {:?}", self.path))?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn ensure_file(&mut self) -> Result<&mut File> {
+        if self.file.is_none() {
+            if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)
+                    .context(format!("Failed to create directory: {:?}", parent))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .context(format!("Failed to open quarantine file: {:?}", self.path))?;
+            self.size = file.metadata().map(|m| m.len()).unwrap_or(0);
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().expect("quarantine file opened above"))
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        self.file = None;
+        fs::rename(&self.path, self.rotated_path())
+            .context(format!("Failed to rotate quarantine file: {:?}", self.path))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_extract::extract_data_array;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wss-collector-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_malformed_message_is_quarantined_with_error() {
+        let dir = test_dir("quarantine");
+        let mut quarantine = Quarantine::new(dir.join("failed.jsonl"), DEFAULT_QUARANTINE_MAX_BYTES);
+
+        let message = r#"{"data": [{"s": "BTCUSDT", "p": "1.0"}"#;
+        let error = extract_data_array(message).unwrap_err();
+        quarantine.record(message, &format!("{:#}", error)).unwrap();
+
+        let content = fs::read_to_string(quarantine.path()).unwrap();
+        let lines: Vec<Value> = content.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["message"], message);
+        assert!(lines[0]["error"].as_str().unwrap().contains("Failed to parse JSON message"));
+        assert!(lines[0]["timestamp"].is_string());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_binary_message_is_quarantined_as_base64() {
+        let dir = test_dir("quarantine-binary");
+        let mut quarantine = Quarantine::new(dir.join("failed.jsonl"), DEFAULT_QUARANTINE_MAX_BYTES);
+
+        let data = [0xc1, 0xff, 0x00];
+        quarantine.record_binary(&data, "Failed to decode MessagePack message").unwrap();
+
+        let content = fs::read_to_string(quarantine.path()).unwrap();
+        let line: Value = serde_json::from_str(content.trim()).unwrap();
+        let decoded = BASE64.decode(line["message_base64"].as_str().unwrap()).unwrap();
+        assert_eq!(decoded, data);
+        assert!(line.get("message").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_when_over_size_cap() {
+        let dir = test_dir("quarantine-rotate");
+        let mut quarantine = Quarantine::new(dir.join("failed.jsonl"), 150);
+
+        quarantine.record("first", "bad").unwrap();
+        quarantine.record("second", "bad").unwrap();
+
+        let current = fs::read_to_string(quarantine.path()).unwrap();
+        let rotated = fs::read_to_string(quarantine.rotated_path()).unwrap();
+        assert!(current.contains("second") && !current.contains("first"));
+        assert!(rotated.contains("first"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+//! Reconnect backoff with jitter and an optional attempt cap
+//!
+//! The delay doubles from 1s up to 60s and every sleep is randomized within ±50% of the
+//! computed value, so a fleet of collectors that lost the server at the same moment does
+//! not reconnect in lockstep. Retries are unlimited unless a maximum is configured.
+
+use rand::Rng;
+use std::time::Duration;
+
+/// Delay before the first retry
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound of the backoff before jitter
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Exponential reconnect backoff for one stream
+#[derive(Debug, Clone)]
+pub struct ReconnectBackoff {
+    backoff: Duration,
+    attempts: u32,
+    max_attempts: Option<u32>,
+}
+
+impl ReconnectBackoff {
+    /// Create a backoff; `max_attempts` of `None` retries forever
+    pub fn new(max_attempts: Option<u32>) -> Self {
+        Self {
+            backoff: INITIAL_BACKOFF,
+            attempts: 0,
+            max_attempts,
+        }
+    }
+
+    /// Consecutive reconnect attempts since the last successful connection
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Start over after a successful connection
+    pub fn reset(&mut self) {
+        self.backoff = INITIAL_BACKOFF;
+        self.attempts = 0;
+    }
+
+    /// Jittered delay before the next attempt, or `None` once the attempt cap is reached
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.next_delay_with(rand::rng().random::<f64>())
+    }
+
+    /// Same as `next_delay` with the jitter taken from `unit` (in `[0, 1)`)
+    pub fn next_delay_with(&mut self, unit: f64) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| self.attempts >= max) {
+            return None;
+        }
+        self.attempts += 1;
+
+        let delay = jittered(self.backoff, unit);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        Some(delay)
+    }
+}
+
+/// Scale `base` into `[0.5, 1.5) * base` using `unit` in `[0, 1)`
+pub fn jittered(base: Duration, unit: f64) -> Duration {
+    base.mul_f64(0.5 + unit.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays_are_jittered_around_backoff() {
+        let mut backoff = ReconnectBackoff::new(None);
+        let delays: Vec<Duration> = [0.0, 0.5, 0.75, 0.25]
+            .iter()
+            .map(|unit| backoff.next_delay_with(*unit).unwrap())
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(500),
+                Duration::from_secs(2),
+                Duration::from_secs(5),
+                Duration::from_secs(6),
+            ]
+        );
+
+        // Random jitter stays within ±50% of the capped backoff and is not constant
+        let mut backoff = ReconnectBackoff::new(None);
+        for _ in 0..10 {
+            backoff.next_delay();
+        }
+        let delays: Vec<Duration> = (0..20).map(|_| backoff.next_delay().unwrap()).collect();
+        assert!(delays.iter().all(|d| *d >= MAX_BACKOFF / 2 && *d < MAX_BACKOFF * 3 / 2));
+        assert!(delays.iter().any(|d| *d != delays[0]));
+    }
+
+    #[test]
+    fn test_attempt_cap_is_honored() {
+        let mut backoff = ReconnectBackoff::new(Some(3));
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        assert_eq!(backoff.next_delay(), None);
+        assert_eq!(backoff.attempts(), 3);
+
+        // A successful connection starts a new streak
+        backoff.reset();
+        assert_eq!(backoff.next_delay_with(0.5), Some(INITIAL_BACKOFF));
+    }
+}
@@ -0,0 +1,118 @@
+//! Subscribe frames sent after connecting
+//!
+//! Exchanges rate-limit subscribe messages and may disconnect clients that send
+//! hundreds of them in a burst after a reconnect, so frames can be paced with an
+//! interval timer.
+
+use anyhow::{Context, Result};
+use futures_util::{Sink, SinkExt};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info};
+
+/// Log progress every this many subscribe frames
+const PROGRESS_LOG_INTERVAL: usize = 50;
+
+/// Smallest accepted rate, so a zero, negative or NaN setting cannot overflow the period
+const MIN_MESSAGES_PER_SEC: f64 = 0.001;
+
+/// Pacing for outbound subscribe frames
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeRateLimit {
+    /// Maximum number of subscribe frames sent per second
+    pub messages_per_sec: f64,
+}
+
+impl SubscribeRateLimit {
+    /// Time between two consecutive frames
+    /// Never zero, which the interval timer rejects, even for an infinite rate
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.messages_per_sec.max(MIN_MESSAGES_PER_SEC))
+            .max(Duration::from_nanos(1))
+    }
+}
+
+/// Send subscribe frames on the socket, paced by `rate_limit` if set
+/// Used both on the first connect and on every resubscribe after a reconnect
+pub async fn send_subscribe_frames<S>(
+    sink: &Mutex<S>,
+    frames: &[String],
+    rate_limit: Option<&SubscribeRateLimit>,
+) -> Result<()>
+where
+    S: Sink<Message> + Unpin,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    if frames.is_empty() {
+        return Ok(());
+    }
+
+    let mut interval = rate_limit.map(|limit| {
+        info!("Sending {} subscribe frames at {} frames/sec", frames.len(), limit.messages_per_sec);
+        tokio::time::interval(limit.period())
+    });
+
+    for (idx, frame) in frames.iter().enumerate() {
+        if let Some(interval) = interval.as_mut() {
+            interval.tick().await;
+        }
+
+        sink.lock().await.send(Message::Text(frame.clone())).await
+            .context(format!("Failed to send subscribe frame {}/{}", idx + 1, frames.len()))?;
+        debug!("Sent subscribe frame: {}", frame);
+
+        let sent = idx + 1;
+        if sent % PROGRESS_LOG_INTERVAL == 0 || sent == frames.len() {
+            info!("Sent {}/{} subscribe frames", sent, frames.len());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_subscribe_frames_are_paced() {
+        let sink = Mutex::new(futures_util::sink::drain());
+        let frames: Vec<String> = (0..5).map(|i| format!(r#"{{"id":{}}}"#, i)).collect();
+        let rate_limit = SubscribeRateLimit { messages_per_sec: 20.0 };
+
+        let start = Instant::now();
+        send_subscribe_frames(&sink, &frames, Some(&rate_limit)).await.unwrap();
+
+        // The first frame goes out immediately, the other 4 wait 50ms each
+        assert!(start.elapsed() >= Duration::from_millis(200), "{:?}", start.elapsed());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_rates_do_not_panic() {
+        let period = |messages_per_sec| SubscribeRateLimit { messages_per_sec }.period();
+        assert_eq!(period(0.0), Duration::from_secs(1000));
+        assert_eq!(period(-5.0), Duration::from_secs(1000));
+        assert_eq!(period(f64::NAN), Duration::from_secs(1000));
+        assert_eq!(period(1e-20), Duration::from_secs(1000));
+        assert_eq!(period(f64::INFINITY), Duration::from_nanos(1));
+
+        // The first frame is sent without waiting a period
+        let sink = Mutex::new(futures_util::sink::drain());
+        let frames = vec![r#"{"id":0}"#.to_string()];
+        let rate_limit = SubscribeRateLimit { messages_per_sec: 0.0 };
+        send_subscribe_frames(&sink, &frames, Some(&rate_limit)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_frames_unlimited() {
+        let sink = Mutex::new(futures_util::sink::drain());
+        let frames: Vec<String> = (0..100).map(|i| format!(r#"{{"id":{}}}"#, i)).collect();
+
+        let start = Instant::now();
+        send_subscribe_frames(&sink, &frames, None).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,186 @@
+//! Token refresh for authenticated WebSocket streams
+//!
+//! Some exchanges issue a short-lived token (e.g. a Binance listen key) that must be
+//! renewed every few minutes or the stream is dropped. This module fetches the token
+//! from an HTTP endpoint and sends a renew frame built from a template on the open socket.
+
+use anyhow::{Context, Result};
+use futures_util::{Sink, SinkExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, info};
+
+/// Placeholder in `message_template` replaced by the fetched token
+pub const TOKEN_PLACEHOLDER: &str = "{token}";
+
+/// Token refresh configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRefreshConfig {
+    /// HTTP endpoint returning the token as JSON (e.g., "https://fapi.binance.com/fapi/v1/listenKey")
+    pub url: String,
+    /// HTTP method used to fetch the token
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// Extra request headers (e.g., API key)
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Field of the JSON response holding the token
+    #[serde(default = "default_token_field")]
+    pub token_field: String,
+    /// Seconds between refreshes
+    pub interval_secs: u64,
+    /// Subscribe/renew frame sent after each fetch; `{token}` is replaced by the token
+    pub message_template: String,
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_token_field() -> String {
+    "listenKey".to_string()
+}
+
+/// Fetches tokens and sends renew frames
+pub struct TokenRefresher {
+    client: reqwest::Client,
+    config: TokenRefreshConfig,
+}
+
+impl TokenRefresher {
+    /// Create a refresher, routing token requests through `proxy` if set
+    pub fn new(config: TokenRefreshConfig, proxy: Option<&str>) -> Result<Self> {
+        let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(10));
+        if let Some(proxy_url) = proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy_url).context("Invalid proxy URL")?);
+        }
+        let client = builder.build().context("Failed to build HTTP client")?;
+        Ok(Self { client, config })
+    }
+
+    /// Fetch a fresh token from the configured endpoint
+    pub async fn fetch_token(&self) -> Result<String> {
+        let method = reqwest::Method::from_bytes(self.config.method.to_uppercase().as_bytes())
+            .context(format!("Invalid HTTP method: {}", self.config.method))?;
+
+        let mut request = self.client.request(method, &self.config.url);
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await
+            .context(format!("Failed to request token from {}", self.config.url))?
+            .error_for_status()
+            .context("Token endpoint returned an error status")?;
+        let body: Value = response.json().await
+            .context("Token response is not valid JSON")?;
+
+        match body.get(&self.config.token_field) {
+            Some(Value::String(token)) => Ok(token.clone()),
+            _ => anyhow::bail!("Token response has no string field '{}'", self.config.token_field),
+        }
+    }
+
+    /// Build the renew frame for a token
+    pub fn render_message(&self, token: &str) -> String {
+        self.config.message_template.replace(TOKEN_PLACEHOLDER, token)
+    }
+
+    /// Fetch a fresh token and send the renew frame on the socket
+    pub async fn renew<S>(&self, sink: &Mutex<S>) -> Result<()>
+    where
+        S: Sink<Message> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let token = self.fetch_token().await?;
+        let message = self.render_message(&token);
+        sink.lock().await.send(Message::Text(message)).await
+            .context("Failed to send token renew frame")?;
+        info!("Sent token renew frame");
+        Ok(())
+    }
+
+    /// Renew the token every `interval_secs` until the task is aborted
+    /// Failed renewals are logged and retried on the next tick
+    pub fn spawn<S>(self: Arc<Self>, sink: Arc<Mutex<S>>) -> JoinHandle<()>
+    where
+        S: Sink<Message> + Unpin + Send + 'static,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        tokio::spawn(async move {
+            let period = Duration::from_secs(self.config.interval_secs.max(1));
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.renew(&sink).await {
+                    error!("Token refresh failed: {:#}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `body` as a JSON response to a single HTTP request
+    async fn mock_token_endpoint(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        format!("http://{}/listenKey", addr)
+    }
+
+    #[tokio::test]
+    async fn test_renew_sends_fetched_token() {
+        let url = mock_token_endpoint(r#"{"listenKey":"abc123"}"#).await;
+        let refresher = TokenRefresher::new(
+            TokenRefreshConfig {
+                url,
+                method: default_method(),
+                headers: HashMap::new(),
+                token_field: default_token_field(),
+                interval_secs: 60,
+                message_template: r#"{"method":"SUBSCRIBE","params":["{token}"],"id":1}"#.to_string(),
+            },
+            None,
+        )
+        .unwrap();
+
+        // WebSocket server returning the first frame it receives
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await.unwrap().unwrap()
+        });
+
+        let stream = crate::connect_wss_stream(&format!("ws://{}", addr), None, &crate::WsConfig::default()).await.unwrap();
+        let (write, _read) = stream.split();
+        refresher.renew(&Mutex::new(write)).await.unwrap();
+
+        let frame = server.await.unwrap();
+        assert_eq!(frame, Message::Text(r#"{"method":"SUBSCRIBE","params":["abc123"],"id":1}"#.to_string()));
+    }
+}
@@ -0,0 +1,270 @@
+//! Bounded queue between the WebSocket read loop and the writer task
+//!
+//! Writing to disk can be slower than a burst of incoming messages. Instead of
+//! stalling the read loop on `write_rows`, messages go through this queue to a
+//! dedicated writer task. When the queue is full the overflow policy decides whether
+//! the reader waits for space or the oldest queued data item is dropped. Control items
+//! such as flushes are never dropped.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// What to do when the queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait until the writer frees up space (the read loop pauses)
+    #[default]
+    Block,
+    /// Drop the oldest queued data item to make room and count the drop
+    /// Waits like `Block` while only control items are queued
+    DropOldest,
+}
+
+/// Queue items that the `DropOldest` policy may discard
+pub trait Evictable {
+    /// Data items return true; control items (flushes, shutdown) return false
+    fn evictable(&self) -> bool;
+}
+
+/// Write queue configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WriteQueueConfig {
+    /// Maximum number of queued messages
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Policy applied when the queue is full
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+fn default_capacity() -> usize {
+    1024
+}
+
+impl Default for WriteQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_capacity(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+struct Shared<T> {
+    queue: Mutex<VecDeque<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: AtomicU64,
+    sender_closed: AtomicBool,
+    receiver_closed: AtomicBool,
+    item_ready: Notify,
+    space_ready: Notify,
+}
+
+/// Sending half, used by the read loop
+pub struct WriteQueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Receiving half, owned by the writer task
+pub struct WriteQueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Create a bounded write queue
+pub fn write_queue<T>(config: &WriteQueueConfig) -> (WriteQueueSender<T>, WriteQueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::new()),
+        capacity: config.capacity.max(1),
+        policy: config.overflow,
+        dropped: AtomicU64::new(0),
+        sender_closed: AtomicBool::new(false),
+        receiver_closed: AtomicBool::new(false),
+        item_ready: Notify::new(),
+        space_ready: Notify::new(),
+    });
+    (
+        WriteQueueSender { shared: shared.clone() },
+        WriteQueueReceiver { shared },
+    )
+}
+
+impl<T: Evictable> WriteQueueSender<T> {
+    /// Queue an item, applying the overflow policy when full
+    /// Fails only if the writer task has stopped
+    pub async fn send(&self, item: T) -> Result<()> {
+        let mut item = Some(item);
+        loop {
+            if self.shared.receiver_closed.load(Ordering::Acquire) {
+                anyhow::bail!("Writer task has stopped");
+            }
+
+            {
+                let mut queue = self.shared.queue.lock().unwrap();
+                if queue.len() >= self.shared.capacity && self.shared.policy == OverflowPolicy::DropOldest {
+                    if let Some(oldest) = queue.iter().position(Evictable::evictable) {
+                        queue.remove(oldest);
+                        let dropped = self.shared.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                        if dropped == 1 || dropped.is_multiple_of(100) {
+                            warn!("Write queue full, dropped oldest message ({} dropped in total)", dropped);
+                        }
+                    }
+                }
+                if queue.len() < self.shared.capacity {
+                    queue.push_back(item.take().expect("item is only taken once"));
+                    self.shared.item_ready.notify_one();
+                    return Ok(());
+                }
+            }
+
+            // Block policy, or nothing evictable queued: wait for the writer to take an item
+            self.shared.space_ready.notified().await;
+        }
+    }
+
+}
+
+impl<T> WriteQueueSender<T> {
+    /// Number of items dropped by the `DropOldest` policy
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Number of queued items
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().unwrap().len()
+    }
+
+    /// Whether the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for WriteQueueSender<T> {
+    fn drop(&mut self) {
+        self.shared.sender_closed.store(true, Ordering::Release);
+        self.shared.item_ready.notify_one();
+    }
+}
+
+impl<T> WriteQueueReceiver<T> {
+    /// Take the next item, or `None` once the sender is dropped and the queue is drained
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut queue = self.shared.queue.lock().unwrap();
+                if let Some(item) = queue.pop_front() {
+                    self.shared.space_ready.notify_one();
+                    return Some(item);
+                }
+                if self.shared.sender_closed.load(Ordering::Acquire) {
+                    return None;
+                }
+            }
+            self.shared.item_ready.notified().await;
+        }
+    }
+}
+
+impl<T> Drop for WriteQueueReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_closed.store(true, Ordering::Release);
+        self.shared.space_ready.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    impl Evictable for u32 {
+        fn evictable(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Item {
+        Data(u32),
+        Flush,
+    }
+
+    impl Evictable for Item {
+        fn evictable(&self) -> bool {
+            matches!(self, Item::Data(_))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_counts_drops() {
+        let config = WriteQueueConfig { capacity: 2, overflow: OverflowPolicy::DropOldest };
+        let (sender, mut receiver) = write_queue(&config);
+
+        for i in 0..5 {
+            sender.send(i).await.unwrap();
+        }
+        assert_eq!(sender.dropped(), 3);
+        drop(sender);
+
+        // Only the newest items are left
+        assert_eq!(receiver.recv().await, Some(3));
+        assert_eq!(receiver.recv().await, Some(4));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_keeps_control_items() {
+        let config = WriteQueueConfig { capacity: 3, overflow: OverflowPolicy::DropOldest };
+        let (sender, mut receiver) = write_queue(&config);
+
+        sender.send(Item::Flush).await.unwrap();
+        sender.send(Item::Data(0)).await.unwrap();
+        sender.send(Item::Flush).await.unwrap();
+        sender.send(Item::Data(1)).await.unwrap();
+        sender.send(Item::Flush).await.unwrap();
+        assert_eq!(sender.dropped(), 2);
+
+        // Only control items are queued now, so the next send waits for the writer
+        assert!(tokio::time::timeout(Duration::from_millis(50), sender.send(Item::Data(2))).await.is_err());
+        assert_eq!(receiver.recv().await, Some(Item::Flush));
+        tokio::time::timeout(Duration::from_secs(1), sender.send(Item::Data(2))).await.unwrap().unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.recv().await, Some(Item::Flush));
+        assert_eq!(receiver.recv().await, Some(Item::Flush));
+        assert_eq!(receiver.recv().await, Some(Item::Data(2)));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_space() {
+        let config = WriteQueueConfig { capacity: 2, overflow: OverflowPolicy::Block };
+        let (sender, mut receiver) = write_queue(&config);
+
+        sender.send(0).await.unwrap();
+        sender.send(1).await.unwrap();
+
+        // The queue is full, so the third send waits
+        assert!(tokio::time::timeout(Duration::from_millis(50), sender.send(2)).await.is_err());
+
+        assert_eq!(receiver.recv().await, Some(0));
+        tokio::time::timeout(Duration::from_secs(1), sender.send(2)).await.unwrap().unwrap();
+        assert_eq!(sender.dropped(), 0);
+        assert_eq!(sender.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_fails_after_receiver_stops() {
+        let (sender, receiver) = write_queue::<u32>(&WriteQueueConfig::default());
+        drop(receiver);
+        assert!(sender.send(1).await.is_err());
+    }
+}
@@ -12,19 +12,30 @@ use serde_json::Value;
 pub type DataRow = HashMap<String, Value>;
 
 /// Writer type selection
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum WriterType {
     /// Parquet format - batched writes
+    #[default]
     Parquet,
     /// JSON format - line-by-line append
     Json,
+    /// Arrow IPC (Feather v2) format - batched writes, one file per flush
+    Feather,
 }
 
-impl Default for WriterType {
-    fn default() -> Self {
-        WriterType::Parquet
-    }
+/// One output of a collector writing to several sinks
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WriterSpec {
+    /// Output format of this sink
+    #[serde(rename = "type")]
+    pub writer_type: WriterType,
+    /// Base path for this sink's files; defaults to the collector's `path`
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Name prefix for this sink's files; defaults to the collector's `name`
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
 /// Common interface for data writers
@@ -1,23 +1,104 @@
 //! WebSocket connection library with proxy support
 //!
 //! This library provides utilities for establishing WebSocket connections
-//! with optional HTTP proxy support.
+//! with optional HTTP proxy support, or over a Unix domain socket for co-located
+//! relays (`ws+unix:///path/to/socket`).
 
 use anyhow::{Context, Result};
 use futures_util::{Sink, Stream};
 use std::pin::Pin;
 use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
-use tracing::{info, error};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tracing::{info, error, warn};
 use url::Url;
 
-/// Enum to hold different stream types for proxied or direct connections
+use crate::deflate::{accepted_permessage_deflate, DeflateStream, PERMESSAGE_DEFLATE};
+use crate::error::{CollectorError, CollectorResult};
+
+/// WebSocket protocol options applied to both the direct and the proxied connection
+///
+/// Size limits left at `None` use the tungstenite defaults: 64 MiB per message and
+/// 16 MiB per frame. Raise them for very large snapshot frames, at the cost of buffering
+/// that much per message; lower them to bound memory. A message over the limit ends the
+/// stream with a capacity error instead of being truncated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WsConfig {
+    /// Offer permessage-deflate compression in the handshake; servers without it are
+    /// read uncompressed (see `crate::deflate`)
+    #[serde(default)]
+    pub compression: bool,
+    /// Maximum size of a complete (possibly fragmented) message in bytes
+    #[serde(default)]
+    pub max_message_size: Option<usize>,
+    /// Maximum size of a single frame in bytes
+    #[serde(default)]
+    pub max_frame_size: Option<usize>,
+    /// PEM file with a CA certificate to trust for `wss://` in addition to the system roots,
+    /// e.g. the private CA of an internal gateway
+    #[serde(default)]
+    pub ca_cert_path: Option<String>,
+    /// INSECURE: accept any server certificate (self-signed, expired, other host names).
+    /// Anyone on the network path can then read and alter the stream; prefer `ca_cert_path`
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+/// Details about an established WebSocket connection, for diagnosing flaky providers
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// Proxy the connection was tunneled through, if any
+    pub via_proxy: Option<String>,
+    /// Subprotocol selected by the server (Sec-WebSocket-Protocol)
+    pub negotiated_protocol: Option<String>,
+    /// Whether the server accepted permessage-deflate compression
+    pub compressed: bool,
+    /// Time from starting the connection (including proxy and TLS) to a completed handshake
+    pub handshake_duration: Duration,
+}
+
+impl ConnectionInfo {
+    fn new(response: &Response, via_proxy: Option<&str>, started: Instant) -> Self {
+        Self {
+            via_proxy: via_proxy.map(str::to_string),
+            negotiated_protocol: response
+                .headers()
+                .get("Sec-WebSocket-Protocol")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            compressed: accepted_permessage_deflate(
+                response
+                    .headers()
+                    .get_all("Sec-WebSocket-Extensions")
+                    .iter()
+                    .filter_map(|value| value.to_str().ok()),
+            )
+            .is_some(),
+            handshake_duration: started.elapsed(),
+        }
+    }
+}
+
+/// URL scheme for WebSocket connections over a Unix domain socket
+pub const UNIX_SOCKET_SCHEME: &str = "ws+unix://";
+
+/// Enum to hold different stream types for proxied, direct or Unix socket connections
+///
+/// Every transport is wrapped in a `DeflateStream` so compressed messages are inflated
+/// whichever way the connection was made.
 pub enum ProxyStream {
-    Plain(WebSocketStream<TcpStream>),
-    Tls(WebSocketStream<tokio_native_tls::TlsStream<TcpStream>>),
-    Direct(WebSocketStream<MaybeTlsStream<TcpStream>>),
+    Plain(WebSocketStream<DeflateStream<TcpStream>>),
+    Tls(WebSocketStream<DeflateStream<tokio_native_tls::TlsStream<TcpStream>>>),
+    Direct(WebSocketStream<DeflateStream<MaybeTlsStream<TcpStream>>>),
+    #[cfg(unix)]
+    Unix(WebSocketStream<DeflateStream<tokio::net::UnixStream>>),
 }
 
 impl ProxyStream {
@@ -41,6 +122,8 @@ impl Stream for ProxyStream {
             ProxyStream::Plain(s) => Pin::new(s).poll_next(cx),
             ProxyStream::Tls(s) => Pin::new(s).poll_next(cx),
             ProxyStream::Direct(s) => Pin::new(s).poll_next(cx),
+            #[cfg(unix)]
+            ProxyStream::Unix(s) => Pin::new(s).poll_next(cx),
         }
     }
 }
@@ -56,6 +139,8 @@ impl Sink<Message> for ProxyStream {
             ProxyStream::Plain(s) => Pin::new(s).poll_ready(cx),
             ProxyStream::Tls(s) => Pin::new(s).poll_ready(cx),
             ProxyStream::Direct(s) => Pin::new(s).poll_ready(cx),
+            #[cfg(unix)]
+            ProxyStream::Unix(s) => Pin::new(s).poll_ready(cx),
         }
     }
 
@@ -64,6 +149,8 @@ impl Sink<Message> for ProxyStream {
             ProxyStream::Plain(s) => Pin::new(s).start_send(item),
             ProxyStream::Tls(s) => Pin::new(s).start_send(item),
             ProxyStream::Direct(s) => Pin::new(s).start_send(item),
+            #[cfg(unix)]
+            ProxyStream::Unix(s) => Pin::new(s).start_send(item),
         }
     }
 
@@ -75,6 +162,8 @@ impl Sink<Message> for ProxyStream {
             ProxyStream::Plain(s) => Pin::new(s).poll_flush(cx),
             ProxyStream::Tls(s) => Pin::new(s).poll_flush(cx),
             ProxyStream::Direct(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            ProxyStream::Unix(s) => Pin::new(s).poll_flush(cx),
         }
     }
 
@@ -86,6 +175,8 @@ impl Sink<Message> for ProxyStream {
             ProxyStream::Plain(s) => Pin::new(s).poll_close(cx),
             ProxyStream::Tls(s) => Pin::new(s).poll_close(cx),
             ProxyStream::Direct(s) => Pin::new(s).poll_close(cx),
+            #[cfg(unix)]
+            ProxyStream::Unix(s) => Pin::new(s).poll_close(cx),
         }
     }
 }
@@ -156,7 +247,7 @@ async fn connect_through_proxy(
         }
     ).await;
     
-    if let Err(_) = read_result {
+    if read_result.is_err() {
         anyhow::bail!("Timeout reading proxy CONNECT response");
     }
     read_result??;
@@ -172,36 +263,160 @@ async fn connect_through_proxy(
     Ok(stream)
 }
 
+/// Build the WebSocket configuration shared by the direct and proxied connection paths
+///
+/// Fragmented messages are always reassembled by tungstenite, so the read loop only sees
+/// whole `Text`/`Binary` messages; `max_message_size` bounds the reassembled size.
+fn websocket_config(config: &WsConfig) -> WebSocketConfig {
+    let mut ws_config = WebSocketConfig::default();
+    if let Some(max_message_size) = config.max_message_size {
+        ws_config.max_message_size = Some(max_message_size);
+    }
+    if let Some(max_frame_size) = config.max_frame_size {
+        ws_config.max_frame_size = Some(max_frame_size);
+    }
+    ws_config
+}
+
+/// Build the handshake request, offering permessage-deflate when `compression` is set
+fn handshake_request(url: &str, config: &WsConfig) -> Result<Request> {
+    let mut request = url.into_client_request().context("Invalid WebSocket request")?;
+    if config.compression {
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Extensions", HeaderValue::from_static(PERMESSAGE_DEFLATE));
+    }
+    Ok(request)
+}
+
+/// Log whether a requested permessage-deflate was accepted
+fn log_compression(config: &WsConfig, info: &ConnectionInfo) {
+    if info.compressed {
+        info!("permessage-deflate compression negotiated");
+    } else if config.compression {
+        info!("Server did not accept permessage-deflate, continuing uncompressed");
+    }
+}
+
+/// Build the TLS connector for `wss://`, trusting `ca_cert_path` in addition to the system roots
+fn tls_connector(config: &WsConfig) -> Result<native_tls::TlsConnector> {
+    let mut builder = native_tls::TlsConnector::builder();
+    if let Some(path) = &config.ca_cert_path {
+        let pem = std::fs::read(path).context(format!("Failed to read CA certificate: {}", path))?;
+        let cert = native_tls::Certificate::from_pem(&pem)
+            .context(format!("Failed to parse PEM CA certificate: {}", path))?;
+        builder.add_root_certificate(cert);
+    }
+    if config.accept_invalid_certs {
+        warn!("TLS certificate verification is disabled (accept_invalid_certs), the connection is not secure");
+        builder.danger_accept_invalid_certs(true);
+    }
+    builder.build().context("Failed to build TLS connector")
+}
+
+/// Connect to a WebSocket served on a Unix domain socket
+///
+/// The handshake requests `/` with `Host: localhost`; there is no TLS since the socket
+/// never leaves the machine.
+#[cfg(unix)]
+async fn connect_unix_socket(
+    socket_path: &str,
+    config: &WsConfig,
+    started: Instant,
+) -> Result<(ProxyStream, ConnectionInfo)> {
+    info!("Connecting to WebSocket over Unix socket: {}", socket_path);
+    let ws_config = websocket_config(config);
+    let request = handshake_request("ws://localhost/", config)?;
+    let connect = async {
+        let stream = tokio::net::UnixStream::connect(socket_path).await
+            .context(format!("Failed to connect to Unix socket: {}", socket_path))?;
+        tokio_tungstenite::client_async_with_config(request, DeflateStream::new(stream, &ws_config), Some(ws_config)).await
+            .context("WebSocket handshake failed")
+    };
+    let (ws_stream, response) = tokio::time::timeout(std::time::Duration::from_secs(10), connect).await
+        .map_err(|_| anyhow::anyhow!("WebSocket connection timeout on Unix socket: {}", socket_path))??;
+
+    info!("WebSocket connection successful");
+    Ok((ProxyStream::Unix(ws_stream), ConnectionInfo::new(&response, None, started)))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix_socket(
+    socket_path: &str,
+    _config: &WsConfig,
+    _started: Instant,
+) -> Result<(ProxyStream, ConnectionInfo)> {
+    anyhow::bail!("Unix socket endpoints are not supported on this platform: {}", socket_path)
+}
+
 /// Connect to a WebSocket with optional HTTP proxy support
+///
+/// `ws+unix:///path/to/socket` URLs connect over a Unix domain socket instead; the proxy
+/// does not apply to them and is ignored with a warning.
+///
+/// `config` carries compression, message size limits and TLS trust settings (see `WsConfig`).
+/// A proxy that cannot tunnel returns `CollectorError::ProxyUnavailable`, any other
+/// failure `CollectorError::Connect`.
 pub async fn connect_wss_stream(
     wss_url: &str,
     proxy: Option<&str>,
-) -> Result<ProxyStream> {
+    config: &WsConfig,
+) -> CollectorResult<ProxyStream> {
+    let (stream, _) = connect_wss_stream_verbose(wss_url, proxy, config).await?;
+    Ok(stream)
+}
+
+/// Same as `connect_wss_stream`, also returning details about the connection
+pub async fn connect_wss_stream_verbose(
+    wss_url: &str,
+    proxy: Option<&str>,
+    config: &WsConfig,
+) -> CollectorResult<(ProxyStream, ConnectionInfo)> {
+    connect_wss_stream_impl(wss_url, proxy, config)
+        .await
+        .map_err(|source| match source.downcast::<CollectorError>() {
+            Ok(error) => error,
+            Err(source) => CollectorError::Connect { url: wss_url.to_string(), source },
+        })
+}
+
+async fn connect_wss_stream_impl(
+    wss_url: &str,
+    proxy: Option<&str>,
+    config: &WsConfig,
+) -> Result<(ProxyStream, ConnectionInfo)> {
+    let started = Instant::now();
+    if let Some(socket_path) = wss_url.strip_prefix(UNIX_SOCKET_SCHEME) {
+        if let Some(proxy_url) = proxy {
+            warn!("Ignoring HTTP proxy {} for Unix socket endpoint {}", proxy_url, wss_url);
+        }
+        let (stream, info) = connect_unix_socket(socket_path, config, started).await?;
+        log_compression(config, &info);
+        return Ok((stream, info));
+    }
+
     let url = Url::parse(wss_url)
         .context("Failed to parse WebSocket URL")?;
+    let ws_config = websocket_config(config);
+    let request = handshake_request(wss_url, config)?;
     
     let scheme = url.scheme();
     let host = url.host_str()
         .context("WebSocket URL must have a host")?;
     let port = url.port().unwrap_or(if scheme == "wss" { 443 } else { 80 });
     
-    if let Some(proxy_url) = proxy {
+    let connected = if let Some(proxy_url) = proxy {
         info!("Using HTTP proxy: {}", proxy_url);
         
         // Connect through proxy
-        let tcp_stream = connect_through_proxy(proxy_url, host, port).await?;
+        let tcp_stream = connect_through_proxy(proxy_url, host, port).await
+            .map_err(|source| CollectorError::ProxyUnavailable { proxy: proxy_url.to_string(), source })?;
         
         // Upgrade to TLS if needed
         if scheme == "wss" {
             info!("Upgrading to TLS for wss://{}", host);
             
-            // Build TLS connector with proper configuration
-            let connector = native_tls::TlsConnector::builder()
-                .danger_accept_invalid_certs(false)  // Keep security enabled
-                .danger_accept_invalid_hostnames(false)
-                .build()
-                .context("Failed to build TLS connector")?;
-            let connector = tokio_native_tls::TlsConnector::from(connector);
+            let connector = tokio_native_tls::TlsConnector::from(tls_connector(config)?);
             
             info!("Establishing TLS connection to: {}", host);
             match tokio::time::timeout(
@@ -213,11 +428,11 @@ pub async fn connect_wss_stream(
                     
                     // Perform WebSocket handshake on TLS stream
                     info!("Performing WebSocket handshake");
-                    let (ws_stream, _) = tokio_tungstenite::client_async(wss_url, tls_stream).await
+                    let (ws_stream, response) = tokio_tungstenite::client_async_with_config(request, DeflateStream::new(tls_stream, &ws_config), Some(ws_config)).await
                         .context("WebSocket handshake failed")?;
                     
                     info!("WebSocket handshake successful");
-                    Ok(ProxyStream::Tls(ws_stream))
+                    Ok((ProxyStream::Tls(ws_stream), ConnectionInfo::new(&response, proxy, started)))
                 }
                 Ok(Err(e)) => {
                     error!("TLS handshake error: {:?}", e);
@@ -235,41 +450,57 @@ pub async fn connect_wss_stream(
         } else {
             // Perform WebSocket handshake on plain TCP stream
             info!("Performing WebSocket handshake");
-            let (ws_stream, _) = tokio_tungstenite::client_async(wss_url, tcp_stream).await
+            let (ws_stream, response) = tokio_tungstenite::client_async_with_config(request, DeflateStream::new(tcp_stream, &ws_config), Some(ws_config)).await
                 .context("WebSocket handshake failed")?;
             
-            Ok(ProxyStream::Plain(ws_stream))
+            Ok((ProxyStream::Plain(ws_stream), ConnectionInfo::new(&response, proxy, started)))
         }
     } else {
         // No proxy, connect directly
         info!("Connecting directly to WebSocket: {}", wss_url);
         info!("Target host: {}:{} (scheme: {})", host, port, scheme);
         
-        // Add timeout and better error reporting for direct connection
-        let connect_result = tokio::time::timeout(
-            std::time::Duration::from_secs(10),
-            connect_async(wss_url)
-        ).await;
+        let connector = if scheme == "wss" {
+            Some(tokio_native_tls::TlsConnector::from(tls_connector(config)?))
+        } else {
+            None
+        };
         
-        match connect_result {
-            Ok(Ok((ws_stream, _))) => {
+        // TCP and TLS are set up here rather than by tungstenite so the transport can be
+        // wrapped in a DeflateStream
+        let connect = async {
+            let tcp_stream = TcpStream::connect((host, port)).await
+                .context(format!("Failed to connect to {}:{}", host, port))?;
+            let stream = match connector {
+                Some(connector) => MaybeTlsStream::NativeTls(
+                    connector.connect(host, tcp_stream).await
+                        .context("Failed to establish TLS connection")?,
+                ),
+                None => MaybeTlsStream::Plain(tcp_stream),
+            };
+            tokio_tungstenite::client_async_with_config(request, DeflateStream::new(stream, &ws_config), Some(ws_config)).await
+                .context("WebSocket handshake failed")
+        };
+
+        // Add timeout and better error reporting for direct connection
+        match tokio::time::timeout(std::time::Duration::from_secs(10), connect).await {
+            Ok(Ok((ws_stream, response))) => {
                 info!("WebSocket connection successful");
-                Ok(ProxyStream::Direct(ws_stream))
+                Ok((ProxyStream::Direct(ws_stream), ConnectionInfo::new(&response, None, started)))
             }
             Ok(Err(e)) => {
-                error!("WebSocket handshake error: {:?}", e);
-                error!("Error details: {}", e);
-                if let Some(source) = std::error::Error::source(&e) {
-                    error!("Error source: {}", source);
-                }
-                Err(anyhow::anyhow!("WebSocket handshake failed: {}", e))
+                error!("WebSocket connection error: {:#}", e);
+                Err(e)
             }
             Err(_) => {
                 error!("WebSocket connection timeout (10s) - possible network/firewall issue");
                 Err(anyhow::anyhow!("WebSocket connection timeout - possible firewall or network block"))
             }
         }
-    }
+    };
+    let (stream, info) = connected?;
+    log_compression(config, &info);
+    Ok((stream, info))
 }
 
 #[cfg(test)]
@@ -281,4 +512,257 @@ mod tests {
         // This is a placeholder test
         // Real tests would require async runtime and mocking
     }
+
+    #[tokio::test]
+    async fn test_connection_info() {
+        use futures_util::SinkExt;
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // WebSocket server accepting two connections
+        let ws_server = tokio::spawn(async move {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                ws.close(None).await.unwrap();
+            }
+        });
+
+        // Minimal HTTP CONNECT proxy that tunnels one connection to the server
+        let proxy_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy_listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = proxy_listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = client.read(&mut buf).await.unwrap();
+            client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+            let mut upstream = TcpStream::connect(addr).await.unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+        });
+
+        let url = format!("ws://{}", addr);
+        let (mut stream, info) = connect_wss_stream_verbose(&url, None, &WsConfig::default()).await.unwrap();
+        assert!(info.via_proxy.is_none());
+        assert!(info.negotiated_protocol.is_none());
+        assert!(info.handshake_duration > Duration::ZERO);
+        let _ = stream.close().await;
+
+        let proxy_url = format!("http://{}", proxy_addr);
+        let (mut stream, info) = connect_wss_stream_verbose(&url, Some(&proxy_url), &WsConfig::default()).await.unwrap();
+        assert_eq!(info.via_proxy.as_deref(), Some(proxy_url.as_str()));
+        assert!(info.handshake_duration > Duration::ZERO);
+        let _ = stream.close().await;
+
+        ws_server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compressed_frames_are_decoded() {
+        use flate2::{Compress, Compression, FlushCompress};
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+
+        const MESSAGE: &str = r#"{"data":[{"s":"BTCUSDT","p":"97000.1"},{"s":"BTCUSDT","p":"97000.2"}]}"#;
+
+        fn frame(first_byte: u8, payload: &[u8]) -> Vec<u8> {
+            let mut out = vec![first_byte, payload.len() as u8];
+            out.extend_from_slice(payload);
+            out
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Server that compresses its messages when the client offers permessage-deflate,
+        // the second one split in two fragments and sharing the first one's window
+        let server = tokio::spawn(async move {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0u8; 1];
+                    stream.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let key = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("sec-websocket-key: ").or_else(|| line.strip_prefix("Sec-WebSocket-Key: ")))
+                    .unwrap();
+                let offered = request.to_lowercase().contains("sec-websocket-extensions: permessage-deflate");
+                let mut response = format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {}\r\n",
+                    derive_accept_key(key.as_bytes())
+                );
+                if offered {
+                    response.push_str("Sec-WebSocket-Extensions: permessage-deflate\r\n");
+                }
+                response.push_str("\r\n");
+                stream.write_all(response.as_bytes()).await.unwrap();
+
+                if offered {
+                    let mut compress = Compress::new(Compression::default(), false);
+                    let mut deflate = |data: &[u8]| {
+                        let mut out = Vec::with_capacity(data.len() + 64);
+                        compress.compress_vec(data, &mut out, FlushCompress::Sync).unwrap();
+                        out.truncate(out.len() - 4);
+                        out
+                    };
+                    let first = deflate(MESSAGE.as_bytes());
+                    let second = deflate(MESSAGE.as_bytes());
+                    let (head, tail) = second.split_at(second.len() / 2);
+                    let mut frames = frame(0x80 | 0x40 | 0x1, &first);
+                    frames.extend(frame(0x40 | 0x1, head));
+                    frames.extend(frame(0x80, tail));
+                    stream.write_all(&frames).await.unwrap();
+                } else {
+                    stream.write_all(&frame(0x80 | 0x1, MESSAGE.as_bytes())[..]).await.unwrap();
+                }
+                stream.write_all(&frame(0x88, &[0x03, 0xe8])).await.unwrap();
+                // Wait for the client's close frame before dropping the connection
+                let _ = stream.read(&mut [0u8; 64]).await;
+            }
+        });
+
+        let url = format!("ws://{}", addr);
+        let ws_config = WsConfig { compression: true, ..Default::default() };
+        let (mut stream, info) = connect_wss_stream_verbose(&url, None, &ws_config).await.unwrap();
+        assert!(info.compressed);
+        for _ in 0..2 {
+            assert_eq!(stream.next().await.unwrap().unwrap(), Message::Text(MESSAGE.to_string()));
+        }
+        assert!(stream.next().await.unwrap().unwrap().is_close());
+        let _ = stream.close().await;
+
+        // Without the option nothing is offered and messages arrive uncompressed
+        let (mut stream, info) = connect_wss_stream_verbose(&url, None, &WsConfig::default()).await.unwrap();
+        assert!(!info.compressed);
+        assert_eq!(stream.next().await.unwrap().unwrap(), Message::Text(MESSAGE.to_string()));
+        let _ = stream.close().await;
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_private_ca_is_trusted_only_when_configured() {
+        use futures_util::{SinkExt, StreamExt};
+
+        // Server certificate for 127.0.0.1 signed by a private test CA (see testdata/tls)
+        let testdata = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tls");
+        let identity = native_tls::Identity::from_pkcs8(
+            &std::fs::read(testdata.join("server.pem")).unwrap(),
+            &std::fs::read(testdata.join("server-key.pem")).unwrap(),
+        )
+        .unwrap();
+        let acceptor = tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity).unwrap());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    // Handshakes rejected by the client end here
+                    let Ok(tls_stream) = acceptor.accept(stream).await else { return };
+                    let Ok(mut ws) = tokio_tungstenite::accept_async(tls_stream).await else { return };
+                    let _ = ws.send(Message::Text("hello".to_string())).await;
+                });
+            }
+        });
+        let url = format!("wss://127.0.0.1:{}", addr.port());
+
+        let error = connect_wss_stream(&url, None, &WsConfig::default()).await.err().unwrap();
+        assert!(error.to_string().contains("certificate"), "unexpected error: {}", error);
+
+        let ws_config = WsConfig {
+            ca_cert_path: Some(testdata.join("ca.pem").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let mut stream = connect_wss_stream(&url, None, &ws_config).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), Message::Text("hello".to_string()));
+
+        let ws_config = WsConfig { accept_invalid_certs: true, ..Default::default() };
+        let mut stream = connect_wss_stream(&url, None, &ws_config).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), Message::Text("hello".to_string()));
+
+        let ws_config = WsConfig { ca_cert_path: Some("/nonexistent/ca.pem".to_string()), ..Default::default() };
+        let error = connect_wss_stream(&url, None, &ws_config).await.err().unwrap();
+        assert!(error.to_string().contains("/nonexistent/ca.pem"), "unexpected error: {}", error);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_round_trip() {
+        use futures_util::{SinkExt, StreamExt};
+
+        let dir = std::env::temp_dir().join(format!("wss-collector-unix-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("relay.sock");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+
+        // Echo server
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(message)) = ws.next().await {
+                if message.is_text() && ws.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        // The proxy is ignored for Unix sockets
+        let url = format!("{}{}", UNIX_SOCKET_SCHEME, socket_path.display());
+        let (mut stream, info) = connect_wss_stream_verbose(&url, Some("http://127.0.0.1:1"), &WsConfig::default())
+            .await
+            .unwrap();
+        assert!(matches!(stream, ProxyStream::Unix(_)));
+        assert!(info.via_proxy.is_none());
+
+        stream.send(Message::Text("ping".to_string())).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), Message::Text("ping".to_string()));
+        stream.close().await.unwrap();
+        server.await.unwrap();
+
+        let error = connect_wss_stream(&url, None, &WsConfig::default()).await.err().unwrap();
+        assert!(error.to_string().contains("relay.sock"), "unexpected error: {}", error);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_message_over_size_limit_errors() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Error as WsError;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Server sending one message just under and one just over the client's limit
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.send(Message::Text("a".repeat(1000))).await.unwrap();
+            let _ = ws.send(Message::Text("b".repeat(1100))).await;
+        });
+
+        let ws_config = WsConfig {
+            max_message_size: Some(1024),
+            max_frame_size: Some(1024),
+            ..Default::default()
+        };
+        let mut stream = connect_wss_stream(&format!("ws://{}", addr), None, &ws_config).await.unwrap();
+
+        let message = stream.next().await.unwrap().unwrap();
+        assert_eq!(message, Message::Text("a".repeat(1000)));
+
+        let error = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(error, WsError::Capacity(_)), "unexpected error: {:?}", error);
+
+        server.await.unwrap();
+    }
 }